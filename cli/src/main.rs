@@ -1,13 +1,37 @@
 use arboard::Clipboard;
 use clap::*;
+use engine::audit::*;
+use engine::backup::*;
+use engine::breach::*;
 use engine::data_base::*;
 use engine::file_io::*;
+use engine::generator::*;
+use engine::header::{DBHeader, FormatVersion};
+use engine::kdf::*;
+use engine::key_file::KeyFile;
+use engine::key_slots::*;
 use engine::master_secrets::*;
+use engine::metadata::*;
+use engine::migration::*;
+use engine::organize::*;
+use engine::otp::*;
+use engine::recovery::*;
+use engine::pw_policy::{MasterPWPolicy, PWScore, StrengthEstimate};
+use engine::search::*;
+use engine::shamir::{ShamirError, Share};
+use engine::trash::*;
+use engine::user_secrets::{SessionKeyNonce, WrappedSessionKey};
+use engine::vault_path::VaultPath;
+use engine::x25519::{PubKey, SecKey};
 use single_instance::SingleInstance;
+use std::fs;
 use std::io;
-use std::io::{Read, Write, stdin};
+use std::io::{IsTerminal, Read, Write, stdin};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::string::String;
+use std::time::Duration;
 use zeroize::*;
 
 use engine::init::sodium_init;
@@ -19,15 +43,70 @@ fn main() {
         return;
     }
 
-    // let mut should_save_db = true;
-
-    let (user_wran, mut db_header, encrypted_db) = match load_db() {
+    let args = CliArgs::parse();
+    let default_retention = RetentionPolicy::default();
+    let vault = VaultPath::resolve(args.vault).with_retention_policy(RetentionPolicy {
+        keep_last: args.keep_last.unwrap_or(default_retention.keep_last),
+        keep_daily: args.keep_daily.unwrap_or(default_retention.keep_daily),
+        keep_weekly: args.keep_weekly.unwrap_or(default_retention.keep_weekly),
+    });
+    let master_pw_policy = MasterPWPolicy {
+        min_chars: args.min_master_pw_chars.unwrap_or(MasterPWPolicy::default().min_chars),
+        min_score: args.min_master_pw_score.unwrap_or(MasterPWPolicy::default().min_score),
+    };
+    let breach_list = args.hibp.as_deref().and_then(|path| match BreachList::open(path) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            println!("Error opening breach list: {}", e);
+            None
+        }
+    });
+    let mut key_file = match args.keyfile.as_deref().map(KeyFile::read) {
+        None => None,
+        Some(Ok(v)) => Some(v),
+        Some(Err(e)) => {
+            println!("Error reading key file: {}", e);
+            exit(0);
+        }
+    };
+    if let Some(path) = &args.teammate_public_key {
+        let result = if args.generate_teammate_key { KeyFile::generate(path) } else { KeyFile::read(path) };
+        match result {
+            Ok(v) => println!("Teammate public key: {}", TeammateKey::from_key_file(&v).to_text()),
+            Err(e) => println!("Error reading key file: {}", e),
+        }
+        exit(0);
+    }
+    println!("Vault: {}", vault.db_file().display());
+    // 종료할 때까지 잡고 있어서 같은 볼트를 여는 다른 프로세스는 에러를 받음
+    let vault = match VaultHandle::open(vault) {
         Ok(v) => v,
         Err(e) => {
-            println!("Error loading db: {}", e);
+            println!("Error opening vault: {}", e);
             exit(0);
         }
     };
+
+    // let mut should_save_db = true;
+
+    let mut read_only = false;
+    let (user_wran, mut db_header, encrypted_db) = loop {
+        match load_db(&vault) {
+            Ok(v) => break v,
+            Err(FileIOError::Quarantined(quarantine_path, cause)) => {
+                println!("Error loading db: {}", cause);
+                println!("The file was moved to {}", quarantine_path.display());
+                if let Some((header, ciphertext)) = recover_quarantined_db(&vault, &quarantine_path) {
+                    read_only = true;
+                    break (None, header, Some(ciphertext));
+                }
+            }
+            Err(e) => {
+                println!("Error loading db: {}", e);
+                exit(0);
+            }
+        }
+    };
     match user_wran {
         Some(w) => {
             println!("Warn loading db: {}", w)
@@ -35,7 +114,13 @@ fn main() {
         None => {}
     }
 
+    if args.check_migration {
+        check_migration(&db_header, encrypted_db.as_ref(), key_file.as_ref());
+        exit(0);
+    }
+
     let mut db: DB;
+    let mut sec_key;
     let mut pub_key;
     let mut wrapped_user_key;
     let mut user_key_nonce;
@@ -46,10 +131,13 @@ fn main() {
             io::stdout().flush().unwrap();
             let mut master_pw = String::new();
             stdin().read_line(&mut master_pw).unwrap();
-            if let Err(err) = master_pw_validation(&master_pw) {
-                println!("MasterPW creation error: {}", err);
-                master_pw.zeroize();
-                continue;
+            match check_new_master_pw(&master_pw, &master_pw_policy) {
+                Ok(estimate) => print_strength(&estimate),
+                Err(err) => {
+                    println!("MasterPW creation error: {}", err);
+                    master_pw.zeroize();
+                    continue;
+                }
             };
 
             print!("Please confirm master password: ");
@@ -65,20 +153,16 @@ fn main() {
                 continue;
             }
 
-            (
-                pub_key,
-                db_header.master_pw_salt,
-                wrapped_user_key,
-                user_key_nonce,
-            ) = first_login(&mut master_pw_confirm);
+            (sec_key, pub_key, wrapped_user_key, user_key_nonce) =
+                first_login(&mut master_pw_confirm, &mut db_header, key_file.as_ref());
 
             break;
         }
 
         db = DB::new();
         loop {
-            let encrypted_db = encrypt_db(&db, &pub_key);
-            if let Err(e) = save_db(&mut db_header, encrypted_db) {
+            let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+            if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
                 println!("Error saving db: {}", e);
                 println!(
                     "Please press <Enter> to try again after check your system, or enter <C> to exit this app"
@@ -92,7 +176,7 @@ fn main() {
                 }
                 continue;
             }
-            if let Err(err) = mark_as_graceful_exited_to_file() {
+            if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
                 println!("Error saving db: {}", err);
                 println!(
                     "Please press <Enter> to try again after check your system, or enter <C> to exit this app"
@@ -111,35 +195,144 @@ fn main() {
         }
     } else {
         println!("[ General Login ]");
+        if let Some(index) = args.slot {
+            if !list_key_slots(&db_header).iter().any(|slot| slot.index == index) {
+                println!("Error checking key slot: {}", KeySlotError::NoSuchSlot(index));
+                print_key_slots(&db_header);
+                exit(0);
+            }
+        } else if let Err(e) = check_key_file(&db_header, key_file.as_ref()) {
+            println!("Error checking key file: {}", e);
+            exit(0);
+        }
+        // 대기 중인 KDF 변경은 비밀번호가 맞는 것을 확인한 뒤에 새 설정으로 다시 유도함
+        let mut upgrade_pw: Option<Zeroizing<String>>;
+        let payload;
         loop {
-            print!("Please enter master password: ");
-            io::stdout().flush().unwrap();
-            let mut master_pw = String::new();
-            stdin().read_line(&mut master_pw).unwrap();
-            io::stdout().flush().unwrap();
-            if let Err(err) = master_pw_validation(&master_pw) {
-                println!("MasterPW checking master pw: {}", err);
-                master_pw.zeroize();
-                continue;
-            };
-
-            let sec_key;
+            if let Some(index) = args.slot {
+                (sec_key, pub_key, wrapped_user_key, user_key_nonce) = match read_key_slot_login(&db_header, index, key_file.as_ref()) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        println!("Error checking key slot: {}", e);
+                        continue;
+                    }
+                };
+                // 마스터 비밀번호 슬롯은 새 마스터 비밀번호를 정할 때 현재 KDF 설정으로 다시 감쌈
+                upgrade_pw = None;
+            } else {
+                print!("Please enter master password: ");
+                io::stdout().flush().unwrap();
+                let mut master_pw = String::new();
+                stdin().read_line(&mut master_pw).unwrap();
+                io::stdout().flush().unwrap();
+                if let Err(err) = master_pw_validation(&master_pw) {
+                    println!("MasterPW checking master pw: {}", err);
+                    master_pw.zeroize();
+                    continue;
+                };
 
-            (sec_key, pub_key, wrapped_user_key, user_key_nonce) =
-                general_login(&mut master_pw, &db_header.master_pw_salt);
+                upgrade_pw = db_header.pending_kdf_params().map(|_| Zeroizing::new(master_pw.clone()));
+                (sec_key, pub_key, wrapped_user_key, user_key_nonce) =
+                    match general_login(&mut master_pw, &db_header, key_file.as_ref()) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error checking master pw: {}", e);
+                            continue;
+                        }
+                    };
+            }
 
-            db = match decrypt_db(encrypted_db.as_ref().unwrap(), sec_key) {
+            payload = match decrypt_db_payload(encrypted_db.as_ref().unwrap(), &db_header, &sec_key) {
                 Ok(v) => v,
                 Err(e) => {
                     println!("Error decrypting db: {}", e);
                     drop(pub_key);
                     drop(wrapped_user_key);
+                    drop(upgrade_pw);
                     continue;
                 }
             };
 
             break;
         }
+
+        if !db_header.is_current_version() && !read_only {
+            match backup_before_migration(&vault, &db_header) {
+                Ok(path) => println!("The database before upgrading is kept at {}", path.display()),
+                Err(e) => {
+                    println!("Error backing up db before upgrading: {}", e);
+                    exit(0);
+                }
+            }
+        }
+        let migrations;
+        (db, migrations) =
+            match migrate_db(
+                &mut db_header,
+                payload,
+                &mut sec_key,
+                &mut pub_key,
+                &mut wrapped_user_key,
+                &mut user_key_nonce,
+            ) {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("Error upgrading db: {}", e);
+                    exit(0);
+                }
+            };
+        for migration in &migrations {
+            println!("Upgraded db {} -> {}: {}", migration.from, migration.to, migration.description);
+        }
+        if !migrations.is_empty() && !read_only {
+            let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+            if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                println!("Error saving upgraded db: {}", e);
+            }
+        }
+
+        let kdf_upgraded = upgrade_pw
+            .filter(|_| !read_only)
+            .is_some_and(|master_pw| apply_kdf_upgrade(&mut db_header, &master_pw, key_file.as_ref(), &sec_key));
+        if kdf_upgraded {
+            let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+            if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                println!("Error saving upgraded db: {}", e);
+            }
+            println!("KDF upgraded to {}", db_header.kdf_params);
+        }
+
+        // 복구 코드나 조각으로 열었으면 새 마스터 비밀번호를 정하기 전에는 쓸 수 없음. 키 파일도 더 이상 요구하지 않음.
+        // 다른 키 슬롯으로 열었으면 마스터 비밀번호는 그대로 둠
+        let opened_with_recovery_slot = args.slot.is_some_and(|index| {
+            list_key_slots(&db_header).iter().any(|slot| slot.index == index && slot.kind.is_recovery())
+        });
+        if opened_with_recovery_slot && !read_only {
+            println!("[ New Master Password ]");
+            loop {
+                let mut master_pw = read_new_pw(&master_pw_policy, "master password");
+                change_master_pw(&mut db_header, &mut master_pw, None, &sec_key);
+                key_file = None;
+                let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+                if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                    println!("Error saving db: {}", e);
+                    continue;
+                }
+                break;
+            }
+            println!("Master password changed. recovery codes, shares and key slots still work until you make new ones or revoke them");
+        }
+    }
+
+    // 보관 기간이 지난 휴지통 항목은 로그인할 때 지움
+    if !read_only {
+        let purged = purge_expired(&mut db);
+        if purged != 0 {
+            println!("Purged {} entries older than {} days from the trash", purged, db.trash_retention_days());
+            if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                println!("Error saving status: {}", err);
+            }
+        }
     }
 
     // let mut previous_save_status = false;
@@ -154,27 +347,36 @@ fn main() {
         let words = input.split_whitespace();
         let args = std::iter::once(">").chain(words);
         match UserRequest::try_parse_from(args) {
+            Ok(request) if read_only && request.modifies_db() => {
+                println!("The database is opened read-only");
+            }
             Ok(request) => match request {
-                UserRequest::AddUserPW { site, id, pw } => {
+                UserRequest::AddUserPW { site, id, pw, generator, .. } => {
+                    let Some(pw) = pw.or_else(|| generate_user_pw(&generator)) else {
+                        continue;
+                    };
                     if let Err(e) =
                         add_user_pw(&mut db, site, id, pw, &wrapped_user_key, &user_key_nonce)
                     {
                         println!("Error adding password: {}", e);
                         continue;
                     }
-                    if let Err(err) = mark_as_ungraceful_exited_to_file() {
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
                         println!("Error saving status: {}", err);
                         continue;
                     }
                 }
-                UserRequest::ChangeUserPW { site, id, pw } => {
+                UserRequest::ChangeUserPW { site, id, pw, generator, .. } => {
+                    let Some(pw) = pw.or_else(|| generate_user_pw(&generator)) else {
+                        continue;
+                    };
                     if let Err(e) =
                         change_user_pw(&mut db, &site, &id, pw, &wrapped_user_key, &user_key_nonce)
                     {
                         println!("Error changing password: {}", e);
                         continue;
                     }
-                    if let Err(err) = mark_as_ungraceful_exited_to_file() {
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
                         println!("Error saving status: {}", err);
                         continue;
                     }
@@ -184,7 +386,7 @@ fn main() {
                         println!("Error removing password: {}", e);
                         continue;
                     }
-                    if let Err(err) = mark_as_ungraceful_exited_to_file() {
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
                         println!("Error saving status: {}", err);
                         continue;
                     }
@@ -225,149 +427,1596 @@ fn main() {
                     ctx.set_text(pw.as_str().to_owned()).unwrap();
                     pw.zeroize();
                 }
-                UserRequest::PrefixSearch { site } => {
-                    // prefix_range(&db, site)
-                    // continue;
-                    // explor_db(&mut db, site, &wrapped_user_key);
-                    for site in prefix_range(&db, &*(site).unwrap_or("".to_string())) {
-                        println!("{}", site.0.as_str());
-                        for user in site.1.iter() {
-                            println!("  {}", user.0.as_str());
+                UserRequest::Otp { site, id } => {
+                    let generated = match generate_otp_code(
+                        &mut db,
+                        &site,
+                        &id,
+                        &wrapped_user_key,
+                        &user_key_nonce,
+                    ) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error generating OTP: {}", e);
+                            continue;
+                        }
+                    };
+
+                    let mut ctx = Clipboard::new().unwrap();
+                    ctx.set_text(generated.code.as_str().to_owned()).unwrap();
+                    match (generated.remaining_secs, generated.period) {
+                        (Some(remaining), Some(period)) => {
+                            // 남은 시간을 막대로 보여줌
+                            let width = 20;
+                            let filled = (remaining * width / period) as usize;
+                            println!(
+                                "Copied. [{}{}] {}s left",
+                                "#".repeat(filled),
+                                "-".repeat(width as usize - filled),
+                                remaining
+                            );
+                        }
+                        _ => {
+                            println!("Copied. HOTP counter advanced");
+                            if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                                println!("Error saving status: {}", err);
+                                continue;
+                            }
+                        }
+                    }
+                }
+                UserRequest::Generate { generator, clipboard } => {
+                    let policy = generator.policy();
+                    let mut generated = match generate(&policy) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error generating password: {}", e);
+                            continue;
                         }
+                    };
+                    if clipboard {
+                        let mut ctx = Clipboard::new().unwrap();
+                        ctx.set_text(generated.pw.as_str().to_owned()).unwrap();
+                    } else {
+                        println!("{}", generated.pw.as_str());
                     }
+                    println!("entropy: {:.1} bits", generated.entropy_bits);
+                    generated.pw.zeroize();
                 }
-                UserRequest::ChangeMasterPW => {
-                    print!("Please enter new master password: ");
-                    io::stdout().flush().unwrap();
-                    let mut master_pw = String::new();
-                    stdin().read_line(&mut master_pw).unwrap();
-                    if let Err(err) = master_pw_validation(&master_pw) {
-                        println!("MasterPW creation error: {}", err);
-                        master_pw.zeroize();
-                        continue;
+                UserRequest::Audit { json, max_age_days, min_score } => {
+                    let default_policy = AuditPolicy::default();
+                    let policy = AuditPolicy {
+                        max_age_days: max_age_days.unwrap_or(default_policy.max_age_days),
+                        min_score: min_score.unwrap_or(default_policy.min_score),
                     };
-
-                    print!("Please confirm master password: ");
-                    io::stdout().flush().unwrap();
-                    let mut master_pw_confirm = String::new();
-                    stdin().read_line(&mut master_pw_confirm).unwrap();
-
-                    let is_match = master_pw == master_pw_confirm;
-                    master_pw.zeroize();
-                    if !is_match {
-                        println!("password is missmatch");
-                        master_pw_confirm.zeroize();
+                    let report = match audit_db(&db, &policy, &wrapped_user_key, &user_key_nonce) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error auditing db: {}", e);
+                            continue;
+                        }
+                    };
+                    if json {
+                        println!("{}", report.to_json());
                         continue;
                     }
-
-                    (pub_key, db_header.master_pw_salt) = match change_master_pw(
-                        &mut db,
-                        &mut master_pw_confirm,
-                        &mut wrapped_user_key,
-                        &mut user_key_nonce,
+                    println!(
+                        "Audited {} entries: {} issues",
+                        report.entry_count,
+                        report.issues.len()
+                    );
+                    for kind in AuditIssue::KINDS {
+                        let count = report.count(kind);
+                        if count == 0 {
+                            continue;
+                        }
+                        println!("[{}] {}", kind, count);
+                        for issue in report.issues.iter().filter(|issue| issue.kind() == kind) {
+                            println!("  {}", issue);
+                        }
+                    }
+                }
+                UserRequest::CheckBreaches => {
+                    let Some(breach_list) = &breach_list else {
+                        println!("No breach list. Restart with --hibp <PATH>");
+                        continue;
+                    };
+                    let results = match check_db(breach_list, &db, &wrapped_user_key, &user_key_nonce) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error checking breaches: {}", e);
+                            continue;
+                        }
+                    };
+                    for result in &results {
+                        if result.count == 0 {
+                            println!("{} / {}: not found", result.site.as_str(), result.id.as_str());
+                        } else {
+                            println!("{} / {}: seen {} times", result.site.as_str(), result.id.as_str(), result.count);
+                        }
+                    }
+                    println!(
+                        "{} of {} passwords found in {}",
+                        results.iter().filter(|result| result.count != 0).count(),
+                        results.len(),
+                        breach_list.path().display()
+                    );
+                }
+                UserRequest::ShowEntry { site, id, reveal } => {
+                    let entry = match get_entry(&db, &site, &id) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error getting entry: {}", e);
+                            continue;
+                        }
+                    };
+                    println!("created:   {}", entry.created());
+                    println!("modified:  {}", entry.modified());
+                    match entry.last_used() {
+                        Some(last_used) => println!("last used: {}", last_used),
+                        None => println!("last used: never"),
+                    }
+                    if entry.has_otp() {
+                        println!("otp:       set");
+                    }
+                    let details = match read_entry_details(
+                        &db,
+                        &site,
+                        &id,
+                        &wrapped_user_key,
+                        &user_key_nonce,
                     ) {
                         Ok(v) => v,
                         Err(e) => {
-                            println!("Error setting master pw: {}", e);
+                            println!("Error getting entry: {}", e);
                             continue;
                         }
                     };
-
-                    let encrypted_db = encrypt_db(&db, &pub_key);
-
-                    if let Err(e) = save_db(&mut db_header, encrypted_db) {
-                        println!("Error saving db: {}", e);
+                    if let Some(breach_list) = &breach_list {
+                        match check_entry(breach_list, &db, &site, &id, &wrapped_user_key, &user_key_nonce) {
+                            Ok(0) => println!("breaches:  not found"),
+                            Ok(count) => println!("breaches:  seen {} times", count),
+                            Err(e) => println!("Error checking breaches: {}", e),
+                        }
+                    }
+                    for url in &details.urls {
+                        println!("url: {}", url);
+                    }
+                    for field in &details.custom_fields {
+                        if field.kind == CustomFieldKind::Hidden && !reveal {
+                            println!("{} ({}): ********", field.name, field.kind);
+                        } else {
+                            println!("{} ({}): {}", field.name, field.kind, field.value.as_str());
+                        }
+                    }
+                    if !details.notes.is_empty() {
+                        println!("notes:");
+                        for line in details.notes.lines() {
+                            println!("  {}", line);
+                        }
+                    }
+                }
+                UserRequest::EditNotes { site, id } => {
+                    println!("Please enter notes, and an empty line to finish (nothing clears the notes):");
+                    let mut notes = Zeroizing::new(String::new());
+                    loop {
+                        let mut line = Zeroizing::new(String::new());
+                        stdin().read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        notes.push_str(&line);
+                    }
+                    if let Err(e) = edit_entry_details(&mut db, &site, &id, &wrapped_user_key, &user_key_nonce, |details| {
+                        details.notes = notes;
+                    }) {
+                        println!("Error editing notes: {}", e);
                         continue;
                     }
-                    if let Err(err) = mark_as_graceful_exited_to_file() {
-                        println!("Error saving db: {}", err);
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
                         continue;
                     }
                 }
-                UserRequest::SaveDB => {
-                    // if should_save_db {
-                    let encrypted_db = encrypt_db(&db, &pub_key);
-
-                    if let Err(e) = save_db(&mut db_header, encrypted_db) {
-                        println!("Error saving db: {}", e);
+                UserRequest::AddUrl { site, id, url } => {
+                    if let Err(e) = edit_entry_details(&mut db, &site, &id, &wrapped_user_key, &user_key_nonce, |details| {
+                        details.urls.push(url);
+                    }) {
+                        println!("Error adding url: {}", e);
                         continue;
                     }
-                    if let Err(err) = mark_as_graceful_exited_to_file() {
-                        println!("Error saving db: {}", err);
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
                         continue;
                     }
-                    // }
                 }
-                UserRequest::ExitAppWithSave => {
-                    // if should_save_db {
-                    let encrypted_db = encrypt_db(&db, &pub_key);
-
-                    if let Err(e) = save_db(&mut db_header, encrypted_db) {
-                        println!("Error saving db: {}", e);
+                UserRequest::RemoveUrl { site, id, url } => {
+                    if let Err(e) = edit_entry_details(&mut db, &site, &id, &wrapped_user_key, &user_key_nonce, |details| {
+                        details.urls.retain(|u| *u != url);
+                    }) {
+                        println!("Error removing url: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
                         continue;
                     }
-                    // } else {
-                    //     mark_as_graceful_exited_to_file().ok();
-                    // }
-                    drop(wrapped_user_key);
-                    drop(pub_key);
-                    drop(db);
-                    exit(0);
                 }
-                UserRequest::ExitAppWithoutSave => {
-                    // if !should_save_db {
-                    mark_as_graceful_exited_to_file().ok();
-                    // }
-                    drop(wrapped_user_key);
-                    drop(pub_key);
-                    drop(db);
-                    exit(0);
+                UserRequest::SetField { site, id, name, kind } => {
+                    print!("Please enter value of {}: ", name);
+                    io::stdout().flush().unwrap();
+                    let mut value = Zeroizing::new(String::new());
+                    stdin().read_line(&mut value).unwrap();
+                    let field = CustomField {
+                        name,
+                        kind,
+                        value: Zeroizing::new(value.trim().to_owned()),
+                    };
+                    if let Err(e) = edit_entry_details(&mut db, &site, &id, &wrapped_user_key, &user_key_nonce, |details| {
+                        match details.custom_fields.iter_mut().find(|f| f.name == field.name) {
+                            Some(existing) => *existing = field,
+                            None => details.custom_fields.push(field),
+                        }
+                    }) {
+                        println!("Error setting field: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
                 }
-            },
-
-            Err(e) => {
-                println!("Invalid input: {}", e);
-            }
-        }
-        // if !previous_save_status && should_save_db {
-        //     if let Err(err) = mark_as_ungraceful_exited_to_file() {
-        //         println!("Error saving status: {}", err);
-        //         continue;
-        //     }
-        //     previous_save_status = should_save_db;
-        // }
-    }
-}
-
-#[derive(Parser)]
-pub enum UserRequest {
-    AddUserPW {
-        site: SiteName,
-        id: UserID,
-        pw: UserPW,
-    },
-    ChangeUserPW {
-        site: SiteName,
-        id: UserID,
-        pw: UserPW,
-    },
-    RemoveUserPW {
-        site: SiteName,
-        id: UserID,
-    },
-    GetUserPW {
-        site: SiteName,
-        id: UserID,
-    },
-    GetUserPWToClipboard {
-        site: SiteName,
-        id: UserID,
-    },
-    PrefixSearch {
-        site: Option<String>,
-    },
-    ChangeMasterPW,
-    SaveDB,
-    ExitAppWithSave,
-    ExitAppWithoutSave,
+                UserRequest::RemoveField { site, id, name } => {
+                    if let Err(e) = edit_entry_details(&mut db, &site, &id, &wrapped_user_key, &user_key_nonce, |details| {
+                        details.custom_fields.retain(|f| f.name != name);
+                    }) {
+                        println!("Error removing field: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::SetOtp { site, id } => {
+                    print!("Please enter otpauth:// URI or secret: ");
+                    io::stdout().flush().unwrap();
+                    let mut input = Zeroizing::new(String::new());
+                    stdin().read_line(&mut input).unwrap();
+                    let otp = match OtpConfig::parse(&input) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error reading OTP: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = set_otp(&mut db, &site, &id, Some(&otp), &wrapped_user_key, &user_key_nonce) {
+                        println!("Error setting OTP: {}", e);
+                        continue;
+                    }
+                    match otp.kind {
+                        OtpKind::Totp { period } => println!("TOTP ({}, {} digits, {}s)", otp.algorithm, otp.digits, period),
+                        OtpKind::Hotp { counter } => println!("HOTP ({}, {} digits, counter {})", otp.algorithm, otp.digits, counter),
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::RemoveOtp { site, id } => {
+                    if let Err(e) = set_otp(&mut db, &site, &id, None, &wrapped_user_key, &user_key_nonce) {
+                        println!("Error removing OTP: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::History { site, id, reveal } => {
+                    let entry = match get_entry(&db, &site, &id) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error getting history: {}", e);
+                            continue;
+                        }
+                    };
+                    println!("  *  since {}", entry.pw_changed());
+                    if entry.pw_history().is_empty() {
+                        println!("No previous passwords");
+                    }
+                    for (i, past) in entry.pw_history().iter().enumerate() {
+                        if !reveal {
+                            println!("{:>3}  {} ~ {}", i + 1, past.set_at(), past.replaced_at());
+                            continue;
+                        }
+                        match get_past_user_pw(&db, &site, &id, i, &wrapped_user_key, &user_key_nonce) {
+                            Ok(pw) => println!("{:>3}  {} ~ {}  {}", i + 1, past.set_at(), past.replaced_at(), pw.as_str()),
+                            Err(e) => println!("{:>3}  Error getting password: {}", i + 1, e),
+                        }
+                    }
+                }
+                UserRequest::RestoreUserPW { site, id, index } => {
+                    if let Err(e) = restore_user_pw(
+                        &mut db,
+                        &site,
+                        &id,
+                        index.wrapping_sub(1),
+                        &wrapped_user_key,
+                        &user_key_nonce,
+                    ) {
+                        println!("Error restoring password: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                    println!("Password restored. The replaced password is kept in the history");
+                }
+                UserRequest::PrefixSearch { query, tag, folder, favourites } => {
+                    let filter = EntryFilter {
+                        tags: tag,
+                        folder,
+                        favourites_only: favourites,
+                    };
+                    let query = query.join(" ");
+                    if query.trim().is_empty() {
+                        let sites = match db.sites() {
+                            Ok(v) => v,
+                            Err(e) => {
+                                println!("Error listing entries: {}", e);
+                                continue;
+                            }
+                        };
+                        for (site, users) in filter_sites(sites, &filter) {
+                            println!("{}", site.as_str());
+                            for (id, entry) in users {
+                                println!("  {}{}", id.as_str(), entry_labels(entry));
+                            }
+                        }
+                        continue;
+                    }
+
+                    // 색인에는 복호화한 이름과 메모가 있으므로 검색마다 만들고 바로 버림
+                    let index = match SearchIndex::build(&db, &wrapped_user_key, &user_key_nonce) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error building search index: {}", e);
+                            continue;
+                        }
+                    };
+                    let ansi = io::stdout().is_terminal();
+                    let mut found = false;
+                    for hit in index.search(&query) {
+                        let Ok(entry) = get_entry(&db, &hit.site, &hit.id) else {
+                            continue;
+                        };
+                        if !filter.matches(entry) {
+                            continue;
+                        }
+                        found = true;
+                        let site = hit.field(SearchField::Site).map_or_else(
+                            || hit.site.as_str().to_string(),
+                            |matched| highlight(&matched.text, &matched.ranges, ansi),
+                        );
+                        let id = hit.field(SearchField::UserID).map_or_else(
+                            || hit.id.as_str().to_string(),
+                            |matched| highlight(&matched.text, &matched.ranges, ansi),
+                        );
+                        println!("{}  {}{}", site, id, entry_labels(entry));
+                        for matched in &hit.matches {
+                            match matched.field {
+                                SearchField::Site | SearchField::UserID => {}
+                                // 사이트 이름에서 이미 보임
+                                SearchField::Domain if hit.field(SearchField::Site).is_some() => {}
+                                field => println!("    {}: {}", field, highlight(&matched.text, &matched.ranges, ansi)),
+                            }
+                        }
+                    }
+                    if !found {
+                        println!("No matches");
+                    }
+                }
+                UserRequest::Trash { action } => {
+                    let result = match action.unwrap_or(TrashAction::List) {
+                        TrashAction::List => {
+                            if db.trash().is_empty() {
+                                println!("The trash is empty");
+                            }
+                            for (i, trashed) in db.trash().iter().enumerate() {
+                                match db.trashed_names(trashed) {
+                                    Ok((site, id)) => println!("{:>3}  {}  {}  deleted {}", i + 1, site.as_str(), id.as_str(), trashed.deleted_at()),
+                                    Err(e) => println!("{:>3}  ({})", i + 1, e),
+                                }
+                            }
+                            match db.trash_retention_days() {
+                                0 => println!("Entries are kept until purged"),
+                                days => println!("Entries are purged {} days after deletion", days),
+                            }
+                            continue;
+                        }
+                        TrashAction::Restore { index } => restore_trashed(&mut db, index.wrapping_sub(1)),
+                        TrashAction::Purge { index } => purge_trashed(&mut db, index.wrapping_sub(1)),
+                        TrashAction::Empty => {
+                            println!("Purged {} entries", empty_trash(&mut db));
+                            Ok(())
+                        }
+                        TrashAction::Retention { days } => {
+                            set_trash_retention_days(&mut db, days);
+                            Ok(())
+                        }
+                    };
+                    if let Err(e) = result {
+                        println!("Error updating trash: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::Tag { site, id, tags } => {
+                    if let Err(e) = edit_tags(&mut db, &site, &id, |current| current.extend(tags)) {
+                        println!("Error setting tags: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::Untag { site, id, tags } => {
+                    let removed: Vec<String> = tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+                    if let Err(e) = edit_tags(&mut db, &site, &id, |current| current.retain(|tag| !removed.contains(tag))) {
+                        println!("Error removing tags: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::SetFolder { site, id, folder } => {
+                    if let Err(e) = set_folder(&mut db, &site, &id, folder.unwrap_or_default()) {
+                        println!("Error setting folder: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::Favourite { site, id, off } => {
+                    if let Err(e) = set_favourite(&mut db, &site, &id, !off) {
+                        println!("Error setting favourite: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::Tags => {
+                    let counts = tag_counts(&db);
+                    if counts.is_empty() {
+                        println!("No tags");
+                    }
+                    for (tag, count) in counts {
+                        println!("{:>4}  {}", count, tag);
+                    }
+                }
+                UserRequest::Folders => {
+                    print_folder_tree(&folder_tree(&db), 0);
+                }
+                UserRequest::EncryptNames { off } => {
+                    let result = if off {
+                        disable_metadata_encryption(&mut db)
+                    } else {
+                        enable_metadata_encryption(&mut db, &wrapped_user_key, &user_key_nonce)
+                    };
+                    if let Err(e) = result {
+                        println!("Error changing name encryption: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+                        println!("Error saving status: {}", err);
+                        continue;
+                    }
+                    if db.is_metadata_encrypted() {
+                        println!("Site names and user IDs are encrypted");
+                    } else {
+                        println!("Site names and user IDs are stored in plaintext inside the encrypted DB");
+                    }
+                }
+                UserRequest::ChangeMasterPW => {
+                    print!("Please enter new master password: ");
+                    io::stdout().flush().unwrap();
+                    let mut master_pw = String::new();
+                    stdin().read_line(&mut master_pw).unwrap();
+                    match check_new_master_pw(&master_pw, &master_pw_policy) {
+                        Ok(estimate) => print_strength(&estimate),
+                        Err(err) => {
+                            println!("MasterPW creation error: {}", err);
+                            master_pw.zeroize();
+                            continue;
+                        }
+                    };
+
+                    print!("Please confirm master password: ");
+                    io::stdout().flush().unwrap();
+                    let mut master_pw_confirm = String::new();
+                    stdin().read_line(&mut master_pw_confirm).unwrap();
+
+                    let is_match = master_pw == master_pw_confirm;
+                    master_pw.zeroize();
+                    if !is_match {
+                        println!("password is missmatch");
+                        master_pw_confirm.zeroize();
+                        continue;
+                    }
+
+                    change_master_pw(&mut db_header, &mut master_pw_confirm, key_file.as_ref(), &sec_key);
+
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
+                        println!("Error saving db: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::SetKeyFile { path, generate, .. } => {
+                    print!("Please enter master password: ");
+                    io::stdout().flush().unwrap();
+                    let mut master_pw = String::new();
+                    stdin().read_line(&mut master_pw).unwrap();
+                    if let Err(e) = verify_master_pw(&master_pw, &db_header, key_file.as_ref(), &pub_key) {
+                        println!("Error checking master pw: {}", e);
+                        master_pw.zeroize();
+                        continue;
+                    }
+
+                    let new_key_file = match path {
+                        None => None,
+                        Some(path) => {
+                            let result = if generate { KeyFile::generate(&path) } else { KeyFile::read(&path) };
+                            match result {
+                                Ok(v) => Some(v),
+                                Err(e) => {
+                                    println!("Error setting key file: {}", e);
+                                    master_pw.zeroize();
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    change_key_file(&mut db_header, &mut master_pw, new_key_file.as_ref(), &sec_key);
+                    key_file = new_key_file;
+
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
+                        println!("Error saving db: {}", err);
+                        continue;
+                    }
+                    if key_file.is_some() {
+                        println!("The vault now needs the master password and this key file. keep a copy of the key file");
+                    } else {
+                        println!("The vault opens with the master password only");
+                    }
+                }
+                UserRequest::Slots { action } => {
+                    let Some(action) = action else {
+                        print_key_slots(&db_header);
+                        continue;
+                    };
+
+                    if let SlotsAction::Label { index, label } = &action {
+                        if let Err(e) = label_key_slot(&mut db_header, *index, label) {
+                            println!("Error labeling key slot: {}", e);
+                            continue;
+                        }
+                    } else {
+                        print!("Please enter master password: ");
+                        io::stdout().flush().unwrap();
+                        let mut master_pw = String::new();
+                        stdin().read_line(&mut master_pw).unwrap();
+                        let result = match &action {
+                            SlotsAction::AddPassword { label } => {
+                                let mut slot_pw = read_new_pw(&master_pw_policy, "slot password");
+                                add_key_slot(
+                                    &mut db_header,
+                                    &mut master_pw,
+                                    key_file.as_ref(),
+                                    &pub_key,
+                                    label,
+                                    NewKeySlot::Password(&mut slot_pw),
+                                )
+                                .map(|index| format!("Added password slot {}", index))
+                            }
+                            SlotsAction::AddKeyFile { label, path, generate } => {
+                                let result = if *generate { KeyFile::generate(path) } else { KeyFile::read(path) };
+                                let slot_key_file = match result {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        println!("Error reading key file: {}", e);
+                                        master_pw.zeroize();
+                                        continue;
+                                    }
+                                };
+                                add_key_slot(
+                                    &mut db_header,
+                                    &mut master_pw,
+                                    key_file.as_ref(),
+                                    &pub_key,
+                                    label,
+                                    NewKeySlot::KeyFile(&slot_key_file),
+                                )
+                                .map(|index| format!("Added key file slot {}. keep a copy of the key file", index))
+                            }
+                            SlotsAction::AddRecoveryCode { label, output } => {
+                                match add_recovery_code_slot(&mut db_header, &mut master_pw, key_file.as_ref(), &pub_key, label) {
+                                    Ok((index, recovery_key)) => {
+                                        let kit = emergency_kit(&recovery_key, index, vault.db_file());
+                                        let written = output.as_ref().map(|path| (path, fs::write(path, kit.as_bytes())));
+                                        match written {
+                                            Some((path, Ok(_))) => Ok(format!(
+                                                "Added recovery code slot {}. emergency kit written to {}. print it and delete the file",
+                                                index,
+                                                path.display()
+                                            )),
+                                            Some((_, Err(e))) => {
+                                                println!("Error writing emergency kit: {}", e);
+                                                println!("{}", kit.as_str());
+                                                Ok(format!("Added recovery code slot {}", index))
+                                            }
+                                            None => {
+                                                println!("{}", kit.as_str());
+                                                Ok(format!("Added recovery code slot {}", index))
+                                            }
+                                        }
+                                    }
+                                    Err(e) => Err(e),
+                                }
+                            }
+                            SlotsAction::AddShares { label, threshold, count, output_dir } => {
+                                add_recovery_shares_slot(
+                                    &mut db_header,
+                                    &mut master_pw,
+                                    key_file.as_ref(),
+                                    &pub_key,
+                                    label,
+                                    *threshold,
+                                    *count,
+                                )
+                                .map(|(index, shares)| {
+                                    write_share_sheets(&shares, index, vault.db_file(), output_dir.as_deref());
+                                    format!(
+                                        "Added recovery shares slot {}. give each share to a different person and delete any copies left here",
+                                        index
+                                    )
+                                })
+                            }
+                            SlotsAction::AddTeammate { label, public_key } => match TeammateKey::parse(public_key) {
+                                Ok(teammate_key) => add_key_slot(
+                                    &mut db_header,
+                                    &mut master_pw,
+                                    key_file.as_ref(),
+                                    &pub_key,
+                                    label,
+                                    NewKeySlot::Teammate(&teammate_key),
+                                )
+                                .map(|index| format!("Added teammate slot {}", index)),
+                                Err(e) => {
+                                    master_pw.zeroize();
+                                    Err(e)
+                                }
+                            },
+                            SlotsAction::Revoke { index } => {
+                                revoke_key_slot(&mut db_header, &mut master_pw, key_file.as_ref(), &pub_key, *index)
+                                    .map(|_| format!("Key slot {} was revoked", index))
+                            }
+                            SlotsAction::Label { .. } => unreachable!(),
+                        };
+                        match result {
+                            Ok(message) => println!("{}", message),
+                            Err(e) => {
+                                println!("Error changing key slots: {}", e);
+                                continue;
+                            }
+                        }
+                    }
+
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
+                        println!("Error saving db: {}", err);
+                        continue;
+                    }
+                }
+                UserRequest::UpgradeKdf {
+                    target_ms,
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                } => {
+                    let kdf_params = if let Some(target_ms) = target_ms {
+                        println!("Benchmarking KDF...");
+                        benchmark_kdf_params(Duration::from_millis(target_ms))
+                    } else {
+                        let current = db_header.kdf_params;
+                        match KdfParams::argon2id(
+                            memory_kib.unwrap_or(current.m_cost_kib()),
+                            iterations.unwrap_or(current.t_cost()),
+                            parallelism.unwrap_or(current.p_cost()),
+                        ) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                println!("Error setting KDF: {}", e);
+                                continue;
+                            }
+                        }
+                    };
+                    db_header.request_kdf_upgrade(kdf_params);
+
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
+                        println!("Error saving db: {}", err);
+                        continue;
+                    }
+                    println!("Current KDF: {}", db_header.kdf_params);
+                    println!("{} will be applied on next login", kdf_params);
+                }
+                UserRequest::ListBackups => {
+                    let snapshots = match list_snapshots(&vault) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error listing backups: {}", e);
+                            continue;
+                        }
+                    };
+                    if snapshots.is_empty() {
+                        println!("No backups");
+                    }
+                    for (i, snapshot) in snapshots.iter().enumerate() {
+                        println!("{:>3}  {}", i + 1, snapshot.created_utc());
+                    }
+                }
+                UserRequest::InspectBackup { index } => {
+                    let Some(snapshot) = select_snapshot(&vault, index) else {
+                        continue;
+                    };
+                    print!("Please enter master password of the backup: ");
+                    io::stdout().flush().unwrap();
+                    let mut master_pw = String::new();
+                    stdin().read_line(&mut master_pw).unwrap();
+                    let result = inspect_snapshot(&snapshot, &master_pw, key_file.as_ref());
+                    master_pw.zeroize();
+                    match result {
+                        Ok(summary) => println!(
+                            "{}: {} sites, {} entries",
+                            snapshot.created_utc(),
+                            summary.site_count,
+                            summary.entry_count
+                        ),
+                        Err(e) => println!("Error inspecting backup: {}", e),
+                    }
+                }
+                UserRequest::RestoreBackup { index } => {
+                    let Some(snapshot) = select_snapshot(&vault, index) else {
+                        continue;
+                    };
+                    print!(
+                        "Unsaved changes will be lost. Restore {}? [y/N]: ",
+                        snapshot.created_utc()
+                    );
+                    io::stdout().flush().unwrap();
+                    let mut answer = String::new();
+                    stdin().read_line(&mut answer).unwrap();
+                    if !answer.trim().eq_ignore_ascii_case("y") {
+                        continue;
+                    }
+                    if let Err(e) = restore_snapshot(&vault, &snapshot) {
+                        println!("Error restoring backup: {}", e);
+                        continue;
+                    }
+                    println!("Backup restored. Please restart the app and log in again");
+                    drop(wrapped_user_key);
+                    drop(pub_key);
+                    drop(db);
+                    exit(0);
+                }
+                UserRequest::SaveDB => {
+                    // if should_save_db {
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    if let Err(err) = mark_as_graceful_exited_to_file(&vault) {
+                        println!("Error saving db: {}", err);
+                        continue;
+                    }
+                    // }
+                }
+                UserRequest::ExitAppWithSave => {
+                    // if should_save_db {
+                    let encrypted_db = encrypt_db(&db, &db_header, &pub_key);
+
+                    if let Err(e) = save_db(&vault, &mut db_header, encrypted_db).map(print_save_warn) {
+                        println!("Error saving db: {}", e);
+                        continue;
+                    }
+                    // } else {
+                    //     mark_as_graceful_exited_to_file(&vault).ok();
+                    // }
+                    drop(wrapped_user_key);
+                    drop(pub_key);
+                    drop(db);
+                    exit(0);
+                }
+                UserRequest::ExitAppWithoutSave => {
+                    // if !should_save_db {
+                    mark_as_graceful_exited_to_file(&vault).ok();
+                    // }
+                    drop(wrapped_user_key);
+                    drop(pub_key);
+                    drop(db);
+                    exit(0);
+                }
+            },
+
+            Err(e) => {
+                println!("Invalid input: {}", e);
+            }
+        }
+        // if !previous_save_status && should_save_db {
+        //     if let Err(err) = mark_as_ungraceful_exited_to_file(&vault) {
+        //         println!("Error saving status: {}", err);
+        //         continue;
+        //     }
+        //     previous_save_status = should_save_db;
+        // }
+    }
+}
+
+/// 실행 인자. `--vault`가 없으면 `PW_MANAGER_VAULT`, 그다음 기본 볼트를 씀
+#[derive(Parser)]
+struct CliArgs {
+    #[arg(long)]
+    vault: Option<PathBuf>,
+    /// 백업 보존 정책
+    #[arg(long)]
+    keep_last: Option<usize>,
+    #[arg(long)]
+    keep_daily: Option<usize>,
+    #[arg(long)]
+    keep_weekly: Option<usize>,
+    /// 구버전 DB의 마이그레이션을 메모리에서만 해 보고 종료함
+    #[arg(long)]
+    check_migration: bool,
+    /// 새 마스터 비밀번호 조건. 점수는 0..=4
+    #[arg(long)]
+    min_master_pw_score: Option<PWScore>,
+    #[arg(long)]
+    min_master_pw_chars: Option<usize>,
+    /// 로컬 HIBP SHA-1 목록. 정렬된 파일 하나나 범위 파일 디렉터리
+    #[arg(long)]
+    hibp: Option<PathBuf>,
+    /// 마스터 비밀번호와 함께 쓰는 키 파일. 처음 만들 때 주면 이 볼트는 키 파일도 있어야 열림
+    #[arg(long)]
+    keyfile: Option<PathBuf>,
+    /// 마스터 비밀번호 대신 이 번호의 키 슬롯으로 엶. 빈 번호면 슬롯 목록을 보여주고 끝냄.
+    /// 복구 코드나 복구 조각 슬롯으로 열면 새 마스터 비밀번호를 정함. `--keyfile`은 키 파일을 정한 마스터 비밀번호 슬롯에만 씀
+    #[arg(long)]
+    slot: Option<usize>,
+    /// 볼트를 열지 않고 이 키 파일의 팀원 공개키를 출력함. 볼트 주인이 이 공개키로 팀원 슬롯을 만듦
+    #[arg(long)]
+    teammate_public_key: Option<PathBuf>,
+    /// `--teammate-public-key`의 키 파일을 새로 만듦
+    #[arg(long, requires = "teammate_public_key")]
+    generate_teammate_key: bool,
+}
+
+#[derive(Parser)]
+pub enum UserRequest {
+    /// `--generate`를 주면 비밀번호를 입력하는 대신 생성기로 만듦
+    AddUserPW {
+        site: SiteName,
+        id: UserID,
+        #[arg(required_unless_present = "generate")]
+        pw: Option<UserPW>,
+        #[arg(long, conflicts_with = "pw")]
+        generate: bool,
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+    ChangeUserPW {
+        site: SiteName,
+        id: UserID,
+        #[arg(required_unless_present = "generate")]
+        pw: Option<UserPW>,
+        #[arg(long, conflicts_with = "pw")]
+        generate: bool,
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+    /// 휴지통으로 옮김. `trash`로 되살리거나 완전히 지울 수 있음
+    RemoveUserPW {
+        site: SiteName,
+        id: UserID,
+    },
+    GetUserPW {
+        site: SiteName,
+        id: UserID,
+    },
+    GetUserPWToClipboard {
+        site: SiteName,
+        id: UserID,
+    },
+    /// OTP 코드를 클립보드에 복사함. HOTP는 카운터가 올라감
+    Otp {
+        site: SiteName,
+        id: UserID,
+    },
+    /// 만든 비밀번호를 보여주기만 하고 저장하지 않음
+    Generate {
+        #[command(flatten)]
+        generator: GeneratorArgs,
+        /// 화면에 출력하지 않고 클립보드에 복사함
+        #[arg(long)]
+        clipboard: bool,
+    },
+    /// 재사용, 약한 비밀번호, 오래된 비밀번호 등을 점검함. `--json`이면 JSON 한 줄로 출력
+    Audit {
+        #[arg(long)]
+        json: bool,
+        #[arg(long)]
+        max_age_days: Option<u64>,
+        /// 이 점수(0..=4) 미만을 약한 비밀번호로 봄
+        #[arg(long)]
+        min_score: Option<PWScore>,
+    },
+    /// `--hibp`로 준 유출 목록에서 모든 비밀번호를 찾아봄
+    CheckBreaches,
+    /// 시각, URL, 사용자 정의 필드, 메모. 숨김 필드는 `--reveal`을 줘야 보여줌
+    ShowEntry {
+        site: SiteName,
+        id: UserID,
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// 여러 줄을 입력받아 메모를 교체함
+    EditNotes {
+        site: SiteName,
+        id: UserID,
+    },
+    AddUrl {
+        site: SiteName,
+        id: UserID,
+        url: String,
+    },
+    RemoveUrl {
+        site: SiteName,
+        id: UserID,
+        url: String,
+    },
+    /// 같은 이름의 필드가 있으면 교체함. 값은 따로 입력받음
+    SetField {
+        site: SiteName,
+        id: UserID,
+        name: String,
+        #[arg(long, default_value_t = CustomFieldKind::Text)]
+        kind: CustomFieldKind,
+    },
+    RemoveField {
+        site: SiteName,
+        id: UserID,
+        name: String,
+    },
+    /// `otpauth://` URI나 base32 비밀 키를 따로 입력받음
+    SetOtp {
+        site: SiteName,
+        id: UserID,
+    },
+    RemoveOtp {
+        site: SiteName,
+        id: UserID,
+    },
+    /// 이전 비밀번호 목록. 비밀번호는 `--reveal`을 줘야 보여줌
+    History {
+        site: SiteName,
+        id: UserID,
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// 번호는 `history`에 나온 번호
+    RestoreUserPW {
+        site: SiteName,
+        id: UserID,
+        index: usize,
+    },
+    /// 사이트, 도메인, ID, 태그, 메모에서 흩어진 글자까지 찾아 점수 순으로 보여줌. 검색어가 없으면 전부.
+    /// `--tag`(여러 번 줄 수 있음), `--folder`, `--favourites`로 계정을 거를 수 있음.
+    /// 폴더는 하위 폴더까지 포함함
+    #[command(alias = "search")]
+    PrefixSearch {
+        query: Vec<String>,
+        #[arg(long)]
+        tag: Vec<String>,
+        #[arg(long)]
+        folder: Option<FolderPath>,
+        #[arg(long)]
+        favourites: bool,
+    },
+    Tag {
+        site: SiteName,
+        id: UserID,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    Untag {
+        site: SiteName,
+        id: UserID,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// `a/b/c` 꼴. 주지 않으면 최상위로 옮김
+    SetFolder {
+        site: SiteName,
+        id: UserID,
+        folder: Option<FolderPath>,
+    },
+    /// `--off`를 주면 즐겨찾기에서 뺌
+    Favourite {
+        site: SiteName,
+        id: UserID,
+        #[arg(long)]
+        off: bool,
+    },
+    /// 지운 계정. 하위 명령이 없으면 목록을 보여줌
+    Trash {
+        #[command(subcommand)]
+        action: Option<TrashAction>,
+    },
+    /// 태그별 계정 수
+    Tags,
+    /// 폴더 트리와 폴더별 계정 수
+    Folders,
+    /// 사이트 이름과 ID도 세션 키로 암호화해 두고 메모리에는 keyed hash로만 찾음.
+    /// `--off`를 주면 다시 평문으로 둠
+    EncryptNames {
+        #[arg(long)]
+        off: bool,
+    },
+    ChangeMasterPW,
+    /// 키 파일을 추가하거나 바꿈. `--generate`면 `path`에 무작위 키 파일을 새로 만들고,
+    /// `--remove`면 마스터 비밀번호만으로 열리게 함. 마스터 비밀번호를 다시 입력받음
+    SetKeyFile {
+        #[arg(required_unless_present = "remove", conflicts_with = "remove")]
+        path: Option<PathBuf>,
+        #[arg(long, requires = "path")]
+        generate: bool,
+        #[arg(long)]
+        remove: bool,
+    },
+    /// 볼트를 여는 키 슬롯. 마스터 비밀번호, 복구 코드, 복구 조각도 슬롯임. 하위 명령이 없으면 슬롯 목록을 보여줌
+    Slots {
+        #[command(subcommand)]
+        action: Option<SlotsAction>,
+    },
+    /// 다음 로그인부터 쓸 KDF 설정. `--target-ms`를 주면 이 기기에서 벤치마크해서 고름
+    UpgradeKdf {
+        #[arg(long, conflicts_with_all = ["memory_kib", "iterations", "parallelism"])]
+        target_ms: Option<u64>,
+        #[arg(long)]
+        memory_kib: Option<u32>,
+        #[arg(long)]
+        iterations: Option<u32>,
+        #[arg(long)]
+        parallelism: Option<u32>,
+    },
+    ListBackups,
+    /// 스냅샷 번호는 `list-backups`에 나온 번호
+    InspectBackup {
+        index: usize,
+    },
+    RestoreBackup {
+        index: usize,
+    },
+    SaveDB,
+    ExitAppWithSave,
+    ExitAppWithoutSave,
+}
+
+/// 이름 바꾸기 말고는 마스터 비밀번호를 다시 입력받음. 번호는 `slots`에 나온 번호
+#[derive(Subcommand)]
+pub enum SlotsAction {
+    /// 새 비밀번호를 입력받음
+    AddPassword {
+        label: String,
+    },
+    /// `--generate`면 키 파일을 새로 만듦
+    AddKeyFile {
+        label: String,
+        path: PathBuf,
+        #[arg(long)]
+        generate: bool,
+    },
+    /// 새 복구 코드를 만들고 비상 키트를 출력함. `--output`을 주면 화면 대신 파일로 씀
+    AddRecoveryCode {
+        label: String,
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// `count`개 조각 중 아무 `threshold`개로 열리게 나누고 조각마다 안내문을 출력함.
+    /// `--output-dir`을 주면 화면 대신 조각마다 파일로 씀
+    AddShares {
+        label: String,
+        threshold: u8,
+        count: u8,
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+    /// 팀원이 `--teammate-public-key`로 출력한 공개키
+    AddTeammate {
+        label: String,
+        public_key: String,
+    },
+    Label {
+        index: usize,
+        label: String,
+    },
+    Revoke {
+        index: usize,
+    },
+}
+
+/// 번호는 `trash`에 나온 번호
+#[derive(Subcommand)]
+pub enum TrashAction {
+    List,
+    Restore {
+        index: usize,
+    },
+    Purge {
+        index: usize,
+    },
+    Empty,
+    /// 지운 뒤 이 일수가 지나면 로그인할 때 완전히 지움. 0이면 지우지 않음
+    Retention {
+        days: u32,
+    },
+}
+
+/// 생성기 설정. `--words`를 주면 패스프레이즈, 아니면 문자 단위 비밀번호
+#[derive(Args)]
+pub struct GeneratorArgs {
+    #[arg(long, default_value_t = CharsetPolicy::default().length, conflicts_with = "words")]
+    length: usize,
+    /// 쓸 문자 종류. 주지 않으면 모두 씀
+    #[arg(long = "class", value_delimiter = ',')]
+    classes: Vec<CharClass>,
+    /// 쓰는 종류가 각각 한 글자 이상 들어가야 한다는 조건을 끔
+    #[arg(long)]
+    no_required: bool,
+    /// I, l, 1, O, 0 처럼 헷갈리는 문자를 뺌
+    #[arg(long)]
+    exclude_look_alikes: bool,
+    #[arg(long)]
+    words: Option<usize>,
+    #[arg(long, default_value = "-", requires = "words")]
+    separator: String,
+    #[arg(long, requires = "words")]
+    capitalize: bool,
+}
+
+impl GeneratorArgs {
+    fn policy(&self) -> GeneratorPolicy {
+        if let Some(word_count) = self.words {
+            return GeneratorPolicy::Passphrase(PassphrasePolicy {
+                word_count,
+                separator: self.separator.clone(),
+                capitalize: self.capitalize,
+            });
+        }
+        let classes = if self.classes.is_empty() {
+            CharClass::ALL.to_vec()
+        } else {
+            self.classes.clone()
+        };
+        GeneratorPolicy::Charset(CharsetPolicy {
+            length: self.length,
+            required: if self.no_required { Vec::new() } else { classes.clone() },
+            classes,
+            exclude_look_alikes: self.exclude_look_alikes,
+        })
+    }
+}
+
+/// 즐겨찾기 표시, 태그, 폴더
+fn entry_labels(entry: &Entry) -> String {
+    let mut labels = String::new();
+    if entry.is_favourite() {
+        labels.push_str(" *");
+    }
+    if !entry.tags().is_empty() {
+        labels.push_str(&format!("  [{}]", entry.tags().join(", ")));
+    }
+    if !entry.folder().is_root() {
+        labels.push_str(&format!("  ({})", entry.folder()));
+    }
+    labels
+}
+
+/// 터미널이면 굵은 밑줄로, 아니면 대괄호로 맞은 부분을 표시함
+fn highlight(text: &str, ranges: &[Range<usize>], ansi: bool) -> String {
+    let (open, close) = if ansi { ("\x1b[1;4m", "\x1b[0m") } else { ("[", "]") };
+    let mut highlighted = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in ranges {
+        highlighted.push_str(&text[last..range.start]);
+        highlighted.push_str(open);
+        highlighted.push_str(&text[range.clone()]);
+        highlighted.push_str(close);
+        last = range.end;
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+/// 폴더 하나에 한 줄. `바로 든 계정 수 / 하위 폴더까지 합친 수`
+fn print_folder_tree(node: &FolderNode, depth: usize) {
+    println!("{}{}  {}/{}", "  ".repeat(depth), node.name(), node.entry_count, node.total_count);
+    for child in node.children.values() {
+        print_folder_tree(child, depth + 1);
+    }
+}
+
+fn print_strength(estimate: &StrengthEstimate) {
+    println!(
+        "Strength: {} ({}/4), about 10^{:.0} guesses",
+        estimate.score(),
+        estimate.score().value(),
+        estimate.guesses_log10
+    );
+}
+
+/// `--generate`로 만든 비밀번호. 실패하면 이유를 출력하고 `None`
+fn generate_user_pw(generator: &GeneratorArgs) -> Option<UserPW> {
+    match generate(&generator.policy()) {
+        Ok(generated) => {
+            println!("Generated a password with {:.1} bits of entropy", generated.entropy_bits);
+            Some(generated.pw)
+        }
+        Err(e) => {
+            println!("Error generating password: {}", e);
+            None
+        }
+    }
+}
+
+impl UserRequest {
+    fn modifies_db(&self) -> bool {
+        !matches!(
+            self,
+            UserRequest::GetUserPW { .. }
+                | UserRequest::GetUserPWToClipboard { .. }
+                | UserRequest::Generate { .. }
+                | UserRequest::Audit { .. }
+                | UserRequest::CheckBreaches
+                | UserRequest::ShowEntry { .. }
+                | UserRequest::History { .. }
+                | UserRequest::PrefixSearch { .. }
+                | UserRequest::Tags
+                | UserRequest::Folders
+                | UserRequest::Trash { action: None | Some(TrashAction::List) }
+                | UserRequest::Slots { action: None }
+                | UserRequest::ListBackups
+                | UserRequest::InspectBackup { .. }
+                // 열려 있는 DB가 아니라 파일을 교체하므로 읽기 전용이어도 허용
+                | UserRequest::RestoreBackup { .. }
+                | UserRequest::ExitAppWithoutSave
+        )
+    }
+}
+
+/// 조건을 통과하고 확인 입력과 같을 때까지 새 비밀번호를 입력받음. 키 슬롯 비밀번호도 마스터 비밀번호 조건을 따름
+fn read_new_pw(policy: &MasterPWPolicy, name: &str) -> String {
+    loop {
+        print!("Please enter new {}: ", name);
+        io::stdout().flush().unwrap();
+        let mut master_pw = String::new();
+        stdin().read_line(&mut master_pw).unwrap();
+        match check_new_master_pw(&master_pw, policy) {
+            Ok(estimate) => print_strength(&estimate),
+            Err(err) => {
+                println!("MasterPW creation error: {}", err);
+                master_pw.zeroize();
+                continue;
+            }
+        };
+
+        print!("Please confirm {}: ", name);
+        io::stdout().flush().unwrap();
+        let mut master_pw_confirm = String::new();
+        stdin().read_line(&mut master_pw_confirm).unwrap();
+
+        let is_match = master_pw == master_pw_confirm;
+        master_pw.zeroize();
+        if !is_match {
+            println!("password is missmatch");
+            master_pw_confirm.zeroize();
+            continue;
+        }
+        return master_pw_confirm;
+    }
+}
+
+fn print_key_slots(header: &DBHeader) {
+    let slots = list_key_slots(header);
+    if slots.is_empty() {
+        println!("No key slots are set");
+    }
+    for slot in slots {
+        println!("{:>3}  {:<13}  {}", slot.index, slot.kind.to_string(), slot.label);
+    }
+}
+
+/// 슬롯 종류에 맞는 자격 증명을 입력받아 슬롯을 엶. 키 파일을 정한 비밀번호 슬롯은 `--keyfile`의 키 파일을 씀
+fn read_key_slot_login(
+    header: &DBHeader,
+    index: usize,
+    key_file: Option<&KeyFile>,
+) -> Result<(SecKey, PubKey, WrappedSessionKey, SessionKeyNonce), Box<dyn std::error::Error>> {
+    let kind = list_key_slots(header)
+        .into_iter()
+        .find(|slot| slot.index == index)
+        .ok_or(KeySlotError::NoSuchSlot(index))?
+        .kind;
+    if key_file.is_some() && kind != KeySlotKind::PasswordAndKeyFile {
+        return Err(MasterPWError::KeyFileNotUsed.into());
+    }
+    let mut input = String::new();
+    let result = match kind {
+        KeySlotKind::Password => {
+            print!("Please enter slot password: ");
+            io::stdout().flush().unwrap();
+            stdin().read_line(&mut input).unwrap();
+            key_slot_login(header, index, &SlotCredential::Password(&input))
+        }
+        KeySlotKind::PasswordAndKeyFile => {
+            let Some(key_file) = key_file else {
+                return Err(MasterPWError::KeyFileRequired.into());
+            };
+            print!("Please enter master password: ");
+            io::stdout().flush().unwrap();
+            stdin().read_line(&mut input).unwrap();
+            key_slot_login(header, index, &SlotCredential::PasswordAndKeyFile(&input, key_file))
+        }
+        KeySlotKind::KeyFile | KeySlotKind::Teammate => {
+            print!("Please enter key file path: ");
+            io::stdout().flush().unwrap();
+            stdin().read_line(&mut input).unwrap();
+            let slot_key_file = KeyFile::read(Path::new(input.trim()))?;
+            key_slot_login(header, index, &SlotCredential::KeyFile(&slot_key_file))
+        }
+        KeySlotKind::RecoveryCode => {
+            print!("Please enter recovery code: ");
+            io::stdout().flush().unwrap();
+            stdin().read_line(&mut input).unwrap();
+            let recovery_key = RecoveryKey::parse(&input);
+            input.zeroize();
+            key_slot_login(header, index, &SlotCredential::RecoveryCode(&recovery_key?))
+        }
+        KeySlotKind::RecoveryShares => {
+            let shares = read_recovery_shares();
+            key_slot_login(header, index, &SlotCredential::Shares(&shares))
+        }
+    };
+    input.zeroize();
+    Ok(result?)
+}
+
+/// 첫 조각에 적힌 필요한 수만큼 복구 조각을 입력받음. 잘못 옮겨 적은 조각은 다시 입력받음
+fn read_recovery_shares() -> Vec<Share> {
+    let mut shares: Vec<Share> = Vec::new();
+    loop {
+        let threshold = match shares.first() {
+            Some(first) if shares.len() >= first.threshold() as usize => return shares,
+            Some(first) => first.threshold(),
+            None => 0,
+        };
+        if threshold == 0 {
+            print!("Please enter a recovery share: ");
+        } else {
+            print!("Please enter recovery share ({} of {}): ", shares.len() + 1, threshold);
+        }
+        io::stdout().flush().unwrap();
+        let mut text = String::new();
+        stdin().read_line(&mut text).unwrap();
+        let share = Share::parse(&text);
+        text.zeroize();
+        match share {
+            Ok(share) if shares.iter().any(|other| other.index() == share.index()) => {
+                println!("Error reading recovery share: {}", ShamirError::DuplicateShare(share.index()));
+            }
+            Ok(share) => shares.push(share),
+            Err(e) => println!("Error reading recovery share: {}", e),
+        }
+    }
+}
+
+/// `output_dir`이 없으면 화면에 출력함
+fn write_share_sheets(shares: &[Share], slot_index: usize, vault_file: &Path, output_dir: Option<&Path>) {
+    let output_dir = output_dir.filter(|dir| match fs::create_dir_all(dir) {
+        Ok(_) => true,
+        Err(e) => {
+            println!("Error creating share directory: {}", e);
+            false
+        }
+    });
+    for share in shares {
+        let sheet = share_sheet(share, shares.len(), slot_index, vault_file);
+        let Some(dir) = output_dir else {
+            println!("{}", sheet.as_str());
+            continue;
+        };
+        let path = dir.join(format!("share_{}.txt", share.index()));
+        match fs::write(&path, sheet.as_bytes()) {
+            Ok(_) => println!("Recovery share {} written to {}", share.index(), path.display()),
+            Err(e) => {
+                println!("Error writing recovery share: {}", e);
+                println!("{}", sheet.as_str());
+            }
+        }
+    }
+}
+
+/// 손상되어 격리된 DB의 복구 방법을 사용자에게 물음.
+/// 읽기 전용으로 열기를 고르면 그 내용을 반환하고, 나머지는 `load_db`를 다시 시도하게 함
+fn recover_quarantined_db(
+    vault: &VaultHandle,
+    quarantine_path: &Path,
+) -> Option<(DBHeader, EncryptedDB)> {
+    loop {
+        println!("[ Recovery ]");
+        println!("  1) retry reading the quarantined file");
+        println!("  2) open the quarantined file read-only");
+        println!("  3) restore from a backup file");
+        println!("  4) start with a new empty database (the quarantined file is kept)");
+        println!("  5) exit");
+        print!("Please select: ");
+        io::stdout().flush().unwrap();
+        let mut choice = String::new();
+        stdin().read_line(&mut choice).unwrap();
+
+        match choice.trim() {
+            "1" => match restore_db_from(vault, quarantine_path) {
+                Ok(()) => return None,
+                Err(e) => println!("Error reading quarantined db: {}", e),
+            },
+            "2" => match open_db_read_only(quarantine_path) {
+                Ok(v) => return Some(v),
+                Err(e) => println!("Error opening quarantined db: {}", e),
+            },
+            "3" => {
+                let snapshots = list_snapshots(vault).unwrap_or_default();
+                for (i, snapshot) in snapshots.iter().enumerate() {
+                    println!("{:>3}  {}", i + 1, snapshot.created_utc());
+                }
+                print!("Please enter backup number or file path: ");
+                io::stdout().flush().unwrap();
+                let mut backup = String::new();
+                stdin().read_line(&mut backup).unwrap();
+                let backup_path = match backup.trim().parse::<usize>() {
+                    Ok(i) if (1..=snapshots.len()).contains(&i) => {
+                        snapshots[i - 1].path().to_path_buf()
+                    }
+                    _ => PathBuf::from(backup.trim()),
+                };
+                match restore_db_from(vault, &backup_path) {
+                    Ok(()) => return None,
+                    Err(e) => println!("Error restoring db: {}", e),
+                }
+            }
+            "4" => return None,
+            "5" => exit(0),
+            _ => println!("Invalid input"),
+        }
+    }
+}
+
+/// 항목 내용을 복호화해서 `edit`로 고친 뒤 다시 저장함
+fn edit_entry_details(
+    db: &mut DB,
+    site: &SiteName,
+    id: &UserID,
+    wrapped_user_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+    edit: impl FnOnce(&mut EntryDetails),
+) -> Result<(), DBIOError> {
+    let mut details = read_entry_details(db, site, id, wrapped_user_key, user_key_nonce)?;
+    edit(&mut details);
+    update_entry_details(db, site, id, &details, wrapped_user_key, user_key_nonce)
+}
+
+fn edit_tags(
+    db: &mut DB,
+    site: &SiteName,
+    id: &UserID,
+    edit: impl FnOnce(&mut Vec<String>),
+) -> Result<(), DBIOError> {
+    let mut tags = get_entry(db, site, id)?.tags().to_vec();
+    edit(&mut tags);
+    set_tags(db, site, id, &tags)
+}
+
+fn select_snapshot(vault: &VaultPath, index: usize) -> Option<Snapshot> {
+    let snapshots = match list_snapshots(vault) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Error listing backups: {}", e);
+            return None;
+        }
+    };
+    if index == 0 || index > snapshots.len() {
+        println!("No such backup: {}", index);
+        return None;
+    }
+    Some(snapshots[index - 1].clone())
+}
+
+/// 저장은 됐지만 백업 스냅샷을 갱신하지 못했으면 알림
+fn print_save_warn(warn: Option<FileIOWarn>) {
+    if let Some(w) = warn {
+        println!("Warn saving db: {}", w);
+    }
+}
+
+/// `--check-migration`. 적용할 단계를 보여주고, 비밀번호를 받아 메모리에서만 마이그레이션해 봄
+fn check_migration(db_header: &DBHeader, encrypted_db: Option<&EncryptedDB>, key_file: Option<&KeyFile>) {
+    let plan = match db_header.version().map_err(MigrationError::from).and_then(migration_plan) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Error checking migration: {}", e);
+            return;
+        }
+    };
+    let Some(encrypted_db) = encrypted_db.filter(|_| !plan.is_empty()) else {
+        println!("The database is already at version {}", FormatVersion::CURRENT);
+        return;
+    };
+    for migration in &plan {
+        println!("  {} -> {}: {}", migration.from, migration.to, migration.description);
+    }
+
+    print!("Please enter master password to check the migration: ");
+    io::stdout().flush().unwrap();
+    let mut master_pw = String::new();
+    stdin().read_line(&mut master_pw).unwrap();
+    let result = dry_run_migration(db_header, encrypted_db, &master_pw, key_file);
+    master_pw.zeroize();
+    match result {
+        Ok(report) => println!(
+            "Migration from {} would succeed: {} sites, {} entries. nothing was written",
+            report.from, report.site_count, report.entry_count
+        ),
+        Err(e) => println!("Migration check failed: {}", e),
+    }
 }
@@ -159,6 +159,35 @@ pub fn aes256gcm_encrypt_write_to_ptr(
     debug_assert_eq!(actual_ciphertext_len, ciphertext_len, "AES-GCM ciphertext length mismatch of {{ plaintext length + verifier tag langth == 16 }}");
 }
 
+pub fn aes256gcm_encrypt_with_ad_write_to_ptr(
+    key: &AesKey, nonce: &AesNonce,
+    plaintext: &[u8], additional_data: &[u8],
+    ciphertext: *mut u8
+) -> () {
+    let mut actual_ciphertext_len: c_ulonglong = 0;
+    let ciphertext_len = get_aes256gcm_ciphertext_len(plaintext.len()) as c_ulonglong;
+    unsafe {
+        crypto_aead_aes256gcm_encrypt(
+            ciphertext, addr_of_mut!(actual_ciphertext_len),
+            plaintext.as_ptr(), plaintext.len() as c_ulonglong,
+            additional_data.as_ptr(), additional_data.len() as c_ulonglong,
+            null(), nonce.as_ptr(), key.as_ptr()
+        );
+    }
+    debug_assert_eq!(actual_ciphertext_len, ciphertext_len, "AES-GCM ciphertext length mismatch of {{ plaintext length + verifier tag langth == 16 }}");
+}
+
+pub fn aes256gcm_encrypt_with_ad(
+    key: &AesKey, nonce: &AesNonce,
+    plaintext: &[u8], additional_data: &[u8]
+) -> Vec<u8> {
+    let ciphertext_len = get_aes256gcm_ciphertext_len(plaintext.len());
+    let mut ciphertext = Vec::with_capacity(ciphertext_len);
+    unsafe { ciphertext.set_len(ciphertext_len); }
+    aes256gcm_encrypt_with_ad_write_to_ptr(key, nonce, plaintext, additional_data, ciphertext.as_mut_ptr());
+    ciphertext
+}
+
 pub fn aes256gcm_encrypt_to_sodium_box(
     key: &AesKey, nonce: &AesNonce,
     plaintext: &[u8]
@@ -244,6 +273,28 @@ pub fn aes256gcm_decrypt_write_to_ptr(
     Ok ( () )
 }
 
+pub fn aes256gcm_decrypt_with_ad_write_to_ptr(
+    key: &AesKey, nonce: &AesNonce,
+    ciphertext: &[u8], additional_data: &[u8],
+    plaintext: *mut u8
+) -> Result<(), ()> {
+    let mut actual_plaintext_len: c_ulonglong = 0;
+    let plaintext_len = get_aes256gcm_plaintext_len(ciphertext.len()) as c_ulonglong;
+    let rc = unsafe {
+        crypto_aead_aes256gcm_decrypt(
+            plaintext, addr_of_mut!(actual_plaintext_len), null_mut(),
+            ciphertext.as_ptr(), ciphertext.len() as c_ulonglong,
+            additional_data.as_ptr(), additional_data.len() as c_ulonglong,
+            nonce.as_ptr(), key.as_ptr()
+        )
+    };
+    if rc != 0 {
+        return Err( () )
+    }
+    debug_assert_eq!(actual_plaintext_len, plaintext_len, "AES-GCM ciphertext length mismatch of {{ plaintext length - verifier tag langth == 16 }}");
+    Ok ( () )
+}
+
 pub fn aes256gcm_decrypt(
     key: &AesKey, nonce: &AesNonce,
     ciphertext: &[u8]
@@ -266,6 +317,28 @@ pub fn aes256gcm_decrypt(
     Ok ( plaintext )
 }
 
+pub fn aes256gcm_decrypt_with_ad(
+    key: &AesKey, nonce: &AesNonce,
+    ciphertext: &[u8], additional_data: &[u8]
+) -> Result<SodiumBox<u8>, ()> {
+    let mut actual_plaintext_len: c_ulonglong = 0;
+    let plaintext_len = get_aes256gcm_plaintext_len(ciphertext.len()) as c_ulonglong;
+    let mut plaintext = SodiumBox::<c_uchar>::new_with_size(plaintext_len as usize);
+    let rc = unsafe {
+        crypto_aead_aes256gcm_decrypt(
+            plaintext.as_mut_ptr(), addr_of_mut!(actual_plaintext_len), null_mut(),
+            ciphertext.as_ptr(), ciphertext.len() as c_ulonglong,
+            additional_data.as_ptr(), additional_data.len() as c_ulonglong,
+            nonce.as_ptr(), key.as_ptr()
+        )
+    };
+    if rc != 0 {
+        return Err( () )
+    }
+    debug_assert_eq!(actual_plaintext_len, plaintext_len, "AES-GCM ciphertext length mismatch of {{ plaintext length - verifier tag langth == 16 }}");
+    Ok ( plaintext )
+}
+
 pub fn aes256gcm_decrypt_from_ptr(
     key: &AesKey, nonce: &AesNonce,
     ciphertext: *const u8, ciphertext_len: usize,
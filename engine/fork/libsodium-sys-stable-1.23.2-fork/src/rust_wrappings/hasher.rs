@@ -34,6 +34,22 @@ impl<const out_len: usize> Blake2b<out_len> {
             }
         }
     }
+    /// keyed BLAKE2b. `key_len`은 16 ~ 64
+    pub fn new_keyed(key: *const u8, key_len: usize) -> Self {
+        unsafe {
+            let mut boxed: SodiumBox<u8> = SodiumBox::new_with_size(Self::MEM_SIZE);
+            let redundant = boxed.as_ptr() as usize % Self::ALIGN_BYTES;
+            let diff_begin = Self::ALIGN_BYTES - redundant;
+            let begin = boxed.as_mut_ptr().add(diff_begin).cast();
+
+            crypto_generichash_blake2b_init(begin, key, key_len, out_len);
+
+            Blake2b {
+                inner: boxed,
+                begin,
+            }
+        }
+    }
     pub fn update(&mut self, data: &[u8]) {
         unsafe {
             crypto_generichash_blake2b_update(self.begin, data.as_ptr(), data.len() as c_ulonglong);
@@ -1,5 +1,6 @@
 pub mod aes256gcm;
 pub mod hasher;
 pub mod init;
+pub mod randombytes;
 pub mod sodium_box;
 pub mod x25519;
@@ -0,0 +1,13 @@
+use crate::sodium_bindings::{randombytes_buf, randombytes_uniform};
+
+/// `buf`를 CSPRNG 출력으로 채움
+pub fn random_buf(buf: &mut [u8]) {
+    unsafe {
+        randombytes_buf(buf.as_mut_ptr().cast(), buf.len());
+    }
+}
+
+/// `0..upper_bound` 범위의 균등 난수. modulo bias 없음
+pub fn random_uniform(upper_bound: u32) -> u32 {
+    unsafe { randombytes_uniform(upper_bound) }
+}
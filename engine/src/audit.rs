@@ -0,0 +1,347 @@
+use crate::data_base::{DB, DBIOError, SiteName, Timestamp, UserID};
+use crate::hasher::Blake2b;
+use crate::pw_policy::{PWScore, PWWarning, estimate_strength};
+use crate::randombytes::random_buf;
+use crate::user_secrets::{SessionKeyNonce, WrappedSessionKey, decrypt_user_pw};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use zeroize::Zeroize;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+const PW_DIGEST_SIZE: usize = 32;
+
+/// 점검 기준
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AuditPolicy {
+    /// 마지막으로 바꾼 지 이보다 오래된 비밀번호를 알림
+    pub max_age_days: u64,
+    pub min_score: PWScore,
+}
+
+impl Default for AuditPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: 365,
+            min_score: PWScore::Strong,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditedEntry {
+    pub site: SiteName,
+    pub id: UserID,
+}
+
+#[derive(Clone, Debug)]
+pub enum AuditIssue {
+    /// 서로 다른 계정이 같은 비밀번호를 씀. 같은 도메인의 같은 ID는 같은 계정으로 봄
+    Reused { entries: Vec<AuditedEntry> },
+    Weak {
+        entry: AuditedEntry,
+        score: PWScore,
+        warning: Option<PWWarning>,
+    },
+    Old {
+        entry: AuditedEntry,
+        pw_changed: Timestamp,
+        age_days: u64,
+    },
+    /// 추가(또는 구버전에서 옮겨 온) 뒤로 비밀번호를 바꾼 적이 없음
+    NeverChanged {
+        entry: AuditedEntry,
+        created: Timestamp,
+    },
+    /// 같은 등록 도메인의 여러 사이트에 같은 ID가 있는데 비밀번호가 다름.
+    /// 한쪽만 바꾸고 다른 쪽은 예전 비밀번호로 남아 있을 가능성이 큼
+    DivergentCredentials {
+        domain: String,
+        id: UserID,
+        sites: Vec<SiteName>,
+    },
+}
+
+impl AuditIssue {
+    pub const KINDS: [&'static str; 5] = [
+        "reused",
+        "weak",
+        "old",
+        "never-changed",
+        "divergent-credentials",
+    ];
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuditIssue::Reused { .. } => Self::KINDS[0],
+            AuditIssue::Weak { .. } => Self::KINDS[1],
+            AuditIssue::Old { .. } => Self::KINDS[2],
+            AuditIssue::NeverChanged { .. } => Self::KINDS[3],
+            AuditIssue::DivergentCredentials { .. } => Self::KINDS[4],
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str(&format!("{{\"kind\":{}", json_string(self.kind())));
+        match self {
+            AuditIssue::Reused { entries } => {
+                out.push_str(",\"entries\":[");
+                for (i, entry) in entries.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    out.push('{');
+                    write_entry_json(entry, out);
+                    out.push('}');
+                }
+                out.push(']');
+            }
+            AuditIssue::Weak { entry, score, warning } => {
+                out.push(',');
+                write_entry_json(entry, out);
+                out.push_str(&format!(",\"score\":{}", score.value()));
+                match warning {
+                    Some(warning) => out.push_str(&format!(",\"warning\":{}", json_string(&warning.to_string()))),
+                    None => out.push_str(",\"warning\":null"),
+                }
+            }
+            AuditIssue::Old { entry, pw_changed, age_days } => {
+                out.push(',');
+                write_entry_json(entry, out);
+                out.push_str(&format!(
+                    ",\"pw_changed\":{},\"age_days\":{}",
+                    json_string(&pw_changed.to_string()),
+                    age_days
+                ));
+            }
+            AuditIssue::NeverChanged { entry, created } => {
+                out.push(',');
+                write_entry_json(entry, out);
+                out.push_str(&format!(",\"created\":{}", json_string(&created.to_string())));
+            }
+            AuditIssue::DivergentCredentials { domain, id, sites } => {
+                out.push_str(&format!(
+                    ",\"domain\":{},\"id\":{},\"sites\":[",
+                    json_string(domain),
+                    json_string(id.as_str())
+                ));
+                for (i, site) in sites.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&json_string(site.as_str()));
+                }
+                out.push(']');
+            }
+        }
+        out.push('}');
+    }
+}
+
+impl Display for AuditedEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} / {}", self.site.as_str(), self.id.as_str())
+    }
+}
+
+impl Display for AuditIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditIssue::Reused { entries } => {
+                write!(f, "same password on {} accounts:", entries.len())?;
+                for entry in entries {
+                    write!(f, " [{}]", entry)?;
+                }
+                Ok(())
+            }
+            AuditIssue::Weak { entry, score, warning } => {
+                write!(f, "{}: {} password ({}/4)", entry, score, score.value())?;
+                if let Some(warning) = warning {
+                    write!(f, ". {}", warning)?;
+                }
+                Ok(())
+            }
+            AuditIssue::Old { entry, pw_changed, age_days } => {
+                write!(f, "{}: not changed for {} days (since {})", entry, age_days, pw_changed)
+            }
+            AuditIssue::NeverChanged { entry, created } => {
+                write!(f, "{}: never changed since {}", entry, created)
+            }
+            AuditIssue::DivergentCredentials { domain, id, sites } => {
+                write!(f, "{} on {} has different passwords on:", id.as_str(), domain)?;
+                for site in sites {
+                    write!(f, " {}", site.as_str())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditReport {
+    pub audited_at: Timestamp,
+    pub policy: AuditPolicy,
+    pub entry_count: usize,
+    pub issues: Vec<AuditIssue>,
+}
+
+impl AuditReport {
+    pub fn count(&self, kind: &str) -> usize {
+        self.issues.iter().filter(|issue| issue.kind() == kind).count()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "{{\"audited_at\":{},\"max_age_days\":{},\"min_score\":{},\"entry_count\":{},\"issues\":[",
+            json_string(&self.audited_at.to_string()),
+            self.policy.max_age_days,
+            self.policy.min_score.value(),
+            self.entry_count
+        ));
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            issue.write_json(&mut out);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// 모든 비밀번호를 한 번씩 복호화해서 점검함.
+/// 비밀번호끼리의 비교는 이번 점검에서만 쓰는 임의 salt를 붙인 해시로 하고, 평문은 바로 지움
+pub fn audit_db(
+    db: &DB,
+    policy: &AuditPolicy,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<AuditReport, DBIOError> {
+    audit_db_at(db, policy, Timestamp::now(), wrapped_key, user_key_nonce)
+}
+
+/// `now` 기준으로 비밀번호의 나이를 셈
+pub fn audit_db_at(
+    db: &DB,
+    policy: &AuditPolicy,
+    now: Timestamp,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<AuditReport, DBIOError> {
+    let mut salt = [0u8; PW_DIGEST_SIZE];
+    random_buf(&mut salt);
+
+    let sites = db.sites()?;
+    let mut issues = Vec::new();
+    let mut entry_count = 0;
+    let mut by_digest: BTreeMap<[u8; PW_DIGEST_SIZE], Vec<AuditedEntry>> = BTreeMap::new();
+    // 등록 도메인 -> ID -> (사이트, 비밀번호 해시)
+    let mut by_domain: BTreeMap<&str, BTreeMap<&UserID, Vec<(&SiteName, [u8; PW_DIGEST_SIZE])>>> =
+        BTreeMap::new();
+
+    for (site, users) in &sites {
+        for (id, entry) in users {
+            entry_count += 1;
+            let audited = AuditedEntry {
+                site: site.clone(),
+                id: id.clone(),
+            };
+
+            let pw = decrypt_user_pw(site, id, &entry.pw, wrapped_key, user_key_nonce)?;
+            let estimate = estimate_strength(pw.as_str());
+            let mut hasher = Blake2b::<PW_DIGEST_SIZE>::new();
+            hasher.update(&salt);
+            hasher.update(pw.as_str().as_bytes());
+            drop(pw);
+            let mut digest = [0u8; PW_DIGEST_SIZE];
+            hasher.finalize_write_to(digest.as_mut_ptr());
+
+            if estimate.score() < policy.min_score {
+                issues.push(AuditIssue::Weak {
+                    entry: audited.clone(),
+                    score: estimate.score(),
+                    warning: estimate.feedback.warning,
+                });
+            }
+            let age_days = now.unix_secs().saturating_sub(entry.pw_changed().unix_secs()) / SECS_PER_DAY;
+            if age_days > policy.max_age_days {
+                issues.push(AuditIssue::Old {
+                    entry: audited.clone(),
+                    pw_changed: entry.pw_changed(),
+                    age_days,
+                });
+            }
+            if entry.pw_history().is_empty() && entry.pw_changed() == entry.created() {
+                issues.push(AuditIssue::NeverChanged {
+                    entry: audited.clone(),
+                    created: entry.created(),
+                });
+            }
+
+            by_digest.entry(digest).or_default().push(audited);
+            by_domain
+                .entry(site.reg())
+                .or_default()
+                .entry(id)
+                .or_default()
+                .push((site, digest));
+        }
+    }
+    salt.zeroize();
+
+    for entries in by_digest.into_values() {
+        let accounts: BTreeSet<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.site.reg(), entry.id.as_str()))
+            .collect();
+        if accounts.len() > 1 {
+            issues.push(AuditIssue::Reused { entries });
+        }
+    }
+    for (domain, users) in by_domain {
+        for (id, sites) in users {
+            let digests: BTreeSet<_> = sites.iter().map(|(_, digest)| digest).collect();
+            if digests.len() > 1 {
+                issues.push(AuditIssue::DivergentCredentials {
+                    domain: domain.to_string(),
+                    id: id.clone(),
+                    sites: sites.into_iter().map(|(site, _)| site.clone()).collect(),
+                });
+            }
+        }
+    }
+
+    Ok(AuditReport {
+        audited_at: now,
+        policy: *policy,
+        entry_count,
+        issues,
+    })
+}
+
+fn write_entry_json(entry: &AuditedEntry, out: &mut String) {
+    out.push_str(&format!(
+        "\"site\":{},\"id\":{}",
+        json_string(entry.site.as_str()),
+        json_string(entry.id.as_str())
+    ));
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
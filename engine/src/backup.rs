@@ -0,0 +1,259 @@
+use crate::data_base::Timestamp;
+use crate::file_io::{
+    FileIOError, VaultHandle, mark_as_graceful_exited_to_file, read_verified_db,
+    write_db_file_atomically,
+};
+use crate::header::DBHeader;
+use crate::key_file::KeyFile;
+use crate::master_secrets::{EncryptedDB, MasterPWError, check_key_file, decrypt_db_payload, general_login};
+use crate::migration::{MigrationError, migrate_db};
+use crate::vault_path::VaultPath;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_EXTENSION: &str = "bin";
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// 스냅샷 보존 정책. 세 조건 중 하나라도 만족하는 스냅샷은 남김
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// 가장 최근 스냅샷 N개
+    pub keep_last: usize,
+    /// 최근 N일 동안 하루에 하나(그날의 마지막 스냅샷)
+    pub keep_daily: usize,
+    /// 최근 N주 동안 한 주에 하나(그 주의 마지막 스냅샷)
+    pub keep_weekly: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            keep_last: 10,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    FileIO(FileIOError),
+    MasterPW(MasterPWError),
+    /// 구버전 포맷 스냅샷을 읽지 못함
+    Migration(MigrationError),
+    NoSuchSnapshot,
+}
+impl Display for BackupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::FileIO(err) => write!(f, "{}", err),
+            BackupError::MasterPW(err) => write!(f, "Failed to unlock snapshot: {}", err),
+            BackupError::Migration(err) => write!(f, "Failed to read snapshot: {}", err),
+            BackupError::NoSuchSnapshot => write!(f, "No such snapshot"),
+        }
+    }
+}
+impl Error for BackupError {}
+impl From<FileIOError> for BackupError {
+    fn from(value: FileIOError) -> Self {
+        BackupError::FileIO(value)
+    }
+}
+impl From<MasterPWError> for BackupError {
+    fn from(value: MasterPWError) -> Self {
+        BackupError::MasterPW(value)
+    }
+}
+impl From<MigrationError> for BackupError {
+    fn from(value: MigrationError) -> Self {
+        BackupError::Migration(value)
+    }
+}
+
+/// 백업 디렉터리에 있는 스냅샷 하나. 파일 포맷은 DB 파일과 같음
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    path: PathBuf,
+    created_millis: u64,
+}
+
+impl Snapshot {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn created_unix_secs(&self) -> u64 {
+        self.created_millis / 1000
+    }
+    /// `YYYY-MM-DD HH:MM:SS UTC`
+    pub fn created_utc(&self) -> String {
+        Timestamp::from_unix_secs(self.created_unix_secs()).to_string()
+    }
+
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()? != SNAPSHOT_EXTENSION {
+            return None;
+        }
+        let created_millis = path.file_stem()?.to_str()?.parse().ok()?;
+        Some(Self {
+            path,
+            created_millis,
+        })
+    }
+}
+
+/// 잠금 해제한 스냅샷의 내용 요약
+#[derive(Copy, Clone, Debug)]
+pub struct SnapshotSummary {
+    pub site_count: usize,
+    pub entry_count: usize,
+}
+
+/// `save_db`가 방금 쓴 파일 내용을 그대로 백업 디렉터리에 `<unix millis>.bin`으로 남김
+pub(crate) fn write_snapshot(vault: &VaultPath, bytes: &[u8]) -> Result<Snapshot, FileIOError> {
+    let backup_dir = vault.backup_dir();
+    fs::create_dir_all(&backup_dir).map_err(FileIOError::FileWriteFailed)?;
+
+    let mut created_millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let (mut snapshot_file, path) = loop {
+        let path = backup_dir.join(format!("{}.{}", created_millis, SNAPSHOT_EXTENSION));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => break (file, path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => created_millis += 1,
+            Err(err) => return Err(FileIOError::FileOpenFailed(err)),
+        }
+    };
+
+    snapshot_file.write_all(bytes).map_err(FileIOError::FileWriteFailed)?;
+    snapshot_file.sync_all().map_err(FileIOError::FileSyncFailed)?;
+    Ok(Snapshot {
+        path,
+        created_millis,
+    })
+}
+
+/// 최신 스냅샷부터 정렬됨
+pub fn list_snapshots(vault: &VaultPath) -> Result<Vec<Snapshot>, FileIOError> {
+    let entries = match fs::read_dir(vault.backup_dir()) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(FileIOError::FileReadFailed(err)),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(FileIOError::FileReadFailed)?;
+        if let Some(snapshot) = Snapshot::from_path(entry.path()) {
+            snapshots.push(snapshot);
+        }
+    }
+    snapshots.sort_by(|a, b| b.created_millis.cmp(&a.created_millis));
+    Ok(snapshots)
+}
+
+/// 보존 정책에 걸리지 않는 스냅샷을 지우고, 지운 파일 목록을 반환함
+pub fn prune_snapshots(
+    vault: &VaultPath,
+    policy: &RetentionPolicy,
+) -> Result<Vec<PathBuf>, FileIOError> {
+    let snapshots = list_snapshots(vault)?;
+
+    let mut keep = vec![false; snapshots.len()];
+    keep.iter_mut().take(policy.keep_last).for_each(|k| *k = true);
+
+    // 최신순이므로 각 기간에서 처음 만나는 것이 그 기간의 마지막 스냅샷
+    let days = snapshots.iter().map(|s| s.created_unix_secs() / SECS_PER_DAY);
+    keep_first_of_each_period(&mut keep, days, policy.keep_daily);
+    // 1970-01-01은 목요일이므로 3일 밀어서 월요일에 주가 시작되게 함
+    let weeks = snapshots
+        .iter()
+        .map(|s| (s.created_unix_secs() / SECS_PER_DAY + 3) / 7);
+    keep_first_of_each_period(&mut keep, weeks, policy.keep_weekly);
+
+    let mut removed = Vec::new();
+    for (snapshot, keep) in snapshots.into_iter().zip(keep) {
+        if !keep {
+            fs::remove_file(&snapshot.path).map_err(FileIOError::FileDeleteFailed)?;
+            removed.push(snapshot.path);
+        }
+    }
+    Ok(removed)
+}
+
+fn keep_first_of_each_period(
+    keep: &mut [bool],
+    periods: impl Iterator<Item = u64>,
+    max_periods: usize,
+) {
+    let mut seen = HashSet::new();
+    for (i, period) in periods.enumerate() {
+        if seen.len() >= max_periods && !seen.contains(&period) {
+            break;
+        }
+        if seen.insert(period) {
+            keep[i] = true;
+        }
+    }
+}
+
+pub fn open_snapshot(snapshot: &Snapshot) -> Result<(DBHeader, EncryptedDB), FileIOError> {
+    let file = File::open(&snapshot.path).map_err(FileIOError::FileOpenFailed)?;
+    read_verified_db(&file)
+}
+
+/// 스냅샷을 만들 당시의 마스터 비밀번호와 키 파일로 잠금 해제해서 항목 수를 셈.
+/// 키 파일을 쓰기 전의 스냅샷이면 `key_file`은 무시함
+pub fn inspect_snapshot(
+    snapshot: &Snapshot,
+    master_pw: &str,
+    key_file: Option<&KeyFile>,
+) -> Result<SnapshotSummary, BackupError> {
+    let (mut header, encrypted_db) = open_snapshot(snapshot)?;
+    let key_file = key_file.filter(|_| header.requires_key_file());
+    check_key_file(&header, key_file)?;
+
+    let mut master_pw = master_pw.to_owned();
+    let (mut sec_key, mut pub_key, mut wrapped_session_key, mut session_key_nonce) =
+        general_login(&mut master_pw, &header, key_file)?;
+    let payload = decrypt_db_payload(&encrypted_db, &header, &sec_key)?;
+    // 이전 버전의 앱이 만든 스냅샷일 수 있으므로 메모리에서만 현재 포맷으로 올림
+    let (db, _) = migrate_db(
+        &mut header,
+        payload,
+        &mut sec_key,
+        &mut pub_key,
+        &mut wrapped_session_key,
+        &mut session_key_nonce,
+    )?;
+
+    Ok(SnapshotSummary {
+        site_count: db.site_count(),
+        entry_count: db.entry_count(),
+    })
+}
+
+/// 스냅샷으로 현재 DB 파일을 교체함. 열려 있는 세션은 다시 로그인해야 함
+pub fn restore_snapshot(vault: &VaultHandle, snapshot: &Snapshot) -> Result<(), BackupError> {
+    if !fs::exists(&snapshot.path).map_err(FileIOError::FileReadFailed)? {
+        return Err(BackupError::NoSuchSnapshot);
+    }
+    let file = File::open(&snapshot.path).map_err(FileIOError::FileOpenFailed)?;
+    read_verified_db(&file)?;
+    let mut bytes = Vec::new();
+    (&file)
+        .read_to_end(&mut bytes)
+        .map_err(FileIOError::FileReadFailed)?;
+    drop(file);
+
+    write_db_file_atomically(vault, &bytes)?;
+    // 열려 있던 세션의 변경은 버리는 것이므로 다음 load_db가 비정상 종료로 보지 않게 함
+    mark_as_graceful_exited_to_file(vault)?;
+    Ok(())
+}
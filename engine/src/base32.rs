@@ -0,0 +1,58 @@
+//! OTP 비밀 키, 복구 코드와 조각, 팀원 공개키에 쓰는 RFC 4648 base32
+
+use zeroize::{Zeroize, Zeroizing};
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 대소문자, 공백, `-`, `=` 패딩은 가리지 않음. 알파벳 밖의 글자가 있으면 `None`
+pub(crate) fn decode(input: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let mut out = Zeroizing::new(Vec::with_capacity(input.len() * 5 / 8 + 1));
+    let mut buffer = 0u64;
+    let mut bits = 0u32;
+    for c in input.bytes().filter(|c| !matches!(c, b' ' | b'-' | b'=')) {
+        let Some(value) = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase()) else {
+            buffer.zeroize();
+            return None;
+        };
+        buffer = (buffer << 5) | value as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    buffer.zeroize();
+    Some(out)
+}
+
+/// 패딩 없이 씀
+pub(crate) fn encode(data: &[u8], out: &mut String) {
+    let mut buffer = 0u64;
+    let mut bits = 0u32;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u64;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    buffer.zeroize();
+}
+
+/// 받아 적기 쉽도록 `group_len` 글자마다 `-`를 넣음. `decode`는 `-`를 무시함
+pub(crate) fn encode_grouped(data: &[u8], group_len: usize) -> Zeroizing<String> {
+    let mut plain = Zeroizing::new(String::new());
+    encode(data, &mut plain);
+    let mut grouped = Zeroizing::new(String::with_capacity(plain.len() + plain.len() / group_len));
+    for (i, c) in plain.chars().enumerate() {
+        if i != 0 && i % group_len == 0 {
+            grouped.push('-');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
@@ -0,0 +1,260 @@
+use crate::data_base::{DB, DBIOError, SiteName, UserID, UserPW, get_entry};
+use crate::sha1::{SHA1_SIZE, sha1};
+use crate::user_secrets::{SessionKeyNonce, WrappedSessionKey, decrypt_user_pw};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const HASH_HEX_LEN: usize = SHA1_SIZE * 2;
+/// 범위 파일 이름이 되는 해시 앞부분의 길이
+const RANGE_PREFIX_LEN: usize = 5;
+
+#[derive(Debug)]
+pub enum BreachError {
+    FileOpenFailed(PathBuf, io::Error),
+    MapFailed(PathBuf, io::Error),
+    /// 범위 파일 디렉터리에 해당 범위 파일이 없음. 미러가 덜 받아졌을 가능성이 큼
+    RangeFileMissing(String),
+    /// 해시는 찾았지만 횟수를 읽을 수 없음
+    MalformedLine(PathBuf),
+    DB(DBIOError),
+}
+impl Display for BreachError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreachError::FileOpenFailed(path, e) => {
+                write!(f, "Failed to open {}: {}", path.display(), e)
+            }
+            BreachError::MapFailed(path, e) => write!(f, "Failed to map {}: {}", path.display(), e),
+            BreachError::RangeFileMissing(prefix) => {
+                write!(f, "Range file {} is missing from the breach list", prefix)
+            }
+            BreachError::MalformedLine(path) => {
+                write!(f, "Malformed line in {}", path.display())
+            }
+            BreachError::DB(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl Error for BreachError {}
+impl From<DBIOError> for BreachError {
+    fn from(e: DBIOError) -> Self {
+        BreachError::DB(e)
+    }
+}
+
+/// HIBP "Pwned Passwords" SHA-1 목록.
+/// `HASH:COUNT`가 해시 순으로 정렬된 파일 하나이거나,
+/// 해시 앞 5자리를 이름으로 하고 나머지 35자리로 정렬된 범위 파일(`ABCDE` 또는 `ABCDE.txt`)의 디렉터리
+pub struct BreachList {
+    path: PathBuf,
+    /// 파일 하나짜리 목록이면 열 때 한 번만 매핑함
+    single_file: Option<MappedFile>,
+}
+
+impl BreachList {
+    pub fn open(path: &Path) -> Result<Self, BreachError> {
+        let single_file = if path.is_dir() {
+            None
+        } else {
+            Some(MappedFile::open(path)?)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            single_file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 유출 목록에 나온 횟수. 없으면 0
+    pub fn breach_count(&self, pw: &UserPW) -> Result<u64, BreachError> {
+        let hash = sha1_hex(pw.as_str().as_bytes());
+        match &self.single_file {
+            Some(file) => find_count(file.bytes(), &hash[..])
+                .map_err(|()| BreachError::MalformedLine(self.path.clone())),
+            None => {
+                let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LEN);
+                let prefix = String::from_utf8_lossy(prefix).into_owned();
+                let range_path = [format!("{}.txt", prefix), prefix.clone()]
+                    .into_iter()
+                    .map(|name| self.path.join(name))
+                    .find(|path| path.is_file())
+                    .ok_or(BreachError::RangeFileMissing(prefix))?;
+                let file = MappedFile::open(&range_path)?;
+                find_count(file.bytes(), suffix).map_err(|()| BreachError::MalformedLine(range_path))
+            }
+        }
+    }
+}
+
+pub struct BreachedEntry {
+    pub site: SiteName,
+    pub id: UserID,
+    pub count: u64,
+}
+
+/// 계정 하나의 현재 비밀번호를 조회함
+pub fn check_entry(
+    list: &BreachList,
+    db: &DB,
+    site: &SiteName,
+    id: &UserID,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<u64, BreachError> {
+    let entry = get_entry(db, site, id)?;
+    let pw = decrypt_user_pw(site, id, &entry.pw, wrapped_key, user_key_nonce)?;
+    list.breach_count(&pw)
+}
+
+/// 모든 계정의 현재 비밀번호를 조회함. 목록에 없는 계정도 0으로 들어감
+pub fn check_db(
+    list: &BreachList,
+    db: &DB,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<Vec<BreachedEntry>, BreachError> {
+    let mut results = Vec::new();
+    for (site, users) in db.sites()? {
+        for (id, entry) in users {
+            let pw = decrypt_user_pw(&site, &id, &entry.pw, wrapped_key, user_key_nonce)?;
+            results.push(BreachedEntry {
+                site: site.clone(),
+                id,
+                count: list.breach_count(&pw)?,
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// 정렬된 `KEY:COUNT` 줄에서 이분 탐색함. 줄 길이가 제각각이라 가운데 위치에서 줄 처음으로 되돌아감.
+/// `key`는 대문자 16진수이고, 파일 쪽은 대소문자를 가리지 않음
+fn find_count(data: &[u8], key: &[u8]) -> Result<u64, ()> {
+    // lo는 항상 줄의 처음
+    let (mut lo, mut hi) = (0, data.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let start = data[lo..mid]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(lo, |i| lo + i + 1);
+        let end = data[mid..hi]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(hi, |i| mid + i);
+        let line = data[start..end].trim_ascii();
+        let (line_key, count) = match line.iter().position(|&b| b == b':') {
+            Some(colon) => (&line[..colon], &line[colon + 1..]),
+            None => (line, &[][..]),
+        };
+
+        match line_key.iter().map(u8::to_ascii_uppercase).cmp(key.iter().copied()) {
+            Ordering::Equal => {
+                return std::str::from_utf8(count)
+                    .ok()
+                    .and_then(|count| count.parse().ok())
+                    .ok_or(());
+            }
+            Ordering::Less => lo = end + 1,
+            Ordering::Greater => hi = start,
+        }
+    }
+    Ok(0)
+}
+
+/// 읽기 전용 매핑. 유닉스가 아니면 파일 전체를 읽어 둠
+struct MappedFile {
+    #[cfg(unix)]
+    ptr: *mut libc::c_void,
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(not(unix))]
+    data: Vec<u8>,
+}
+
+impl MappedFile {
+    #[cfg(unix)]
+    fn open(path: &Path) -> Result<Self, BreachError> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::open(path).map_err(|e| BreachError::FileOpenFailed(path.to_path_buf(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| BreachError::FileOpenFailed(path.to_path_buf(), e))?
+            .len() as usize;
+        // 길이 0은 mmap이 거부함
+        if len == 0 {
+            return Ok(Self { ptr: std::ptr::null_mut(), len });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(BreachError::MapFailed(path.to_path_buf(), io::Error::last_os_error()));
+        }
+        Ok(Self { ptr, len })
+    }
+
+    #[cfg(not(unix))]
+    fn open(path: &Path) -> Result<Self, BreachError> {
+        use std::io::Read;
+
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| BreachError::FileOpenFailed(path.to_path_buf(), e))?;
+        Ok(Self { data })
+    }
+
+    #[cfg(unix)]
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    #[cfg(not(unix))]
+    fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// 대문자 16진수 SHA-1. HIBP 목록의 형식에 맞추려는 것이고 보안 용도로 쓰지 않음
+fn sha1_hex(data: &[u8]) -> Zeroizing<[u8; HASH_HEX_LEN]> {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    let digest = sha1(data);
+    let mut hex = Zeroizing::new([0u8; HASH_HEX_LEN]);
+    for (i, byte) in digest.iter().enumerate() {
+        hex[i * 2] = HEX[(byte >> 4) as usize];
+        hex[i * 2 + 1] = HEX[(byte & 0xf) as usize];
+    }
+    hex
+}
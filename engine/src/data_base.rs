@@ -1,11 +1,18 @@
 use crate::master_secrets::manual_zeroize;
-use crate::user_secrets::{decrypt_user_pw, encrypt_user_pw, EncryptedUserPW, SessionKeyNonce, WrappedSessionKey};
+use crate::user_secrets::{
+    decrypt_entry_field, decrypt_user_pw, encrypt_entry_field, encrypt_user_pw, hash_site_name, hash_user_id,
+    open_site_name, open_site_reg, open_user_id, seal_site_name, seal_user_id, EncryptedField, EncryptedSiteName,
+    EncryptedUserID, EncryptedUserPW, NameHash, NameKey, SessionKeyNonce, WrappedSessionKey,
+};
+use rkyv::with::Skip;
 use rkyv::{Archive, Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use std::sync::atomic::{self, AtomicU64};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 
 #[derive(Debug)]
@@ -184,6 +191,10 @@ impl SiteName {
     pub fn as_str(&self) -> &str {
         &self.full
     }
+    /// 등록 가능한 도메인(eTLD+1). `mail.example.com`이면 `example.com`
+    pub fn reg(&self) -> &str {
+        &self.reg
+    }
 }
 impl Ord for SiteName {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -247,7 +258,974 @@ impl Display for SiteNameError {
 impl Error for SiteNameError {}
 
 
-pub type DB = BTreeMap<SiteName, HashMap<UserID, EncryptedUserPW>>;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// 1970-01-01 UTC부터의 초
+#[derive(
+    Archive, Serialize, Deserialize,
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        )
+    }
+    pub const fn from_unix_secs(secs: u64) -> Self {
+        Self(secs)
+    }
+    pub fn unix_secs(&self) -> u64 {
+        self.0
+    }
+    pub fn year(&self) -> i64 {
+        civil_from_days((self.0 / SECS_PER_DAY) as i64).0
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC`
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days((self.0 / SECS_PER_DAY) as i64);
+        let secs_of_day = self.0 % SECS_PER_DAY;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )
+    }
+}
+
+/// 1970-01-01부터의 일수 -> (년, 월, 일). Howard Hinnant의 civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+
+#[derive(Debug)]
+pub enum EntryFieldError {
+    EmptyFieldName,
+    DuplicateFieldName(String),
+    InvalidEmail(String),
+    InvalidPhone(String),
+    InvalidUrl(String),
+    InvalidTag(String),
+    InvalidFolder(String),
+}
+
+impl Display for EntryFieldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntryFieldError::EmptyFieldName => {
+                write!(f, "Custom field name is empty")
+            }
+            EntryFieldError::DuplicateFieldName(name) => {
+                write!(f, "Custom field \"{}\" is defined more than once", name)
+            }
+            EntryFieldError::InvalidEmail(name) => {
+                write!(f, "Custom field \"{}\" is not a valid email address", name)
+            }
+            EntryFieldError::InvalidPhone(name) => {
+                write!(f, "Custom field \"{}\" is not a valid phone number", name)
+            }
+            EntryFieldError::InvalidUrl(url) => {
+                write!(f, "\"{}\" is not a valid URL", url)
+            }
+            EntryFieldError::InvalidTag(tag) => {
+                write!(f, "\"{}\" is not a valid tag", tag)
+            }
+            EntryFieldError::InvalidFolder(folder) => {
+                write!(f, "\"{}\" is not a valid folder path", folder)
+            }
+        }
+    }
+}
+impl Error for EntryFieldError {}
+
+pub const MAX_TAG_LEN: usize = 64;
+pub const MAX_FOLDER_DEPTH: usize = 16;
+
+/// 앞뒤 공백을 자르고 소문자로 바꿈. 쉼표는 CLI에서 구분자로 쓰므로 넣을 수 없음
+pub fn normalize_tag(tag: &str) -> Result<String, EntryFieldError> {
+    let trimmed = tag.trim();
+    if trimmed.is_empty()
+        || trimmed.chars().count() > MAX_TAG_LEN
+        || trimmed.chars().any(|c| c == ',' || c.is_control())
+    {
+        return Err(EntryFieldError::InvalidTag(tag.to_owned()));
+    }
+    Ok(trimmed.to_lowercase())
+}
+
+/// `a/b/c` 꼴의 폴더 경로. 비어 있으면 최상위
+#[derive(
+    Archive, Serialize, Deserialize,
+    Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct FolderPath(Vec<String>);
+
+impl FolderPath {
+    pub const SEPARATOR: char = '/';
+
+    /// 각 단계의 앞뒤 공백과 빈 단계는 무시함
+    pub fn new(path: &str) -> Result<Self, EntryFieldError> {
+        let components: Vec<String> = path
+            .split(Self::SEPARATOR)
+            .map(str::trim)
+            .filter(|component| !component.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if components.len() > MAX_FOLDER_DEPTH
+            || components.iter().any(|component| component.chars().any(char::is_control))
+        {
+            return Err(EntryFieldError::InvalidFolder(path.to_owned()));
+        }
+        Ok(Self(components))
+    }
+    pub fn root() -> Self {
+        Self(Vec::new())
+    }
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn components(&self) -> &[String] {
+        &self.0
+    }
+    /// 마지막 단계의 이름. 최상위면 `None`
+    pub fn name(&self) -> Option<&str> {
+        self.0.last().map(String::as_str)
+    }
+    pub fn parent(&self) -> Option<FolderPath> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+    pub fn child(&self, name: &str) -> FolderPath {
+        let mut components = self.0.clone();
+        components.push(name.to_owned());
+        Self(components)
+    }
+    /// `other`가 이 폴더 자신이거나 그 아래에 있는지
+    pub fn contains(&self, other: &FolderPath) -> bool {
+        other.0.starts_with(&self.0)
+    }
+}
+
+impl Display for FolderPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return write!(f, "{}", Self::SEPARATOR);
+        }
+        write!(f, "{}", self.0.join("/"))
+    }
+}
+
+impl FromStr for FolderPath {
+    type Err = EntryFieldError;
+    fn from_str(s: &str) -> Result<Self, EntryFieldError> { FolderPath::new(s) }
+}
+
+#[derive(
+    Archive, Serialize, Deserialize,
+    Copy, Clone, Debug, Default, PartialEq, Eq,
+)]
+pub enum CustomFieldKind {
+    #[default]
+    Text,
+    /// 값이 항목 안에서 따로 암호화되고, 편집기에서 가려짐
+    Hidden,
+    Email,
+    Phone,
+}
+
+impl CustomFieldKind {
+    pub const ALL: [CustomFieldKind; 4] = [
+        CustomFieldKind::Text,
+        CustomFieldKind::Hidden,
+        CustomFieldKind::Email,
+        CustomFieldKind::Phone,
+    ];
+}
+impl Display for CustomFieldKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CustomFieldKind::Text => write!(f, "text"),
+            CustomFieldKind::Hidden => write!(f, "hidden"),
+            CustomFieldKind::Email => write!(f, "email"),
+            CustomFieldKind::Phone => write!(f, "phone"),
+        }
+    }
+}
+impl FromStr for CustomFieldKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        CustomFieldKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown field type \"{}\" (text, hidden, email, phone)", s))
+    }
+}
+
+/// 복호화한 사용자 정의 필드
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CustomField {
+    pub name: String,
+    pub kind: CustomFieldKind,
+    pub value: Zeroizing<String>,
+}
+
+impl CustomField {
+    fn validate(&self) -> Result<(), EntryFieldError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(EntryFieldError::EmptyFieldName);
+        }
+        let value = self.value.trim();
+        match self.kind {
+            CustomFieldKind::Email => {
+                let valid = value.split_once('@').is_some_and(|(local, domain)| {
+                    !local.is_empty()
+                        && domain.contains('.')
+                        && !domain.starts_with('.')
+                        && !domain.ends_with('.')
+                        && !domain.contains('@')
+                }) && !value.chars().any(|c| c.is_whitespace());
+                if !valid {
+                    return Err(EntryFieldError::InvalidEmail(name.to_owned()));
+                }
+            }
+            CustomFieldKind::Phone => {
+                // 국가 번호 `+`는 맨 앞에만 허용
+                let digits = value.chars().filter(|c| c.is_ascii_digit()).count();
+                let valid = digits > 0
+                    && value
+                        .trim_start_matches('+')
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '.' | '(' | ')'));
+                if !valid {
+                    return Err(EntryFieldError::InvalidPhone(name.to_owned()));
+                }
+            }
+            CustomFieldKind::Text | CustomFieldKind::Hidden => {}
+        }
+        Ok(())
+    }
+}
+
+/// 편집기에서 다루는 항목의 비밀번호 외 내용. 메모와 숨김 필드는 복호화된 상태
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryDetails {
+    /// 비어 있으면 메모 없음
+    pub notes: Zeroizing<String>,
+    pub urls: Vec<String>,
+    pub custom_fields: Vec<CustomField>,
+}
+
+impl EntryDetails {
+    fn validate(&self) -> Result<(), EntryFieldError> {
+        for url in &self.urls {
+            validate_url(url)?;
+        }
+        for (i, field) in self.custom_fields.iter().enumerate() {
+            field.validate()?;
+            let name = field.name.trim();
+            if self.custom_fields[..i].iter().any(|other| other.name.trim() == name) {
+                return Err(EntryFieldError::DuplicateFieldName(name.to_owned()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 스킴이 없으면 `https://`로 보고 검사함
+fn validate_url(url: &str) -> Result<(), EntryFieldError> {
+    let trimmed = url.trim();
+    let invalid = || EntryFieldError::InvalidUrl(trimmed.to_owned());
+    if trimmed.is_empty() || trimmed.chars().any(|c| c.is_whitespace()) {
+        return Err(invalid());
+    }
+    let with_scheme = if trimmed.contains("://") {
+        trimmed.to_owned()
+    } else {
+        format!("https://{}", trimmed)
+    };
+    let parsed = Url::parse(&with_scheme).map_err(|_| invalid())?;
+    parsed.host_str().ok_or_else(invalid)?;
+    Ok(())
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub(crate) enum StoredFieldValue {
+    Plain(String),
+    Encrypted(EncryptedField),
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub(crate) struct StoredCustomField {
+    name: String,
+    kind: CustomFieldKind,
+    value: StoredFieldValue,
+}
+
+pub(crate) const NOTES_FIELD_TAG: &str = "notes";
+pub(crate) const URL_FIELD_TAG: &str = "url";
+const PW_HISTORY_FIELD_TAG: &str = "pw-history";
+pub(crate) const OTP_FIELD_TAG: &str = "otp";
+/// 항목마다 남기는 이전 비밀번호 수. 넘치면 가장 오래된 것부터 버림
+pub const PW_HISTORY_LEN: usize = 10;
+
+/// 숨김 필드의 암호문을 필드 이름에 묶음
+fn custom_field_tag(name: &str) -> String {
+    format!("field:{}", name)
+}
+
+/// 바뀌기 전의 비밀번호와 그 비밀번호를 쓰던 기간
+#[derive(Archive, Serialize, Deserialize)]
+pub struct PastUserPW {
+    pw: EncryptedField,
+    set: Timestamp,
+    replaced: Timestamp,
+}
+
+impl PastUserPW {
+    /// 이 비밀번호로 바꾼 시각
+    pub fn set_at(&self) -> Timestamp {
+        self.set
+    }
+    /// 다른 비밀번호로 바뀐 시각
+    pub fn replaced_at(&self) -> Timestamp {
+        self.replaced
+    }
+}
+
+/// 한 계정에 저장되는 내용. 비밀번호, 메모, URL, 숨김 필드, 이전 비밀번호, OTP 비밀 키는 각각 (사이트, ID)에 묶여 암호화됨.
+/// 태그, 폴더, 즐겨찾기, 숨김이 아닌 사용자 정의 필드는 DB 파일 전체와 함께만 암호화되므로
+/// 메타데이터 암호화 모드에서도 로그인한 동안은 평문임
+#[derive(Archive, Serialize, Deserialize)]
+pub struct Entry {
+    pub(crate) pw: EncryptedUserPW,
+    pub(crate) notes: Option<EncryptedField>,
+    pub(crate) urls: Vec<EncryptedField>,
+    pub(crate) custom_fields: Vec<StoredCustomField>,
+    pub(crate) created: Timestamp,
+    pub(crate) modified: Timestamp,
+    pub(crate) last_used: Option<Timestamp>,
+    pub(crate) pw_changed: Timestamp,
+    /// 최신순
+    pub(crate) pw_history: Vec<PastUserPW>,
+    /// `otpauth://` URI
+    pub(crate) otp: Option<EncryptedField>,
+    /// 소문자, 정렬, 중복 없음
+    pub(crate) tags: Vec<String>,
+    pub(crate) folder: FolderPath,
+    pub(crate) favourite: bool,
+}
+
+impl Entry {
+    pub(crate) fn new(pw: EncryptedUserPW) -> Self {
+        let now = Timestamp::now();
+        Self {
+            pw,
+            notes: None,
+            urls: Vec::new(),
+            custom_fields: Vec::new(),
+            created: now,
+            modified: now,
+            last_used: None,
+            pw_changed: now,
+            pw_history: Vec::new(),
+            otp: None,
+            tags: Vec::new(),
+            folder: FolderPath::root(),
+            favourite: false,
+        }
+    }
+
+    pub fn created(&self) -> Timestamp {
+        self.created
+    }
+    /// 비밀번호나 다른 내용을 마지막으로 바꾼 시각
+    pub fn modified(&self) -> Timestamp {
+        self.modified
+    }
+    /// 비밀번호를 마지막으로 꺼낸 시각
+    pub fn last_used(&self) -> Option<Timestamp> {
+        self.last_used
+    }
+    pub fn has_notes(&self) -> bool {
+        self.notes.is_some()
+    }
+    pub fn url_count(&self) -> usize {
+        self.urls.len()
+    }
+    pub fn custom_field_count(&self) -> usize {
+        self.custom_fields.len()
+    }
+    /// 지금 비밀번호로 바꾼 시각
+    pub fn pw_changed(&self) -> Timestamp {
+        self.pw_changed
+    }
+    /// 최신순
+    pub fn pw_history(&self) -> &[PastUserPW] {
+        &self.pw_history
+    }
+    pub fn has_otp(&self) -> bool {
+        self.otp.is_some()
+    }
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+    /// 대소문자를 가리지 않음
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        self.tags.binary_search(&tag).is_ok()
+    }
+    pub fn folder(&self) -> &FolderPath {
+        &self.folder
+    }
+    pub fn is_favourite(&self) -> bool {
+        self.favourite
+    }
+
+    /// 지금 비밀번호를 기록의 맨 앞으로 옮기고 `new_pw`로 바꿈.
+    /// `restored`는 `new_pw`를 꺼낸 기록의 위치이고, 바꾼 뒤 그 기록은 지움
+    fn replace_pw(
+        &mut self,
+        site: &SiteName,
+        id: &UserID,
+        new_pw: UserPW,
+        restored: Option<usize>,
+        wrapped_key: &WrappedSessionKey,
+        user_key_nonce: &SessionKeyNonce,
+    ) -> Result<(), DBIOError> {
+        let old_pw = decrypt_user_pw(site, id, &self.pw, wrapped_key, user_key_nonce)?;
+        let past = encrypt_entry_field(site, id, PW_HISTORY_FIELD_TAG, old_pw.as_str(), wrapped_key, user_key_nonce)?;
+        drop(old_pw);
+        let new_pw = encrypt_user_pw(site, id, new_pw, wrapped_key, user_key_nonce)?;
+
+        let now = Timestamp::now();
+        self.pw_history.insert(0, PastUserPW {
+            pw: past,
+            set: self.pw_changed,
+            replaced: now,
+        });
+        if let Some(index) = restored {
+            self.pw_history.remove(index + 1);
+        }
+        self.pw_history.truncate(PW_HISTORY_LEN);
+
+        self.pw.zeroize();
+        self.pw = new_pw;
+        self.pw_changed = now;
+        self.modified = now;
+        Ok(())
+    }
+
+    /// 세션 키를 바꿀 때 암호화된 필드를 모두 새 키로 다시 암호화한 값을 만듦. 항목은 바꾸지 않음
+    fn rekeyed(
+        &self,
+        site: &SiteName,
+        id: &UserID,
+        wrapped_key: &WrappedSessionKey,
+        user_key_nonce: &SessionKeyNonce,
+        new_wrapped_key: &WrappedSessionKey,
+        new_user_key_nonce: &SessionKeyNonce,
+    ) -> Result<RekeyedEntry, DBIOError> {
+        let user_pw = decrypt_user_pw(site, id, &self.pw, wrapped_key, user_key_nonce)?;
+        let pw = encrypt_user_pw(site, id, user_pw, new_wrapped_key, new_user_key_nonce)?;
+
+        let notes = match &self.notes {
+            Some(notes) => {
+                let value = decrypt_entry_field(site, id, NOTES_FIELD_TAG, notes, wrapped_key, user_key_nonce)?;
+                Some(encrypt_entry_field(site, id, NOTES_FIELD_TAG, &value, new_wrapped_key, new_user_key_nonce)?)
+            }
+            None => None,
+        };
+
+        let mut urls = Vec::with_capacity(self.urls.len());
+        for url in &self.urls {
+            let value = decrypt_entry_field(site, id, URL_FIELD_TAG, url, wrapped_key, user_key_nonce)?;
+            urls.push(encrypt_entry_field(site, id, URL_FIELD_TAG, &value, new_wrapped_key, new_user_key_nonce)?);
+        }
+
+        let mut pw_history = Vec::with_capacity(self.pw_history.len());
+        for past in &self.pw_history {
+            let value = decrypt_entry_field(site, id, PW_HISTORY_FIELD_TAG, &past.pw, wrapped_key, user_key_nonce)?;
+            pw_history.push(encrypt_entry_field(site, id, PW_HISTORY_FIELD_TAG, &value, new_wrapped_key, new_user_key_nonce)?);
+        }
+
+        let otp = match &self.otp {
+            Some(otp) => {
+                let value = decrypt_entry_field(site, id, OTP_FIELD_TAG, otp, wrapped_key, user_key_nonce)?;
+                Some(encrypt_entry_field(site, id, OTP_FIELD_TAG, &value, new_wrapped_key, new_user_key_nonce)?)
+            }
+            None => None,
+        };
+
+        let mut hidden_values = Vec::new();
+        for (i, field) in self.custom_fields.iter().enumerate() {
+            if let StoredFieldValue::Encrypted(encrypted) = &field.value {
+                let tag = custom_field_tag(&field.name);
+                let value = decrypt_entry_field(site, id, &tag, encrypted, wrapped_key, user_key_nonce)?;
+                hidden_values.push((i, encrypt_entry_field(site, id, &tag, &value, new_wrapped_key, new_user_key_nonce)?));
+            }
+        }
+
+        Ok(RekeyedEntry { pw, notes, urls, pw_history, otp, hidden_values })
+    }
+}
+
+/// `Entry::rekeyed`가 새 키로 다시 암호화한 필드. 시각은 바꾸지 않음
+struct RekeyedEntry {
+    pw: EncryptedUserPW,
+    notes: Option<EncryptedField>,
+    urls: Vec<EncryptedField>,
+    pw_history: Vec<EncryptedField>,
+    otp: Option<EncryptedField>,
+    hidden_values: Vec<(usize, EncryptedField)>,
+}
+
+impl RekeyedEntry {
+    /// 실패하지 않으므로 DB 전체를 다시 암호화한 뒤에 한꺼번에 적용함
+    fn apply(self, entry: &mut Entry) {
+        entry.pw.zeroize();
+        entry.pw = self.pw;
+        entry.notes = self.notes;
+        entry.urls = self.urls;
+        entry.otp = self.otp;
+        for (i, value) in self.hidden_values {
+            entry.custom_fields[i].value = StoredFieldValue::Encrypted(value);
+        }
+        for (past, pw) in entry.pw_history.iter_mut().zip(self.pw_history) {
+            past.pw = pw;
+        }
+    }
+}
+
+
+/// 사이트 -> ID -> 항목
+pub type Entries = BTreeMap<SiteName, HashMap<UserID, Entry>>;
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// 메타데이터 암호화 모드의 계정. ID는 사이트의 해시에 묶여 암호화됨
+#[derive(Archive, Serialize, Deserialize)]
+pub struct SealedUser {
+    pub(crate) id: EncryptedUserID,
+    pub(crate) entry: Entry,
+}
+
+/// 메타데이터 암호화 모드의 사이트. 계정은 ID의 keyed hash로 찾음
+#[derive(Archive, Serialize, Deserialize)]
+pub struct SealedSite {
+    pub(crate) name: EncryptedSiteName,
+    pub(crate) users: HashMap<NameHash, SealedUser>,
+}
+
+/// 사이트 이름의 keyed hash -> 사이트
+pub type SealedEntries = HashMap<NameHash, SealedSite>;
+
+/// `DB::sites`가 돌려주는 사이트 하나. 계정은 ID 순
+pub type SiteEntries<'a> = (SiteName, Vec<(UserID, &'a Entry)>);
+
+/// 휴지통 항목의 (사이트, ID). 옮길 때의 DB 모드를 따름
+#[derive(Archive, Serialize, Deserialize)]
+pub(crate) enum TrashedNames {
+    Plain {
+        site: SiteName,
+        id: UserID,
+    },
+    Sealed {
+        site_hash: NameHash,
+        site: EncryptedSiteName,
+        id: EncryptedUserID,
+    },
+}
+
+impl TrashedNames {
+    pub(crate) fn open(&self, name_key: Option<&NameKey>) -> Result<(SiteName, UserID), DBIOError> {
+        match self {
+            TrashedNames::Plain { site, id } => Ok((site.clone(), id.clone())),
+            TrashedNames::Sealed { site_hash, site, id } => {
+                let name_key = name_key.ok_or(DBIOError::NamesLocked)?;
+                Ok((open_site_name(name_key, site_hash, site)?, open_user_id(name_key, site_hash, id)?))
+            }
+        }
+    }
+}
+
+/// 휴지통으로 옮긴 항목. 원래의 (사이트, ID)에 묶인 암호문을 그대로 가지고 있음
+#[derive(Archive, Serialize, Deserialize)]
+pub struct TrashedEntry {
+    pub(crate) names: TrashedNames,
+    pub(crate) entry: Entry,
+    pub(crate) deleted: Timestamp,
+}
+
+impl TrashedEntry {
+    pub fn entry(&self) -> &Entry {
+        &self.entry
+    }
+    pub fn deleted_at(&self) -> Timestamp {
+        self.deleted
+    }
+}
+
+/// 암호화되어 저장되는 내용 전체. 계정은 모드와 상관없이 `sites`, `all_entries`, `get_entry`로 다루고, 휴지통은 따로 다룸
+#[derive(Archive, Serialize, Deserialize)]
+pub struct DB {
+    /// 메타데이터 암호화 모드에서는 비어 있음
+    pub(crate) entries: Entries,
+    /// 메타데이터 암호화 모드면 `Some`이고 계정은 모두 여기 있음
+    pub(crate) sealed: Option<SealedEntries>,
+    /// 최신순
+    pub(crate) trash: Vec<TrashedEntry>,
+    /// 휴지통에서 이 일수보다 오래된 항목은 로그인할 때 지움. 0이면 지우지 않음
+    pub(crate) trash_retention_days: u32,
+    /// 메타데이터 암호화 모드에서 로그인한 동안만 있음. 저장하지 않음
+    #[rkyv(with = Skip)]
+    pub(crate) name_key: Option<NameKey>,
+    /// 바꿀 때마다 새로 받음. 저장하지 않음
+    #[rkyv(with = Skip)]
+    generation: Generation,
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// DB를 바꿀 때마다 새로 받는 값. 프로세스 안에서 겹치지 않으므로 다른 DB로 바꿔 끼워도 같은 값이 나오지 않음
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Generation(u64);
+
+impl Default for Generation {
+    fn default() -> Self {
+        Generation(NEXT_GENERATION.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+}
+
+impl DB {
+    pub fn new() -> Self {
+        Self {
+            entries: Entries::new(),
+            sealed: None,
+            trash: Vec::new(),
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            name_key: None,
+            generation: Generation::default(),
+        }
+    }
+    pub(crate) fn from_entries(entries: Entries) -> Self {
+        Self { entries, ..Self::new() }
+    }
+    pub fn trash(&self) -> &[TrashedEntry] {
+        &self.trash
+    }
+    pub fn trash_retention_days(&self) -> u32 {
+        self.trash_retention_days
+    }
+
+    /// 계정이나 휴지통을 바꾸는 함수는 모두 이 값을 바꿈. 같으면 그 사이에 바뀐 것이 없음
+    pub fn generation(&self) -> Generation {
+        self.generation
+    }
+    pub(crate) fn bump_generation(&mut self) {
+        self.generation = Generation::default();
+    }
+
+    pub fn is_metadata_encrypted(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    /// 메타데이터 암호화 모드면 세션 키로 이름 키를 유도해 둠. 불러온 직후와 세션 키가 바뀐 뒤에 부름
+    pub fn unlock_names(&mut self, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce) -> Result<(), DBIOError> {
+        let has_sealed_trash = self.trash.iter().any(|trashed| matches!(trashed.names, TrashedNames::Sealed { .. }));
+        if self.sealed.is_some() || has_sealed_trash {
+            self.name_key = Some(NameKey::derive(wrapped_key, user_key_nonce)?);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn name_key(&self) -> Result<&NameKey, DBIOError> {
+        self.name_key.as_ref().ok_or(DBIOError::NamesLocked)
+    }
+
+    pub fn site_count(&self) -> usize {
+        match &self.sealed {
+            Some(sealed) => sealed.len(),
+            None => self.entries.len(),
+        }
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.all_entries().count()
+    }
+
+    /// 이름 없이 항목만 훑음. 순서는 정해져 있지 않음
+    pub fn all_entries(&self) -> impl Iterator<Item = &Entry> {
+        let plain = self.entries.values().flat_map(HashMap::values);
+        let sealed = self
+            .sealed
+            .iter()
+            .flat_map(HashMap::values)
+            .flat_map(|site| site.users.values())
+            .map(|user| &user.entry);
+        plain.chain(sealed)
+    }
+
+    /// 모든 사이트를 정렬해서 돌려줌. 메타데이터 암호화 모드에서는 이름을 복호화해서 만듦
+    pub fn sites(&self) -> Result<Vec<SiteEntries<'_>>, DBIOError> {
+        self.sites_with_prefix("")
+    }
+
+    /// 사이트의 ID를 정렬해서 돌려줌
+    pub fn user_ids(&self, site_name: &SiteName) -> Result<Vec<UserID>, DBIOError> {
+        let mut ids: Vec<_> = match &self.sealed {
+            None => self.entries.get(site_name).ok_or(DBIOError::SiteNotFound)?.keys().cloned().collect(),
+            Some(sealed) => {
+                let name_key = self.name_key()?;
+                let site_hash = hash_site_name(name_key, site_name);
+                let site = sealed.get(&site_hash).ok_or(DBIOError::SiteNotFound)?;
+                site.users
+                    .values()
+                    .map(|user| open_user_id(name_key, &site_hash, &user.id))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn sites_with_prefix(&self, prefix: &str) -> Result<Vec<SiteEntries<'_>>, DBIOError> {
+        let mut sites = Vec::new();
+        match &self.sealed {
+            None => {
+                let lower = SiteName::from_unchecked("", prefix);
+                let mut upper_reg = prefix.to_string();
+                upper_reg.push(char::MAX);
+                let upper = SiteName::from_unchecked("", &upper_reg);
+
+                for (site, users) in self.entries.range(lower..upper) {
+                    sites.push((site.clone(), users.iter().map(|(id, entry)| (id.clone(), entry)).collect()));
+                }
+            }
+            Some(sealed) => {
+                let name_key = self.name_key()?;
+                let prefix = prefix.trim().to_lowercase();
+                for (site_hash, site) in sealed {
+                    // 전체 이름은 맞는 사이트만 복호화함
+                    if !open_site_reg(name_key, site_hash, &site.name)?.starts_with(&prefix) {
+                        continue;
+                    }
+                    let mut users = Vec::with_capacity(site.users.len());
+                    for user in site.users.values() {
+                        users.push((open_user_id(name_key, site_hash, &user.id)?, &user.entry));
+                    }
+                    sites.push((open_site_name(name_key, site_hash, &site.name)?, users));
+                }
+                sites.sort_by(|(a, _), (b, _)| a.cmp(b));
+            }
+        }
+        for (_, users) in sites.iter_mut() {
+            users.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        Ok(sites)
+    }
+
+    /// 휴지통 항목의 (사이트, ID). 메타데이터 암호화 모드에서 옮긴 항목은 복호화함
+    pub fn trashed_names(&self, trashed: &TrashedEntry) -> Result<(SiteName, UserID), DBIOError> {
+        trashed.names.open(self.name_key.as_ref())
+    }
+
+    /// 같은 (사이트, ID)가 이미 있으면 `UserAlreadyExists`
+    pub(crate) fn insert_entry(&mut self, site: SiteName, id: UserID, entry: Entry) -> Result<(), DBIOError> {
+        self.bump_generation();
+        match &mut self.sealed {
+            None => {
+                let users = self.entries.entry(site).or_default();
+                if users.contains_key(&id) {
+                    return Err(DBIOError::UserAlreadyExists);
+                }
+                users.insert(id, entry);
+            }
+            Some(sealed) => {
+                let name_key = self.name_key.as_ref().ok_or(DBIOError::NamesLocked)?;
+                let site_hash = hash_site_name(name_key, &site);
+                let id_hash = hash_user_id(name_key, &site_hash, &id);
+                if sealed.get(&site_hash).is_some_and(|sealed_site| sealed_site.users.contains_key(&id_hash)) {
+                    return Err(DBIOError::UserAlreadyExists);
+                }
+
+                let sealed_site = sealed.entry(site_hash).or_insert_with(|| SealedSite {
+                    name: seal_site_name(name_key, &site_hash, &site),
+                    users: HashMap::new(),
+                });
+                sealed_site.users.insert(id_hash, SealedUser {
+                    id: seal_user_id(name_key, &site_hash, &id),
+                    entry,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 항목을 빼내고 휴지통에 남길 이름을 만듦. 계정이 남지 않은 사이트는 지움
+    fn take_entry(&mut self, site_name: &SiteName, user_id: &UserID) -> Result<(TrashedNames, Entry), DBIOError> {
+        self.bump_generation();
+        match &mut self.sealed {
+            None => {
+                let users = self.entries.get_mut(site_name)
+                    .ok_or(DBIOError::SiteNotFound)?;
+
+                let (id, entry) = users.remove_entry(user_id)
+                    .ok_or(DBIOError::UserNotFound)?;
+
+                let site = if users.is_empty()
+                    && let Some((site, _)) = self.entries.remove_entry(site_name)
+                {
+                    site
+                } else {
+                    site_name.clone()
+                };
+                Ok((TrashedNames::Plain { site, id }, entry))
+            }
+            Some(sealed) => {
+                let name_key = self.name_key.as_ref().ok_or(DBIOError::NamesLocked)?;
+                let site_hash = hash_site_name(name_key, site_name);
+                let sealed_site = sealed.get_mut(&site_hash)
+                    .ok_or(DBIOError::SiteNotFound)?;
+
+                let user = sealed_site.users.remove(&hash_user_id(name_key, &site_hash, user_id))
+                    .ok_or(DBIOError::UserNotFound)?;
+
+                let site = if sealed_site.users.is_empty()
+                    && let Some(sealed_site) = sealed.remove(&site_hash)
+                {
+                    sealed_site.name
+                } else {
+                    seal_site_name(name_key, &site_hash, site_name)
+                };
+                Ok((TrashedNames::Sealed { site_hash, site, id: user.id }, user.entry))
+            }
+        }
+    }
+
+    /// 세션 키를 바꿀 때 모든 항목과 휴지통을 새 키로 다시 암호화함.
+    /// 메타데이터 암호화 모드면 이름도 새 이름 키로 다시 암호화하고 해시를 다시 계산함.
+    /// 하나라도 실패하면 DB는 그대로임
+    pub(crate) fn rekey(
+        &mut self,
+        wrapped_key: &WrappedSessionKey,
+        user_key_nonce: &SessionKeyNonce,
+        new_wrapped_key: &WrappedSessionKey,
+        new_user_key_nonce: &SessionKeyNonce,
+    ) -> Result<(), DBIOError> {
+        let new_name_key = match self.name_key {
+            Some(_) => Some(NameKey::derive(new_wrapped_key, new_user_key_nonce)?),
+            None => None,
+        };
+
+        // 먼저 모두 새 키로 암호화해 두고, 전부 성공한 뒤에 한꺼번에 바꿈
+        let mut entries = Vec::new();
+        for (site, users) in &self.entries {
+            for (id, entry) in users {
+                entries.push(entry.rekeyed(site, id, wrapped_key, user_key_nonce, new_wrapped_key, new_user_key_nonce)?);
+            }
+        }
+
+        let mut sealed_sites = Vec::new();
+        if let Some(sealed) = &self.sealed {
+            let (Some(name_key), Some(new_name_key)) = (&self.name_key, &new_name_key) else {
+                return Err(DBIOError::NamesLocked);
+            };
+            // 해시가 바뀌므로 맵은 새로 만듦
+            for (site_hash, sealed_site) in sealed {
+                let site = open_site_name(name_key, site_hash, &sealed_site.name)?;
+                let new_site_hash = hash_site_name(new_name_key, &site);
+                let mut users = Vec::with_capacity(sealed_site.users.len());
+                for user in sealed_site.users.values() {
+                    let id = open_user_id(name_key, site_hash, &user.id)?;
+                    let entry = user.entry.rekeyed(&site, &id, wrapped_key, user_key_nonce, new_wrapped_key, new_user_key_nonce)?;
+                    users.push((hash_user_id(new_name_key, &new_site_hash, &id), seal_user_id(new_name_key, &new_site_hash, &id), entry));
+                }
+                sealed_sites.push((new_site_hash, seal_site_name(new_name_key, &new_site_hash, &site), users));
+            }
+        }
+
+        // 휴지통의 항목도 되살릴 수 있도록 같이 바꿈
+        let mut trash = Vec::with_capacity(self.trash.len());
+        for trashed in &self.trash {
+            let (site, id) = trashed.names.open(self.name_key.as_ref())?;
+            let names = match trashed.names {
+                TrashedNames::Sealed { .. } => {
+                    let new_name_key = new_name_key.as_ref().ok_or(DBIOError::NamesLocked)?;
+                    let site_hash = hash_site_name(new_name_key, &site);
+                    Some(TrashedNames::Sealed {
+                        site_hash,
+                        site: seal_site_name(new_name_key, &site_hash, &site),
+                        id: seal_user_id(new_name_key, &site_hash, &id),
+                    })
+                }
+                TrashedNames::Plain { .. } => None,
+            };
+            trash.push((names, trashed.entry.rekeyed(&site, &id, wrapped_key, user_key_nonce, new_wrapped_key, new_user_key_nonce)?));
+        }
+
+        // 그 사이에 바꾸지 않은 맵은 같은 순서로 돎
+        for (entry, rekeyed) in self.entries.values_mut().flat_map(HashMap::values_mut).zip(entries) {
+            rekeyed.apply(entry);
+        }
+        if let Some(sealed) = &mut self.sealed {
+            *sealed = std::mem::take(sealed)
+                .into_values()
+                .zip(sealed_sites)
+                .map(|(old_site, (site_hash, name, users))| {
+                    let users = old_site
+                        .users
+                        .into_values()
+                        .zip(users)
+                        .map(|(mut user, (id_hash, id, rekeyed))| {
+                            rekeyed.apply(&mut user.entry);
+                            (id_hash, SealedUser { id, entry: user.entry })
+                        })
+                        .collect();
+                    (site_hash, SealedSite { name, users })
+                })
+                .collect();
+        }
+        for (trashed, (names, rekeyed)) in self.trash.iter_mut().zip(trash) {
+            rekeyed.apply(&mut trashed.entry);
+            if let Some(names) = names {
+                trashed.names = names;
+            }
+        }
+
+        self.name_key = new_name_key;
+        self.bump_generation();
+        Ok(())
+    }
+}
+
+impl Default for DB {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Debug)]
 pub enum DBIOError {
@@ -256,6 +1234,13 @@ pub enum DBIOError {
     UserAlreadyExists,
 
     InvalidSession,
+    EntryTampered,
+
+    InvalidEntryField(EntryFieldError),
+    PastPWNotFound,
+    TrashedEntryNotFound,
+    /// 메타데이터 암호화 모드인데 `DB::unlock_names`를 부르지 않음
+    NamesLocked,
 }
 
 impl Display for DBIOError {
@@ -273,92 +1258,281 @@ impl Display for DBIOError {
             DBIOError::InvalidSession => {
                 write!(f, "Invalid session")
             }
+            DBIOError::EntryTampered => {
+                write!(f, "Entry has been tampered with or moved to another site/user")
+            }
+            DBIOError::InvalidEntryField(err) => {
+                write!(f, "{}", err)
+            }
+            DBIOError::PastPWNotFound => {
+                write!(f, "No such password in the history")
+            }
+            DBIOError::TrashedEntryNotFound => {
+                write!(f, "No such entry in the trash")
+            }
+            DBIOError::NamesLocked => {
+                write!(f, "Site names and user IDs are encrypted and have not been unlocked")
+            }
         }
     }
 }
 
 impl Error for DBIOError {}
 
+impl From<EntryFieldError> for DBIOError {
+    fn from(value: EntryFieldError) -> Self {
+        DBIOError::InvalidEntryField(value)
+    }
+}
+
 
 #[inline(always)]
 pub fn add_user_pw(db: &mut DB, site_name: SiteName, user_id: UserID, user_pw: UserPW, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
                    -> Result<(), DBIOError> {
     let encrypted_pw = encrypt_user_pw(&site_name, &user_id, user_pw, wrapped_key, user_key_nonce)?;
 
-    let users = db.entry(site_name)
-        .or_insert_with(HashMap::new);
-
-    if users.get(&user_id).is_none() {
-        users.insert(user_id, encrypted_pw);
-        Ok(())
-    } else {
-        Err(DBIOError::UserAlreadyExists)
-    }
+    db.insert_entry(site_name, user_id, Entry::new(encrypted_pw))
 }
 
 #[inline(always)]
 pub fn change_user_pw(db: &mut DB, site_name: &SiteName, user_id: &UserID, new_pw: UserPW, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
                       -> Result<(), DBIOError> {
-    let users = db.get_mut(site_name)
-        .ok_or(DBIOError::SiteNotFound)?;
-    let password = users.get_mut(user_id)
-        .ok_or(DBIOError::UserNotFound)?;
-    password.zeroize();
-    *password = encrypt_user_pw(site_name, user_id, new_pw, wrapped_key, user_key_nonce)?;
+    let entry = get_entry_mut(db, site_name, user_id)?;
+    entry.replace_pw(site_name, user_id, new_pw, None, wrapped_key, user_key_nonce)?;
+    db.bump_generation();
     Ok(())
 }
 
-#[inline(always)]
-pub fn remove_user_pw(db: &mut DB, site_name: &SiteName, user_id: &UserID) -> Result<(), DBIOError> {
-    let users = db.get_mut(site_name)
-        .ok_or(DBIOError::SiteNotFound)?;
+/// `index`는 `Entry::pw_history`에서의 위치
+pub fn get_past_user_pw(db: &DB, site_name: &SiteName, user_id: &UserID, index: usize, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                        -> Result<UserPW, DBIOError> {
+    let entry = get_entry(db, site_name, user_id)?;
+    let past = entry.pw_history.get(index)
+        .ok_or(DBIOError::PastPWNotFound)?;
 
-    users.remove(user_id)
-        .ok_or(DBIOError::UserNotFound)?;
+    let pw = decrypt_entry_field(site_name, user_id, PW_HISTORY_FIELD_TAG, &past.pw, wrapped_key, user_key_nonce)?;
 
-    if users.is_empty() {
-        db.remove(site_name);
-    }
+    Ok( UserPW::from_unchecked(pw.as_str().to_owned()) )
+}
+
+/// 기록의 비밀번호를 다시 지금 비밀번호로 만듦. 바뀌는 지금 비밀번호는 기록에 남음
+pub fn restore_user_pw(db: &mut DB, site_name: &SiteName, user_id: &UserID, index: usize, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                       -> Result<(), DBIOError> {
+    let restored = get_past_user_pw(db, site_name, user_id, index, wrapped_key, user_key_nonce)?;
+    let entry = get_entry_mut(db, site_name, user_id)?;
+    entry.replace_pw(site_name, user_id, restored, Some(index), wrapped_key, user_key_nonce)?;
+    db.bump_generation();
+    Ok(())
+}
+
+#[inline(always)]
+/// 항목을 휴지통으로 옮김. 완전히 지우려면 `trash::purge_trashed`
+pub fn remove_user_pw(db: &mut DB, site_name: &SiteName, user_id: &UserID) -> Result<(), DBIOError> {
+    let (names, entry) = db.take_entry(site_name, user_id)?;
 
+    db.trash.insert(0, TrashedEntry {
+        names,
+        entry,
+        deleted: Timestamp::now(),
+    });
     Ok(())
 }
 
+/// 꺼낸 시각을 항목의 마지막 사용 시각으로 남김. 읽은 것이므로 `DB::generation`은 그대로 둠
 #[inline(always)]
-pub fn get_user_pw(db: &DB, site_name: &SiteName, user_id: &UserID, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+pub fn get_user_pw(db: &mut DB, site_name: &SiteName, user_id: &UserID, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
                    -> Result<UserPW, DBIOError> {
-    let users = db.get(site_name)
-        .ok_or(DBIOError::SiteNotFound)?;
+    let entry = get_entry_mut(db, site_name, user_id)?;
 
-    let encrypted_pw = users.get(user_id)
-        .ok_or(DBIOError::UserNotFound)?;
+    let pw = decrypt_user_pw(&site_name, &user_id, &entry.pw, &wrapped_key, &user_key_nonce)?;
+    entry.last_used = Some(Timestamp::now());
 
-    let pw = decrypt_user_pw(&site_name, &user_id, encrypted_pw, &wrapped_key, &user_key_nonce)?;
-    
     Ok( pw )
 }
 
 #[inline(always)]
-pub fn prefix_range<'a>(db: &'a DB, prefix: &str, )
-    -> impl Iterator<Item = (&'a SiteName, &'a HashMap<UserID, EncryptedUserPW>)> {
-    let lower = SiteName::from_unchecked("", prefix);
-    let mut upper_reg = prefix.to_string();
-    upper_reg.push(char::MAX);
-    let upper = SiteName::from_unchecked("", &upper_reg);
+pub fn get_entry<'a>(db: &'a DB, site_name: &SiteName, user_id: &UserID) -> Result<&'a Entry, DBIOError> {
+    let Some(sealed) = &db.sealed else {
+        return db.entries.get(site_name)
+            .ok_or(DBIOError::SiteNotFound)?
+            .get(user_id)
+            .ok_or(DBIOError::UserNotFound);
+    };
+
+    let name_key = db.name_key()?;
+    let site_hash = hash_site_name(name_key, site_name);
+    sealed.get(&site_hash)
+        .ok_or(DBIOError::SiteNotFound)?
+        .users
+        .get(&hash_user_id(name_key, &site_hash, user_id))
+        .map(|user| &user.entry)
+        .ok_or(DBIOError::UserNotFound)
+}
+
+/// 항목을 바꾼 쪽에서 `DB::bump_generation`을 부름
+#[inline(always)]
+pub(crate) fn get_entry_mut<'a>(db: &'a mut DB, site_name: &SiteName, user_id: &UserID) -> Result<&'a mut Entry, DBIOError> {
+    let Some(sealed) = &mut db.sealed else {
+        return db.entries.get_mut(site_name)
+            .ok_or(DBIOError::SiteNotFound)?
+            .get_mut(user_id)
+            .ok_or(DBIOError::UserNotFound);
+    };
+
+    let name_key = db.name_key.as_ref().ok_or(DBIOError::NamesLocked)?;
+    let site_hash = hash_site_name(name_key, site_name);
+    sealed.get_mut(&site_hash)
+        .ok_or(DBIOError::SiteNotFound)?
+        .users
+        .get_mut(&hash_user_id(name_key, &site_hash, user_id))
+        .map(|user| &mut user.entry)
+        .ok_or(DBIOError::UserNotFound)
+}
+
+/// 메모, URL, 숨김 필드를 복호화해서 편집기용 내용을 만듦
+pub fn read_entry_details(db: &DB, site_name: &SiteName, user_id: &UserID, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                          -> Result<EntryDetails, DBIOError> {
+    let entry = get_entry(db, site_name, user_id)?;
+
+    let notes = match &entry.notes {
+        Some(notes) => decrypt_entry_field(site_name, user_id, NOTES_FIELD_TAG, notes, wrapped_key, user_key_nonce)?,
+        None => Zeroizing::new(String::new()),
+    };
+
+    let mut urls = Vec::with_capacity(entry.urls.len());
+    for url in &entry.urls {
+        let url = decrypt_entry_field(site_name, user_id, URL_FIELD_TAG, url, wrapped_key, user_key_nonce)?;
+        urls.push(url.as_str().to_owned());
+    }
+
+    let mut custom_fields = Vec::with_capacity(entry.custom_fields.len());
+    for field in &entry.custom_fields {
+        let value = match &field.value {
+            StoredFieldValue::Plain(value) => Zeroizing::new(value.clone()),
+            StoredFieldValue::Encrypted(encrypted) => decrypt_entry_field(
+                site_name,
+                user_id,
+                &custom_field_tag(&field.name),
+                encrypted,
+                wrapped_key,
+                user_key_nonce,
+            )?,
+        };
+        custom_fields.push(CustomField {
+            name: field.name.clone(),
+            kind: field.kind,
+            value,
+        });
+    }
+
+    Ok(EntryDetails {
+        notes,
+        urls,
+        custom_fields,
+    })
+}
+
+/// 항목의 메모, URL, 사용자 정의 필드를 `details`로 교체함. 비밀번호는 그대로 둠
+pub fn update_entry_details(db: &mut DB, site_name: &SiteName, user_id: &UserID, details: &EntryDetails, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                            -> Result<(), DBIOError> {
+    details.validate()?;
+    let entry = get_entry_mut(db, site_name, user_id)?;
+
+    let notes = details.notes.trim();
+    let notes = if notes.is_empty() {
+        None
+    } else {
+        Some(encrypt_entry_field(site_name, user_id, NOTES_FIELD_TAG, notes, wrapped_key, user_key_nonce)?)
+    };
+
+    let mut urls = Vec::with_capacity(details.urls.len());
+    for url in &details.urls {
+        urls.push(encrypt_entry_field(site_name, user_id, URL_FIELD_TAG, url.trim(), wrapped_key, user_key_nonce)?);
+    }
 
-    db.range(lower..upper)
+    let mut custom_fields = Vec::with_capacity(details.custom_fields.len());
+    for field in &details.custom_fields {
+        let name = field.name.trim().to_owned();
+        let value = field.value.trim();
+        let value = match field.kind {
+            CustomFieldKind::Hidden => StoredFieldValue::Encrypted(encrypt_entry_field(
+                site_name,
+                user_id,
+                &custom_field_tag(&name),
+                value,
+                wrapped_key,
+                user_key_nonce,
+            )?),
+            _ => StoredFieldValue::Plain(value.to_owned()),
+        };
+        custom_fields.push(StoredCustomField {
+            name,
+            kind: field.kind,
+            value,
+        });
+    }
+
+    entry.notes = notes;
+    entry.urls = urls;
+    entry.custom_fields = custom_fields;
+    entry.modified = Timestamp::now();
+    db.bump_generation();
+    Ok(())
+}
+
+/// 태그를 `tags`로 교체함. 정규화한 뒤 정렬하고 중복을 없앰
+pub fn set_tags<S: AsRef<str>>(db: &mut DB, site_name: &SiteName, user_id: &UserID, tags: &[S]) -> Result<(), DBIOError> {
+    let mut normalized = Vec::with_capacity(tags.len());
+    for tag in tags {
+        normalized.push(normalize_tag(tag.as_ref())?);
+    }
+    normalized.sort();
+    normalized.dedup();
+
+    let entry = get_entry_mut(db, site_name, user_id)?;
+    if entry.tags != normalized {
+        entry.tags = normalized;
+        entry.modified = Timestamp::now();
+        db.bump_generation();
+    }
+    Ok(())
+}
+
+pub fn set_folder(db: &mut DB, site_name: &SiteName, user_id: &UserID, folder: FolderPath) -> Result<(), DBIOError> {
+    let entry = get_entry_mut(db, site_name, user_id)?;
+    if entry.folder != folder {
+        entry.folder = folder;
+        entry.modified = Timestamp::now();
+        db.bump_generation();
+    }
+    Ok(())
+}
+
+/// 즐겨찾기는 내용이 아니라서 수정 시각을 바꾸지 않음
+pub fn set_favourite(db: &mut DB, site_name: &SiteName, user_id: &UserID, favourite: bool) -> Result<(), DBIOError> {
+    get_entry_mut(db, site_name, user_id)?.favourite = favourite;
+    db.bump_generation();
+    Ok(())
+}
+
+/// 등록 가능한 도메인이 `prefix`로 시작하는 사이트들. 정렬은 `DB::sites`와 같음
+#[inline(always)]
+pub fn prefix_range<'a>(db: &'a DB, prefix: &str) -> Result<Vec<SiteEntries<'a>>, DBIOError> {
+    db.sites_with_prefix(prefix)
 }
 
 pub fn explor_db(db: &mut DB, input_site: String, wrapped_key: &WrappedSessionKey, user_key_nonce: SessionKeyNonce) {
-    let range =  prefix_range(db, &*input_site);
+    let Ok(range) = prefix_range(db, &*input_site) else {
+        return;
+    };
     for (site, credentials) in range {
         println!("Site: {}\n", site.as_str());
-        for cred in credentials {
+        for (id, entry) in credentials {
             println!(
                 "  user_id: {:?}\n  password: {:?}\n",
-                &cred.0,
-                get_user_pw(db, &site, &cred.0, &wrapped_key, &user_key_nonce).ok()
+                &id,
+                decrypt_user_pw(&site, &id, &entry.pw, &wrapped_key, &user_key_nonce).ok()
             );
         }
     }
 }
-
@@ -1,22 +1,26 @@
-use crate::header::{DBHeader, HEADER_LEN};
+use crate::backup::{prune_snapshots, write_snapshot};
+use crate::header::{DBHeader, FormatVersion, HEADER_LEN, MIN_HEADER_LEN};
 use crate::master_secrets::EncryptedDB;
+use crate::vault_path::VaultPath;
 use fs2::FileExt;
 use sha2::{Digest, Sha512};
-use std::fs::{self, File, OpenOptions, remove_file};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
     error::Error,
     fmt::{Display, Formatter},
 };
 
-const DB_FILE: &str = "db.bin";
-const DB_BAK_FILE: &str = "db.bin.bak";
-
 #[derive(Debug)]
 pub enum FileIOWarn {
     RevertedForUngracefulExited,
-    ResetDBForCorruptedFile,
+    QuarantinedDBExists(Vec<PathBuf>),
+    LegacyFormatWillBeMigrated(FormatVersion),
+    // DB 저장은 성공했지만 백업 스냅샷 갱신이 실패함
+    SnapshotFailed(FileIOError),
 }
 impl Display for FileIOWarn {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -24,15 +28,31 @@ impl Display for FileIOWarn {
             FileIOWarn::RevertedForUngracefulExited => {
                 write!(
                     f,
-                    "This app is ungracefully exited. reverted the database to the last backup"
+                    "This app is ungracefully exited. unsaved changes are lost and the last saved database is loaded"
                 )
             }
-            FileIOWarn::ResetDBForCorruptedFile => {
+            FileIOWarn::QuarantinedDBExists(paths) => {
                 write!(
                     f,
-                    "Reset the database, because the database file is corrupted."
+                    "A corrupted database is kept at {}. a new database will be created",
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
                 )
             }
+            FileIOWarn::LegacyFormatWillBeMigrated(version) => {
+                write!(
+                    f,
+                    "The database uses an old format ({}). it will be upgraded to {} after login, and a copy of the old file is kept",
+                    version,
+                    FormatVersion::CURRENT
+                )
+            }
+            FileIOWarn::SnapshotFailed(cause) => {
+                write!(f, "DB was saved, but updating the backup snapshots failed: {}", cause)
+            }
         }
     }
 }
@@ -41,7 +61,7 @@ impl Display for FileIOWarn {
 pub enum FileIOError {
     // Lock 관련
     LockUnavailable(io::Error),
-    LockWouldBlock(io::Error), // 다른 프로세스가 락 보유
+    VaultInUse(Option<u32>), // 다른 프로세스가 락 보유. 알 수 있으면 그 PID
 
     // 파일 열기/읽기/쓰기/동기화
     FileOpenFailed(io::Error),
@@ -53,16 +73,24 @@ pub enum FileIOError {
 
     // 헤더/포맷 관련
     InvalidHeader,
-    DBVersionMissMatch,
+    DBVersionMissMatch(FormatVersion),
 
     // 무결성(재시도 이후에도 복원 불가)
     PersistentIntegrityFailure,
+    TruncatedFile,
+    CiphertextLenMissMatch,
+    ChecksumMissMatch,
+
+    // 손상된 파일은 지우지 않고 격리함
+    Quarantined(PathBuf, Box<FileIOError>),
+    DBAlreadyExists,
 }
 impl Display for FileIOError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use FileIOError::*;
         match self {
-            LockWouldBlock(e) => write!(f, "File is locked by another process: {}", e),
+            VaultInUse(Some(pid)) => write!(f, "Vault is in use by PID {}", pid),
+            VaultInUse(None) => write!(f, "Vault is in use by another process"),
             LockUnavailable(e) => write!(f, "Failed to getting file lock: {}", e),
             FileOpenFailed(e) => write!(f, "Failed to open file: {}", e),
             FileReadFailed(e) => write!(f, "Failed to read file: {}", e),
@@ -72,122 +100,315 @@ impl Display for FileIOError {
             FileDeleteFailed(e) => write!(f, "Failed to delete file: {}", e),
             PersistentIntegrityFailure => write!(f, "Failed to write valid DB after retries"),
             InvalidHeader => write!(f, "Invalid DB header"),
-            DBVersionMissMatch => write!(f, "Unsupported DB version"),
+            DBVersionMissMatch(version) => write!(
+                f,
+                "Unsupported DB version {} (this app supports up to {})",
+                version,
+                FormatVersion::CURRENT
+            ),
+            TruncatedFile => write!(f, "DB file is truncated"),
+            CiphertextLenMissMatch => write!(f, "DB file length does not match its header"),
+            ChecksumMissMatch => write!(f, "DB file checksum does not match its header"),
+            Quarantined(path, cause) => write!(
+                f,
+                "{}. the file was moved to {} and left untouched",
+                cause,
+                path.display()
+            ),
+            DBAlreadyExists => write!(f, "DB file already exists"),
         }
     }
 }
 impl Error for FileIOError {}
 
-pub fn load_db() -> Result<(Option<FileIOWarn>, DBHeader, Option<EncryptedDB>), FileIOError> {
-    let bak_path = Path::new(DB_BAK_FILE);
-    let db_path = Path::new(DB_FILE);
+impl FileIOError {
+    /// 파일 내용이 깨졌음을 뜻하는 에러. 이 경우 파일을 격리함
+    fn is_corruption(&self) -> bool {
+        use FileIOError::*;
+        matches!(
+            self,
+            InvalidHeader | TruncatedFile | CiphertextLenMissMatch | ChecksumMissMatch
+        )
+    }
+}
+
+/// 세션 동안 볼트의 잠금 파일을 열고 advisory lock을 잡고 있는 핸들.
+/// 다른 프로세스가 같은 볼트를 열려고 하면 `FileIOError::VaultInUse`를 받음.
+/// drop되면 잠금이 풀림
+#[derive(Debug)]
+pub struct VaultHandle {
+    path: VaultPath,
+    lock_file: File,
+}
+
+impl VaultHandle {
+    pub fn open(path: VaultPath) -> Result<Self, FileIOError> {
+        create_vault_dir(&path)?;
+        let mut lock_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.lock_file())
+            .map_err(FileIOError::FileOpenFailed)?;
+
+        if let Err(err) = lock_file.try_lock_exclusive() {
+            if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                return Err(FileIOError::VaultInUse(read_lock_owner(&lock_file)));
+            }
+            return Err(FileIOError::LockUnavailable(err));
+        }
+
+        // 잠금을 잡은 뒤에 PID를 적으므로 다른 프로세스는 항상 현재 소유자의 PID를 읽음
+        lock_file.set_len(0).map_err(FileIOError::FileWriteFailed)?;
+        lock_file
+            .write_all(std::process::id().to_string().as_bytes())
+            .map_err(FileIOError::FileWriteFailed)?;
+        lock_file.sync_all().map_err(FileIOError::FileSyncFailed)?;
+
+        Ok(Self { path, lock_file })
+    }
+
+    pub fn path(&self) -> &VaultPath {
+        &self.path
+    }
+}
+
+/// 경로만 필요한 곳에는 핸들을 그대로 넘길 수 있게 함
+impl Deref for VaultHandle {
+    type Target = VaultPath;
+
+    fn deref(&self) -> &VaultPath {
+        &self.path
+    }
+}
+
+impl Drop for VaultHandle {
+    fn drop(&mut self) {
+        let _ = self.lock_file.set_len(0);
+        let _ = self.lock_file.unlock();
+    }
+}
+
+/// Windows는 잠긴 범위를 다른 핸들로 읽을 수 없으므로 `None`이 될 수 있음
+fn read_lock_owner(lock_file: &File) -> Option<u32> {
+    let data = read_whole_file(lock_file).ok()?;
+    std::str::from_utf8(&data).ok()?.trim().parse().ok()
+}
+
+/// 손상된 DB 파일은 절대 덮어쓰지 않음. 타임스탬프가 붙은 파일로 옮기고
+/// `FileIOError::Quarantined`를 반환하므로, 호출자가 복구 방법을 골라야 함
+pub fn load_db(
+    vault: &VaultHandle,
+) -> Result<(Option<FileIOWarn>, DBHeader, Option<EncryptedDB>), FileIOError> {
+    let bak_path = vault.bak_file();
+    let dirty_marker_path = vault.dirty_marker_file();
+    let temp_path = vault.temp_file();
+    let db_path = vault.db_file();
 
     let mut user_warn: Option<FileIOWarn> = None;
 
-    let db_exist = fs::exists(db_path).map_err(FileIOError::FileOpenFailed)?;
-    let bak_exist = fs::exists(bak_path).map_err(FileIOError::FileOpenFailed)?;
+    // rename 전에 멈춘 저장. DB 파일은 건드리지 않았으므로 그냥 지움
+    if fs::exists(&temp_path).map_err(FileIOError::FileOpenFailed)? {
+        fs::remove_file(&temp_path).map_err(FileIOError::FileDeleteFailed)?;
+    }
+
+    if fs::exists(&dirty_marker_path).map_err(FileIOError::FileOpenFailed)? {
+        user_warn = Some(FileIOWarn::RevertedForUngracefulExited);
+        fs::remove_file(&dirty_marker_path).map_err(FileIOError::FileDeleteFailed)?;
+    }
 
-    if bak_exist {
+    // 0.1.x가 세션 도중 종료되면 마지막으로 저장한 DB가 `.bak`에 남아 있음
+    if fs::exists(&bak_path).map_err(FileIOError::FileOpenFailed)? {
         user_warn = Some(FileIOWarn::RevertedForUngracefulExited);
 
-        if db_exist {
+        if fs::exists(db_path).map_err(FileIOError::FileOpenFailed)? {
             fs::remove_file(db_path).map_err(FileIOError::FileDeleteFailed)?;
         }
-        fs::rename(bak_path, db_path).map_err(FileIOError::FileRenameFailed)?;
-    } else if !db_exist {
-        return Ok((None, DBHeader::empty_valid(), None));
+        fs::rename(&bak_path, db_path).map_err(FileIOError::FileRenameFailed)?;
+    }
+
+    if !fs::exists(db_path).map_err(FileIOError::FileOpenFailed)? {
+        let quarantined = list_quarantined_dbs(vault)?;
+        if !quarantined.is_empty() {
+            user_warn = Some(FileIOWarn::QuarantinedDBExists(quarantined));
+        }
+        return Ok((user_warn, DBHeader::empty_valid(), None));
+    }
+
+    let db_file = File::open(db_path).map_err(|err| FileIOError::FileOpenFailed(err))?;
+
+    // 저장 전에 비정상 종료된 첫 세션이 남긴 빈 파일
+    let file_len = db_file
+        .metadata()
+        .map_err(|err| FileIOError::FileReadFailed(err))?
+        .len();
+    if file_len == 0 {
+        return Ok((user_warn, DBHeader::empty_valid(), None));
     }
 
-    let db_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(db_path)
-        .map_err(|err| FileIOError::FileOpenFailed(err))?;
-    // match db_file.try_lock_exclusive() {
-    //     Ok(()) => {}
-    //     Err(err) if err.kind() == io::ErrorKind::WouldBlock => { return Err(FileIOError::LockWouldBlock(err)) },
-    //     Err(err) => { return Err(FileIOError::LockUnavailable(err)) }
-    // }
-    let _ = db_file.unlock();
-    if let Err(err) = db_file.try_lock_exclusive() {
-        return Err(FileIOError::LockUnavailable(err));
+    let (header, ciphertext) = match read_verified_db(&db_file) {
+        Ok(v) => v,
+        Err(err) if err.is_corruption() => {
+            drop(db_file);
+            let quarantine_path = quarantine_db(vault)?;
+            return Err(FileIOError::Quarantined(quarantine_path, Box::new(err)));
+        }
+        Err(err) => return Err(err),
     };
 
+    // 로그인하면 파일이 바뀌므로 다른 경고보다 먼저 알림
+    if !header.is_current_version() {
+        user_warn = Some(FileIOWarn::LegacyFormatWillBeMigrated(header.version()?));
+    }
+
+    Ok((user_warn, header, Some(ciphertext)))
+}
+
+/// 헤더, 길이, 체크섬이 모두 맞을 때까지 최대 세 번 읽음
+pub(crate) fn read_verified_db(db_file: &File) -> Result<(DBHeader, EncryptedDB), FileIOError> {
     let read_trials = 3;
+    let mut last_err = FileIOError::ChecksumMissMatch;
     for _ in 0..read_trials {
-        let mut data = Vec::new();
-        (&db_file)
-            .seek(SeekFrom::Start(0))
-            .map_err(|e| FileIOError::FileReadFailed(e))?;
-        (&db_file)
-            .take(u64::MAX)
-            .read_to_end(&mut data)
-            .map_err(|err| FileIOError::FileReadFailed(err))?;
-        (&db_file)
-            .seek(SeekFrom::Start(0))
-            .map_err(|e| FileIOError::FileReadFailed(e))?;
-        let (header, ciphertext) = match DBHeader::parse_header(data.as_slice()) {
-            Ok(v) => v,
-            Err(FileIOError::InvalidHeader) => {
-                return Ok((
-                    Some(FileIOWarn::ResetDBForCorruptedFile),
-                    DBHeader::empty_valid(),
-                    None,
-                ));
-            }
-            Err(err) => return Err(err),
-        };
-        if header.ciphertext_len != ciphertext.len() {
-            return Ok((
-                Some(FileIOWarn::ResetDBForCorruptedFile),
-                DBHeader::empty_valid(),
-                None,
-            ));
+        let data = read_whole_file(db_file)?;
+        if data.len() < MIN_HEADER_LEN {
+            return Err(FileIOError::TruncatedFile);
+        }
+        let (header, ciphertext) = DBHeader::parse_header(data.as_slice())?;
+        if ciphertext.len() < header.ciphertext_len {
+            last_err = FileIOError::TruncatedFile;
+            continue;
+        }
+        if ciphertext.len() > header.ciphertext_len {
+            last_err = FileIOError::CiphertextLenMissMatch;
+            continue;
         }
         let hash = Sha512::digest(ciphertext.as_slice());
         if header.ciphertext_checksum.as_slice() != hash.as_slice() {
+            last_err = FileIOError::ChecksumMissMatch;
             continue;
         }
 
-        return Ok((user_warn, header, Some(ciphertext)));
+        return Ok((header, ciphertext));
     }
 
-    Ok((
-        Some(FileIOWarn::ResetDBForCorruptedFile),
-        DBHeader::empty_valid(),
-        None,
-    ))
+    Err(last_err)
 }
 
-pub fn save_db(header: &mut DBHeader, ciphertext: EncryptedDB) -> Result<(), FileIOError> {
-    let db_path = Path::new(DB_FILE);
-    let bak_path = Path::new(DB_BAK_FILE);
+fn read_whole_file(mut file: &File) -> Result<Vec<u8>, FileIOError> {
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| FileIOError::FileReadFailed(e))?;
+    file.take(u64::MAX)
+        .read_to_end(&mut data)
+        .map_err(|err| FileIOError::FileReadFailed(err))?;
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| FileIOError::FileReadFailed(e))?;
+    Ok(data)
+}
 
-    let db_exists = fs::exists(db_path).map_err(FileIOError::FileReadFailed)?;
-    let bak_exists = fs::exists(bak_path).map_err(FileIOError::FileReadFailed)?;
+/// 현재 DB 파일을 `<db file>.corrupted-<unix time>`으로 옮김
+pub fn quarantine_db(vault: &VaultHandle) -> Result<PathBuf, FileIOError> {
+    let db_path = vault.db_file();
+    let prefix = vault.quarantine_prefix();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut quarantine_path = prefix.clone();
+    quarantine_path.push(timestamp.to_string());
+    let mut quarantine_path = PathBuf::from(quarantine_path);
+    let mut suffix = 1;
+    while fs::exists(&quarantine_path).map_err(FileIOError::FileReadFailed)? {
+        let mut path = prefix.clone();
+        path.push(format!("{}-{}", timestamp, suffix));
+        quarantine_path = PathBuf::from(path);
+        suffix += 1;
+    }
+
+    fs::rename(db_path, &quarantine_path).map_err(FileIOError::FileRenameFailed)?;
+    Ok(quarantine_path)
+}
 
-    if db_exists {
-        if bak_exists {
-            remove_file(db_path).map_err(FileIOError::FileDeleteFailed)?;
-        } else {
-            fs::rename(db_path, bak_path).map_err(FileIOError::FileRenameFailed)?;
+/// 격리된 DB 파일 목록. 오래된 것부터 정렬됨
+pub fn list_quarantined_dbs(vault: &VaultPath) -> Result<Vec<PathBuf>, FileIOError> {
+    let prefix = PathBuf::from(vault.quarantine_prefix());
+    let Some(prefix_name) = prefix.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut quarantined = Vec::new();
+    let entries = match fs::read_dir(vault.dir()) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(quarantined),
+        Err(err) => return Err(FileIOError::FileReadFailed(err)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(FileIOError::FileReadFailed)?;
+        if entry.file_name().to_string_lossy().starts_with(&prefix_name) {
+            quarantined.push(entry.path());
         }
     }
+    quarantined.sort();
+    Ok(quarantined)
+}
 
-    let mut db_file = OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(db_path)
-        .map_err(|err| FileIOError::FileOpenFailed(err))?;
-
-    // if db_file.set_len(db_file.metadata().map_err(|err| FileIOError::FileReadFailed(err))?
-    //         .len()).map_err(|err| FileIOError::FileWriteFailed(err)).is_err() {
-    let _ = db_file.unlock();
-    if let Err(err) = db_file.try_lock_exclusive() {
-        return Err(FileIOError::LockUnavailable(err));
-    };
+/// 격리된 파일이나 백업을 읽기 전용으로 엶. 길이와 체크섬이 틀려도 헤더만 읽을 수 있으면
+/// 내용을 돌려주고, 실제 무결성은 `decrypt_db`의 AEAD 검증에 맡김
+pub fn open_db_read_only(path: &Path) -> Result<(DBHeader, EncryptedDB), FileIOError> {
+    let db_file = File::open(path).map_err(FileIOError::FileOpenFailed)?;
+    let data = read_whole_file(&db_file)?;
+    let (header, mut ciphertext) = DBHeader::parse_header(data.as_slice())?;
+    ciphertext.truncate(header.ciphertext_len);
+    Ok((header, ciphertext))
+}
+
+/// 검증을 통과한 파일만 DB 파일 자리로 복사함. 원본은 그대로 둠.
+/// 격리된 파일에 대해 호출하면 "다시 읽기"가 됨
+pub fn restore_db_from(vault: &VaultHandle, path: &Path) -> Result<(), FileIOError> {
+    let db_path = vault.db_file();
+    create_vault_dir(vault)?;
+    if fs::exists(db_path).map_err(FileIOError::FileReadFailed)? {
+        return Err(FileIOError::DBAlreadyExists);
+    }
+
+    let source = File::open(path).map_err(FileIOError::FileOpenFailed)?;
+    read_verified_db(&source)?;
+    let bytes = read_whole_file(&source)?;
+    drop(source);
+
+    write_db_file_atomically(vault, &bytes)
+}
+
+/// 구버전 DB 파일을 마이그레이션한 결과로 덮어쓰기 전에 원본을 `<db file>.pre-migration-<version>`으로 복사함.
+/// 이미 있으면 처음 남긴 사본을 그대로 둠
+pub fn backup_before_migration(
+    vault: &VaultHandle,
+    header: &DBHeader,
+) -> Result<PathBuf, FileIOError> {
+    let backup_path = vault.pre_migration_file(header.version()?);
+    if fs::exists(&backup_path).map_err(FileIOError::FileReadFailed)? {
+        return Ok(backup_path);
+    }
+
+    fs::copy(vault.db_file(), &backup_path).map_err(FileIOError::FileWriteFailed)?;
+    File::open(&backup_path)
+        .and_then(|file| file.sync_all())
+        .map_err(FileIOError::FileSyncFailed)?;
+    sync_dir(vault.dir())?;
+    Ok(backup_path)
+}
+
+/// 새 내용은 임시 파일에 쓰고 rename으로 교체하므로, 어느 시점에 멈춰도
+/// DB 파일은 이전 저장본이거나 새 저장본 중 하나임.
+/// 백업 스냅샷 갱신이 실패해도 저장은 된 것이므로 경고로 돌려줌
+pub fn save_db(
+    vault: &VaultHandle,
+    header: &mut DBHeader,
+    ciphertext: EncryptedDB,
+) -> Result<Option<FileIOWarn>, FileIOError> {
+    create_vault_dir(vault)?;
 
     header.ciphertext_checksum = Sha512::digest(&ciphertext).into();
     header.ciphertext_len = ciphertext.len();
@@ -196,121 +417,97 @@ pub fn save_db(header: &mut DBHeader, ciphertext: EncryptedDB) -> Result<(), Fil
     header.write_to(&mut bytes);
     bytes.extend(ciphertext);
 
+    write_db_file_atomically(vault, &bytes)?;
+    mark_as_graceful_exited_to_file(vault)?;
+
+    let snapshot = write_snapshot(vault, &bytes).and_then(|_| prune_snapshots(vault, vault.retention_policy()));
+    Ok(snapshot.err().map(FileIOWarn::SnapshotFailed))
+}
+
+/// 임시 파일에 쓰고 fsync한 뒤 다시 읽어 확인하고, DB 파일 자리로 rename한 다음 디렉터리도 fsync함
+pub(crate) fn write_db_file_atomically(vault: &VaultHandle, bytes: &[u8]) -> Result<(), FileIOError> {
+    let temp_path = vault.temp_file();
+    create_vault_dir(vault)?;
+
     let write_trials = 2;
-    let check_counters = 3;
     let mut write_success = false;
     for _ in 0..write_trials {
-        // db_file.set_len(0)
-        //     .map_err(|err| FileIOError::FileWriteFailed(err))?;
-        db_file
-            .write_all(bytes.as_slice())
-            .map_err(|err| FileIOError::FileWriteFailed(err))?;
-        db_file
-            .seek(SeekFrom::Start(0))
-            .map_err(|err| FileIOError::FileWriteFailed(err))?;
-        db_file
-            .sync_all()
-            .map_err(|err| FileIOError::FileSyncFailed(err))?;
-        db_file
-            .seek(SeekFrom::Start(0))
-            .map_err(|err| FileIOError::FileWriteFailed(err))?;
-
-        for _ in 0..check_counters {
-            let file_len = db_file
-                .metadata()
-                .map_err(|err| FileIOError::FileReadFailed(err))?
-                .len();
-            if file_len as usize == bytes.len() {
-                let mut file_reading = Vec::with_capacity(bytes.len());
-                (&db_file)
-                    .take(u64::MAX)
-                    .read_to_end(&mut file_reading)
-                    .map_err(|err| FileIOError::FileReadFailed(err))?;
-                db_file
-                    .seek(SeekFrom::Start(0))
-                    .map_err(|err| FileIOError::FileReadFailed(err))?;
-                if bytes == file_reading {
-                    write_success = true;
-                    continue;
-                } else {
-                    write_success = false;
-                    break;
-                }
-            }
-        }
-        if write_success {
+        let mut temp_file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_path)
+            .map_err(FileIOError::FileOpenFailed)?;
+        temp_file
+            .write_all(bytes)
+            .map_err(FileIOError::FileWriteFailed)?;
+        temp_file.sync_all().map_err(FileIOError::FileSyncFailed)?;
+
+        if read_whole_file(&temp_file)? == bytes {
+            write_success = true;
             break;
         }
     }
-    if write_success == false {
+    if !write_success {
+        let _ = fs::remove_file(&temp_path);
         return Err(FileIOError::PersistentIntegrityFailure);
     }
 
-    match fs::exists(bak_path) {
-        Ok(true) => {
-            fs::remove_file(bak_path).map_err(|err| FileIOError::FileDeleteFailed(err))?;
-        }
-        Ok(false) => {}
-        Err(err) => {
-            return Err(FileIOError::FileReadFailed(err));
-        }
-    }
-
-    Ok(())
+    fs::rename(&temp_path, vault.db_file()).map_err(FileIOError::FileRenameFailed)?;
+    sync_dir(vault.dir())
 }
 
-pub fn mark_as_ungraceful_exited_to_file() -> Result<(), FileIOError> {
-    let db_path = Path::new(DB_FILE);
-    let bak_path = Path::new(DB_BAK_FILE);
-
-    let db_exists = fs::exists(db_path).map_err(FileIOError::FileReadFailed)?;
-    let bak_exists = fs::exists(bak_path).map_err(FileIOError::FileReadFailed)?;
-
-    if bak_exists {
-        return Ok(());
-    }
+/// 저장하지 않은 변경이 생겼음을 표시함. DB 파일은 마지막 저장본 그대로 둠
+pub fn mark_as_ungraceful_exited_to_file(vault: &VaultHandle) -> Result<(), FileIOError> {
+    create_vault_dir(vault)?;
 
-    if db_exists {
-        fs::rename(db_path, bak_path).map_err(FileIOError::FileRenameFailed)?;
-    } else {
-        File::create_new(bak_path).map_err(FileIOError::FileWriteFailed)?;
+    match File::create_new(vault.dirty_marker_file()) {
+        Ok(marker) => marker.sync_all().map_err(FileIOError::FileSyncFailed)?,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        Err(err) => return Err(FileIOError::FileWriteFailed(err)),
     }
-
-    Ok(())
+    sync_dir(vault.dir())
 }
 
-pub fn mark_as_graceful_exited_to_file() -> Result<(), FileIOError> {
-    let db_path = Path::new(DB_FILE);
-    let bak_path = Path::new(DB_BAK_FILE);
-
-    let db_exists = fs::exists(db_path).map_err(FileIOError::FileReadFailed)?;
-    let bak_exists = fs::exists(bak_path).map_err(FileIOError::FileReadFailed)?;
-
-    if db_exists && bak_exists {
-        remove_file(db_path).map_err(FileIOError::FileDeleteFailed)?;
-
-        fs::rename(bak_path, db_path).map_err(FileIOError::FileRenameFailed)?;
-    } else if !db_exists && bak_exists {
-        fs::rename(bak_path, db_path).map_err(FileIOError::FileRenameFailed)?;
+/// 표시만 지움. 저장하지 않은 변경은 버려지고 DB 파일의 마지막 저장본이 남음
+pub fn mark_as_graceful_exited_to_file(vault: &VaultHandle) -> Result<(), FileIOError> {
+    match fs::remove_file(vault.dirty_marker_file()) {
+        Ok(()) => sync_dir(vault.dir()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(FileIOError::FileDeleteFailed(err)),
     }
-
-    Ok(())
 }
 
-pub fn check_can_directly_exit() -> bool {
-    let bak_path = Path::new(DB_BAK_FILE);
+pub fn check_can_directly_exit(vault: &VaultHandle) -> bool {
+    let dirty_marker_path = vault.dirty_marker_file();
 
-    match fs::exists(bak_path) {
+    match fs::exists(dirty_marker_path) {
         Ok(false) => true,
         _ => false,
     }
 }
 
-pub fn remove_db() -> Result<(), FileIOError> {
-    let db_path = Path::new(DB_FILE);
+pub fn remove_db(vault: &VaultHandle) -> Result<(), FileIOError> {
+    let db_path = vault.db_file();
 
-    remove_file(db_path)
+    fs::remove_file(db_path)
         .map_err(FileIOError::FileDeleteFailed)?;
 
     Ok( () )
 }
+
+fn create_vault_dir(vault: &VaultPath) -> Result<(), FileIOError> {
+    fs::create_dir_all(vault.dir()).map_err(FileIOError::FileWriteFailed)
+}
+
+/// rename한 디렉터리 엔트리까지 디스크에 반영함. Windows는 디렉터리를 파일로 열 수 없어 생략
+fn sync_dir(dir: &Path) -> Result<(), FileIOError> {
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(FileIOError::FileSyncFailed)?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
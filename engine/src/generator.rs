@@ -0,0 +1,319 @@
+use crate::data_base::UserPW;
+use crate::randombytes::random_uniform;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+pub const MIN_PW_LENGTH: usize = 4;
+pub const MAX_PW_LENGTH: usize = 128;
+pub const MIN_WORD_COUNT: usize = 3;
+pub const MAX_WORD_COUNT: usize = 20;
+
+/// 헷갈리기 쉬운 문자. `exclude_look_alikes`이면 뺌
+pub const LOOK_ALIKES: &str = "Il1|O0o`'\"";
+
+/// 한 줄에 한 단어. 정렬되어 있고 중복 없음
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+#[derive(Debug)]
+pub enum GeneratorError {
+    LengthOutOfRange,
+    WordCountOutOfRange,
+    NoCharClass,
+    /// 쓰지 않는 문자 종류를 필수로 지정함
+    RequiredClassNotUsed(CharClass),
+    /// 필수 문자 종류가 길이보다 많음
+    TooManyRequiredClasses,
+}
+impl Display for GeneratorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::LengthOutOfRange => write!(
+                f,
+                "Password length must be {}..={}",
+                MIN_PW_LENGTH, MAX_PW_LENGTH
+            ),
+            GeneratorError::WordCountOutOfRange => write!(
+                f,
+                "Passphrase must have {}..={} words",
+                MIN_WORD_COUNT, MAX_WORD_COUNT
+            ),
+            GeneratorError::NoCharClass => write!(f, "No character class is selected"),
+            GeneratorError::RequiredClassNotUsed(class) => {
+                write!(f, "Required character class \"{}\" is not selected", class)
+            }
+            GeneratorError::TooManyRequiredClasses => {
+                write!(f, "More required character classes than the password length")
+            }
+        }
+    }
+}
+impl Error for GeneratorError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [
+        CharClass::Lower,
+        CharClass::Upper,
+        CharClass::Digit,
+        CharClass::Symbol,
+    ];
+
+    pub fn chars(&self) -> &'static str {
+        match self {
+            CharClass::Lower => "abcdefghijklmnopqrstuvwxyz",
+            CharClass::Upper => "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            CharClass::Digit => "0123456789",
+            CharClass::Symbol => "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~",
+        }
+    }
+
+    fn alphabet(self, exclude_look_alikes: bool) -> impl Iterator<Item = char> {
+        self.chars()
+            .chars()
+            .filter(move |c| !(exclude_look_alikes && LOOK_ALIKES.contains(*c)))
+    }
+}
+impl Display for CharClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CharClass::Lower => write!(f, "lower"),
+            CharClass::Upper => write!(f, "upper"),
+            CharClass::Digit => write!(f, "digit"),
+            CharClass::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+impl FromStr for CharClass {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        CharClass::ALL
+            .into_iter()
+            .find(|class| class.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown character class \"{}\" (lower, upper, digit, symbol)", s))
+    }
+}
+
+/// 문자 단위 비밀번호 설정
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CharsetPolicy {
+    pub length: usize,
+    pub classes: Vec<CharClass>,
+    /// 결과에 한 글자 이상 들어가야 하는 종류. `classes`의 부분집합이어야 함
+    pub required: Vec<CharClass>,
+    pub exclude_look_alikes: bool,
+}
+
+impl Default for CharsetPolicy {
+    fn default() -> Self {
+        Self {
+            length: 20,
+            classes: CharClass::ALL.to_vec(),
+            required: CharClass::ALL.to_vec(),
+            exclude_look_alikes: false,
+        }
+    }
+}
+
+impl CharsetPolicy {
+    fn validate(&self) -> Result<(), GeneratorError> {
+        if !(MIN_PW_LENGTH..=MAX_PW_LENGTH).contains(&self.length) {
+            return Err(GeneratorError::LengthOutOfRange);
+        }
+        if self.classes.is_empty() {
+            return Err(GeneratorError::NoCharClass);
+        }
+        if let Some(class) = self.required.iter().find(|c| !self.classes.contains(c)) {
+            return Err(GeneratorError::RequiredClassNotUsed(*class));
+        }
+        if self.required_classes().len() > self.length {
+            return Err(GeneratorError::TooManyRequiredClasses);
+        }
+        Ok(())
+    }
+
+    fn alphabet(&self) -> Vec<char> {
+        CharClass::ALL
+            .into_iter()
+            .filter(|class| self.classes.contains(class))
+            .flat_map(|class| class.alphabet(self.exclude_look_alikes))
+            .collect()
+    }
+
+    fn required_classes(&self) -> Vec<CharClass> {
+        CharClass::ALL
+            .into_iter()
+            .filter(|class| self.required.contains(class))
+            .collect()
+    }
+
+    /// 필수 종류를 만족하는 문자열 수의 log2. 포함-배제로 셈
+    fn entropy_bits(&self) -> f64 {
+        let alphabet_len = self.alphabet().len() as f64;
+        let class_lens: Vec<f64> = self
+            .required_classes()
+            .iter()
+            .map(|class| class.alphabet(self.exclude_look_alikes).count() as f64)
+            .collect();
+
+        // 큰 수끼리 빼지 않도록 전체 경우의 수에 대한 비율로 계산함
+        let mut ratio = 0.0;
+        for subset in 0u32..(1 << class_lens.len()) {
+            let missing: f64 = (0..class_lens.len())
+                .filter(|i| subset & (1 << i) != 0)
+                .map(|i| class_lens[i])
+                .sum();
+            let term = ((alphabet_len - missing) / alphabet_len).powi(self.length as i32);
+            if subset.count_ones() % 2 == 0 {
+                ratio += term;
+            } else {
+                ratio -= term;
+            }
+        }
+        self.length as f64 * alphabet_len.log2() + ratio.log2()
+    }
+}
+
+/// diceware 방식 패스프레이즈 설정
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassphrasePolicy {
+    pub word_count: usize,
+    pub separator: String,
+    pub capitalize: bool,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        Self {
+            word_count: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+        }
+    }
+}
+
+impl PassphrasePolicy {
+    fn validate(&self) -> Result<(), GeneratorError> {
+        if !(MIN_WORD_COUNT..=MAX_WORD_COUNT).contains(&self.word_count) {
+            return Err(GeneratorError::WordCountOutOfRange);
+        }
+        Ok(())
+    }
+
+    /// 구분자가 비어 있으면 단어 경계가 모호해지므로 실제보다 조금 높게 나옴
+    fn entropy_bits(&self) -> f64 {
+        self.word_count as f64 * (wordlist().count() as f64).log2()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GeneratorPolicy {
+    Charset(CharsetPolicy),
+    Passphrase(PassphrasePolicy),
+}
+
+impl Default for GeneratorPolicy {
+    fn default() -> Self {
+        GeneratorPolicy::Charset(CharsetPolicy::default())
+    }
+}
+
+impl GeneratorPolicy {
+    pub fn validate(&self) -> Result<(), GeneratorError> {
+        match self {
+            GeneratorPolicy::Charset(policy) => policy.validate(),
+            GeneratorPolicy::Passphrase(policy) => policy.validate(),
+        }
+    }
+
+    /// 이 설정으로 만든 결과 하나의 엔트로피(비트)
+    pub fn entropy_bits(&self) -> Result<f64, GeneratorError> {
+        self.validate()?;
+        Ok(match self {
+            GeneratorPolicy::Charset(policy) => policy.entropy_bits(),
+            GeneratorPolicy::Passphrase(policy) => policy.entropy_bits(),
+        })
+    }
+}
+
+pub struct Generated {
+    pub pw: UserPW,
+    pub entropy_bits: f64,
+}
+
+pub fn wordlist() -> impl Iterator<Item = &'static str> {
+    WORDLIST.lines()
+}
+
+/// libsodium RNG로 비밀번호나 패스프레이즈를 만듦
+pub fn generate(policy: &GeneratorPolicy) -> Result<Generated, GeneratorError> {
+    let entropy_bits = policy.entropy_bits()?;
+    let mut pw = match policy {
+        GeneratorPolicy::Charset(policy) => generate_charset(policy),
+        GeneratorPolicy::Passphrase(policy) => generate_passphrase(policy),
+    };
+    Ok(Generated {
+        pw: UserPW::from_unchecked(std::mem::take(&mut *pw)),
+        entropy_bits,
+    })
+}
+
+#[inline(always)]
+fn pick<T: Copy>(items: &[T]) -> T {
+    items[random_uniform(items.len() as u32) as usize]
+}
+
+fn generate_charset(policy: &CharsetPolicy) -> Zeroizing<String> {
+    let alphabet = policy.alphabet();
+    let required: Vec<Vec<char>> = policy
+        .required_classes()
+        .iter()
+        .map(|class| class.alphabet(policy.exclude_look_alikes).collect())
+        .collect();
+
+    // 필수 종류가 빠진 결과는 버리고 다시 뽑음.
+    // 조건을 만족하는 문자열 중에서 균등하게 고르는 것과 같아서 엔트로피 계산과 맞음
+    loop {
+        let mut pw = Zeroizing::new(String::with_capacity(policy.length));
+        for _ in 0..policy.length {
+            pw.push(pick(&alphabet));
+        }
+        if required
+            .iter()
+            .all(|class| pw.chars().any(|c| class.contains(&c)))
+        {
+            return pw;
+        }
+    }
+}
+
+fn generate_passphrase(policy: &PassphrasePolicy) -> Zeroizing<String> {
+    let words: Vec<&str> = wordlist().collect();
+    let longest = words.iter().map(|w| w.len()).max().unwrap_or(0);
+    // 다시 할당되면서 이전 버퍼가 지워지지 않은 채 남지 않도록 미리 잡아 둠
+    let mut pw = Zeroizing::new(String::with_capacity(
+        policy.word_count * (longest + policy.separator.len()),
+    ));
+    for i in 0..policy.word_count {
+        if i != 0 {
+            pw.push_str(&policy.separator);
+        }
+        let word = pick(&words);
+        if policy.capitalize {
+            let mut chars = word.chars();
+            pw.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+            pw.push_str(chars.as_str());
+        } else {
+            pw.push_str(word);
+        }
+    }
+    pw
+}
@@ -1,11 +1,43 @@
 use crate::file_io::FileIOError;
+use crate::kdf::KdfParams;
+use crate::key_slots::KeySlotKind;
 use crate::master_secrets::EncryptedDB;
 use bytemuck::{Pod, Zeroable};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 
 const SALT_LEN: usize = 32;
 const MAGIC_LEN: usize = 78;
 const VERSION_LEN: usize = 18;
-const HEADER_USED_LEN: usize = SALT_LEN + MAGIC_LEN + VERSION_LEN;
+const KDF_PARAMS_LEN: usize = size_of::<KdfParams>();
+const KEY_SIZE: usize = 32;
+const RECOVERY_NONCE_LEN: usize = 12;
+/// An X25519 secret key plus the AES-GCM tag
+const WRAPPED_KEY_LEN: usize = KEY_SIZE + 16;
+const RECOVERY_SLOT_LEN: usize = size_of::<RecoverySlot>();
+/// Number of entries in the key slot table, including the master password slot
+pub const KEY_SLOT_COUNT: usize = 16;
+/// The key slot opened by the master password. It can be rewrapped but never freed
+pub const MASTER_SLOT: usize = 0;
+/// Label of the master password slot until it is renamed
+pub(crate) const MASTER_SLOT_LABEL: &str = "master password";
+/// Where headers before 0.2.8 put the credentials that had their own fields, so that
+/// `authenticated_bytes` can read them back in the order they were stored
+const LEGACY_RECOVERY_SLOT: usize = 1;
+const LEGACY_SHARES_SLOT: usize = 2;
+/// Number of entries in the key slot table from 0.2.6 to 0.2.7
+const LEGACY_KEY_SLOT_COUNT: usize = 8;
+const LEGACY_KEY_SLOTS: Range<usize> = 3..3 + LEGACY_KEY_SLOT_COUNT;
+/// Longest key slot label in UTF-8 bytes
+pub const KEY_SLOT_LABEL_LEN: usize = 32;
+const KEY_SLOT_LEN: usize = size_of::<KeySlot>();
+/// Used bytes of the header layout from 0.1.6 to 0.2.3
+const KDF_HEADER_USED_LEN: usize = SALT_LEN + MAGIC_LEN + VERSION_LEN + KDF_PARAMS_LEN * 2 + size_of::<u32>();
+/// Used bytes of the header layout from 0.2.4 to 0.2.5
+const RECOVERY_HEADER_USED_LEN: usize = KDF_HEADER_USED_LEN + RECOVERY_SLOT_LEN * 2;
+/// Used bytes of the header layout from 0.2.6 to 0.2.7
+const SLOT_TABLE_HEADER_USED_LEN: usize = RECOVERY_HEADER_USED_LEN + KEY_SLOT_LEN * LEGACY_KEY_SLOT_COUNT;
+const HEADER_USED_LEN: usize = MAGIC_LEN + VERSION_LEN + KDF_PARAMS_LEN * 2 + KEY_SLOT_LEN * KEY_SLOT_COUNT;
 
 type Magic = [u8; MAGIC_LEN];
 type Version = [u8; VERSION_LEN];
@@ -16,42 +48,552 @@ pub type CipherTextLen = usize;
 /// Program internal magic literal
 const DB_MAGIC: Magic =
     *b"This is DB file of PW Manager. A Project Created By Team5 of 2025 Rust Study.\n";
-/// Program-internal DB format version
-const DB_VERSION: Version = *b"DB Ver: 0.1.2.000\n";
+const VERSION_PREFIX: &[u8; 8] = b"DB Ver: ";
+
+/// Structured form of the header version field, `DB Ver: <major>.<minor>.<patch>.<revision>\n`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FormatVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub revision: u16,
+}
+
+impl FormatVersion {
+    /// User password nonces were derived from (site, user ID) instead of stored
+    pub const V0_1_2: Self = Self::new(0, 1, 2, 0);
+    /// User passwords were not bound to their (site, user ID) key
+    pub const V0_1_3: Self = Self::new(0, 1, 3, 0);
+    /// The header was not bound to the DB ciphertext
+    pub const V0_1_4: Self = Self::new(0, 1, 4, 0);
+    /// Last format with the fixed-size 0.1.x header (KDF parameters were hardcoded)
+    pub const V0_1_5: Self = Self::new(0, 1, 5, 0);
+    /// Accounts held only an encrypted password
+    pub const V0_1_6: Self = Self::new(0, 1, 6, 0);
+    /// Entries had no password history
+    pub const V0_1_7: Self = Self::new(0, 1, 7, 0);
+    /// Entries had no OTP secret
+    pub const V0_1_8: Self = Self::new(0, 1, 8, 0);
+    /// Entries had no tags, folder or favourite flag
+    pub const V0_1_9: Self = Self::new(0, 1, 9, 0);
+    /// The DB held only the account map, with no trash
+    pub const V0_2_0: Self = Self::new(0, 2, 0, 0);
+    /// Trashed entries always kept their site name and user ID in plaintext
+    pub const V0_2_1: Self = Self::new(0, 2, 1, 0);
+    /// The header had no key file flag
+    pub const V0_2_2: Self = Self::new(0, 2, 2, 0);
+    /// The header had no recovery key slot
+    pub const V0_2_3: Self = Self::new(0, 2, 3, 0);
+    /// The header had no slot for recovery shares
+    pub const V0_2_4: Self = Self::new(0, 2, 4, 0);
+    /// The header had no key slot table
+    pub const V0_2_5: Self = Self::new(0, 2, 5, 0);
+    /// Entry URLs were stored in plaintext inside the DB
+    pub const V0_2_6: Self = Self::new(0, 2, 6, 0);
+    /// The vault secret key was derived from the master password, and the master password, recovery code and
+    /// recovery shares had their own header fields beside the key slot table
+    pub const V0_2_7: Self = Self::new(0, 2, 7, 0);
+    /// A random vault secret key is sealed into every key slot, including the master password slot
+    pub const V0_2_8: Self = Self::new(0, 2, 8, 0);
+    /// Program-internal DB format version
+    pub const CURRENT: Self = Self::V0_2_8;
+
+    pub const fn new(major: u8, minor: u8, patch: u8, revision: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            revision,
+        }
+    }
+
+    fn parse(field: &Version) -> Option<Self> {
+        let text = std::str::from_utf8(field.strip_prefix(VERSION_PREFIX)?).ok()?;
+        let mut parts = text.strip_suffix('\n')?.split('.');
+        let version = Self::new(
+            parts.next()?.parse().ok()?,
+            parts.next()?.parse().ok()?,
+            parts.next()?.parse().ok()?,
+            parts.next()?.parse().ok()?,
+        );
+        if parts.next().is_some() || version.to_field() != *field {
+            return None;
+        }
+        Some(version)
+    }
+
+    /// The field is fixed-width, so each part but the 3-digit revision must be a single digit
+    const fn to_field(self) -> Version {
+        assert!(self.major < 10 && self.minor < 10 && self.patch < 10 && self.revision < 1000);
+        let mut field = [0u8; VERSION_LEN];
+        let mut i = 0;
+        while i < VERSION_PREFIX.len() {
+            field[i] = VERSION_PREFIX[i];
+            i += 1;
+        }
+        field[8] = b'0' + self.major;
+        field[9] = b'.';
+        field[10] = b'0' + self.minor;
+        field[11] = b'.';
+        field[12] = b'0' + self.patch;
+        field[13] = b'.';
+        field[14] = b'0' + (self.revision / 100) as u8;
+        field[15] = b'0' + (self.revision / 10 % 10) as u8;
+        field[16] = b'0' + (self.revision % 10) as u8;
+        field[17] = b'\n';
+        field
+    }
+}
+
+impl Display for FormatVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}.{:03}", self.major, self.minor, self.patch, self.revision)
+    }
+}
+
+const DB_VERSION: Version = FormatVersion::CURRENT.to_field();
+/// Versions stored with `LegacyDBHeader`
+const LEGACY_HEADER_VERSIONS: [FormatVersion; 4] = [
+    FormatVersion::V0_1_2,
+    FormatVersion::V0_1_3,
+    FormatVersion::V0_1_4,
+    FormatVersion::V0_1_5,
+];
+/// Versions stored with `KdfDBHeader`
+const KDF_HEADER_VERSIONS: [FormatVersion; 8] = [
+    FormatVersion::V0_1_6,
+    FormatVersion::V0_1_7,
+    FormatVersion::V0_1_8,
+    FormatVersion::V0_1_9,
+    FormatVersion::V0_2_0,
+    FormatVersion::V0_2_1,
+    FormatVersion::V0_2_2,
+    FormatVersion::V0_2_3,
+];
+/// Versions stored with `RecoveryDBHeader`
+const RECOVERY_HEADER_VERSIONS: [FormatVersion; 2] = [FormatVersion::V0_2_4, FormatVersion::V0_2_5];
+/// Versions stored with `SlotTableDBHeader`
+const SLOT_TABLE_HEADER_VERSIONS: [FormatVersion; 2] = [FormatVersion::V0_2_6, FormatVersion::V0_2_7];
+/// Versions stored with the current `DBHeader` layout
+const HEADER_VERSIONS: [FormatVersion; 1] = [FormatVersion::V0_2_8];
+
+/// The vault secret key encrypted to the public key of a recovery code or of a secret split into recovery shares,
+/// all zero when unused.
+/// Sealed like `encrypt_db` with a one-time peer key, so it can be rewrapped without the code
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RecoverySlot {
+    pub(crate) recovery_pub_key: [u8; KEY_SIZE],
+    pub(crate) peer_pub_key: [u8; KEY_SIZE],
+    pub(crate) nonce: [u8; RECOVERY_NONCE_LEN],
+    pub(crate) wrapped_sec_key: [u8; WRAPPED_KEY_LEN],
+}
+impl RecoverySlot {
+    pub(crate) const EMPTY: Self = Self {
+        recovery_pub_key: [0u8; KEY_SIZE],
+        peer_pub_key: [0u8; KEY_SIZE],
+        nonce: [0u8; RECOVERY_NONCE_LEN],
+        wrapped_sec_key: [0u8; WRAPPED_KEY_LEN],
+    };
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::EMPTY
+    }
+}
+
+/// One entry of the key slot table: the vault secret key sealed to a key derived from the slot's credential.
+/// All zero when free. Before 0.2.8 the master password slot has nothing sealed, because the master password
+/// derived the vault secret key itself
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct KeySlot {
+    /// `KeySlotKind` as stored, 0 when free
+    pub(crate) kind: u32,
+    /// UTF-8, zero padded
+    pub(crate) label: [u8; KEY_SLOT_LABEL_LEN],
+    /// Password slots only
+    pub(crate) salt: Salt,
+    /// Password slots only. Opening the slot always uses this copy, not `DBHeader::kdf_params`
+    pub(crate) kdf_params: KdfParams,
+    pub(crate) sealed: RecoverySlot,
+}
+impl KeySlot {
+    pub(crate) const EMPTY: Self = Self {
+        kind: 0,
+        label: [0u8; KEY_SLOT_LABEL_LEN],
+        salt: [0u8; SALT_LEN],
+        kdf_params: KdfParams::NONE,
+        sealed: RecoverySlot::EMPTY,
+    };
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::EMPTY
+    }
+    /// `None` for free slots
+    pub(crate) fn kind(&self) -> Option<KeySlotKind> {
+        KeySlotKind::from_raw(self.kind)
+    }
+
+    /// The master password slot of a header before 0.2.8, which only keeps how the key was derived
+    fn legacy_master(salt: Salt, kdf_params: KdfParams, key_file_required: u32) -> Result<Self, FileIOError> {
+        let kind = match key_file_required {
+            0 => KeySlotKind::Password,
+            1 => KeySlotKind::PasswordAndKeyFile,
+            _ => return Err(FileIOError::InvalidHeader),
+        };
+        Ok(Self::legacy(kind, MASTER_SLOT_LABEL, RecoverySlot::EMPTY, salt, kdf_params))
+    }
+
+    /// A recovery slot of a header before 0.2.8, free if nothing was sealed
+    fn legacy_recovery(kind: KeySlotKind, label: &str, sealed: RecoverySlot) -> Self {
+        if sealed.is_empty() {
+            return Self::EMPTY;
+        }
+        Self::legacy(kind, label, sealed, [0u8; SALT_LEN], KdfParams::NONE)
+    }
+
+    fn legacy(kind: KeySlotKind, label: &str, sealed: RecoverySlot, salt: Salt, kdf_params: KdfParams) -> Self {
+        let mut slot = Self {
+            kind: kind.to_raw(),
+            label: [0u8; KEY_SLOT_LABEL_LEN],
+            salt,
+            kdf_params,
+            sealed,
+        };
+        slot.set_label(label);
+        slot
+    }
+
+    /// For labels chosen in code, which always fit. Labels from users go through `key_slots::encode_label`
+    pub(crate) fn set_label(&mut self, label: &str) {
+        self.label = [0u8; KEY_SLOT_LABEL_LEN];
+        self.label[..label.len()].copy_from_slice(label.as_bytes());
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct DBHeader {
     pub(crate) magic: Magic,
     pub(crate) version: Version,
-    pub master_pw_salt: Salt,
+    /// KDF settings used when the master password slot is resealed and when new password slots are made.
+    /// The master password slot keeps its own copy, which is what opening it uses; the two only differ while a
+    /// change to this field has not been sealed yet, e.g. before `first_login`
+    pub kdf_params: KdfParams,
+    /// KDF settings to switch to on the next successful login, or `KdfParams::NONE`
+    pub(crate) pending_kdf_params: KdfParams,
+    /// Credentials that each unlock the vault secret key on their own, starting with the master password
+    pub(crate) key_slots: [KeySlot; KEY_SLOT_COUNT],
     _padding: [u8; HEADER_USED_LEN.next_power_of_two() - HEADER_USED_LEN],
     pub(crate) ciphertext_checksum: CiphTxtChecksum,
     pub(crate) ciphertext_len: CipherTextLen,
 }
 pub const HEADER_LEN: usize = size_of::<DBHeader>();
 
+/// On-disk header layout used up to 0.1.5
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct LegacyDBHeader {
+    magic: Magic,
+    version: Version,
+    master_pw_salt: Salt,
+    ciphertext_checksum: CiphTxtChecksum,
+    ciphertext_len: CipherTextLen,
+}
+const LEGACY_HEADER_LEN: usize = size_of::<LegacyDBHeader>();
+
+/// On-disk header layout used from 0.1.6 to 0.2.3
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct KdfDBHeader {
+    magic: Magic,
+    version: Version,
+    master_pw_salt: Salt,
+    kdf_params: KdfParams,
+    pending_kdf_params: KdfParams,
+    key_file_required: u32,
+    _padding: [u8; KDF_HEADER_USED_LEN.next_power_of_two() - KDF_HEADER_USED_LEN],
+    ciphertext_checksum: CiphTxtChecksum,
+    ciphertext_len: CipherTextLen,
+}
+const KDF_HEADER_LEN: usize = size_of::<KdfDBHeader>();
+
+/// On-disk header layout used from 0.2.4 to 0.2.5
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RecoveryDBHeader {
+    magic: Magic,
+    version: Version,
+    master_pw_salt: Salt,
+    kdf_params: KdfParams,
+    pending_kdf_params: KdfParams,
+    key_file_required: u32,
+    recovery_slot: RecoverySlot,
+    shares_slot: RecoverySlot,
+    _padding: [u8; RECOVERY_HEADER_USED_LEN.next_power_of_two() - RECOVERY_HEADER_USED_LEN],
+    ciphertext_checksum: CiphTxtChecksum,
+    ciphertext_len: CipherTextLen,
+}
+const RECOVERY_HEADER_LEN: usize = size_of::<RecoveryDBHeader>();
+
+/// On-disk header layout used from 0.2.6 to 0.2.7
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SlotTableDBHeader {
+    magic: Magic,
+    version: Version,
+    master_pw_salt: Salt,
+    kdf_params: KdfParams,
+    pending_kdf_params: KdfParams,
+    key_file_required: u32,
+    recovery_slot: RecoverySlot,
+    shares_slot: RecoverySlot,
+    key_slots: [KeySlot; LEGACY_KEY_SLOT_COUNT],
+    _padding: [u8; SLOT_TABLE_HEADER_USED_LEN.next_power_of_two() - SLOT_TABLE_HEADER_USED_LEN],
+    ciphertext_checksum: CiphTxtChecksum,
+    ciphertext_len: CipherTextLen,
+}
+const SLOT_TABLE_HEADER_LEN: usize = size_of::<SlotTableDBHeader>();
+/// Shortest header of any readable format
+pub const MIN_HEADER_LEN: usize = LEGACY_HEADER_LEN;
+
 impl DBHeader {
     pub fn parse_header(bytes: &[u8]) -> Result<(DBHeader, EncryptedDB), FileIOError> {
-        if bytes.len() < HEADER_LEN {
+        if bytes.len() < MAGIC_LEN + VERSION_LEN {
+            return Err(FileIOError::InvalidHeader);
+        }
+        if bytes[..MAGIC_LEN] != DB_MAGIC {
             return Err(FileIOError::InvalidHeader);
         }
 
-        let (head, body) = bytes.split_at(HEADER_LEN);
-
-        let header: DBHeader = *bytemuck::from_bytes::<DBHeader>(head);
+        let version: Version = bytes[MAGIC_LEN..MAGIC_LEN + VERSION_LEN].try_into().unwrap();
+        let Some(version) = FormatVersion::parse(&version) else {
+            return Err(FileIOError::InvalidHeader);
+        };
+        if LEGACY_HEADER_VERSIONS.contains(&version) {
+            return Self::parse_legacy_header(bytes);
+        }
+        let (header, body) = if KDF_HEADER_VERSIONS.contains(&version) {
+            Self::parse_kdf_header(bytes)?
+        } else if RECOVERY_HEADER_VERSIONS.contains(&version) {
+            Self::parse_recovery_header(bytes, version)?
+        } else if SLOT_TABLE_HEADER_VERSIONS.contains(&version) {
+            Self::parse_slot_table_header(bytes)?
+        } else if HEADER_VERSIONS.contains(&version) {
+            if bytes.len() < HEADER_LEN {
+                return Err(FileIOError::TruncatedFile);
+            }
+            let (head, body) = bytes.split_at(HEADER_LEN);
+            (bytemuck::pod_read_unaligned::<DBHeader>(head), body)
+        } else {
+            return Err(FileIOError::DBVersionMissMatch(version));
+        };
 
-        if header.magic != DB_MAGIC {
+        if header.kdf_params.validate().is_err()
+            || (!header.pending_kdf_params.is_none()
+                && header.pending_kdf_params.validate().is_err())
+            || !header.master_slot().kind().is_some_and(KeySlotKind::is_password)
+            || header.key_slots.iter().any(|slot| !slot.is_empty() && !is_valid_key_slot(slot))
+        {
             return Err(FileIOError::InvalidHeader);
         }
 
-        if header.version != DB_VERSION {
-            return Err(FileIOError::DBVersionMissMatch);
+        Ok((header, body.to_vec()))
+    }
+
+    fn parse_kdf_header(bytes: &[u8]) -> Result<(DBHeader, &[u8]), FileIOError> {
+        if bytes.len() < KDF_HEADER_LEN {
+            return Err(FileIOError::TruncatedFile);
+        }
+
+        let (head, body) = bytes.split_at(KDF_HEADER_LEN);
+
+        let old: KdfDBHeader = bytemuck::pod_read_unaligned::<KdfDBHeader>(head);
+
+        let mut key_slots = [KeySlot::EMPTY; KEY_SLOT_COUNT];
+        key_slots[MASTER_SLOT] = KeySlot::legacy_master(old.master_pw_salt, old.kdf_params, old.key_file_required)?;
+        let header = DBHeader {
+            magic: old.magic,
+            version: old.version,
+            kdf_params: old.kdf_params,
+            pending_kdf_params: old.pending_kdf_params,
+            key_slots,
+            _padding: [0u8; _],
+            ciphertext_checksum: old.ciphertext_checksum,
+            ciphertext_len: old.ciphertext_len,
+        };
+
+        Ok((header, body))
+    }
+
+    fn parse_recovery_header(bytes: &[u8], version: FormatVersion) -> Result<(DBHeader, &[u8]), FileIOError> {
+        if bytes.len() < RECOVERY_HEADER_LEN {
+            return Err(FileIOError::TruncatedFile);
+        }
+
+        let (head, body) = bytes.split_at(RECOVERY_HEADER_LEN);
+
+        let old: RecoveryDBHeader = bytemuck::pod_read_unaligned::<RecoveryDBHeader>(head);
+
+        let mut key_slots = [KeySlot::EMPTY; KEY_SLOT_COUNT];
+        key_slots[MASTER_SLOT] = KeySlot::legacy_master(old.master_pw_salt, old.kdf_params, old.key_file_required)?;
+        key_slots[LEGACY_RECOVERY_SLOT] =
+            KeySlot::legacy_recovery(KeySlotKind::RecoveryCode, "recovery code", old.recovery_slot);
+        // Padding in 0.2.4, which is not authenticated, so never trust it
+        if version >= FormatVersion::V0_2_5 {
+            key_slots[LEGACY_SHARES_SLOT] =
+                KeySlot::legacy_recovery(KeySlotKind::RecoveryShares, "recovery shares", old.shares_slot);
+        }
+        let header = DBHeader {
+            magic: old.magic,
+            version: old.version,
+            kdf_params: old.kdf_params,
+            pending_kdf_params: old.pending_kdf_params,
+            key_slots,
+            _padding: [0u8; _],
+            ciphertext_checksum: old.ciphertext_checksum,
+            ciphertext_len: old.ciphertext_len,
+        };
+
+        Ok((header, body))
+    }
+
+    fn parse_slot_table_header(bytes: &[u8]) -> Result<(DBHeader, &[u8]), FileIOError> {
+        if bytes.len() < SLOT_TABLE_HEADER_LEN {
+            return Err(FileIOError::TruncatedFile);
+        }
+
+        let (head, body) = bytes.split_at(SLOT_TABLE_HEADER_LEN);
+
+        let old: SlotTableDBHeader = bytemuck::pod_read_unaligned::<SlotTableDBHeader>(head);
+
+        let mut key_slots = [KeySlot::EMPTY; KEY_SLOT_COUNT];
+        key_slots[MASTER_SLOT] = KeySlot::legacy_master(old.master_pw_salt, old.kdf_params, old.key_file_required)?;
+        key_slots[LEGACY_RECOVERY_SLOT] =
+            KeySlot::legacy_recovery(KeySlotKind::RecoveryCode, "recovery code", old.recovery_slot);
+        key_slots[LEGACY_SHARES_SLOT] =
+            KeySlot::legacy_recovery(KeySlotKind::RecoveryShares, "recovery shares", old.shares_slot);
+        key_slots[LEGACY_KEY_SLOTS].copy_from_slice(&old.key_slots);
+        let header = DBHeader {
+            magic: old.magic,
+            version: old.version,
+            kdf_params: old.kdf_params,
+            pending_kdf_params: old.pending_kdf_params,
+            key_slots,
+            _padding: [0u8; _],
+            ciphertext_checksum: old.ciphertext_checksum,
+            ciphertext_len: old.ciphertext_len,
+        };
+
+        Ok((header, body))
+    }
+
+    fn parse_legacy_header(bytes: &[u8]) -> Result<(DBHeader, EncryptedDB), FileIOError> {
+        if bytes.len() < LEGACY_HEADER_LEN {
+            return Err(FileIOError::TruncatedFile);
         }
 
+        let (head, body) = bytes.split_at(LEGACY_HEADER_LEN);
+
+        let legacy: LegacyDBHeader = bytemuck::pod_read_unaligned::<LegacyDBHeader>(head);
+
+        let mut key_slots = [KeySlot::EMPTY; KEY_SLOT_COUNT];
+        key_slots[MASTER_SLOT] = KeySlot::legacy_master(legacy.master_pw_salt, KdfParams::LEGACY, 0)?;
+        let header = DBHeader {
+            magic: legacy.magic,
+            version: legacy.version,
+            kdf_params: KdfParams::LEGACY,
+            pending_kdf_params: KdfParams::NONE,
+            key_slots,
+            _padding: [0u8; _],
+            ciphertext_checksum: legacy.ciphertext_checksum,
+            ciphertext_len: legacy.ciphertext_len,
+        };
+
         Ok((header, body.to_vec()))
     }
 
+    /// The version field is read from the file as is, so this errors if it does not parse
+    pub fn version(&self) -> Result<FormatVersion, FileIOError> {
+        FormatVersion::parse(&self.version).ok_or(FileIOError::InvalidHeader)
+    }
+    /// A version that does not parse has none of the later features, so its associated data never matches
+    /// and decryption reports a tampered header
+    fn is_at_least(&self, version: FormatVersion) -> bool {
+        self.version().is_ok_and(|v| v >= version)
+    }
+    /// Whether the header is authenticated as associated data of the DB ciphertext
+    pub fn has_bound_header(&self) -> bool {
+        self.is_at_least(FormatVersion::V0_1_5)
+    }
+    pub fn is_current_version(&self) -> bool {
+        self.version == DB_VERSION
+    }
+
+    pub fn pending_kdf_params(&self) -> Option<KdfParams> {
+        if self.pending_kdf_params.is_none() {
+            None
+        } else {
+            Some(self.pending_kdf_params)
+        }
+    }
+    /// Re-derive the master keys with `kdf_params` on the next successful login.
+    /// The header has to be saved for the request to persist
+    pub fn request_kdf_upgrade(&mut self, kdf_params: KdfParams) {
+        self.pending_kdf_params = kdf_params;
+    }
+    /// Whether unlocking needs a key file besides the master password
+    pub fn requires_key_file(&self) -> bool {
+        self.master_slot().kind() == Some(KeySlotKind::PasswordAndKeyFile)
+    }
+    pub(crate) fn master_slot(&self) -> &KeySlot {
+        &self.key_slots[MASTER_SLOT]
+    }
+    /// Whether the vault secret key is random and sealed in the master password slot.
+    /// Before 0.2.8 the master password derived it, so the session key came from the KDF as well
+    pub(crate) fn has_vault_key(&self) -> bool {
+        self.is_at_least(FormatVersion::V0_2_8)
+    }
+    /// Only migration steps change the version
+    pub(crate) fn set_version(&mut self, version: FormatVersion) {
+        self.version = version.to_field();
+    }
+
+    /// Header fields bound to the DB ciphertext as AES-GCM associated data
+    pub(crate) fn authenticated_bytes(&self, ciphertext_len: CipherTextLen) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_USED_LEN + size_of::<u64>());
+        out.extend_from_slice(&self.magic);
+        out.extend_from_slice(&self.version);
+        if self.has_vault_key() {
+            out.extend_from_slice(bytemuck::bytes_of(&self.kdf_params));
+            out.extend_from_slice(bytemuck::bytes_of(&self.pending_kdf_params));
+            out.extend_from_slice(bytemuck::bytes_of(&self.key_slots));
+        } else {
+            self.legacy_authenticated_fields(&mut out);
+        }
+        out.extend_from_slice(&(ciphertext_len as u64).to_le_bytes());
+        out
+    }
+
+    /// The fields of headers before 0.2.8, read back from where `parse_header` put them
+    fn legacy_authenticated_fields(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.master_slot().salt);
+        if self.is_at_least(FormatVersion::V0_1_6) {
+            out.extend_from_slice(bytemuck::bytes_of(&self.kdf_params));
+            out.extend_from_slice(bytemuck::bytes_of(&self.pending_kdf_params));
+        }
+        if self.is_at_least(FormatVersion::V0_2_3) {
+            out.extend_from_slice(&(self.requires_key_file() as u32).to_le_bytes());
+        }
+        if self.is_at_least(FormatVersion::V0_2_4) {
+            out.extend_from_slice(bytemuck::bytes_of(&self.key_slots[LEGACY_RECOVERY_SLOT].sealed));
+        }
+        if self.is_at_least(FormatVersion::V0_2_5) {
+            out.extend_from_slice(bytemuck::bytes_of(&self.key_slots[LEGACY_SHARES_SLOT].sealed));
+        }
+        if self.is_at_least(FormatVersion::V0_2_6) {
+            out.extend_from_slice(bytemuck::cast_slice(&self.key_slots[LEGACY_KEY_SLOTS]));
+        }
+    }
+
     pub fn write_to(&self, out: &mut Vec<u8>) {
         out.extend_from_slice(bytemuck::bytes_of(self));
     }
@@ -59,7 +601,9 @@ impl DBHeader {
         Self {
             magic: DB_MAGIC,
             version: DB_VERSION,
-            master_pw_salt: Salt::default(),
+            kdf_params: KdfParams::LEGACY,
+            pending_kdf_params: KdfParams::NONE,
+            key_slots: [KeySlot::EMPTY; KEY_SLOT_COUNT],
             _padding: [0u8; _],
             ciphertext_checksum: [0u8; _],
             ciphertext_len: 0,
@@ -67,6 +611,14 @@ impl DBHeader {
     }
 }
 
+/// Kinds are the ones `KeySlotKind` knows, password slots carry usable KDF settings and labels are UTF-8
+fn is_valid_key_slot(slot: &KeySlot) -> bool {
+    let label_len = slot.label.iter().position(|&b| b == 0).unwrap_or(KEY_SLOT_LABEL_LEN);
+    slot.kind().is_some_and(|kind| !kind.is_password() || slot.kdf_params.validate().is_ok())
+        && std::str::from_utf8(&slot.label[..label_len]).is_ok()
+        && slot.label[label_len..].iter().all(|&b| b == 0)
+}
+
 impl Default for DBHeader {
     fn default() -> Self {
         Self::empty_valid()
@@ -75,3 +627,15 @@ impl Default for DBHeader {
 
 unsafe impl Zeroable for DBHeader {}
 unsafe impl Pod for DBHeader {}
+unsafe impl Zeroable for LegacyDBHeader {}
+unsafe impl Pod for LegacyDBHeader {}
+unsafe impl Zeroable for KdfDBHeader {}
+unsafe impl Pod for KdfDBHeader {}
+unsafe impl Zeroable for RecoveryDBHeader {}
+unsafe impl Pod for RecoveryDBHeader {}
+unsafe impl Zeroable for SlotTableDBHeader {}
+unsafe impl Pod for SlotTableDBHeader {}
+unsafe impl Zeroable for RecoverySlot {}
+unsafe impl Pod for RecoverySlot {}
+unsafe impl Zeroable for KeySlot {}
+unsafe impl Pod for KeySlot {}
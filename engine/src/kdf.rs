@@ -0,0 +1,207 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use bytemuck::{Pod, Zeroable};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hint;
+use std::thread::available_parallelism;
+use std::time::{Duration, Instant};
+use sysinfo::{System, SystemExt};
+
+const MIN_M_COST_KIB: u32 = 8 * 1024;
+const MAX_M_COST_KIB: u32 = 4 * 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 64;
+
+#[derive(Debug)]
+pub enum KdfParamsError {
+    UnknownAlgorithm,
+    OutOfRange,
+}
+impl Display for KdfParamsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdfParamsError::UnknownAlgorithm => write!(f, "Unknown KDF algorithm"),
+            KdfParamsError::OutOfRange => write!(
+                f,
+                "KDF parameters out of range (memory {}..={} KiB, iterations 1..={}, parallelism 1..={})",
+                MIN_M_COST_KIB, MAX_M_COST_KIB, MAX_T_COST, MAX_P_COST
+            ),
+        }
+    }
+}
+impl Error for KdfParamsError {}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    Argon2id = 1,
+}
+
+/// 마스터 비밀번호 KDF 설정. `DBHeader`에 그대로 저장됨
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    algorithm: u32,
+    m_cost_kib: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfParams {
+    /// 0.1.x 포맷에 하드코딩되어 있던 값. 기존 DB와 호환되는 기본값
+    pub const LEGACY: Self = Self {
+        algorithm: KdfAlgorithm::Argon2id as u32,
+        m_cost_kib: 128 * 1024,
+        t_cost: 1,
+        p_cost: 12,
+    };
+    /// 헤더에서 "설정 없음"을 나타내는 값
+    pub(crate) const NONE: Self = Self {
+        algorithm: 0,
+        m_cost_kib: 0,
+        t_cost: 0,
+        p_cost: 0,
+    };
+
+    pub fn argon2id(m_cost_kib: u32, t_cost: u32, p_cost: u32) -> Result<Self, KdfParamsError> {
+        let params = Self {
+            algorithm: KdfAlgorithm::Argon2id as u32,
+            m_cost_kib,
+            t_cost,
+            p_cost,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn algorithm(&self) -> Option<KdfAlgorithm> {
+        match self.algorithm {
+            1 => Some(KdfAlgorithm::Argon2id),
+            _ => None,
+        }
+    }
+    pub fn m_cost_kib(&self) -> u32 {
+        self.m_cost_kib
+    }
+    pub fn t_cost(&self) -> u32 {
+        self.t_cost
+    }
+    pub fn p_cost(&self) -> u32 {
+        self.p_cost
+    }
+
+    pub(crate) fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// 파일에서 읽은 값으로 수 TB를 할당하는 일이 없도록 상한도 검사
+    pub(crate) fn validate(&self) -> Result<(), KdfParamsError> {
+        if self.algorithm().is_none() {
+            return Err(KdfParamsError::UnknownAlgorithm);
+        }
+        if !(MIN_M_COST_KIB..=MAX_M_COST_KIB).contains(&self.m_cost_kib)
+            || !(1..=MAX_T_COST).contains(&self.t_cost)
+            || !(1..=MAX_P_COST).contains(&self.p_cost)
+        {
+            return Err(KdfParamsError::OutOfRange);
+        }
+        Ok(())
+    }
+
+    /// 마스터 비밀번호 -> X25519 비밀키
+    pub(crate) fn master_argon2(&self, out_len: usize) -> Argon2<'static> {
+        let params = Params::new(self.m_cost_kib, self.t_cost, self.p_cost, Some(out_len)).unwrap();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    }
+
+    /// X25519 비밀키 -> 세션 키. 기존 포맷처럼 마스터 KDF의 절반 비용을 씀
+    pub(crate) fn session_argon2(&self, out_len: usize) -> Argon2<'static> {
+        let params = Params::new(
+            self.m_cost_kib / 2,
+            self.t_cost,
+            (self.p_cost / 2).max(1),
+            Some(out_len),
+        )
+        .unwrap();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::LEGACY
+    }
+}
+
+impl Display for KdfParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.algorithm() {
+            Some(KdfAlgorithm::Argon2id) => write!(
+                f,
+                "Argon2id (memory: {} KiB, iterations: {}, parallelism: {})",
+                self.m_cost_kib, self.t_cost, self.p_cost
+            ),
+            None => write!(f, "Unknown"),
+        }
+    }
+}
+
+unsafe impl Zeroable for KdfParams {}
+unsafe impl Pod for KdfParams {}
+
+/// 이 기기에서 로그인 한 번(마스터 키 + 세션 키 유도)이 `target` 이상 걸리는 가장 가벼운 설정을 고름.
+/// 메모리는 사용 가능한 RAM의 1/4까지만 늘리고, 그 이후로는 반복 횟수를 늘림
+pub fn benchmark_kdf_params(target: Duration) -> KdfParams {
+    let p_cost = available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1)
+        .clamp(1, MAX_P_COST);
+    let mut sys = System::new();
+    sys.refresh_memory();
+    let memory_cap_kib = (sys.available_memory() / 1024 / 4)
+        .clamp(MIN_M_COST_KIB as u64, MAX_M_COST_KIB as u64) as u32;
+
+    let mut params = KdfParams {
+        algorithm: KdfAlgorithm::Argon2id as u32,
+        m_cost_kib: MIN_M_COST_KIB,
+        t_cost: 1,
+        p_cost,
+    };
+    loop {
+        let elapsed = measure_login_kdf(&params);
+        if elapsed >= target {
+            return params;
+        }
+
+        let scale = (target.as_secs_f64() / elapsed.as_secs_f64().max(0.001)).min(4.0);
+        if params.m_cost_kib < memory_cap_kib {
+            params.m_cost_kib = ((params.m_cost_kib as f64 * scale) as u32)
+                .clamp(params.m_cost_kib + 1, memory_cap_kib);
+        } else if params.t_cost < MAX_T_COST {
+            params.t_cost = ((params.t_cost as f64 * scale).ceil() as u32)
+                .clamp(params.t_cost + 1, MAX_T_COST);
+        } else {
+            return params;
+        }
+    }
+}
+
+fn measure_login_kdf(params: &KdfParams) -> Duration {
+    let salt = [0u8; 32];
+    let mut master_out = [0u8; 32];
+    let mut session_out = [0u8; 32];
+
+    let begin = Instant::now();
+    params
+        .master_argon2(master_out.len())
+        .hash_password_into(b"kdf benchmark", &salt, &mut master_out)
+        .unwrap();
+    params
+        .session_argon2(session_out.len())
+        .hash_password_into(&master_out, &salt, &mut session_out)
+        .unwrap();
+    let elapsed = begin.elapsed();
+
+    hint::black_box(session_out);
+    elapsed
+}
@@ -0,0 +1,94 @@
+use libsodium_sys::rust_wrappings::hasher::Blake2b;
+use libsodium_sys::rust_wrappings::randombytes::random_buf;
+use libsodium_sys::rust_wrappings::sodium_box::SodiumBox;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+use zeroize::Zeroize;
+
+/// 새로 만드는 키 파일의 크기
+pub const GENERATED_KEY_FILE_SIZE: usize = 32;
+pub(crate) const KEY_FILE_DIGEST_SIZE: usize = 64;
+pub(crate) const KEY_FILE_SLOT_SECRET_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub enum KeyFileError {
+    Empty,
+    /// 새 키 파일이 기존 파일을 덮어쓰지 않음
+    AlreadyExists,
+    ReadFailed(io::Error),
+    WriteFailed(io::Error),
+}
+impl Display for KeyFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyFileError::Empty => write!(f, "Key file is empty"),
+            KeyFileError::AlreadyExists => write!(f, "Key file already exists"),
+            KeyFileError::ReadFailed(e) => write!(f, "Failed to read key file: {}", e),
+            KeyFileError::WriteFailed(e) => write!(f, "Failed to write key file: {}", e),
+        }
+    }
+}
+impl Error for KeyFileError {}
+
+/// 키 파일 내용의 BLAKE2b 해시. 파일은 아무 바이트나 될 수 있고, 내용은 들고 있지 않음
+pub struct KeyFile {
+    digest: SodiumBox<u8>,
+}
+impl KeyFile {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeyFileError> {
+        if bytes.is_empty() {
+            return Err(KeyFileError::Empty);
+        }
+        let mut digest: SodiumBox<u8> = SodiumBox::new_with_size(KEY_FILE_DIGEST_SIZE);
+        let mut hasher = Blake2b::<KEY_FILE_DIGEST_SIZE>::new();
+        hasher.update(b"pw-manager key file");
+        hasher.update(bytes);
+        hasher.finalize_write_to(digest.as_mut_ptr());
+        Ok(Self { digest })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, KeyFileError> {
+        let mut bytes = fs::read(path).map_err(KeyFileError::ReadFailed)?;
+        let key_file = Self::from_bytes(&bytes);
+        bytes.zeroize();
+        key_file
+    }
+
+    /// 무작위 키 파일을 `path`에 새로 만듦. 이미 있으면 `AlreadyExists`
+    pub fn generate(path: &Path) -> Result<Self, KeyFileError> {
+        let mut bytes = [0u8; GENERATED_KEY_FILE_SIZE];
+        random_buf(&mut bytes);
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .and_then(|mut file| {
+                file.write_all(&bytes)?;
+                file.sync_all()
+            })
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => KeyFileError::AlreadyExists,
+                _ => KeyFileError::WriteFailed(e),
+            });
+        let key_file = written.and_then(|_| Self::from_bytes(&bytes));
+        bytes.zeroize();
+        key_file
+    }
+
+    /// 키 슬롯을 여는 X25519 비밀 키. `mix`와 달리 키 파일만으로 정해짐
+    pub(crate) fn slot_secret(&self, out: &mut [u8; KEY_FILE_SLOT_SECRET_SIZE]) {
+        let mut hasher = Blake2b::<KEY_FILE_SLOT_SECRET_SIZE>::new_keyed(self.digest.as_ptr(), KEY_FILE_DIGEST_SIZE);
+        hasher.update(b"pw-manager key slot");
+        hasher.finalize_write_to(out.as_mut_ptr());
+    }
+
+    /// 마스터 비밀번호와 섞어 KDF의 입력을 만듦
+    pub(crate) fn mix(&self, master_pw: &str, out: &mut [u8; KEY_FILE_DIGEST_SIZE]) {
+        let mut hasher = Blake2b::<KEY_FILE_DIGEST_SIZE>::new_keyed(self.digest.as_ptr(), KEY_FILE_DIGEST_SIZE);
+        hasher.update(master_pw.as_bytes());
+        hasher.finalize_write_to(out.as_mut_ptr());
+    }
+}
@@ -0,0 +1,459 @@
+//! LUKS처럼 무작위 볼트 비밀 키를 자격 증명마다 따로 감싸 헤더의 키 슬롯 표에 넣음.
+//! 마스터 비밀번호도 `MASTER_SLOT`에 있는 비밀번호 슬롯이라 바꾸면 그 슬롯만 다시 감쌈.
+//! 슬롯을 더하거나 이름을 바꾸거나 없애도 항목은 다시 암호화하지 않음.
+//! 헤더는 DB의 associated data이므로 슬롯 표를 바꾸는 함수(`master_secrets`의 마스터 비밀번호 슬롯 변경 포함)를
+//! 부른 뒤에는 DB를 다시 암호화해 즉시 저장해야 함
+
+use crate::base32;
+use crate::header::{DBHeader, KEY_SLOT_COUNT, KEY_SLOT_LABEL_LEN, KeySlot, MASTER_SLOT};
+use crate::kdf::KdfParams;
+use crate::key_file::{KEY_FILE_SLOT_SECRET_SIZE, KeyFile};
+use crate::master_secrets::{MasterPWError, manual_zeroize, master_pw_kdf, unlock_with_master_pw, vault_keys};
+use crate::recovery::{
+    RecoveryKey, X25519_KEY_SIZE, open_slot, pub_key_to_bytes, rewrap_recovery_slot, seal_slot, shares_key_pair,
+};
+use crate::shamir::{SHARE_SECRET_SIZE, ShamirError, Share, combine_shares, split_secret};
+use crate::user_secrets::{SessionKeyNonce, WrappedSessionKey};
+use libsodium_sys::rust_wrappings::hasher::Blake2b;
+use libsodium_sys::rust_wrappings::randombytes::random_buf;
+use libsodium_sys::rust_wrappings::x25519::*;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use zeroize::Zeroizing;
+
+/// 팀원 공개키를 잘못 옮겨 적은 경우를 잡는 용도
+const TEAMMATE_KEY_CHECKSUM_SIZE: usize = 4;
+const TEAMMATE_KEY_GROUP_LEN: usize = 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeySlotKind {
+    /// 슬롯에 적힌 salt와 KDF 설정으로 유도함. 마스터 비밀번호도 이 종류임
+    Password,
+    /// 키 파일만으로 엶
+    KeyFile,
+    /// 비상 키트 형식의 복구 코드
+    RecoveryCode,
+    /// 팀원이 알려 준 공개키. 팀원은 자기 키 파일로 엶
+    Teammate,
+    /// 필요한 수만큼 모은 복구 조각
+    RecoveryShares,
+    /// 비밀번호와 키 파일을 함께 KDF에 넣음. 키 파일을 정한 마스터 비밀번호 슬롯
+    PasswordAndKeyFile,
+}
+impl KeySlotKind {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(KeySlotKind::Password),
+            2 => Some(KeySlotKind::KeyFile),
+            3 => Some(KeySlotKind::RecoveryCode),
+            4 => Some(KeySlotKind::Teammate),
+            5 => Some(KeySlotKind::RecoveryShares),
+            6 => Some(KeySlotKind::PasswordAndKeyFile),
+            _ => None,
+        }
+    }
+    pub(crate) fn to_raw(self) -> u32 {
+        match self {
+            KeySlotKind::Password => 1,
+            KeySlotKind::KeyFile => 2,
+            KeySlotKind::RecoveryCode => 3,
+            KeySlotKind::Teammate => 4,
+            KeySlotKind::RecoveryShares => 5,
+            KeySlotKind::PasswordAndKeyFile => 6,
+        }
+    }
+
+    /// 슬롯에 salt와 KDF 설정이 있는 종류
+    pub fn is_password(self) -> bool {
+        matches!(self, KeySlotKind::Password | KeySlotKind::PasswordAndKeyFile)
+    }
+    /// 마스터 비밀번호를 잊었을 때 쓰는 종류. 이걸로 열면 새 마스터 비밀번호를 정해야 함
+    pub fn is_recovery(self) -> bool {
+        matches!(self, KeySlotKind::RecoveryCode | KeySlotKind::RecoveryShares)
+    }
+}
+impl Display for KeySlotKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySlotKind::Password => write!(f, "password"),
+            KeySlotKind::KeyFile => write!(f, "key file"),
+            KeySlotKind::RecoveryCode => write!(f, "recovery code"),
+            KeySlotKind::Teammate => write!(f, "teammate"),
+            KeySlotKind::RecoveryShares => write!(f, "recovery shares"),
+            KeySlotKind::PasswordAndKeyFile => write!(f, "password + key file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum KeySlotError {
+    NoFreeSlot,
+    /// 범위 밖이거나 빈 슬롯
+    NoSuchSlot(usize),
+    /// 비어 있거나 `KEY_SLOT_LABEL_LEN` 바이트보다 김
+    InvalidLabel,
+    InvalidTeammateKey,
+    /// 슬롯 종류로 열 수 없는 자격 증명
+    WrongCredential(KeySlotKind),
+    IncorrectCredential,
+    /// 마스터 비밀번호 슬롯은 비울 수 없고 `change_master_pw`로만 바꿈
+    MasterSlot,
+    Shares(ShamirError),
+    MasterPW(MasterPWError),
+}
+impl Display for KeySlotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySlotError::NoFreeSlot => write!(f, "All {} key slots are in use", KEY_SLOT_COUNT),
+            KeySlotError::NoSuchSlot(index) => write!(f, "Key slot {} is empty", index),
+            KeySlotError::InvalidLabel => {
+                write!(f, "Key slot label must be 1 to {} bytes", KEY_SLOT_LABEL_LEN)
+            }
+            KeySlotError::InvalidTeammateKey => write!(f, "Not a teammate public key"),
+            KeySlotError::WrongCredential(kind) => write!(f, "This key slot opens with a {}", kind),
+            KeySlotError::IncorrectCredential => write!(f, "Credential does not match this key slot"),
+            KeySlotError::MasterSlot => {
+                write!(f, "Key slot {} holds the master password. change the master password instead", MASTER_SLOT)
+            }
+            KeySlotError::Shares(err) => write!(f, "{}", err),
+            KeySlotError::MasterPW(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl Error for KeySlotError {}
+impl From<MasterPWError> for KeySlotError {
+    fn from(err: MasterPWError) -> Self {
+        KeySlotError::MasterPW(err)
+    }
+}
+impl From<ShamirError> for KeySlotError {
+    fn from(err: ShamirError) -> Self {
+        KeySlotError::Shares(err)
+    }
+}
+
+/// `list_key_slots`가 돌려주는 슬롯 하나. 번호는 헤더의 표에서의 위치
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeySlotInfo {
+    pub index: usize,
+    pub kind: KeySlotKind,
+    pub label: String,
+}
+
+/// 팀원의 키 파일에서 유도한 공개키. 팀원은 키 파일 대신 이 텍스트만 보내면 됨
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TeammateKey {
+    pub_key: [u8; X25519_KEY_SIZE],
+}
+impl TeammateKey {
+    pub fn from_key_file(key_file: &KeyFile) -> Self {
+        let (sec_key, pub_key) = key_file_key_pair(key_file);
+        drop(sec_key);
+        Self { pub_key }
+    }
+
+    /// 대소문자, 공백, `-`는 가리지 않음
+    pub fn parse(input: &str) -> Result<Self, KeySlotError> {
+        let decoded = base32::decode(input.trim()).ok_or(KeySlotError::InvalidTeammateKey)?;
+        let Some((pub_key, checksum)) = decoded.split_first_chunk::<X25519_KEY_SIZE>() else {
+            return Err(KeySlotError::InvalidTeammateKey);
+        };
+        if checksum != teammate_key_checksum(pub_key) {
+            return Err(KeySlotError::InvalidTeammateKey);
+        }
+        Ok(Self { pub_key: *pub_key })
+    }
+
+    /// `ABCD-EFGH-...` 꼴
+    pub fn to_text(&self) -> String {
+        let mut bytes = self.pub_key.to_vec();
+        bytes.extend_from_slice(&teammate_key_checksum(&self.pub_key));
+        base32::encode_grouped(&bytes, TEAMMATE_KEY_GROUP_LEN).to_string()
+    }
+}
+
+fn teammate_key_checksum(pub_key: &[u8; X25519_KEY_SIZE]) -> [u8; TEAMMATE_KEY_CHECKSUM_SIZE] {
+    let mut hasher = Blake2b::<16>::new();
+    hasher.update(b"pw-manager teammate key");
+    hasher.update(pub_key);
+    let mut digest = [0u8; 16];
+    hasher.finalize_write_to(digest.as_mut_ptr());
+    std::array::from_fn(|i| digest[i])
+}
+
+/// 키 파일 슬롯과 팀원 슬롯은 같은 키 쌍을 씀. 팀원 슬롯은 키 파일 없이 공개키만으로 만들 수 있음
+fn key_file_key_pair(key_file: &KeyFile) -> (SecKey, [u8; X25519_KEY_SIZE]) {
+    let mut raw = [0u8; KEY_FILE_SLOT_SECRET_SIZE];
+    key_file.slot_secret(&mut raw);
+    let sec_key = SecKey::from_raw(raw.as_ptr());
+    manual_zeroize(&mut raw);
+    let pub_key = pub_key_to_bytes(&PubKey::from_sec_key(&sec_key));
+    (sec_key, pub_key)
+}
+
+/// 빈 슬롯은 건너뜀. 마스터 비밀번호 슬롯이 맨 앞에 옴
+pub fn list_key_slots(header: &DBHeader) -> Vec<KeySlotInfo> {
+    header
+        .key_slots
+        .iter()
+        .enumerate()
+        .filter_map(|(index, slot)| {
+            Some(KeySlotInfo {
+                index,
+                kind: slot.kind()?,
+                label: slot_label(slot),
+            })
+        })
+        .collect()
+}
+
+/// `parse_header`가 종류와 UTF-8을 확인함
+fn slot_label(slot: &KeySlot) -> String {
+    let len = slot.label.iter().position(|&b| b == 0).unwrap_or(KEY_SLOT_LABEL_LEN);
+    String::from_utf8_lossy(&slot.label[..len]).into_owned()
+}
+
+fn encode_label(label: &str) -> Result<[u8; KEY_SLOT_LABEL_LEN], KeySlotError> {
+    let label = label.trim();
+    if label.is_empty() || label.len() > KEY_SLOT_LABEL_LEN || label.contains('\0') {
+        return Err(KeySlotError::InvalidLabel);
+    }
+    let mut out = [0u8; KEY_SLOT_LABEL_LEN];
+    out[..label.len()].copy_from_slice(label.as_bytes());
+    Ok(out)
+}
+
+/// 볼트 비밀 키를 새 salt와 `kdf_params`로 유도한 비밀번호 키로 감쌈. 키 파일을 주면 함께 KDF에 넣음.
+/// 이름은 비워 두므로 호출자가 채움
+pub(crate) fn password_slot(
+    pw: &str,
+    kdf_params: &KdfParams,
+    key_file: Option<&KeyFile>,
+    vault_key: &SecKey,
+) -> KeySlot {
+    let mut slot = KeySlot::EMPTY;
+    slot.kind = match key_file {
+        None => KeySlotKind::Password,
+        Some(_) => KeySlotKind::PasswordAndKeyFile,
+    }
+    .to_raw();
+    random_buf(slot.salt.as_mut_slice());
+    slot.kdf_params = *kdf_params;
+    let (slot_sec_key, slot_pub_key) = password_key_pair(&slot, pw, key_file);
+    drop(slot_sec_key);
+    slot.sealed = seal_slot(&slot_pub_key, vault_key);
+    slot
+}
+
+fn password_key_pair(slot: &KeySlot, pw: &str, key_file: Option<&KeyFile>) -> (SecKey, [u8; X25519_KEY_SIZE]) {
+    let slot_sec_key = master_pw_kdf(pw.trim(), &slot.salt, &slot.kdf_params, key_file);
+    let slot_pub_key = pub_key_to_bytes(&PubKey::from_sec_key(&slot_sec_key));
+    (slot_sec_key, slot_pub_key)
+}
+
+/// 자격 증명을 확인한 뒤 슬롯에 넣는 키. 비밀번호는 헤더의 지금 KDF 설정으로 유도함
+pub enum NewKeySlot<'a> {
+    Password(&'a mut String),
+    KeyFile(&'a KeyFile),
+    Teammate(&'a TeammateKey),
+}
+
+/// 마스터 비밀번호를 확인하고 빈 슬롯에 새 자격 증명을 넣음. 넣은 슬롯 번호를 돌려줌
+pub fn add_key_slot(
+    header: &mut DBHeader,
+    master_pw: &mut String,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+    label: &str,
+    new_slot: NewKeySlot,
+) -> Result<usize, KeySlotError> {
+    let sec_key = unlock_with_master_pw(master_pw, header, key_file, pub_key);
+    manual_zeroize(master_pw);
+    let checked = (|| -> Result<_, KeySlotError> { Ok((encode_label(label)?, free_slot(header)?, sec_key?)) })();
+    let (label, index, sec_key) = match checked {
+        Ok(v) => v,
+        Err(err) => {
+            if let NewKeySlot::Password(new_pw) = new_slot {
+                manual_zeroize(new_pw);
+            }
+            return Err(err);
+        }
+    };
+
+    let mut slot = match new_slot {
+        NewKeySlot::Password(new_pw) => {
+            let slot = password_slot(new_pw, &header.kdf_params, None, &sec_key);
+            manual_zeroize(new_pw);
+            slot
+        }
+        NewKeySlot::KeyFile(slot_key_file) => {
+            sealed_slot(KeySlotKind::KeyFile, &TeammateKey::from_key_file(slot_key_file).pub_key, &sec_key)
+        }
+        NewKeySlot::Teammate(teammate_key) => sealed_slot(KeySlotKind::Teammate, &teammate_key.pub_key, &sec_key),
+    };
+    slot.label = label;
+    header.key_slots[index] = slot;
+    Ok(index)
+}
+
+/// `add_key_slot`과 같지만 새 복구 코드를 만들어 넣고 돌려줌. 비상 키트로 보여주면 됨
+pub fn add_recovery_code_slot(
+    header: &mut DBHeader,
+    master_pw: &mut String,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+    label: &str,
+) -> Result<(usize, RecoveryKey), KeySlotError> {
+    let sec_key = unlock_with_master_pw(master_pw, header, key_file, pub_key);
+    manual_zeroize(master_pw);
+    let label = encode_label(label)?;
+    let index = free_slot(header)?;
+    let sec_key = sec_key?;
+
+    let recovery_key = RecoveryKey::generate();
+    let (recovery_sec_key, recovery_pub_key) = recovery_key.key_pair();
+    drop(recovery_sec_key);
+    let mut slot = sealed_slot(KeySlotKind::RecoveryCode, &recovery_pub_key, &sec_key);
+    slot.label = label;
+    header.key_slots[index] = slot;
+    Ok((index, recovery_key))
+}
+
+/// `add_key_slot`과 같지만 무작위 비밀을 `count`개 조각으로 나눠, 아무 `threshold`개로 열리는 슬롯을 넣음.
+/// 조각마다 `share_sheet`로 안내문을 만들어 나눠 주면 됨
+pub fn add_recovery_shares_slot(
+    header: &mut DBHeader,
+    master_pw: &mut String,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+    label: &str,
+    threshold: u8,
+    count: u8,
+) -> Result<(usize, Vec<Share>), KeySlotError> {
+    let sec_key = unlock_with_master_pw(master_pw, header, key_file, pub_key);
+    manual_zeroize(master_pw);
+    let label = encode_label(label)?;
+    let index = free_slot(header)?;
+    let sec_key = sec_key?;
+
+    let mut secret = Zeroizing::new([0u8; SHARE_SECRET_SIZE]);
+    random_buf(secret.as_mut_slice());
+    let shares = split_secret(&secret, threshold, count)?;
+    let (shares_sec_key, shares_pub_key) = shares_key_pair(&secret);
+    drop(shares_sec_key);
+    let mut slot = sealed_slot(KeySlotKind::RecoveryShares, &shares_pub_key, &sec_key);
+    slot.label = label;
+    header.key_slots[index] = slot;
+    Ok((index, shares))
+}
+
+fn sealed_slot(kind: KeySlotKind, slot_pub_key: &[u8; X25519_KEY_SIZE], vault_key: &SecKey) -> KeySlot {
+    let mut slot = KeySlot::EMPTY;
+    slot.kind = kind.to_raw();
+    slot.sealed = seal_slot(slot_pub_key, vault_key);
+    slot
+}
+
+fn free_slot(header: &DBHeader) -> Result<usize, KeySlotError> {
+    header.key_slots.iter().position(KeySlot::is_empty).ok_or(KeySlotError::NoFreeSlot)
+}
+
+fn occupied_slot(header: &DBHeader, index: usize) -> Result<&KeySlot, KeySlotError> {
+    header
+        .key_slots
+        .get(index)
+        .filter(|slot| !slot.is_empty())
+        .ok_or(KeySlotError::NoSuchSlot(index))
+}
+
+/// 이름은 슬롯을 구분하는 용도일 뿐이라 마스터 비밀번호를 묻지 않음
+pub fn label_key_slot(header: &mut DBHeader, index: usize, label: &str) -> Result<(), KeySlotError> {
+    occupied_slot(header, index)?;
+    header.key_slots[index].label = encode_label(label)?;
+    Ok(())
+}
+
+/// 마스터 비밀번호를 확인하고 슬롯을 비움. 마스터 비밀번호 슬롯은 비울 수 없음
+pub fn revoke_key_slot(
+    header: &mut DBHeader,
+    master_pw: &mut String,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+    index: usize,
+) -> Result<(), KeySlotError> {
+    let sec_key = unlock_with_master_pw(master_pw, header, key_file, pub_key);
+    manual_zeroize(master_pw);
+    if index == MASTER_SLOT {
+        return Err(KeySlotError::MasterSlot);
+    }
+    occupied_slot(header, index)?;
+    drop(sec_key?);
+
+    header.key_slots[index] = KeySlot::EMPTY;
+    Ok(())
+}
+
+/// 슬롯을 여는 자격 증명. 키 파일 슬롯과 팀원 슬롯은 모두 키 파일로 엶
+pub enum SlotCredential<'a> {
+    Password(&'a str),
+    PasswordAndKeyFile(&'a str, &'a KeyFile),
+    KeyFile(&'a KeyFile),
+    RecoveryCode(&'a RecoveryKey),
+    Shares(&'a [Share]),
+}
+
+/// 슬롯 하나로 `general_login`과 같은 키들을 얻음. 마스터 비밀번호 슬롯도 이 번호로 열 수 있음
+pub fn key_slot_login(
+    header: &DBHeader,
+    index: usize,
+    credential: &SlotCredential,
+) -> Result<(SecKey, PubKey, WrappedSessionKey, SessionKeyNonce), KeySlotError> {
+    let sec_key = open_key_slot(header, index, credential)?;
+    Ok(vault_keys(header, sec_key))
+}
+
+/// 슬롯을 열어 볼트 비밀 키를 꺼냄
+pub(crate) fn open_key_slot(header: &DBHeader, index: usize, credential: &SlotCredential) -> Result<SecKey, KeySlotError> {
+    let slot = occupied_slot(header, index)?;
+    let kind = slot.kind().ok_or(KeySlotError::NoSuchSlot(index))?;
+    let (slot_sec_key, slot_pub_key) = match (kind, credential) {
+        (KeySlotKind::Password, SlotCredential::Password(pw)) => password_key_pair(slot, pw, None),
+        (KeySlotKind::PasswordAndKeyFile, SlotCredential::PasswordAndKeyFile(pw, key_file)) => {
+            password_key_pair(slot, pw, Some(key_file))
+        }
+        (KeySlotKind::KeyFile | KeySlotKind::Teammate, SlotCredential::KeyFile(key_file)) => {
+            key_file_key_pair(key_file)
+        }
+        (KeySlotKind::RecoveryCode, SlotCredential::RecoveryCode(recovery_key)) => recovery_key.key_pair(),
+        (KeySlotKind::RecoveryShares, SlotCredential::Shares(shares)) => shares_key_pair(&*combine_shares(shares)?),
+        _ => return Err(KeySlotError::WrongCredential(kind)),
+    };
+    // 0.2.8 이전에는 마스터 비밀번호에서 유도한 키가 곧 볼트 비밀 키였음. 맞는지는 복호화할 때 확인됨
+    if index == MASTER_SLOT && !header.has_vault_key() {
+        return Ok(slot_sec_key);
+    }
+    if slot_pub_key != slot.sealed.recovery_pub_key {
+        return Err(KeySlotError::IncorrectCredential);
+    }
+    let sec_key = open_slot(&slot.sealed, &slot_sec_key).ok_or(KeySlotError::IncorrectCredential)?;
+    drop(slot_sec_key);
+    Ok(sec_key)
+}
+
+/// 0.2.8로 올릴 때 무작위 볼트 비밀 키를 모든 슬롯에 감쌈. 마스터 비밀번호 슬롯은 지금까지 비밀번호에서 유도하던
+/// 비밀 키의 공개키로, 다른 슬롯은 같은 자격 증명으로 열리도록 감싸고 빈 번호 없이 앞으로 모음
+pub(crate) fn seal_vault_key(header: &mut DBHeader, legacy_pub_key: &PubKey, vault_key: &SecKey) {
+    let mut slots = [KeySlot::EMPTY; KEY_SLOT_COUNT];
+    slots[MASTER_SLOT] = KeySlot {
+        sealed: seal_slot(&pub_key_to_bytes(legacy_pub_key), vault_key),
+        ..header.key_slots[MASTER_SLOT]
+    };
+    let others = header.key_slots.iter().enumerate().filter(|(index, slot)| *index != MASTER_SLOT && !slot.is_empty());
+    for (new_slot, (_, slot)) in slots[MASTER_SLOT + 1..].iter_mut().zip(others) {
+        *new_slot = KeySlot {
+            sealed: rewrap_recovery_slot(&slot.sealed, vault_key),
+            ..*slot
+        };
+    }
+    header.key_slots = slots;
+}
@@ -3,11 +3,30 @@
 #[cfg(not(target_pointer_width = "64"))]
 compile_error!("이 코드는 64비트 환경(usize가 8바이트)에서만 컴파일됩니다.");
 
+pub mod audit;
+pub mod backup;
+mod base32;
+pub mod breach;
 pub mod data_base;
 pub mod file_io;
+pub mod generator;
 pub mod header;
+pub mod kdf;
+pub mod key_file;
+pub mod key_slots;
 pub mod master_secrets;
+pub mod metadata;
+pub mod migration;
+pub mod organize;
+pub mod otp;
+pub mod pw_policy;
+pub mod recovery;
+pub mod search;
+pub mod shamir;
+mod sha1;
+pub mod trash;
 pub mod user_secrets;
+pub mod vault_path;
 
 pub use libsodium_sys as sodium;
 pub use sodium::rust_wrappings::*;
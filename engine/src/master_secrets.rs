@@ -1,15 +1,16 @@
-use crate::data_base::{DB, DBIOError, change_user_pw, get_user_pw};
-use crate::header::Salt;
+use crate::data_base::DB;
+use crate::header::{DBHeader, MASTER_SLOT, MASTER_SLOT_LABEL, Salt};
+use crate::kdf::KdfParams;
+use crate::key_file::{KEY_FILE_DIGEST_SIZE, KeyFile};
+use crate::key_slots::{SlotCredential, open_key_slot, password_slot};
+use crate::pw_policy::{MasterPWPolicy, PWFeedback, StrengthEstimate, char_count, estimate_strength};
 use crate::user_secrets::{
     SESSION_KEY_SIZE, SessionKey, SessionKeyNonce, WrappedSessionKey, wrap_session_key,
 };
-use argon2::password_hash::rand_core;
-use argon2::{Argon2, Params};
-use libsodium_sys::rust_wrappings::aes256gcm::{AES_NONCE_SIZE, AesNonce, get_aes256gcm_ciphertext_len, get_aes256gcm_plaintext_len, aes256gcm_decrypt_write_to_ptr, aes256gcm_encrypt_write_to_ptr};
+use libsodium_sys::rust_wrappings::aes256gcm::{AES_NONCE_SIZE, AesNonce, get_aes256gcm_ciphertext_len, get_aes256gcm_plaintext_len, aes256gcm_decrypt_with_ad_write_to_ptr, aes256gcm_encrypt_with_ad_write_to_ptr};
+use libsodium_sys::rust_wrappings::hasher::Blake2b;
 use libsodium_sys::rust_wrappings::init::sodium_init;
 use libsodium_sys::rust_wrappings::x25519::*;
-use rand_core::OsRng;
-use rand_core::RngCore;
 use rkyv::rancor::Error;
 use std::error::Error as StdError;
 use std::fmt::{Debug, Display, Formatter};
@@ -18,6 +19,8 @@ use std::{hint, ptr};
 use std::alloc::GlobalAlloc;
 use std::arch::x86_64::_mm_clflush;
 use std::sync::atomic::{fence, Ordering};
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
 use rkyv::util::AlignedVec;
 use secrecy::{ExposeSecret, ExposeSecretMut, SecretBox};
 use zeroize::{Zeroize};
@@ -27,11 +30,20 @@ use libsodium_sys::rust_wrappings::sodium_box::SodiumBox;
 pub enum MasterPWError {
     // 생성
     Empty,
-    TooShort,
+    /// 최소 글자 수
+    TooShort(usize),
     ContainsWhitespace,
+    TooWeak(PWFeedback),
 
     // 로그인
+    /// salt나 KDF 설정을 바꾼 헤더는 다른 키를 유도하므로 비밀번호가 틀린 경우와 구분할 수 없음
     IncorrectPW,
+    /// 키는 맞지만 헤더나 암호문이 바뀌었거나 잘림
+    TamperedHeader,
+    /// 헤더는 키 파일을 요구하는데 주지 않음
+    KeyFileRequired,
+    /// 키 파일을 쓰지 않는 볼트에 키 파일을 줌
+    KeyFileNotUsed,
 
     // 프로세스 유효성
     InvalidSession,
@@ -42,14 +54,26 @@ impl Display for MasterPWError {
             MasterPWError::Empty => {
                 write!(f, "Empty")
             }
-            MasterPWError::TooShort => {
-                write!(f, "TooShort")
+            MasterPWError::TooShort(min_chars) => {
+                write!(f, "TooShort: at least {} characters", min_chars)
             }
             MasterPWError::ContainsWhitespace => {
-                write!(f, "ContainsWhitespace")
+                write!(f, "ContainsWhitespace: only plain spaces are allowed")
+            }
+            MasterPWError::TooWeak(feedback) => {
+                write!(f, "TooWeak: {}", feedback)
             }
             MasterPWError::IncorrectPW => {
-                write!(f, "IncorrectPW")
+                write!(f, "IncorrectPW: wrong password or modified header")
+            }
+            MasterPWError::TamperedHeader => {
+                write!(f, "TamperedHeader")
+            }
+            MasterPWError::KeyFileRequired => {
+                write!(f, "KeyFileRequired: this vault also needs its key file")
+            }
+            MasterPWError::KeyFileNotUsed => {
+                write!(f, "KeyFileNotUsed: this vault does not use a key file")
             }
             MasterPWError::InvalidSession => {
                 write!(f, "InvalidSession")
@@ -58,56 +82,83 @@ impl Display for MasterPWError {
     }
 }
 impl StdError for MasterPWError {}
+/// 로그인할 때의 검사. 정책이 바뀌어도 기존 비밀번호로 열 수 있어야 하므로 비어 있는지만 봄
 #[inline(always)]
 pub fn master_pw_validation(raw_pw: &String) -> Result<(), MasterPWError> {
+    if raw_pw.trim().is_empty() {
+        return Err(MasterPWError::Empty);
+    }
+    Ok(())
+}
+
+/// 새 마스터 비밀번호를 정할 때의 검사. 통과하면 강도 추정 결과를 돌려줌.
+/// 탭, 줄바꿈, 전각 공백처럼 입력 방법에 따라 다른 바이트가 되는 공백은 막고 일반 공백만 허용함
+pub fn check_new_master_pw(raw_pw: &str, policy: &MasterPWPolicy) -> Result<StrengthEstimate, MasterPWError> {
     let trimmed = raw_pw.trim();
 
     if trimmed.is_empty() {
         return Err(MasterPWError::Empty);
     }
-    if trimmed.len() < 8 {
-        return Err(MasterPWError::TooShort);
+    if trimmed.chars().any(|c| c.is_whitespace() && c != ' ') {
+        return Err(MasterPWError::ContainsWhitespace);
+    }
+    if char_count(trimmed) < policy.min_chars {
+        return Err(MasterPWError::TooShort(policy.min_chars));
+    }
+    let estimate = estimate_strength(trimmed);
+    if estimate.score() < policy.min_score {
+        return Err(MasterPWError::TooWeak(estimate.feedback));
     }
-    if trimmed.chars().any(|c| c.is_whitespace()) {}
 
-    Ok(())
+    Ok(estimate)
 }
 
+/// 헤더가 키 파일을 요구하는지와 로그인에 준 키 파일이 맞는지 확인함. 키 파일의 내용이 맞는지는 복호화해야 알 수 있음
+pub fn check_key_file(header: &DBHeader, key_file: Option<&KeyFile>) -> Result<(), MasterPWError> {
+    match (header.requires_key_file(), key_file.is_some()) {
+        (true, false) => Err(MasterPWError::KeyFileRequired),
+        (false, true) => Err(MasterPWError::KeyFileNotUsed),
+        _ => Ok(()),
+    }
+}
+
+/// 키 파일이 있으면 비밀번호 대신 키 파일 해시로 keyed BLAKE2b한 값을 KDF에 넣음
 #[inline(always)]
-fn master_pw_kdf(master_pw: &str, salt: &Salt) -> SecKey {
-    let params = Params::new(
-        128 * 1024, // 메모리 요구량 (KB 단위)
-        1,          // 반복 횟수
-        12,         // 병렬 처리 수준
-        Some(32),   // 출력 길이
-    )
-    .unwrap();
-    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+pub(crate) fn master_pw_kdf(master_pw: &str, salt: &Salt, kdf_params: &KdfParams, key_file: Option<&KeyFile>) -> SecKey {
+    let argon2 = kdf_params.master_argon2(ECIES_SK_SIZE);
     let mut kdf_out = [0u8; ECIES_SK_SIZE];
     hint::black_box(kdf_out.as_mut_ptr());
-    argon2
-        .hash_password_into(master_pw.as_bytes(), salt, kdf_out.as_mut())
-        .unwrap();
+    match key_file {
+        None => argon2
+            .hash_password_into(master_pw.as_bytes(), salt, kdf_out.as_mut())
+            .unwrap(),
+        Some(key_file) => {
+            let mut kdf_in = [0u8; KEY_FILE_DIGEST_SIZE];
+            hint::black_box(kdf_in.as_mut_ptr());
+            key_file.mix(master_pw, &mut kdf_in);
+            argon2
+                .hash_password_into(kdf_in.as_slice(), salt, kdf_out.as_mut())
+                .unwrap();
+            manual_zeroize(&mut kdf_in);
+        }
+    }
     let sec_key = SecKey::from_raw(kdf_out.as_ptr());
     manual_zeroize(&mut kdf_out);
     sec_key
 }
 
+/// 0.2.8 이전 포맷의 세션 키. 비밀 키를 마스터 비밀번호에서 유도했으므로 세션 키도 KDF로 유도함
 #[inline]
-fn get_wrapped_session_key(sec_key: &SecKey) -> (WrappedSessionKey, SessionKeyNonce) {
+pub(crate) fn get_wrapped_session_key(
+    sec_key: &SecKey,
+    kdf_params: &KdfParams,
+) -> (WrappedSessionKey, SessionKeyNonce) {
     let halo = [
         40u8, 167, 39, 179, 72, 65, 122, 230, 190, 236, 125, 99, 81, 178, 50, 71, 35, 205, 141,
         170, 74, 54, 227, 7, 92, 208, 212, 206, 126, 216, 55, 37,
     ];
 
-    let params = Params::new(
-        64 * 1024,              // 메모리 요구량 (KB 단위)
-        1,                      // 반복 횟수
-        6,                      // 병렬 처리 수준
-        Some(SESSION_KEY_SIZE), // 출력 길이
-    )
-    .unwrap();
-    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let argon2 = kdf_params.session_argon2(SESSION_KEY_SIZE);
 
     let mut rust_owned_sec_key = [0u8; ECIES_SK_SIZE];
     hint::black_box(rust_owned_sec_key.as_mut_ptr());
@@ -128,75 +179,144 @@ fn get_wrapped_session_key(sec_key: &SecKey) -> (WrappedSessionKey, SessionKeyNo
     wrap_session_key(session_key)
 }
 
-pub fn general_login(
-    master_pw: &mut String,
-    salt: &Salt,
-) -> (SecKey, PubKey, WrappedSessionKey, SessionKeyNonce) {
-    let sec_key = master_pw_kdf(master_pw, salt);
-    manual_zeroize(master_pw);
-    let pub_key = PubKey::from_sec_key(&sec_key);
-    let (wrapped_session_key, session_key_nonce) = get_wrapped_session_key(&sec_key);
+/// 무작위 볼트 비밀 키의 세션 키. 키가 이미 무작위이므로 KDF 없이 해시 한 번으로 유도함
+pub(crate) fn vault_session_key(vault_key: &SecKey) -> (WrappedSessionKey, SessionKeyNonce) {
+    let halo = [
+        203u8, 14, 97, 238, 61, 150, 33, 172, 9, 88, 214, 121, 66, 190, 5, 147, 226, 79, 18, 133,
+        250, 43, 108, 199, 57, 164, 30, 91, 242, 116, 3, 184,
+    ];
+
+    let mut rust_owned_sec_key = [0u8; ECIES_SK_SIZE];
+    hint::black_box(rust_owned_sec_key.as_mut_ptr());
+    vault_key.copy_to(rust_owned_sec_key.as_mut_ptr());
+    let mut hasher = Blake2b::<{ SESSION_KEY_SIZE }>::new();
+    hasher.update(&halo);
+    hasher.update(&rust_owned_sec_key);
+    manual_zeroize(&mut rust_owned_sec_key);
+    let mut rust_owned_sess_key = [0u8; SESSION_KEY_SIZE];
+    hint::black_box(rust_owned_sess_key.as_mut_ptr());
+    hasher.finalize_write_to(rust_owned_sess_key.as_mut_ptr());
+    let session_key = SessionKey::from_raw(rust_owned_sess_key.as_ptr());
+    manual_zeroize(&mut rust_owned_sess_key);
+
+    wrap_session_key(session_key)
+}
 
+/// 볼트 비밀 키에서 공개키와 세션 키를 만듦. 이름 키는 세션 키에서 유도됨
+pub(crate) fn vault_keys(header: &DBHeader, sec_key: SecKey) -> (SecKey, PubKey, WrappedSessionKey, SessionKeyNonce) {
+    let pub_key = PubKey::from_sec_key(&sec_key);
+    let (wrapped_session_key, session_key_nonce) = if header.has_vault_key() {
+        vault_session_key(&sec_key)
+    } else {
+        get_wrapped_session_key(&sec_key, &header.kdf_params)
+    };
     (sec_key, pub_key, wrapped_session_key, session_key_nonce)
 }
-pub fn first_login(master_pw: &mut String) -> (PubKey, Salt, WrappedSessionKey, SessionKeyNonce) {
-    let mut salt = Salt::default();
-    OsRng.fill_bytes(salt.as_mut_slice());
-    let sec_key = master_pw_kdf(master_pw.trim(), &salt);
 
+/// 마스터 비밀번호 슬롯을 열어 볼트 비밀 키를 얻음. 돌려받은 비밀 키는 마스터 비밀번호를 바꿀 때 쓰므로 로그인한 동안 들고 있음
+pub fn general_login(
+    master_pw: &mut String,
+    header: &DBHeader,
+    key_file: Option<&KeyFile>,
+) -> Result<(SecKey, PubKey, WrappedSessionKey, SessionKeyNonce), MasterPWError> {
+    let sec_key = open_master_slot(master_pw, header, key_file);
     manual_zeroize(master_pw);
+    Ok(vault_keys(header, sec_key?))
+}
 
-    let pub_key = PubKey::from_sec_key(&sec_key);
-    let (wrapped_session_key, session_key_nonce) = get_wrapped_session_key(&sec_key);
-    drop(sec_key);
+/// 무작위 볼트 비밀 키를 만들고 마스터 비밀번호 슬롯에 감쌈. 키 파일을 주면 이 볼트는 키 파일도 있어야 열림
+pub fn first_login(
+    master_pw: &mut String,
+    header: &mut DBHeader,
+    key_file: Option<&KeyFile>,
+) -> (SecKey, PubKey, WrappedSessionKey, SessionKeyNonce) {
+    let vault_key = SecKey::gen_rand();
+    let mut slot = password_slot(master_pw, &header.kdf_params, key_file, &vault_key);
+    manual_zeroize(master_pw);
+    slot.set_label(MASTER_SLOT_LABEL);
+    header.key_slots[MASTER_SLOT] = slot;
 
-    (pub_key, salt, wrapped_session_key, session_key_nonce)
+    vault_keys(header, vault_key)
 }
+
+/// 마스터 비밀번호 슬롯만 새 salt와 헤더의 KDF 설정으로 다시 감싸고 키 파일 여부를 바꿈.
+/// 볼트 비밀 키, 항목, 다른 슬롯은 그대로임
 pub fn change_master_pw(
-    db: &mut DB,
+    header: &mut DBHeader,
     new_master_pw: &mut String,
-    wrapped_session_key: &mut WrappedSessionKey,
-    session_key_nonce: &mut SessionKeyNonce,
-) -> Result<(PubKey, Salt), DBIOError> {
-    let mut salt = Salt::default();
-    OsRng.fill_bytes(salt.as_mut_slice());
-    let sec_key = master_pw_kdf(new_master_pw.trim(), &salt);
-
+    key_file: Option<&KeyFile>,
+    vault_key: &SecKey,
+) {
+    reseal_master_slot(header, new_master_pw, key_file, vault_key);
     manual_zeroize(new_master_pw);
+}
 
-    let pub_key = PubKey::from_sec_key(&sec_key);
-    let (new_wrapped_user_key, new_user_key_nonce) = get_wrapped_session_key(&sec_key);
-    drop(sec_key);
+fn reseal_master_slot(header: &mut DBHeader, master_pw: &str, key_file: Option<&KeyFile>, vault_key: &SecKey) {
+    let label = header.master_slot().label;
+    let mut slot = password_slot(master_pw, &header.kdf_params, key_file, vault_key);
+    slot.label = label;
+    header.key_slots[MASTER_SLOT] = slot;
+}
 
-    let mut users_archive = vec![];
-    for site in &mut *db {
-        for user in site.1 {
-            users_archive.push((site.0.clone(), user.0.clone()));
-        }
-    }
+fn open_master_slot(master_pw: &str, header: &DBHeader, key_file: Option<&KeyFile>) -> Result<SecKey, MasterPWError> {
+    check_key_file(header, key_file)?;
+    let credential = match key_file {
+        None => SlotCredential::Password(master_pw),
+        Some(key_file) => SlotCredential::PasswordAndKeyFile(master_pw, key_file),
+    };
+    open_key_slot(header, MASTER_SLOT, &credential).map_err(|_| MasterPWError::IncorrectPW)
+}
 
-    for user in users_archive.into_iter() {
-        let user_pw = get_user_pw(
-            &db,
-            &user.0,
-            &user.1,
-            &wrapped_session_key,
-            &session_key_nonce,
-        )?;
-
-        change_user_pw(
-            &mut *db,
-            &user.0,
-            &user.1,
-            user_pw,
-            &new_wrapped_user_key,
-            &new_user_key_nonce,
-        )?;
+/// 로그인한 뒤에 다시 입력받은 마스터 비밀번호와 지금 쓰는 키 파일로 볼트 비밀 키를 꺼냄
+pub(crate) fn unlock_with_master_pw(
+    master_pw: &str,
+    header: &DBHeader,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+) -> Result<SecKey, MasterPWError> {
+    let sec_key = open_master_slot(master_pw, header, key_file)?;
+    if get_key_check(&PubKey::from_sec_key(&sec_key)) != get_key_check(pub_key) {
+        return Err(MasterPWError::IncorrectPW);
     }
+    Ok(sec_key)
+}
+
+/// 로그인한 뒤에 다시 입력받은 마스터 비밀번호와 지금 쓰는 키 파일이 `pub_key`의 볼트를 여는지 확인함
+pub fn verify_master_pw(
+    master_pw: &str,
+    header: &DBHeader,
+    key_file: Option<&KeyFile>,
+    pub_key: &PubKey,
+) -> Result<(), MasterPWError> {
+    unlock_with_master_pw(master_pw, header, key_file, pub_key).map(drop)
+}
 
-    *wrapped_session_key = new_wrapped_user_key;
-    *session_key_nonce = new_user_key_nonce;
-    Ok( (pub_key, salt) )
+/// 키 파일을 추가, 교체하거나(`Some`) 없앰(`None`). 마스터 비밀번호는 그대로 두고 마스터 비밀번호 슬롯만 다시 감쌈.
+/// `verify_master_pw`로 비밀번호를 확인한 뒤에 부름
+pub fn change_key_file(
+    header: &mut DBHeader,
+    master_pw: &mut String,
+    new_key_file: Option<&KeyFile>,
+    vault_key: &SecKey,
+) {
+    change_master_pw(header, master_pw, new_key_file, vault_key)
+}
+
+/// 헤더에 대기 중인 KDF 변경이 있으면 새 설정으로 마스터 비밀번호 슬롯을 다시 감싸고 true를 돌려줌.
+/// 틀린 비밀번호로 KDF를 두 번 돌리지 않도록 복호화에 성공한 뒤 로그인에 쓴 것과 같은 입력으로 호출함
+pub fn apply_kdf_upgrade(
+    header: &mut DBHeader,
+    master_pw: &str,
+    key_file: Option<&KeyFile>,
+    vault_key: &SecKey,
+) -> bool {
+    let Some(kdf_params) = header.pending_kdf_params() else {
+        return false;
+    };
+    header.kdf_params = kdf_params;
+    header.request_kdf_upgrade(KdfParams::NONE);
+    reseal_master_slot(header, master_pw, key_file, vault_key);
+    true
 }
 
 thread_local! {
@@ -206,11 +326,35 @@ thread_local! {
 const AES_PK_BEGIN: usize = 0;
 const AES_NONCE_BEGIN: usize = AES_PK_BEGIN + ECIES_PK_SIZE;
 const AES_NONCE_END: usize = AES_NONCE_BEGIN + AES_NONCE_SIZE;
+const KEY_CHECK_BEGIN: usize = AES_NONCE_END;
+const KEY_CHECK_END: usize = KEY_CHECK_BEGIN + KEY_CHECK_SIZE;
 const CIPHERTEXT_BEGIN: usize = AES_NONCE_END.next_power_of_two();
+const _: () = assert!(KEY_CHECK_END <= CIPHERTEXT_BEGIN);
+
+const KEY_CHECK_SIZE: usize = 32;
+const X25519_PK_BYTES: usize = 32;
 
 pub type EncryptedDB = Vec<u8>;
 
-pub fn encrypt_db(db: &DB, pk: &PubKey) -> EncryptedDB {
+/// 비밀번호가 틀린 경우와 헤더가 변조된 경우를 구분하기 위한 공개키 확인값.
+/// 공개키는 볼트 비밀 키에서만 유도되므로 확인값이 맞으면 AEAD 실패는 변조를 뜻함
+fn get_key_check(pk: &PubKey) -> [u8; KEY_CHECK_SIZE] {
+    let halo = [
+        17u8, 203, 88, 140, 61, 249, 7, 166, 118, 35, 212, 90, 153, 44, 180, 9, 231, 74, 126,
+        198, 52, 15, 101, 237, 83, 170, 29, 144, 66, 219, 5, 112,
+    ];
+    let mut hasher = Blake2b::<{ KEY_CHECK_SIZE }>::new();
+    hasher.update(&halo);
+    hasher.update_from_ptr(pk.as_ptr(), X25519_PK_BYTES);
+    let mut key_check = [0u8; KEY_CHECK_SIZE];
+    hasher.finalize_write_to(key_check.as_mut_ptr());
+    key_check
+}
+
+/// `EncryptedDB` 레이아웃: [ peer pk | nonce | key check | padding | ciphertext + tag ]
+/// 헤더의 magic, version, salt, ciphertext 길이는 associated data로 묶임
+pub fn encrypt_db(db: &DB, header: &DBHeader, pk: &PubKey) -> EncryptedDB {
+    debug_assert!(header.is_current_version(), "header must be upgraded before encrypting");
     let peer_sk = SecKey::gen_rand();
     let shared = SharedSecret::from_sk_pk(&peer_sk, &pk);
     let once_aes_key = shared_secret_to_aes_key(&shared);
@@ -221,41 +365,92 @@ pub fn encrypt_db(db: &DB, pk: &PubKey) -> EncryptedDB {
 
     let mut serialized = rkyv::to_bytes::<Error>(db).unwrap();
     let len = CIPHERTEXT_BEGIN + get_aes256gcm_ciphertext_len(serialized.len());
+    let header_ad = header.authenticated_bytes(len);
 
     let mut result = vec![Default::default(); len];
-    aes256gcm_encrypt_write_to_ptr(
+    aes256gcm_encrypt_with_ad_write_to_ptr(
         &once_aes_key,
         &nonce,
         &serialized,
+        &header_ad,
         addr_of_mut!(result[CIPHERTEXT_BEGIN]),
     );
     manual_zeroize(&mut serialized);
 
     peer_pk.copy_to(addr_of_mut!(result[AES_PK_BEGIN]));
     nonce.copy_to(addr_of_mut!(result[AES_NONCE_BEGIN]));
+    result[KEY_CHECK_BEGIN..KEY_CHECK_END].copy_from_slice(&get_key_check(pk));
 
     result
 }
 
-pub fn decrypt_db(bytes: &Vec<u8>, sk: SecKey) -> Result<DB, MasterPWError> {
+/// 복호화된 DB 평문(rkyv로 직렬화된 `DB`). drop될 때 지워짐
+pub struct DBPayload(Vec<u8>);
+impl DBPayload {
+    /// 마이그레이션 중에는 구버전 레이아웃도 직렬화함
+    pub(crate) fn serialize<T>(db: &T) -> Self
+    where
+        T: for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, Error>>,
+    {
+        let mut serialized = rkyv::to_bytes::<Error>(db).unwrap();
+        let payload = Self(serialized.to_vec());
+        manual_zeroize(&mut serialized);
+        payload
+    }
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl Drop for DBPayload {
+    fn drop(&mut self) {
+        manual_zeroize(&mut self.0);
+    }
+}
+
+/// 현재 포맷 전용. 구버전 파일은 `decrypt_db_payload`와 `migration::migrate_db`를 거쳐야 함
+pub fn decrypt_db(bytes: &Vec<u8>, header: &DBHeader, sk: &SecKey) -> Result<DB, MasterPWError> {
+    debug_assert!(header.is_current_version(), "legacy payloads must be migrated");
+    let payload = decrypt_db_payload(bytes, header, sk)?;
+    let db = rkyv::from_bytes::<DB, Error>(payload.as_bytes()).unwrap();
+    Ok(db)
+}
+
+/// 복호화만 하고 역직렬화는 하지 않음. 평문의 레이아웃은 헤더의 버전을 따름
+pub fn decrypt_db_payload(bytes: &Vec<u8>, header: &DBHeader, sk: &SecKey) -> Result<DBPayload, MasterPWError> {
+    if bytes.len() < CIPHERTEXT_BEGIN + get_aes256gcm_ciphertext_len(0) {
+        return Err(MasterPWError::TamperedHeader);
+    }
     let peer_pk = PubKey::from_raw(addr_of!(bytes[0]));
     let nonce: AesNonce = AesNonce::from_raw(addr_of!(bytes[AES_NONCE_BEGIN]));
+    let key_check = &bytes[KEY_CHECK_BEGIN..KEY_CHECK_END];
     let ciphertext = &bytes[CIPHERTEXT_BEGIN..];
 
-    let shared = SharedSecret::from_sk_pk(&sk, &peer_pk);
-    drop(sk);
+    let header_bound = header.has_bound_header();
+    if header_bound {
+        if key_check != get_key_check(&PubKey::from_sec_key(sk)).as_slice() {
+            return Err(MasterPWError::IncorrectPW);
+        }
+    } else if key_check.iter().any(|b| *b != 0) {
+        // 현재 포맷 파일의 version만 구버전으로 바꾼 경우
+        return Err(MasterPWError::TamperedHeader);
+    }
+    let header_ad = if header_bound {
+        header.authenticated_bytes(header.ciphertext_len)
+    } else {
+        Vec::new()
+    };
+
+    let shared = SharedSecret::from_sk_pk(sk, &peer_pk);
     let once_aes_key = shared_secret_to_aes_key(&shared);
     drop(shared);
 
     let plaintext_len = get_aes256gcm_plaintext_len(ciphertext.len());
     let mut plaintext = vec![Default::default(); plaintext_len];
-    aes256gcm_decrypt_write_to_ptr(&once_aes_key, &nonce, &ciphertext, plaintext.as_mut_ptr())
-        .map_err(|_| MasterPWError::IncorrectPW)?;
+    aes256gcm_decrypt_with_ad_write_to_ptr(&once_aes_key, &nonce, &ciphertext, &header_ad, plaintext.as_mut_ptr())
+        .map_err(|_| if header_bound { MasterPWError::TamperedHeader } else { MasterPWError::IncorrectPW })?;
     drop(once_aes_key);
 
-    let db = rkyv::from_bytes::<DB, Error>(&plaintext).unwrap();
-    manual_zeroize(&mut plaintext);
-    Ok(db)
+    Ok(DBPayload(plaintext))
 }
 
 // #[macro_export]
@@ -0,0 +1,95 @@
+use crate::data_base::{DB, DBIOError, Entries, SealedEntries, SealedSite, SealedUser, TrashedNames};
+use crate::user_secrets::{
+    NameKey, SessionKeyNonce, WrappedSessionKey, hash_site_name, hash_user_id, open_site_name, open_user_id,
+    seal_site_name, seal_user_id,
+};
+use std::collections::HashMap;
+
+/// 모든 계정과 휴지통의 사이트 이름과 ID를 세션 키에서 유도한 키로 암호화하고, 색인에는 keyed hash만 남김.
+/// URL은 모드와 상관없이 항목마다 암호화되어 있음. 태그, 폴더, 즐겨찾기, 숨김이 아닌 사용자 정의 필드는 그대로 평문임.
+/// 이미 켜져 있으면 아무것도 하지 않음
+pub fn enable_metadata_encryption(db: &mut DB, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+    -> Result<(), DBIOError> {
+    if db.is_metadata_encrypted() {
+        return Ok(());
+    }
+    let name_key = NameKey::derive(wrapped_key, user_key_nonce)?;
+
+    let mut sealed = SealedEntries::new();
+    for (site, users) in std::mem::take(&mut db.entries) {
+        let site_hash = hash_site_name(&name_key, &site);
+        let users = users
+            .into_iter()
+            .map(|(id, entry)| {
+                let user = SealedUser {
+                    id: seal_user_id(&name_key, &site_hash, &id),
+                    entry,
+                };
+                (hash_user_id(&name_key, &site_hash, &id), user)
+            })
+            .collect();
+        sealed.insert(site_hash, SealedSite {
+            name: seal_site_name(&name_key, &site_hash, &site),
+            users,
+        });
+    }
+
+    for trashed in db.trash.iter_mut() {
+        if let TrashedNames::Plain { site, id } = &trashed.names {
+            let site_hash = hash_site_name(&name_key, site);
+            trashed.names = TrashedNames::Sealed {
+                site_hash,
+                site: seal_site_name(&name_key, &site_hash, site),
+                id: seal_user_id(&name_key, &site_hash, id),
+            };
+        }
+    }
+
+    db.sealed = Some(sealed);
+    db.name_key = Some(name_key);
+    db.bump_generation();
+    Ok(())
+}
+
+/// 이름을 다시 평문으로 둠. 꺼져 있으면 아무것도 하지 않음
+pub fn disable_metadata_encryption(db: &mut DB) -> Result<(), DBIOError> {
+    let Some(sealed) = &db.sealed else {
+        return Ok(());
+    };
+    let name_key = db.name_key()?;
+
+    // 모두 복호화한 뒤에 옮겨서 중간에 실패해도 DB가 두 모드로 섞이지 않게 함
+    let mut names = HashMap::with_capacity(sealed.len());
+    for (site_hash, site) in sealed {
+        let mut ids = HashMap::with_capacity(site.users.len());
+        for (id_hash, user) in &site.users {
+            ids.insert(*id_hash, open_user_id(name_key, site_hash, &user.id)?);
+        }
+        names.insert(*site_hash, (open_site_name(name_key, site_hash, &site.name)?, ids));
+    }
+    let mut trash_names = Vec::with_capacity(db.trash.len());
+    for trashed in &db.trash {
+        trash_names.push(db.trashed_names(trashed)?);
+    }
+
+    let Some(sealed) = db.sealed.take() else {
+        return Ok(());
+    };
+    let mut entries = Entries::new();
+    for (site_hash, site) in sealed {
+        let (site_name, mut ids) = names.remove(&site_hash).ok_or(DBIOError::SiteNotFound)?;
+        let users = site
+            .users
+            .into_iter()
+            .map(|(id_hash, user)| Ok((ids.remove(&id_hash).ok_or(DBIOError::UserNotFound)?, user.entry)))
+            .collect::<Result<_, DBIOError>>()?;
+        entries.insert(site_name, users);
+    }
+    db.entries = entries;
+    for (trashed, (site, id)) in db.trash.iter_mut().zip(trash_names) {
+        trashed.names = TrashedNames::Plain { site, id };
+    }
+    db.name_key = None;
+    db.bump_generation();
+    Ok(())
+}
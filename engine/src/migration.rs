@@ -0,0 +1,731 @@
+use crate::data_base::{
+    DB, DBIOError, DEFAULT_TRASH_RETENTION_DAYS, Entries, Entry, FolderPath, PastUserPW, SealedEntries, SealedSite,
+    SealedUser, SiteName, StoredCustomField, Timestamp, TrashedEntry, TrashedNames, URL_FIELD_TAG, UserID,
+};
+use crate::file_io::FileIOError;
+use crate::header::{DBHeader, FormatVersion};
+use crate::key_file::KeyFile;
+use crate::key_slots::seal_vault_key;
+use crate::master_secrets::{
+    DBPayload, EncryptedDB, MasterPWError, decrypt_db_payload, general_login, vault_session_key,
+};
+use crate::user_secrets::{
+    EncryptedField, EncryptedSiteName, EncryptedUserID, EncryptedUserPW, LegacyUserPWFormat, NameHash, NameKey,
+    SessionKeyNonce, WrappedSessionKey, decrypt_legacy_user_pw, encrypt_entry_field, encrypt_user_pw, open_site_name,
+    open_user_id,
+};
+use libsodium_sys::rust_wrappings::x25519::{PubKey, SecKey};
+use rkyv::rancor::Error as RkyvError;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use zeroize::Zeroize;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// 레지스트리에 이 버전에서 시작하는 단계가 없음
+    NoMigrationPath(FormatVersion),
+    MasterPW(MasterPWError),
+    DBIO(DBIOError),
+    /// 평문이 헤더의 버전이 말하는 레이아웃이 아님
+    InvalidPayload(FormatVersion),
+    FileIO(FileIOError),
+}
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::NoMigrationPath(version) => {
+                write!(f, "No migration from DB version {} to {}", version, FormatVersion::CURRENT)
+            }
+            MigrationError::MasterPW(err) => write!(f, "{}", err),
+            MigrationError::DBIO(err) => write!(f, "{}", err),
+            MigrationError::InvalidPayload(version) => {
+                write!(f, "DB contents do not match the layout of version {}", version)
+            }
+            MigrationError::FileIO(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl Error for MigrationError {}
+impl From<MasterPWError> for MigrationError {
+    fn from(value: MasterPWError) -> Self {
+        MigrationError::MasterPW(value)
+    }
+}
+impl From<FileIOError> for MigrationError {
+    fn from(value: FileIOError) -> Self {
+        MigrationError::FileIO(value)
+    }
+}
+impl From<DBIOError> for MigrationError {
+    fn from(value: DBIOError) -> Self {
+        MigrationError::DBIO(value)
+    }
+}
+
+/// 포맷 변경 한 단계. 복호화된 평문을 `to` 버전의 레이아웃으로 바꿈.
+/// 헤더만 바뀐 버전은 평문을 그대로 둠 (헤더는 `parse_header`가 이미 현재 구조로 읽음)
+pub struct Migration {
+    pub from: FormatVersion,
+    pub to: FormatVersion,
+    pub description: &'static str,
+    migrate: fn(DBPayload, &mut MigrationKeys) -> Result<DBPayload, MigrationError>,
+}
+
+/// 로그인으로 얻은 헤더와 키들. 볼트 비밀 키를 바꾸는 단계는 이 값들도 바꿈
+struct MigrationKeys<'a> {
+    header: &'a mut DBHeader,
+    sec_key: &'a mut SecKey,
+    pub_key: &'a mut PubKey,
+    wrapped_session_key: &'a mut WrappedSessionKey,
+    session_key_nonce: &'a mut SessionKeyNonce,
+}
+
+/// `from`마다 단계가 하나씩 있어야 함. 새 포맷을 만들면 이전 `CURRENT`에서 시작하는 단계를 추가함
+static MIGRATIONS: &[Migration] = &[
+    // 0.1.3의 비밀번호 포맷도 0.1.4에서 다시 바뀌므로 바로 0.1.4로 올림
+    Migration {
+        from: FormatVersion::V0_1_2,
+        to: FormatVersion::V0_1_4,
+        description: "re-encrypt user passwords with a random nonce bound to their site and user ID",
+        migrate: |payload, keys| {
+            reencrypt_legacy_user_pws(payload, FormatVersion::V0_1_2, LegacyUserPWFormat::DerivedNonce, keys.wrapped_session_key, keys.session_key_nonce)
+        },
+    },
+    Migration {
+        from: FormatVersion::V0_1_3,
+        to: FormatVersion::V0_1_4,
+        description: "bind user passwords to their site and user ID",
+        migrate: |payload, keys| {
+            reencrypt_legacy_user_pws(payload, FormatVersion::V0_1_3, LegacyUserPWFormat::UnboundNonce, keys.wrapped_session_key, keys.session_key_nonce)
+        },
+    },
+    Migration {
+        from: FormatVersion::V0_1_4,
+        to: FormatVersion::V0_1_5,
+        description: "authenticate the header as associated data of the DB",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_1_5,
+        to: FormatVersion::V0_1_6,
+        description: "record the KDF parameters in the header",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_1_6,
+        to: FormatVersion::V0_1_7,
+        description: "store each account as an entry with notes, URLs, custom fields and timestamps",
+        migrate: |payload, _| wrap_user_pws_in_entries(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_1_7,
+        to: FormatVersion::V0_1_8,
+        description: "keep a history of previous passwords for each entry",
+        migrate: |payload, _| add_pw_history(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_1_8,
+        to: FormatVersion::V0_1_9,
+        description: "add an optional encrypted TOTP/HOTP secret to each entry",
+        migrate: |payload, _| add_otp(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_1_9,
+        to: FormatVersion::V0_2_0,
+        description: "add tags, a folder and a favourite flag to each entry",
+        migrate: |payload, _| add_organization(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_0,
+        to: FormatVersion::V0_2_1,
+        description: "add a trash for removed entries",
+        migrate: |payload, _| add_trash(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_1,
+        to: FormatVersion::V0_2_2,
+        description: "add an optional mode that encrypts site names and user IDs",
+        migrate: |payload, _| add_metadata_encryption(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_2,
+        to: FormatVersion::V0_2_3,
+        description: "record in the header whether a key file is required",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_3,
+        to: FormatVersion::V0_2_4,
+        description: "add an empty recovery key slot to the header",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_4,
+        to: FormatVersion::V0_2_5,
+        description: "add an empty recovery shares slot to the header",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_5,
+        to: FormatVersion::V0_2_6,
+        description: "add an empty key slot table to the header",
+        migrate: |payload, _| Ok(payload),
+    },
+    Migration {
+        from: FormatVersion::V0_2_6,
+        to: FormatVersion::V0_2_7,
+        description: "encrypt entry URLs bound to their site and user ID",
+        migrate: |payload, keys| encrypt_urls(payload, keys.wrapped_session_key, keys.session_key_nonce),
+    },
+    Migration {
+        from: FormatVersion::V0_2_7,
+        to: FormatVersion::V0_2_8,
+        description: "seal a random vault key in every key slot, with the master password, recovery code and shares as slots",
+        migrate: add_vault_key,
+    },
+];
+
+pub fn migrations() -> &'static [Migration] {
+    MIGRATIONS
+}
+
+/// `from`에서 현재 버전까지 적용할 단계들. 이미 현재 버전이면 비어 있음
+pub fn migration_plan(from: FormatVersion) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut plan = Vec::new();
+    let mut version = from;
+    while version != FormatVersion::CURRENT {
+        let step = MIGRATIONS
+            .iter()
+            .find(|step| step.from == version)
+            .ok_or(MigrationError::NoMigrationPath(version))?;
+        plan.push(step);
+        version = step.to;
+    }
+    Ok(plan)
+}
+
+/// 평문을 현재 포맷의 DB로 만들고 헤더의 버전을 올림. 적용한 단계가 있으면 호출자가 즉시 저장해야 하고,
+/// 그 전에(헤더를 넘기기 전에) `file_io::backup_before_migration`으로 원본을 남겨야 함.
+/// 0.2.8로 올리면 볼트 비밀 키가 바뀌므로 로그인으로 얻은 키들을 모두 새 키로 바꿈
+pub fn migrate_db(
+    header: &mut DBHeader,
+    mut payload: DBPayload,
+    sec_key: &mut SecKey,
+    pub_key: &mut PubKey,
+    wrapped_session_key: &mut WrappedSessionKey,
+    session_key_nonce: &mut SessionKeyNonce,
+) -> Result<(DB, Vec<&'static Migration>), MigrationError> {
+    let plan = migration_plan(header.version()?)?;
+    let mut keys = MigrationKeys {
+        header: &mut *header,
+        sec_key,
+        pub_key,
+        wrapped_session_key: &mut *wrapped_session_key,
+        session_key_nonce: &mut *session_key_nonce,
+    };
+    for step in &plan {
+        payload = (step.migrate)(payload, &mut keys)?;
+    }
+
+    let mut db = deserialize_db(&payload, FormatVersion::CURRENT)?;
+    db.unlock_names(wrapped_session_key, session_key_nonce)?;
+    if !plan.is_empty() {
+        header.set_version(FormatVersion::CURRENT);
+    }
+    Ok((db, plan))
+}
+
+/// 마이그레이션을 메모리에서만 해 보고 결과를 요약함. 파일은 건드리지 않음
+pub struct MigrationReport {
+    pub from: FormatVersion,
+    pub steps: Vec<&'static Migration>,
+    pub site_count: usize,
+    pub entry_count: usize,
+}
+
+pub fn dry_run_migration(
+    header: &DBHeader,
+    encrypted_db: &EncryptedDB,
+    master_pw: &str,
+    key_file: Option<&KeyFile>,
+) -> Result<MigrationReport, MigrationError> {
+    let mut header = *header;
+    let from = header.version()?;
+    // 비밀번호가 틀려도 단계가 없다는 것부터 알려줌
+    migration_plan(from)?;
+
+    let mut master_pw = master_pw.to_owned();
+    let (mut sec_key, mut pub_key, mut wrapped_session_key, mut session_key_nonce) =
+        general_login(&mut master_pw, &header, key_file)?;
+    let payload = decrypt_db_payload(encrypted_db, &header, &sec_key)?;
+    let (db, steps) = migrate_db(
+        &mut header,
+        payload,
+        &mut sec_key,
+        &mut pub_key,
+        &mut wrapped_session_key,
+        &mut session_key_nonce,
+    )?;
+
+    Ok(MigrationReport {
+        from,
+        steps,
+        site_count: db.site_count(),
+        entry_count: db.entry_count(),
+    })
+}
+
+/// 0.1.6까지의 DB 레이아웃. 계정마다 암호화된 비밀번호만 있음
+type PWOnlyDB = BTreeMap<SiteName, HashMap<UserID, EncryptedUserPW>>;
+
+/// 0.1.7의 항목 레이아웃. 비밀번호를 바꾼 시각과 이전 비밀번호가 없음
+#[derive(Archive, Serialize, Deserialize)]
+struct EntryWithoutHistory {
+    pw: EncryptedUserPW,
+    notes: Option<EncryptedField>,
+    urls: Vec<String>,
+    custom_fields: Vec<StoredCustomField>,
+    created: Timestamp,
+    modified: Timestamp,
+    last_used: Option<Timestamp>,
+}
+type DBWithoutHistory = BTreeMap<SiteName, HashMap<UserID, EntryWithoutHistory>>;
+
+/// 0.1.8의 항목 레이아웃. OTP 비밀 키가 없음
+#[derive(Archive, Serialize, Deserialize)]
+struct EntryWithoutOtp {
+    pw: EncryptedUserPW,
+    notes: Option<EncryptedField>,
+    urls: Vec<String>,
+    custom_fields: Vec<StoredCustomField>,
+    created: Timestamp,
+    modified: Timestamp,
+    last_used: Option<Timestamp>,
+    pw_changed: Timestamp,
+    pw_history: Vec<PastUserPW>,
+}
+type DBWithoutOtp = BTreeMap<SiteName, HashMap<UserID, EntryWithoutOtp>>;
+
+/// 0.1.9의 항목 레이아웃. 태그, 폴더, 즐겨찾기가 없음
+#[derive(Archive, Serialize, Deserialize)]
+struct EntryWithoutOrganization {
+    pw: EncryptedUserPW,
+    notes: Option<EncryptedField>,
+    urls: Vec<String>,
+    custom_fields: Vec<StoredCustomField>,
+    created: Timestamp,
+    modified: Timestamp,
+    last_used: Option<Timestamp>,
+    pw_changed: Timestamp,
+    pw_history: Vec<PastUserPW>,
+    otp: Option<EncryptedField>,
+}
+type DBWithoutOrganization = BTreeMap<SiteName, HashMap<UserID, EntryWithoutOrganization>>;
+
+/// 0.2.0 ~ 0.2.6의 항목 레이아웃. URL이 평문
+#[derive(Archive, Serialize, Deserialize)]
+struct EntryWithPlainUrls {
+    pw: EncryptedUserPW,
+    notes: Option<EncryptedField>,
+    urls: Vec<String>,
+    custom_fields: Vec<StoredCustomField>,
+    created: Timestamp,
+    modified: Timestamp,
+    last_used: Option<Timestamp>,
+    pw_changed: Timestamp,
+    pw_history: Vec<PastUserPW>,
+    otp: Option<EncryptedField>,
+    tags: Vec<String>,
+    folder: FolderPath,
+    favourite: bool,
+}
+type EntriesWithPlainUrls = BTreeMap<SiteName, HashMap<UserID, EntryWithPlainUrls>>;
+
+/// 0.2.0의 레이아웃. 휴지통 없이 계정 맵만 있음
+type DBWithoutTrash = EntriesWithPlainUrls;
+
+/// 0.2.1의 휴지통 항목. 이름이 항상 평문
+#[derive(Archive, Serialize, Deserialize)]
+struct PlainTrashedEntry {
+    site: SiteName,
+    id: UserID,
+    entry: EntryWithPlainUrls,
+    deleted: Timestamp,
+}
+
+/// 0.2.1의 레이아웃. 메타데이터 암호화 모드가 없음
+#[derive(Archive, Serialize, Deserialize)]
+struct DBWithoutSealedNames {
+    entries: EntriesWithPlainUrls,
+    trash: Vec<PlainTrashedEntry>,
+    trash_retention_days: u32,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+struct SealedUserWithPlainUrls {
+    id: EncryptedUserID,
+    entry: EntryWithPlainUrls,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+struct SealedSiteWithPlainUrls {
+    name: EncryptedSiteName,
+    users: HashMap<NameHash, SealedUserWithPlainUrls>,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+struct TrashedEntryWithPlainUrls {
+    names: TrashedNames,
+    entry: EntryWithPlainUrls,
+    deleted: Timestamp,
+}
+
+/// 0.2.2 ~ 0.2.6의 레이아웃. `DB`와 같지만 항목의 URL이 평문
+#[derive(Archive, Serialize, Deserialize)]
+struct DBWithPlainUrls {
+    entries: EntriesWithPlainUrls,
+    sealed: Option<HashMap<NameHash, SealedSiteWithPlainUrls>>,
+    trash: Vec<TrashedEntryWithPlainUrls>,
+    trash_retention_days: u32,
+}
+
+fn deserialize_db(payload: &DBPayload, version: FormatVersion) -> Result<DB, MigrationError> {
+    rkyv::from_bytes::<DB, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(version))
+}
+
+fn deserialize_pw_only_db(payload: &DBPayload, version: FormatVersion) -> Result<PWOnlyDB, MigrationError> {
+    rkyv::from_bytes::<PWOnlyDB, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(version))
+}
+
+fn deserialize_db_without_history(payload: &DBPayload) -> Result<DBWithoutHistory, MigrationError> {
+    rkyv::from_bytes::<DBWithoutHistory, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_1_7))
+}
+
+fn deserialize_db_without_otp(payload: &DBPayload) -> Result<DBWithoutOtp, MigrationError> {
+    rkyv::from_bytes::<DBWithoutOtp, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_1_8))
+}
+
+fn deserialize_db_without_organization(payload: &DBPayload) -> Result<DBWithoutOrganization, MigrationError> {
+    rkyv::from_bytes::<DBWithoutOrganization, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_1_9))
+}
+
+fn deserialize_db_without_trash(payload: &DBPayload) -> Result<DBWithoutTrash, MigrationError> {
+    rkyv::from_bytes::<DBWithoutTrash, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_2_0))
+}
+
+fn deserialize_db_without_sealed_names(payload: &DBPayload) -> Result<DBWithoutSealedNames, MigrationError> {
+    rkyv::from_bytes::<DBWithoutSealedNames, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_2_1))
+}
+
+fn deserialize_db_with_plain_urls(payload: &DBPayload) -> Result<DBWithPlainUrls, MigrationError> {
+    rkyv::from_bytes::<DBWithPlainUrls, RkyvError>(payload.as_bytes())
+        .map_err(|_| MigrationError::InvalidPayload(FormatVersion::V0_2_6))
+}
+
+/// 0.1.2 ~ 0.1.3은 0.1.6과 DB 레이아웃이 같고 비밀번호 암호문 포맷만 다름
+fn reencrypt_legacy_user_pws(
+    payload: DBPayload,
+    version: FormatVersion,
+    legacy_format: LegacyUserPWFormat,
+    wrapped_session_key: &WrappedSessionKey,
+    session_key_nonce: &SessionKeyNonce,
+) -> Result<DBPayload, MigrationError> {
+    let mut db = deserialize_pw_only_db(&payload, version)?;
+    drop(payload);
+
+    for (site, users) in db.iter_mut() {
+        for (id, encrypted_pw) in users.iter_mut() {
+            let user_pw = decrypt_legacy_user_pw(
+                site,
+                id,
+                encrypted_pw,
+                legacy_format,
+                wrapped_session_key,
+                session_key_nonce,
+            )?;
+            encrypted_pw.zeroize();
+            *encrypted_pw =
+                encrypt_user_pw(site, id, user_pw, wrapped_session_key, session_key_nonce)?;
+        }
+    }
+
+    Ok(DBPayload::serialize(&db))
+}
+
+/// 암호문은 그대로 옮김. 원래 만든 시각은 알 수 없으므로 마이그레이션한 시각을 씀
+fn wrap_user_pws_in_entries(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let pw_only = deserialize_pw_only_db(&payload, FormatVersion::V0_1_6)?;
+    drop(payload);
+
+    let now = Timestamp::now();
+    let db: DBWithoutHistory = pw_only
+        .into_iter()
+        .map(|(site, users)| {
+            let users = users
+                .into_iter()
+                .map(|(id, pw)| {
+                    let entry = EntryWithoutHistory {
+                        pw,
+                        notes: None,
+                        urls: Vec::new(),
+                        custom_fields: Vec::new(),
+                        created: now,
+                        modified: now,
+                        last_used: None,
+                    };
+                    (id, entry)
+                })
+                .collect();
+            (site, users)
+        })
+        .collect();
+
+    Ok(DBPayload::serialize(&db))
+}
+
+/// 0.1.7은 비밀번호를 바꾼 시각을 따로 남기지 않았으므로 마지막으로 수정한 시각으로 둠
+fn add_pw_history(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let without_history = deserialize_db_without_history(&payload)?;
+    drop(payload);
+
+    let db: DBWithoutOtp = without_history
+        .into_iter()
+        .map(|(site, users)| {
+            let users = users
+                .into_iter()
+                .map(|(id, old)| {
+                    let entry = EntryWithoutOtp {
+                        pw: old.pw,
+                        notes: old.notes,
+                        urls: old.urls,
+                        custom_fields: old.custom_fields,
+                        created: old.created,
+                        modified: old.modified,
+                        last_used: old.last_used,
+                        pw_changed: old.modified,
+                        pw_history: Vec::new(),
+                    };
+                    (id, entry)
+                })
+                .collect();
+            (site, users)
+        })
+        .collect();
+
+    Ok(DBPayload::serialize(&db))
+}
+
+fn add_otp(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let without_otp = deserialize_db_without_otp(&payload)?;
+    drop(payload);
+
+    let db: DBWithoutOrganization = without_otp
+        .into_iter()
+        .map(|(site, users)| {
+            let users = users
+                .into_iter()
+                .map(|(id, old)| {
+                    let entry = EntryWithoutOrganization {
+                        pw: old.pw,
+                        notes: old.notes,
+                        urls: old.urls,
+                        custom_fields: old.custom_fields,
+                        created: old.created,
+                        modified: old.modified,
+                        last_used: old.last_used,
+                        pw_changed: old.pw_changed,
+                        pw_history: old.pw_history,
+                        otp: None,
+                    };
+                    (id, entry)
+                })
+                .collect();
+            (site, users)
+        })
+        .collect();
+
+    Ok(DBPayload::serialize(&db))
+}
+
+/// 모든 항목을 태그 없이 최상위 폴더에 둠
+fn add_organization(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let without_organization = deserialize_db_without_organization(&payload)?;
+    drop(payload);
+
+    let db: DBWithoutTrash = without_organization
+        .into_iter()
+        .map(|(site, users)| {
+            let users = users
+                .into_iter()
+                .map(|(id, old)| {
+                    let entry = EntryWithPlainUrls {
+                        pw: old.pw,
+                        notes: old.notes,
+                        urls: old.urls,
+                        custom_fields: old.custom_fields,
+                        created: old.created,
+                        modified: old.modified,
+                        last_used: old.last_used,
+                        pw_changed: old.pw_changed,
+                        pw_history: old.pw_history,
+                        otp: old.otp,
+                        tags: Vec::new(),
+                        folder: FolderPath::root(),
+                        favourite: false,
+                    };
+                    (id, entry)
+                })
+                .collect();
+            (site, users)
+        })
+        .collect();
+
+    Ok(DBPayload::serialize(&db))
+}
+
+/// 휴지통은 비어 있고 보관 기간은 기본값
+fn add_trash(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let entries = deserialize_db_without_trash(&payload)?;
+    drop(payload);
+
+    Ok(DBPayload::serialize(&DBWithoutSealedNames {
+        entries,
+        trash: Vec::new(),
+        trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+    }))
+}
+
+/// 모드는 꺼진 채로 둠. 휴지통의 이름은 평문 그대로 옮김
+fn add_metadata_encryption(payload: DBPayload) -> Result<DBPayload, MigrationError> {
+    let old = deserialize_db_without_sealed_names(&payload)?;
+    drop(payload);
+
+    Ok(DBPayload::serialize(&DBWithPlainUrls {
+        entries: old.entries,
+        sealed: None,
+        trash: old
+            .trash
+            .into_iter()
+            .map(|trashed| TrashedEntryWithPlainUrls {
+                names: TrashedNames::Plain {
+                    site: trashed.site,
+                    id: trashed.id,
+                },
+                entry: trashed.entry,
+                deleted: trashed.deleted,
+            })
+            .collect(),
+        trash_retention_days: old.trash_retention_days,
+    }))
+}
+
+/// URL마다 (사이트, ID)에 묶어 암호화함. 메타데이터 암호화 모드의 이름은 세션 키에서 유도한 이름 키로 열어서 씀
+fn encrypt_urls(payload: DBPayload, wrapped_session_key: &WrappedSessionKey, session_key_nonce: &SessionKeyNonce)
+    -> Result<DBPayload, MigrationError> {
+    let old = deserialize_db_with_plain_urls(&payload)?;
+    drop(payload);
+
+    let has_sealed_trash = old.trash.iter().any(|trashed| matches!(trashed.names, TrashedNames::Sealed { .. }));
+    let name_key = if old.sealed.is_some() || has_sealed_trash {
+        Some(NameKey::derive(wrapped_session_key, session_key_nonce)?)
+    } else {
+        None
+    };
+    let with_encrypted_urls = |old: EntryWithPlainUrls, site: &SiteName, id: &UserID| {
+        entry_with_encrypted_urls(old, site, id, wrapped_session_key, session_key_nonce)
+    };
+
+    let mut entries = Entries::new();
+    for (site, users) in old.entries {
+        let mut new_users = HashMap::with_capacity(users.len());
+        for (id, entry) in users {
+            let entry = with_encrypted_urls(entry, &site, &id)?;
+            new_users.insert(id, entry);
+        }
+        entries.insert(site, new_users);
+    }
+
+    let mut db = DB::from_entries(entries);
+    if let Some(old_sealed) = old.sealed {
+        let name_key = name_key.as_ref().ok_or(DBIOError::NamesLocked)?;
+        let mut sealed = SealedEntries::with_capacity(old_sealed.len());
+        for (site_hash, old_site) in old_sealed {
+            let site = open_site_name(name_key, &site_hash, &old_site.name)?;
+            let mut users = HashMap::with_capacity(old_site.users.len());
+            for (id_hash, old_user) in old_site.users {
+                let id = open_user_id(name_key, &site_hash, &old_user.id)?;
+                let entry = with_encrypted_urls(old_user.entry, &site, &id)?;
+                users.insert(id_hash, SealedUser { id: old_user.id, entry });
+            }
+            sealed.insert(site_hash, SealedSite { name: old_site.name, users });
+        }
+        db.sealed = Some(sealed);
+    }
+    for trashed in old.trash {
+        let (site, id) = trashed.names.open(name_key.as_ref())?;
+        db.trash.push(TrashedEntry {
+            names: trashed.names,
+            entry: with_encrypted_urls(trashed.entry, &site, &id)?,
+            deleted: trashed.deleted,
+        });
+    }
+    db.trash_retention_days = old.trash_retention_days;
+
+    Ok(DBPayload::serialize(&db))
+}
+
+fn entry_with_encrypted_urls(
+    old: EntryWithPlainUrls,
+    site: &SiteName,
+    id: &UserID,
+    wrapped_session_key: &WrappedSessionKey,
+    session_key_nonce: &SessionKeyNonce,
+) -> Result<Entry, MigrationError> {
+    let mut urls = Vec::with_capacity(old.urls.len());
+    for url in &old.urls {
+        urls.push(encrypt_entry_field(site, id, URL_FIELD_TAG, url, wrapped_session_key, session_key_nonce)?);
+    }
+    Ok(Entry {
+        pw: old.pw,
+        notes: old.notes,
+        urls,
+        custom_fields: old.custom_fields,
+        created: old.created,
+        modified: old.modified,
+        last_used: old.last_used,
+        pw_changed: old.pw_changed,
+        pw_history: old.pw_history,
+        otp: old.otp,
+        tags: old.tags,
+        folder: old.folder,
+        favourite: old.favourite,
+    })
+}
+
+/// 무작위 볼트 비밀 키를 만들어 모든 키 슬롯에 감싸고, 모든 항목을 그 키의 세션 키로 다시 암호화함.
+/// 지금까지 마스터 비밀번호에서 유도하던 비밀 키는 마스터 비밀번호 슬롯을 여는 키로만 남음
+fn add_vault_key(payload: DBPayload, keys: &mut MigrationKeys) -> Result<DBPayload, MigrationError> {
+    let mut db = deserialize_db(&payload, FormatVersion::V0_2_7)?;
+    drop(payload);
+    db.unlock_names(keys.wrapped_session_key, keys.session_key_nonce)?;
+
+    let vault_key = SecKey::gen_rand();
+    let (wrapped_session_key, session_key_nonce) = vault_session_key(&vault_key);
+    db.rekey(keys.wrapped_session_key, keys.session_key_nonce, &wrapped_session_key, &session_key_nonce)?;
+    seal_vault_key(keys.header, keys.pub_key, &vault_key);
+
+    *keys.pub_key = PubKey::from_sec_key(&vault_key);
+    *keys.sec_key = vault_key;
+    *keys.wrapped_session_key = wrapped_session_key;
+    *keys.session_key_nonce = session_key_nonce;
+    Ok(DBPayload::serialize(&db))
+}
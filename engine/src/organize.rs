@@ -0,0 +1,85 @@
+use crate::data_base::{DB, Entry, FolderPath, SiteEntries};
+use std::collections::BTreeMap;
+
+/// 비어 있는 조건은 모두 통과함
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+    /// 모두 붙어 있어야 함. 대소문자를 가리지 않음
+    pub tags: Vec<String>,
+    /// 이 폴더와 그 아래 폴더
+    pub folder: Option<FolderPath>,
+    pub favourites_only: bool,
+}
+
+impl EntryFilter {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.folder.is_none() && !self.favourites_only
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        (!self.favourites_only || entry.is_favourite())
+            && self.folder.as_ref().is_none_or(|folder| folder.contains(entry.folder()))
+            && self.tags.iter().all(|tag| entry.has_tag(tag))
+    }
+}
+
+/// `sites`는 `DB::sites`나 `prefix_range`의 결과. 조건에 맞는 계정이 있는 사이트만 남김
+pub fn filter_sites<'a, I>(sites: I, filter: &EntryFilter) -> Vec<SiteEntries<'a>>
+where
+    I: IntoIterator<Item = SiteEntries<'a>>,
+{
+    sites
+        .into_iter()
+        .filter_map(|(site, mut users)| {
+            users.retain(|(_, entry)| filter.matches(entry));
+            (!users.is_empty()).then_some((site, users))
+        })
+        .collect()
+}
+
+/// 태그마다 붙어 있는 계정 수
+pub fn tag_counts(db: &DB) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for entry in db.all_entries() {
+        for tag in entry.tags() {
+            *counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// 계정이 들어 있는 폴더로만 만든 트리. 최상위는 `FolderPath::root()`
+#[derive(Clone, Debug, Default)]
+pub struct FolderNode {
+    pub path: FolderPath,
+    /// 이 폴더에 바로 들어 있는 계정 수
+    pub entry_count: usize,
+    /// 아래 폴더까지 합친 계정 수
+    pub total_count: usize,
+    pub children: BTreeMap<String, FolderNode>,
+}
+
+impl FolderNode {
+    /// 최상위면 `/`
+    pub fn name(&self) -> &str {
+        self.path.name().unwrap_or("/")
+    }
+}
+
+pub fn folder_tree(db: &DB) -> FolderNode {
+    let mut root = FolderNode::default();
+    for entry in db.all_entries() {
+        let mut node = &mut root;
+        node.total_count += 1;
+        for component in entry.folder().components() {
+            let path = node.path.child(component);
+            node = node.children.entry(component.clone()).or_insert_with(|| FolderNode {
+                path,
+                ..FolderNode::default()
+            });
+            node.total_count += 1;
+        }
+        node.entry_count += 1;
+    }
+    root
+}
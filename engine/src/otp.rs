@@ -0,0 +1,423 @@
+use crate::base32;
+use crate::data_base::{DB, DBIOError, OTP_FIELD_TAG, SiteName, Timestamp, UserID, get_entry, get_entry_mut};
+use crate::sha1::sha1;
+use crate::user_secrets::{SessionKeyNonce, WrappedSessionKey, decrypt_entry_field, encrypt_entry_field};
+use sha2::{Digest, Sha256, Sha512};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const MIN_DIGITS: u32 = 6;
+pub const MAX_DIGITS: u32 = 10;
+pub const DEFAULT_DIGITS: u32 = 6;
+pub const DEFAULT_PERIOD: u64 = 30;
+
+const URI_SCHEME: &str = "otpauth://";
+
+#[derive(Debug)]
+pub enum OtpError {
+    NotOtpAuthUri,
+    UnknownType(String),
+    /// URI의 퍼센트 인코딩이 잘못되었거나 UTF-8이 아님
+    InvalidEncoding,
+    MissingSecret,
+    InvalidSecret,
+    UnknownAlgorithm(String),
+    DigitsOutOfRange,
+    InvalidPeriod,
+    /// HOTP에 counter가 없거나 숫자가 아님
+    InvalidCounter,
+    /// 항목에 OTP가 없음
+    NoOtp,
+    DB(DBIOError),
+}
+impl Display for OtpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpError::NotOtpAuthUri => write!(f, "Not an otpauth:// URI or a base32 secret"),
+            OtpError::UnknownType(kind) => write!(f, "Unknown OTP type \"{}\" (totp, hotp)", kind),
+            OtpError::InvalidEncoding => write!(f, "Invalid percent-encoding in the URI"),
+            OtpError::MissingSecret => write!(f, "OTP secret is missing"),
+            OtpError::InvalidSecret => write!(f, "OTP secret is not valid base32"),
+            OtpError::UnknownAlgorithm(algorithm) => {
+                write!(f, "Unknown OTP algorithm \"{}\" (SHA1, SHA256, SHA512)", algorithm)
+            }
+            OtpError::DigitsOutOfRange => {
+                write!(f, "OTP digits must be {}..={}", MIN_DIGITS, MAX_DIGITS)
+            }
+            OtpError::InvalidPeriod => write!(f, "TOTP period must be a positive number of seconds"),
+            OtpError::InvalidCounter => write!(f, "HOTP counter is missing or invalid"),
+            OtpError::NoOtp => write!(f, "Entry has no OTP secret"),
+            OtpError::DB(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl Error for OtpError {}
+impl From<DBIOError> for OtpError {
+    fn from(e: DBIOError) -> Self {
+        OtpError::DB(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OtpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgorithm {
+    pub const ALL: [OtpAlgorithm; 3] = [OtpAlgorithm::Sha1, OtpAlgorithm::Sha256, OtpAlgorithm::Sha512];
+
+    fn block_size(self) -> usize {
+        match self {
+            OtpAlgorithm::Sha1 | OtpAlgorithm::Sha256 => 64,
+            OtpAlgorithm::Sha512 => 128,
+        }
+    }
+
+    fn hash(self, data: &[u8]) -> Zeroizing<Vec<u8>> {
+        match self {
+            OtpAlgorithm::Sha1 => Zeroizing::new(sha1(data).to_vec()),
+            OtpAlgorithm::Sha256 => Zeroizing::new(Sha256::digest(data).to_vec()),
+            OtpAlgorithm::Sha512 => Zeroizing::new(Sha512::digest(data).to_vec()),
+        }
+    }
+
+    /// RFC 2104
+    fn hmac(self, key: &[u8], message: &[u8]) -> Zeroizing<Vec<u8>> {
+        let block_size = self.block_size();
+        let mut key_block = Zeroizing::new(vec![0u8; block_size]);
+        if key.len() > block_size {
+            let hashed = self.hash(key);
+            key_block[..hashed.len()].copy_from_slice(&hashed);
+        } else {
+            key_block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Zeroizing::new(Vec::with_capacity(block_size + message.len()));
+        inner.extend(key_block.iter().map(|b| b ^ 0x36));
+        inner.extend_from_slice(message);
+        let inner_hash = self.hash(&inner);
+
+        let mut outer = Zeroizing::new(Vec::with_capacity(block_size + inner_hash.len()));
+        outer.extend(key_block.iter().map(|b| b ^ 0x5c));
+        outer.extend_from_slice(&inner_hash);
+        self.hash(&outer)
+    }
+}
+impl Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpAlgorithm::Sha1 => write!(f, "SHA1"),
+            OtpAlgorithm::Sha256 => write!(f, "SHA256"),
+            OtpAlgorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+impl FromStr for OtpAlgorithm {
+    type Err = OtpError;
+    fn from_str(s: &str) -> Result<Self, OtpError> {
+        let normalized = s.trim().replace('-', "");
+        OtpAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.to_string().eq_ignore_ascii_case(&normalized))
+            .ok_or_else(|| OtpError::UnknownAlgorithm(s.to_string()))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OtpKind {
+    /// RFC 6238. 코드가 `period`초마다 바뀜
+    Totp { period: u64 },
+    /// RFC 4226. 다음에 쓸 카운터
+    Hotp { counter: u64 },
+}
+
+/// `otpauth://` URI 하나의 내용
+pub struct OtpConfig {
+    pub kind: OtpKind,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+    secret: Zeroizing<Vec<u8>>,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+impl OtpConfig {
+    /// 기본값(SHA1, 6자리, 30초)의 TOTP
+    pub fn totp(secret: Vec<u8>) -> Result<Self, OtpError> {
+        Self::new(OtpKind::Totp { period: DEFAULT_PERIOD }, OtpAlgorithm::Sha1, DEFAULT_DIGITS, secret)
+    }
+
+    pub fn new(kind: OtpKind, algorithm: OtpAlgorithm, digits: u32, secret: Vec<u8>) -> Result<Self, OtpError> {
+        let config = Self {
+            kind,
+            algorithm,
+            digits,
+            secret: Zeroizing::new(secret),
+            issuer: None,
+            account: None,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), OtpError> {
+        if self.secret.is_empty() {
+            return Err(OtpError::MissingSecret);
+        }
+        if !(MIN_DIGITS..=MAX_DIGITS).contains(&self.digits) {
+            return Err(OtpError::DigitsOutOfRange);
+        }
+        if self.kind == (OtpKind::Totp { period: 0 }) {
+            return Err(OtpError::InvalidPeriod);
+        }
+        Ok(())
+    }
+
+    /// `otpauth://` URI나, 인증 앱에 직접 입력하는 base32 비밀 키(기본값 TOTP)를 받음
+    pub fn parse(input: &str) -> Result<Self, OtpError> {
+        let input = input.trim();
+        let Some(rest) = input
+            .get(..URI_SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(URI_SCHEME))
+            .map(|_| &input[URI_SCHEME.len()..])
+        else {
+            return Self::totp(decode_base32(input)?.to_vec());
+        };
+
+        let (kind, rest) = rest.split_once('/').ok_or(OtpError::NotOtpAuthUri)?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let label = percent_decode(label)?;
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim().to_string()),
+            None => (None, label.trim().to_string()),
+        };
+
+        let mut secret = None;
+        let mut algorithm = OtpAlgorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = percent_decode(value)?;
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(decode_base32(&value)?),
+                "issuer" => issuer = Some(value.trim().to_string()),
+                "algorithm" => algorithm = value.parse()?,
+                "digits" => digits = value.trim().parse().map_err(|_| OtpError::DigitsOutOfRange)?,
+                "period" => period = value.trim().parse().map_err(|_| OtpError::InvalidPeriod)?,
+                "counter" => counter = Some(value.trim().parse().map_err(|_| OtpError::InvalidCounter)?),
+                // image 등 쓰지 않는 값은 무시함
+                _ => {}
+            }
+        }
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or(OtpError::InvalidCounter)?,
+            },
+            _ => return Err(OtpError::UnknownType(kind.to_string())),
+        };
+        let mut config = Self::new(
+            kind,
+            algorithm,
+            digits,
+            secret.ok_or(OtpError::MissingSecret)?.to_vec(),
+        )?;
+        config.issuer = issuer.filter(|issuer| !issuer.is_empty());
+        config.account = Some(account).filter(|account| !account.is_empty());
+        Ok(config)
+    }
+
+    /// 다시 `parse`할 수 있는 URI. DB에는 이 형태로 암호화해서 저장함
+    pub fn to_uri(&self) -> Zeroizing<String> {
+        let label_len = self.issuer.as_ref().map_or(0, String::len) + self.account.as_ref().map_or(0, String::len);
+        // 다시 할당되면서 이전 버퍼가 지워지지 않은 채 남지 않도록 미리 잡아 둠
+        let mut uri = Zeroizing::new(String::with_capacity(128 + label_len * 6 + self.secret.len() * 2));
+        uri.push_str(URI_SCHEME);
+        uri.push_str(match self.kind {
+            OtpKind::Totp { .. } => "totp/",
+            OtpKind::Hotp { .. } => "hotp/",
+        });
+        if let Some(issuer) = &self.issuer {
+            percent_encode(issuer, &mut uri);
+            uri.push(':');
+        }
+        if let Some(account) = &self.account {
+            percent_encode(account, &mut uri);
+        }
+        uri.push_str("?secret=");
+        base32::encode(&self.secret, &mut uri);
+        if let Some(issuer) = &self.issuer {
+            uri.push_str("&issuer=");
+            percent_encode(issuer, &mut uri);
+        }
+        uri.push_str(&format!("&algorithm={}&digits={}", self.algorithm, self.digits));
+        match self.kind {
+            OtpKind::Totp { period } => uri.push_str(&format!("&period={}", period)),
+            OtpKind::Hotp { counter } => uri.push_str(&format!("&counter={}", counter)),
+        }
+        uri
+    }
+
+    /// TOTP는 `unix_secs` 시각의 코드, HOTP는 지금 카운터의 코드
+    pub fn code_at(&self, unix_secs: u64) -> Zeroizing<String> {
+        match self.kind {
+            OtpKind::Totp { period } => totp(&self.secret, unix_secs, period, self.digits, self.algorithm),
+            OtpKind::Hotp { counter } => hotp(&self.secret, counter, self.digits, self.algorithm),
+        }
+    }
+
+    /// TOTP 코드가 바뀌기까지 남은 초. HOTP는 `None`
+    pub fn remaining_secs(&self, unix_secs: u64) -> Option<u64> {
+        match self.kind {
+            OtpKind::Totp { period } => Some(period - unix_secs % period),
+            OtpKind::Hotp { .. } => None,
+        }
+    }
+}
+
+/// RFC 4226의 HOTP
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: OtpAlgorithm) -> Zeroizing<String> {
+    let mac = algorithm.hmac(secret, &counter.to_be_bytes());
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let mut binary = u32::from_be_bytes(std::array::from_fn(|i| mac[offset + i])) & 0x7fff_ffff;
+    let mut code = binary as u64 % 10u64.pow(digits);
+    let formatted = Zeroizing::new(format!("{:0width$}", code, width = digits as usize));
+    binary.zeroize();
+    code.zeroize();
+    formatted
+}
+
+/// RFC 6238의 TOTP. T0은 0
+pub fn totp(secret: &[u8], unix_secs: u64, period: u64, digits: u32, algorithm: OtpAlgorithm) -> Zeroizing<String> {
+    hotp(secret, unix_secs / period, digits, algorithm)
+}
+
+/// 만든 코드와, TOTP면 남은 시간
+pub struct OtpCode {
+    pub code: Zeroizing<String>,
+    pub remaining_secs: Option<u64>,
+    pub period: Option<u64>,
+}
+
+/// `None`이면 OTP를 지움
+pub fn set_otp(
+    db: &mut DB,
+    site_name: &SiteName,
+    user_id: &UserID,
+    otp: Option<&OtpConfig>,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<(), OtpError> {
+    let encrypted = match otp {
+        Some(otp) => {
+            otp.validate()?;
+            Some(encrypt_entry_field(site_name, user_id, OTP_FIELD_TAG, &otp.to_uri(), wrapped_key, user_key_nonce)?)
+        }
+        None => None,
+    };
+    let entry = get_entry_mut(db, site_name, user_id)?;
+    entry.otp = encrypted;
+    entry.modified = Timestamp::now();
+    db.bump_generation();
+    Ok(())
+}
+
+pub fn read_otp(
+    db: &DB,
+    site_name: &SiteName,
+    user_id: &UserID,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<Option<OtpConfig>, OtpError> {
+    let entry = get_entry(db, site_name, user_id)?;
+    let Some(encrypted) = &entry.otp else {
+        return Ok(None);
+    };
+    let uri = decrypt_entry_field(site_name, user_id, OTP_FIELD_TAG, encrypted, wrapped_key, user_key_nonce)?;
+    Ok(Some(OtpConfig::parse(&uri)?))
+}
+
+/// TOTP는 지금 시각의 코드를 만듦.
+/// HOTP는 저장된 카운터의 코드를 만들고, 같은 코드가 다시 나오지 않게 카운터를 올려 저장함
+pub fn generate_otp_code(
+    db: &mut DB,
+    site_name: &SiteName,
+    user_id: &UserID,
+    wrapped_key: &WrappedSessionKey,
+    user_key_nonce: &SessionKeyNonce,
+) -> Result<OtpCode, OtpError> {
+    let mut otp = read_otp(db, site_name, user_id, wrapped_key, user_key_nonce)?.ok_or(OtpError::NoOtp)?;
+    let now = Timestamp::now().unix_secs();
+    let code = otp.code_at(now);
+
+    match &mut otp.kind {
+        OtpKind::Totp { period } => Ok(OtpCode {
+            code,
+            remaining_secs: Some(*period - now % *period),
+            period: Some(*period),
+        }),
+        OtpKind::Hotp { counter } => {
+            *counter += 1;
+            let encrypted = encrypt_entry_field(site_name, user_id, OTP_FIELD_TAG, &otp.to_uri(), wrapped_key, user_key_nonce)?;
+            get_entry_mut(db, site_name, user_id)?.otp = Some(encrypted);
+            db.bump_generation();
+            Ok(OtpCode {
+                code,
+                remaining_secs: None,
+                period: None,
+            })
+        }
+    }
+}
+
+/// RFC 4648. 대소문자, 공백, `=` 패딩은 가리지 않음
+fn decode_base32(input: &str) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    let out = base32::decode(input).ok_or(OtpError::InvalidSecret)?;
+    if out.is_empty() {
+        return Err(OtpError::MissingSecret);
+    }
+    Ok(out)
+}
+
+fn percent_decode(s: &str) -> Result<Zeroizing<String>, OtpError> {
+    let mut bytes = Zeroizing::new(Vec::with_capacity(s.len()));
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hi = iter.next().and_then(|c| (c as char).to_digit(16));
+        let lo = iter.next().and_then(|c| (c as char).to_digit(16));
+        match (hi, lo) {
+            (Some(hi), Some(lo)) => bytes.push((hi << 4 | lo) as u8),
+            _ => return Err(OtpError::InvalidEncoding),
+        }
+    }
+    match String::from_utf8(std::mem::take(&mut *bytes)) {
+        Ok(decoded) => Ok(Zeroizing::new(decoded)),
+        Err(e) => {
+            e.into_bytes().zeroize();
+            Err(OtpError::InvalidEncoding)
+        }
+    }
+}
+
+/// RFC 3986의 unreserved 문자만 그대로 둠
+fn percent_encode(s: &str, out: &mut String) {
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+}
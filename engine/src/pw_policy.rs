@@ -0,0 +1,872 @@
+//! zxcvbn 방식의 비밀번호 강도 추정.
+//! 비밀번호를 사전 단어, 키보드 패턴, 연속/반복 문자, 날짜 같은 조각으로 나누는 방법 중
+//! 추측 횟수가 가장 적은 것을 찾아서 그 추측 횟수로 점수를 매김
+
+use crate::data_base::Timestamp;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use zeroize::Zeroizing;
+
+/// 흔한 비밀번호. 많이 쓰이는 순서
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+/// 패스프레이즈 단어 목록을 영어 단어 사전으로도 씀
+const ENGLISH_WORDS: &str = include_str!("wordlist.txt");
+
+/// 이 뒤의 문자는 추정하지 않음. 계산량이 길이의 세제곱으로 늘어나기 때문
+const MAX_ESTIMATED_CHARS: usize = 100;
+/// 무차별 대입에서 한 글자당 추측 횟수의 log10
+const BRUTEFORCE_LOG10_PER_CHAR: f64 = 1.0;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+const MIN_YEAR_SPACE: i64 = 20;
+const MAX_SEQUENCE_DELTA: i64 = 5;
+const MAX_REPEAT_UNIT: usize = 16;
+const KEYBOARD_STARTING_POSITIONS: f64 = 94.0;
+const KEYBOARD_AVERAGE_DEGREE: f64 = 4.6;
+const DATE_SEPARATORS: &str = "/-._ ";
+
+/// 왼쪽 위부터 한 줄씩. 같은 자리의 Shift 문자는 두 번째 배열
+const KEYBOARD_ROWS: [(&str, &str); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+"),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|"),
+    ("asdfghjkl;'", "ASDFGHJKL:\""),
+    ("zxcvbnm,./", "ZXCVBNM<>?"),
+];
+
+/// l33t 치환. 같은 문자가 여러 글자로 읽히면 앞의 것을 먼저 시도함
+const L33T_TABLE: [(char, char); 19] = [
+    ('4', 'a'), ('@', 'a'), ('8', 'b'), ('(', 'c'), ('3', 'e'), ('6', 'g'), ('9', 'g'),
+    ('1', 'i'), ('!', 'i'), ('|', 'i'), ('1', 'l'), ('|', 'l'), ('0', 'o'), ('$', 's'),
+    ('5', 's'), ('+', 't'), ('7', 't'), ('2', 'z'), ('%', 'x'),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DictionaryKind {
+    CommonPassword,
+    EnglishWord,
+}
+
+struct Dictionary {
+    ranks: HashMap<&'static str, (DictionaryKind, usize)>,
+    /// 가장 긴 단어의 글자 수
+    max_chars: usize,
+}
+
+static DICTIONARY: LazyLock<Dictionary> = LazyLock::new(|| {
+    let mut ranks = HashMap::new();
+    for (i, password) in COMMON_PASSWORDS.lines().enumerate() {
+        ranks.entry(password).or_insert((DictionaryKind::CommonPassword, i + 1));
+    }
+    // 사용 빈도 순서가 아니므로 모든 단어를 같은 순위로 봄
+    let word_count = ENGLISH_WORDS.lines().count();
+    for word in ENGLISH_WORDS.lines() {
+        ranks.entry(word).or_insert((DictionaryKind::EnglishWord, word_count));
+    }
+    let max_chars = ranks.keys().map(|word| word.chars().count()).max().unwrap_or(0);
+    Dictionary { ranks, max_chars }
+});
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PWScore {
+    /// 추측 10^3회 미만
+    #[default]
+    VeryWeak,
+    /// 10^6회 미만
+    Weak,
+    /// 10^8회 미만
+    Fair,
+    /// 10^10회 미만
+    Strong,
+    VeryStrong,
+}
+
+impl PWScore {
+    pub const ALL: [PWScore; 5] = [
+        PWScore::VeryWeak,
+        PWScore::Weak,
+        PWScore::Fair,
+        PWScore::Strong,
+        PWScore::VeryStrong,
+    ];
+
+    fn from_guesses_log10(guesses_log10: f64) -> Self {
+        match guesses_log10 {
+            g if g < 3.0 => PWScore::VeryWeak,
+            g if g < 6.0 => PWScore::Weak,
+            g if g < 8.0 => PWScore::Fair,
+            g if g < 10.0 => PWScore::Strong,
+            _ => PWScore::VeryStrong,
+        }
+    }
+
+    /// 0..=4
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+}
+impl Display for PWScore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PWScore::VeryWeak => write!(f, "very weak"),
+            PWScore::Weak => write!(f, "weak"),
+            PWScore::Fair => write!(f, "fair"),
+            PWScore::Strong => write!(f, "strong"),
+            PWScore::VeryStrong => write!(f, "very strong"),
+        }
+    }
+}
+/// 이름(`very-strong`처럼 써도 됨)이나 0..=4
+impl FromStr for PWScore {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        PWScore::ALL
+            .into_iter()
+            .find(|score| {
+                score.value().to_string() == s
+                    || score.to_string().eq_ignore_ascii_case(&s.replace(['-', '_'], " "))
+            })
+            .ok_or_else(|| format!("unknown score \"{}\" (0..=4, very-weak, weak, fair, strong, very-strong)", s))
+    }
+}
+
+/// 비밀번호에서 가장 크게 눈에 띈 약점
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PWWarning {
+    TopPassword,
+    CommonPassword,
+    SimilarToCommonPassword,
+    WordByItself,
+    KeyboardPattern,
+    Repeat,
+    Sequence,
+    RecentYear,
+    Date,
+}
+impl Display for PWWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PWWarning::TopPassword => write!(f, "This is a top-10 common password"),
+            PWWarning::CommonPassword => write!(f, "This is a commonly used password"),
+            PWWarning::SimilarToCommonPassword => {
+                write!(f, "This is similar to a commonly used password")
+            }
+            PWWarning::WordByItself => write!(f, "A word by itself is easy to guess"),
+            PWWarning::KeyboardPattern => write!(f, "Keyboard patterns are easy to guess"),
+            PWWarning::Repeat => write!(f, "Repeated characters or words are easy to guess"),
+            PWWarning::Sequence => write!(f, "Sequences like abc or 6543 are easy to guess"),
+            PWWarning::RecentYear => write!(f, "Recent years are easy to guess"),
+            PWWarning::Date => write!(f, "Dates are often easy to guess"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PWSuggestion {
+    UseAFewWords,
+    NoNeedForSymbols,
+    AddAnotherWord,
+    CapitalizationDoesntHelp,
+    AllUppercaseIsEasy,
+    ReversedWordsDontHelp,
+    PredictableSubstitutions,
+    AvoidKeyboardPatterns,
+    AvoidRepeats,
+    AvoidSequences,
+    AvoidDates,
+}
+impl Display for PWSuggestion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PWSuggestion::UseAFewWords => write!(f, "Use a few words, avoid common phrases"),
+            PWSuggestion::NoNeedForSymbols => {
+                write!(f, "No need for symbols, digits, or uppercase letters")
+            }
+            PWSuggestion::AddAnotherWord => {
+                write!(f, "Add another word or two. Uncommon words are better")
+            }
+            PWSuggestion::CapitalizationDoesntHelp => {
+                write!(f, "Capitalization doesn't help very much")
+            }
+            PWSuggestion::AllUppercaseIsEasy => {
+                write!(f, "All-uppercase is almost as easy to guess as all-lowercase")
+            }
+            PWSuggestion::ReversedWordsDontHelp => {
+                write!(f, "Reversed words aren't much harder to guess")
+            }
+            PWSuggestion::PredictableSubstitutions => {
+                write!(f, "Predictable substitutions like '@' instead of 'a' don't help very much")
+            }
+            PWSuggestion::AvoidKeyboardPatterns => {
+                write!(f, "Avoid keyboard patterns like qwer or 1qaz")
+            }
+            PWSuggestion::AvoidRepeats => write!(f, "Avoid repeated words and characters"),
+            PWSuggestion::AvoidSequences => write!(f, "Avoid sequences"),
+            PWSuggestion::AvoidDates => {
+                write!(f, "Avoid dates and years that are associated with you")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PWFeedback {
+    pub score: PWScore,
+    pub warning: Option<PWWarning>,
+    pub suggestions: Vec<PWSuggestion>,
+}
+impl Display for PWFeedback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}/4)", self.score, self.score.value())?;
+        if let Some(warning) = self.warning {
+            write!(f, ". {}", warning)?;
+        }
+        for suggestion in &self.suggestions {
+            write!(f, ". {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StrengthEstimate {
+    /// 추측 횟수의 log10
+    pub guesses_log10: f64,
+    pub feedback: PWFeedback,
+}
+
+impl StrengthEstimate {
+    pub fn score(&self) -> PWScore {
+        self.feedback.score
+    }
+}
+
+/// 새 마스터 비밀번호에 적용하는 조건
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MasterPWPolicy {
+    /// 바이트가 아니라 글자 수. 조합 중인 한글 자모는 한 글자로 셈
+    pub min_chars: usize,
+    pub min_score: PWScore,
+}
+
+impl Default for MasterPWPolicy {
+    fn default() -> Self {
+        Self {
+            min_chars: 8,
+            min_score: PWScore::Strong,
+        }
+    }
+}
+
+/// 사람이 보는 글자 수. 한글 자모(U+1100..)로 입력된 음절은 한 글자로 셈
+pub fn char_count(pw: &str) -> usize {
+    compose_hangul(pw).chars().count()
+}
+
+pub fn estimate_strength(pw: &str) -> StrengthEstimate {
+    let composed = compose_hangul(pw);
+    let chars: Zeroizing<Vec<char>> = Zeroizing::new(composed.chars().collect());
+    let estimated = &chars[..chars.len().min(MAX_ESTIMATED_CHARS)];
+    let rest = chars.len() - estimated.len();
+
+    let (guesses_log10, sequence) = most_guessable(estimated);
+    let guesses_log10 = guesses_log10 + rest as f64 * BRUTEFORCE_LOG10_PER_CHAR;
+    let score = PWScore::from_guesses_log10(guesses_log10);
+    StrengthEstimate {
+        guesses_log10,
+        feedback: feedback(score, &sequence, estimated),
+    }
+}
+
+/// 한글 첫가끝 자모열을 완성형 음절로 합침.
+/// 입력기에 따라 같은 글자가 자모열로 들어오기도 해서 글자 수와 사전 비교가 달라지지 않게 함
+fn compose_hangul(pw: &str) -> Zeroizing<String> {
+    let mut composed = Zeroizing::new(String::with_capacity(pw.len()));
+    let mut last: Option<char> = None;
+    for c in pw.chars() {
+        if let Some(prev) = last {
+            if let Some(syllable) = compose_jamo(prev, c) {
+                last = Some(syllable);
+                continue;
+            }
+            composed.push(prev);
+        }
+        last = Some(c);
+    }
+    composed.extend(last);
+    composed
+}
+
+/// 초성 + 중성, 받침 없는 음절 + 종성을 한 음절로 합침
+fn compose_jamo(prev: char, next: char) -> Option<char> {
+    const S_BASE: u32 = 0xAC00;
+    const L_BASE: u32 = 0x1100;
+    const V_BASE: u32 = 0x1161;
+    const T_BASE: u32 = 0x11A7;
+    const L_COUNT: u32 = 19;
+    const V_COUNT: u32 = 21;
+    const T_COUNT: u32 = 28;
+    const S_COUNT: u32 = L_COUNT * V_COUNT * T_COUNT;
+
+    let (prev, code) = (prev as u32, next as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&prev) && (V_BASE..V_BASE + V_COUNT).contains(&code) {
+        return char::from_u32(S_BASE + ((prev - L_BASE) * V_COUNT + (code - V_BASE)) * T_COUNT);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&prev)
+        && (prev - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&code)
+    {
+        return char::from_u32(prev + code - T_BASE);
+    }
+    None
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Dictionary {
+        kind: DictionaryKind,
+        rank: usize,
+        reversed: bool,
+        l33t: bool,
+    },
+    Spatial,
+    Repeat,
+    Sequence,
+    Year,
+    Date,
+    Bruteforce,
+}
+
+#[derive(Clone, Debug)]
+struct Match {
+    /// 글자 단위 [start, end)
+    start: usize,
+    end: usize,
+    guesses_log10: f64,
+    pattern: Pattern,
+}
+
+impl Match {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// 추측 횟수가 가장 적은 조각 나누기와 그 추측 횟수(log10).
+/// 조각 l개의 추측 횟수는 l! * (각 조각 추측 횟수의 곱) + 10000^(l-1)
+fn most_guessable(chars: &[char]) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (0.0, Vec::new());
+    }
+
+    let mut matches = Vec::new();
+    dictionary_matches(chars, &mut matches);
+    spatial_matches(chars, &mut matches);
+    repeat_matches(chars, &mut matches);
+    sequence_matches(chars, &mut matches);
+    date_matches(chars, &mut matches);
+    for start in 0..n {
+        for end in start + 1..=n {
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: (end - start) as f64 * BRUTEFORCE_LOG10_PER_CHAR,
+                pattern: Pattern::Bruteforce,
+            });
+        }
+    }
+    // 전체가 아닌 조각은 최소 추측 횟수를 둬서 짧은 조각을 잘게 나누는 쪽이 유리하지 않게 함
+    for m in matches.iter_mut().filter(|m| m.len() < n) {
+        let min = if m.len() == 1 {
+            MIN_SUBMATCH_GUESSES_SINGLE_CHAR
+        } else {
+            MIN_SUBMATCH_GUESSES_MULTI_CHAR
+        };
+        m.guesses_log10 = m.guesses_log10.max(min.log10());
+    }
+
+    let mut by_end: Vec<Vec<usize>> = vec![Vec::new(); n + 1];
+    for (i, m) in matches.iter().enumerate() {
+        by_end[m.end].push(i);
+    }
+
+    // best[k][l]: 앞의 k글자를 조각 l개로 덮을 때 (추측 횟수 곱의 log10, 마지막 조각)
+    let mut best: Vec<Vec<Option<(f64, usize)>>> = vec![vec![None; n + 1]; n + 1];
+    best[0][0] = Some((0.0, usize::MAX));
+    for k in 1..=n {
+        for &i in &by_end[k] {
+            let m = &matches[i];
+            for l in 0..=m.start {
+                let Some((product, _)) = best[m.start][l] else {
+                    continue;
+                };
+                let candidate = product + m.guesses_log10;
+                if best[k][l + 1].is_none_or(|(current, _)| candidate < current) {
+                    best[k][l + 1] = Some((candidate, i));
+                }
+            }
+        }
+    }
+
+    // 한 글자 조각이 모든 위치에 있어 전체를 덮는 나누기가 항상 있음
+    let Some((guesses_log10, count)) = (1..=n)
+        .filter_map(|l| {
+            best[n][l].map(|(product, _)| {
+                let guesses = log10_add(log10_factorial(l) + product, 4.0 * (l - 1) as f64);
+                (guesses, l)
+            })
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+    else {
+        return (n as f64 * BRUTEFORCE_LOG10_PER_CHAR, Vec::new());
+    };
+
+    let mut sequence = Vec::with_capacity(count);
+    let (mut k, mut l) = (n, count);
+    while l > 0
+        && let Some((_, i)) = best[k][l]
+    {
+        sequence.push(matches[i].clone());
+        k = matches[i].start;
+        l -= 1;
+    }
+    sequence.reverse();
+    (guesses_log10, sequence)
+}
+
+fn dictionary_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let dictionary = &*DICTIONARY;
+    let lower: Zeroizing<Vec<char>> = Zeroizing::new(
+        chars
+            .iter()
+            .map(|c| c.to_lowercase().next().unwrap_or(*c))
+            .collect(),
+    );
+    let mut token = Zeroizing::new(String::new());
+    let lookup = |token: &str, start: usize, end: usize, reversed: bool, l33t: bool, matches: &mut Vec<Match>| {
+        let Some(&(kind, rank)) = dictionary.ranks.get(token) else {
+            return;
+        };
+        let original = &chars[start..end];
+        let mut guesses_log10 = (rank as f64).log10() + uppercase_variations_log10(original);
+        if l33t {
+            guesses_log10 += l33t_variations_log10(original, &lower[start..end]);
+        }
+        if reversed {
+            guesses_log10 += 2f64.log10();
+        }
+        matches.push(Match {
+            start,
+            end,
+            guesses_log10,
+            pattern: Pattern::Dictionary {
+                kind,
+                rank,
+                reversed,
+                l33t,
+            },
+        });
+    };
+
+    for start in 0..lower.len() {
+        for end in start + 1..=lower.len().min(start + dictionary.max_chars) {
+            let slice = &lower[start..end];
+
+            token.clear();
+            token.extend(slice);
+            lookup(token.as_str(), start, end, false, false, matches);
+
+            token.clear();
+            token.extend(slice.iter().rev());
+            if slice.len() > 1 && !slice.iter().eq(slice.iter().rev()) {
+                lookup(token.as_str(), start, end, true, false, matches);
+            }
+
+            // 1 -> i, 1 -> l처럼 여러 뜻이 있는 문자는 처음 것과 마지막 것을 각각 시도함
+            for last in [false, true] {
+                token.clear();
+                token.extend(slice.iter().map(|c| unl33t(*c, last)));
+                if token.chars().ne(slice.iter().copied()) {
+                    lookup(token.as_str(), start, end, false, true, matches);
+                }
+            }
+        }
+    }
+}
+
+fn unl33t(c: char, last: bool) -> char {
+    let mut candidates = L33T_TABLE.iter().filter(|(l33t, _)| *l33t == c);
+    let found = if last { candidates.next_back() } else { candidates.next() };
+    found.map(|(_, letter)| *letter).unwrap_or(c)
+}
+
+fn uppercase_variations_log10(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 0.0;
+    }
+    let first_only = word.first().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    let last_only = word.last().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    if lower == 0 || first_only || last_only {
+        return 2f64.log10();
+    }
+    (1..=upper.min(lower))
+        .map(|i| n_choose_k(upper + lower, i))
+        .sum::<f64>()
+        .log10()
+}
+
+fn l33t_variations_log10(original: &[char], lower: &[char]) -> f64 {
+    let mut variations_log10 = 0.0;
+    for (l33t, letter) in L33T_TABLE {
+        let subbed = original.iter().filter(|c| **c == l33t).count();
+        if subbed == 0 {
+            continue;
+        }
+        let unsubbed = lower.iter().filter(|c| **c == letter).count();
+        variations_log10 += if unsubbed == 0 {
+            2f64.log10()
+        } else {
+            (1..=subbed.min(unsubbed))
+                .map(|i| n_choose_k(subbed + unsubbed, i))
+                .sum::<f64>()
+                .log10()
+        };
+    }
+    variations_log10
+}
+
+/// (줄, 칸, Shift 여부)
+fn keyboard_position(c: char) -> Option<(i64, i64, bool)> {
+    KEYBOARD_ROWS.iter().enumerate().find_map(|(row, (plain, shifted))| {
+        if let Some(col) = plain.chars().position(|k| k == c) {
+            return Some((row as i64, col as i64, false));
+        }
+        shifted
+            .chars()
+            .position(|k| k == c)
+            .map(|col| (row as i64, col as i64, true))
+    })
+}
+
+/// 이웃한 키면 방향을 돌려줌. 윗줄은 한 칸 왼쪽으로, 아랫줄은 한 칸 오른쪽으로 밀려 있음
+fn keyboard_direction(from: (i64, i64), to: (i64, i64)) -> Option<(i64, i64)> {
+    let (dr, dc) = (to.0 - from.0, to.1 - from.1);
+    let adjacent = match dr {
+        0 => dc.abs() == 1,
+        -1 => dc == 0 || dc == 1,
+        1 => dc == 0 || dc == -1,
+        _ => false,
+    };
+    adjacent.then_some((dr, dc))
+}
+
+fn spatial_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let positions: Vec<_> = chars.iter().map(|c| keyboard_position(*c)).collect();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start + 1;
+        let mut turns = 0;
+        let mut last_direction = None;
+        while end < chars.len() {
+            let (Some(from), Some(to)) = (positions[end - 1], positions[end]) else {
+                break;
+            };
+            let Some(direction) = keyboard_direction((from.0, from.1), (to.0, to.1)) else {
+                break;
+            };
+            if last_direction != Some(direction) {
+                turns += 1;
+                last_direction = Some(direction);
+            }
+            end += 1;
+        }
+        if end - start >= 3 {
+            let len = end - start;
+            let mut guesses = 0.0;
+            for i in 2..=len {
+                for j in 1..=turns.min(i - 1) {
+                    guesses += n_choose_k(i - 1, j - 1)
+                        * KEYBOARD_STARTING_POSITIONS
+                        * KEYBOARD_AVERAGE_DEGREE.powi(j as i32);
+                }
+            }
+            let shifted = positions[start..end]
+                .iter()
+                .filter(|p| p.is_some_and(|p| p.2))
+                .count();
+            let unshifted = len - shifted;
+            let shift_variations = if shifted == 0 {
+                1.0
+            } else if unshifted == 0 {
+                2.0
+            } else {
+                (1..=shifted.min(unshifted))
+                    .map(|i| n_choose_k(len, i))
+                    .sum()
+            };
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: guesses.log10() + shift_variations.log10(),
+                pattern: Pattern::Spatial,
+            });
+        }
+        start = end.max(start + 1);
+    }
+}
+
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len() {
+        for unit_len in 1..=MAX_REPEAT_UNIT.min((chars.len() - start) / 2) {
+            let unit = &chars[start..start + unit_len];
+            let count = chars[start..]
+                .chunks_exact(unit_len)
+                .take_while(|chunk| *chunk == unit)
+                .count();
+            if count < 2 || (unit_len == 1 && count < 3) {
+                continue;
+            }
+            let (unit_guesses_log10, _) = most_guessable(unit);
+            matches.push(Match {
+                start,
+                end: start + unit_len * count,
+                guesses_log10: unit_guesses_log10 + (count as f64).log10(),
+                pattern: Pattern::Repeat,
+            });
+        }
+    }
+}
+
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let codes: Vec<i64> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c) as i64)
+        .collect();
+    let mut start = 0;
+    while start + 1 < codes.len() {
+        let delta = codes[start + 1] - codes[start];
+        if delta == 0 || delta.abs() > MAX_SEQUENCE_DELTA {
+            start += 1;
+            continue;
+        }
+        let mut end = start + 2;
+        while end < codes.len() && codes[end] - codes[end - 1] == delta {
+            end += 1;
+        }
+        if end - start >= 3 {
+            let first = chars[start];
+            let mut base: f64 = if "az19".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            if delta < 0 {
+                base *= 2.0;
+            }
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: (base * (end - start) as f64).log10(),
+                pattern: Pattern::Sequence,
+            });
+        }
+        start = end - 1;
+    }
+}
+
+fn date_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let reference_year = Timestamp::now().year();
+    let year_space = |year: i64| ((year - reference_year).abs().max(MIN_YEAR_SPACE)) as f64;
+
+    for start in 0..chars.len() {
+        for end in start + 4..=chars.len().min(start + 10) {
+            let token = &chars[start..end];
+            if token.len() == 4 && token.iter().all(|c| c.is_ascii_digit()) {
+                let year = digits_value(token);
+                if (1900..=2099).contains(&year) {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses_log10: year_space(year).log10(),
+                        pattern: Pattern::Year,
+                    });
+                }
+            }
+            let Some((year, separated)) = parse_date(token) else {
+                continue;
+            };
+            let mut guesses = year_space(year) * 365.0;
+            if separated {
+                guesses *= 4.0;
+            }
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: guesses.log10(),
+                pattern: Pattern::Date,
+            });
+        }
+    }
+}
+
+/// 날짜로 읽히면 (년, 구분자 있음)
+fn parse_date(token: &[char]) -> Option<(i64, bool)> {
+    let (parts, separated): (Vec<&[char]>, bool) = match token.iter().find(|c| !c.is_ascii_digit()) {
+        None if token.len() == 6 => (vec![&token[..2], &token[2..4], &token[4..]], false),
+        None if token.len() == 8 => {
+            // YYYYMMDD, DDMMYYYY, MMDDYYYY
+            return [
+                [&token[..4], &token[4..6], &token[6..]],
+                [&token[4..], &token[2..4], &token[..2]],
+                [&token[4..], &token[..2], &token[2..4]],
+            ]
+            .into_iter()
+            .find_map(|[year, month, day]| valid_date(year, month, day))
+            .map(|year| (year, false));
+        }
+        None => return None,
+        Some(separator) => {
+            if !DATE_SEPARATORS.contains(*separator) {
+                return None;
+            }
+            let parts: Vec<&[char]> = token.split(|c| c == separator).collect();
+            if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || !p.iter().all(|c| c.is_ascii_digit())) {
+                return None;
+            }
+            (parts, true)
+        }
+    };
+    let [a, b, c] = [parts[0], parts[1], parts[2]];
+    [[a, b, c], [c, b, a], [c, a, b]]
+        .into_iter()
+        .find_map(|[year, month, day]| valid_date(year, month, day))
+        .map(|year| (year, separated))
+}
+
+fn valid_date(year: &[char], month: &[char], day: &[char]) -> Option<i64> {
+    if month.len() > 2 || day.len() > 2 {
+        return None;
+    }
+    let year = match year.len() {
+        2 => {
+            let year = digits_value(year);
+            if year > 50 { 1900 + year } else { 2000 + year }
+        }
+        4 => digits_value(year),
+        _ => return None,
+    };
+    let valid = (1900..=2099).contains(&year)
+        && (1..=12).contains(&digits_value(month))
+        && (1..=31).contains(&digits_value(day));
+    valid.then_some(year)
+}
+
+fn digits_value(digits: &[char]) -> i64 {
+    digits
+        .iter()
+        .fold(0, |acc, c| acc * 10 + c.to_digit(10).unwrap_or(0) as i64)
+}
+
+fn feedback(score: PWScore, sequence: &[Match], chars: &[char]) -> PWFeedback {
+    let mut feedback = PWFeedback {
+        score,
+        warning: None,
+        suggestions: Vec::new(),
+    };
+    if score >= PWScore::Strong {
+        return feedback;
+    }
+    if chars.is_empty() {
+        feedback.suggestions = vec![PWSuggestion::UseAFewWords, PWSuggestion::NoNeedForSymbols];
+        return feedback;
+    }
+
+    feedback.suggestions.push(PWSuggestion::AddAnotherWord);
+    let Some(longest) = sequence
+        .iter()
+        .filter(|m| !matches!(m.pattern, Pattern::Bruteforce))
+        .max_by_key(|m| m.len())
+    else {
+        return feedback;
+    };
+    let is_sole_match = sequence.len() == 1;
+    match &longest.pattern {
+        Pattern::Dictionary {
+            kind,
+            rank,
+            reversed,
+            l33t,
+        } => {
+            feedback.warning = match kind {
+                DictionaryKind::CommonPassword if *l33t || *reversed => {
+                    Some(PWWarning::SimilarToCommonPassword)
+                }
+                DictionaryKind::CommonPassword if is_sole_match && *rank <= 10 => {
+                    Some(PWWarning::TopPassword)
+                }
+                DictionaryKind::CommonPassword => Some(PWWarning::CommonPassword),
+                DictionaryKind::EnglishWord if is_sole_match => Some(PWWarning::WordByItself),
+                DictionaryKind::EnglishWord => None,
+            };
+            let word = &chars[longest.start..longest.end];
+            let upper = word.iter().filter(|c| c.is_uppercase()).count();
+            if word.len() > 1 && upper == word.len() {
+                feedback.suggestions.push(PWSuggestion::AllUppercaseIsEasy);
+            } else if word.first().is_some_and(|c| c.is_uppercase()) {
+                feedback.suggestions.push(PWSuggestion::CapitalizationDoesntHelp);
+            }
+            if *reversed && word.len() >= 4 {
+                feedback.suggestions.push(PWSuggestion::ReversedWordsDontHelp);
+            }
+            if *l33t {
+                feedback.suggestions.push(PWSuggestion::PredictableSubstitutions);
+            }
+        }
+        Pattern::Spatial => {
+            feedback.warning = Some(PWWarning::KeyboardPattern);
+            feedback.suggestions.push(PWSuggestion::AvoidKeyboardPatterns);
+        }
+        Pattern::Repeat => {
+            feedback.warning = Some(PWWarning::Repeat);
+            feedback.suggestions.push(PWSuggestion::AvoidRepeats);
+        }
+        Pattern::Sequence => {
+            feedback.warning = Some(PWWarning::Sequence);
+            feedback.suggestions.push(PWSuggestion::AvoidSequences);
+        }
+        Pattern::Year => {
+            feedback.warning = Some(PWWarning::RecentYear);
+            feedback.suggestions.push(PWSuggestion::AvoidDates);
+        }
+        Pattern::Date => {
+            feedback.warning = Some(PWWarning::Date);
+            feedback.suggestions.push(PWSuggestion::AvoidDates);
+        }
+        Pattern::Bruteforce => {}
+    }
+    feedback
+}
+
+fn n_choose_k(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (1..=k).fold(1.0, |acc, i| acc * (n + 1 - i) as f64 / i as f64)
+}
+
+fn log10_factorial(n: usize) -> f64 {
+    (2..=n).map(|i| (i as f64).log10()).sum()
+}
+
+/// log10(10^a + 10^b)
+fn log10_add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high + (1.0 + 10f64.powf(low - high)).log10()
+}
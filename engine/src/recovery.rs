@@ -0,0 +1,221 @@
+//! 복구 코드와 복구 조각에서 키 쌍을 유도하고 볼트 비밀 키를 헤더의 슬롯에 감쌈.
+//! 슬롯을 만들고 여는 것은 `key_slots`가 맡음
+
+use crate::base32;
+use crate::data_base::Timestamp;
+use crate::header::RecoverySlot;
+use crate::master_secrets::manual_zeroize;
+use crate::shamir::{SHARE_SECRET_SIZE, Share};
+use libsodium_sys::rust_wrappings::aes256gcm::{
+    AesNonce, aes256gcm_decrypt_with_ad_write_to_ptr, aes256gcm_encrypt_with_ad_write_to_ptr,
+};
+use libsodium_sys::rust_wrappings::hasher::Blake2b;
+use libsodium_sys::rust_wrappings::randombytes::random_buf;
+use libsodium_sys::rust_wrappings::x25519::*;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// 복구 코드의 무작위 바이트 수. base32로 32글자
+pub const RECOVERY_CODE_SIZE: usize = 20;
+/// 받아 적기 쉽도록 이 글자 수마다 `-`를 넣음
+const RECOVERY_CODE_GROUP_LEN: usize = 4;
+pub(crate) const X25519_KEY_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RecoveryError {
+    /// base32가 아니거나 길이가 맞지 않음
+    InvalidCode,
+}
+impl Display for RecoveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecoveryError::InvalidCode => {
+                write!(f, "Recovery code must be {} base32 characters", RECOVERY_CODE_SIZE * 8 / 5)
+            }
+        }
+    }
+}
+impl Error for RecoveryError {}
+
+/// 비상 키트에 적는 복구 코드. 복구 코드 슬롯을 여는 키 쌍은 이 코드에서만 유도됨
+pub struct RecoveryKey {
+    code: Zeroizing<[u8; RECOVERY_CODE_SIZE]>,
+}
+impl RecoveryKey {
+    pub(crate) fn generate() -> Self {
+        let mut code = Zeroizing::new([0u8; RECOVERY_CODE_SIZE]);
+        random_buf(code.as_mut_slice());
+        Self { code }
+    }
+
+    /// 대소문자, 공백, `-`는 가리지 않음
+    pub fn parse(input: &str) -> Result<Self, RecoveryError> {
+        let decoded = base32::decode(input.trim()).ok_or(RecoveryError::InvalidCode)?;
+        if decoded.len() != RECOVERY_CODE_SIZE {
+            return Err(RecoveryError::InvalidCode);
+        }
+        let mut code = Zeroizing::new([0u8; RECOVERY_CODE_SIZE]);
+        code.copy_from_slice(&decoded);
+        Ok(Self { code })
+    }
+
+    /// `ABCD-EFGH-...` 꼴
+    pub fn to_code(&self) -> Zeroizing<String> {
+        base32::encode_grouped(self.code.as_slice(), RECOVERY_CODE_GROUP_LEN)
+    }
+
+    pub(crate) fn key_pair(&self) -> (SecKey, [u8; X25519_KEY_SIZE]) {
+        derive_key_pair(b"pw-manager recovery key", self.code.as_slice())
+    }
+}
+
+/// 복구 코드와 조각으로 나누는 비밀은 충분히 무작위이므로 KDF 없이 해시 한 번으로 유도함
+fn derive_key_pair(halo: &[u8], secret: &[u8]) -> (SecKey, [u8; X25519_KEY_SIZE]) {
+    let mut hasher = Blake2b::<X25519_KEY_SIZE>::new();
+    hasher.update(halo);
+    hasher.update(secret);
+    let mut raw = [0u8; X25519_KEY_SIZE];
+    hasher.finalize_write_to(raw.as_mut_ptr());
+    let sec_key = SecKey::from_raw(raw.as_ptr());
+    manual_zeroize(&mut raw);
+    let pub_key = pub_key_to_bytes(&PubKey::from_sec_key(&sec_key));
+    (sec_key, pub_key)
+}
+pub(crate) fn shares_key_pair(secret: &[u8; SHARE_SECRET_SIZE]) -> (SecKey, [u8; X25519_KEY_SIZE]) {
+    derive_key_pair(b"pw-manager recovery shares", secret)
+}
+
+/// `PubKey`는 `EncryptedDB` 레이아웃 때문에 `ECIES_PK_SIZE`만큼 읽고 씀
+pub(crate) fn pub_key_to_bytes(pub_key: &PubKey) -> [u8; X25519_KEY_SIZE] {
+    let mut buf = [0u8; ECIES_PK_SIZE];
+    pub_key.copy_to(buf.as_mut_ptr());
+    std::array::from_fn(|i| buf[i])
+}
+fn pub_key_from_bytes(bytes: &[u8; X25519_KEY_SIZE]) -> PubKey {
+    let mut buf = [0u8; ECIES_PK_SIZE];
+    buf[..X25519_KEY_SIZE].copy_from_slice(bytes);
+    PubKey::from_raw(buf.as_ptr())
+}
+
+fn slot_ad(recovery_pub_key: &[u8; X25519_KEY_SIZE]) -> Vec<u8> {
+    let mut ad = b"pw-manager recovery slot".to_vec();
+    ad.extend_from_slice(recovery_pub_key);
+    ad
+}
+
+/// 볼트 비밀 키를 복구 공개키로 `encrypt_db`와 같은 방식으로 암호화함. 키 슬롯도 같은 방식을 씀
+pub(crate) fn seal_slot(recovery_pub_key: &[u8; X25519_KEY_SIZE], sec_key: &SecKey) -> RecoverySlot {
+    let peer_sk = SecKey::gen_rand();
+    let shared = SharedSecret::from_sk_pk(&peer_sk, &pub_key_from_bytes(recovery_pub_key));
+    let once_aes_key = shared_secret_to_aes_key(&shared);
+    drop(shared);
+    let peer_pk = PubKey::from_sec_key(&peer_sk);
+    drop(peer_sk);
+    let nonce = AesNonce::gen_rand();
+
+    let mut slot = RecoverySlot::EMPTY;
+    slot.recovery_pub_key = *recovery_pub_key;
+    slot.peer_pub_key = pub_key_to_bytes(&peer_pk);
+    nonce.copy_to(slot.nonce.as_mut_ptr());
+
+    let mut raw_sec_key = [0u8; ECIES_SK_SIZE];
+    sec_key.copy_to(raw_sec_key.as_mut_ptr());
+    aes256gcm_encrypt_with_ad_write_to_ptr(
+        &once_aes_key,
+        &nonce,
+        &raw_sec_key,
+        &slot_ad(recovery_pub_key),
+        slot.wrapped_sec_key.as_mut_ptr(),
+    );
+    manual_zeroize(&mut raw_sec_key);
+    slot
+}
+
+pub(crate) fn open_slot(slot: &RecoverySlot, recovery_sec_key: &SecKey) -> Option<SecKey> {
+    let shared = SharedSecret::from_sk_pk(recovery_sec_key, &pub_key_from_bytes(&slot.peer_pub_key));
+    let once_aes_key = shared_secret_to_aes_key(&shared);
+    drop(shared);
+    let nonce = AesNonce::from_raw(slot.nonce.as_ptr());
+
+    let mut raw_sec_key = [0u8; ECIES_SK_SIZE];
+    let opened = aes256gcm_decrypt_with_ad_write_to_ptr(
+        &once_aes_key,
+        &nonce,
+        &slot.wrapped_sec_key,
+        &slot_ad(&slot.recovery_pub_key),
+        raw_sec_key.as_mut_ptr(),
+    );
+    let sec_key = opened.ok().map(|_| SecKey::from_raw(raw_sec_key.as_ptr()));
+    manual_zeroize(&mut raw_sec_key);
+    sec_key
+}
+
+/// 볼트 비밀 키가 바뀔 때 같은 자격 증명으로 열리도록 다시 암호화함. 자격 증명은 필요 없음
+pub(crate) fn rewrap_recovery_slot(slot: &RecoverySlot, new_sec_key: &SecKey) -> RecoverySlot {
+    if slot.is_empty() {
+        return RecoverySlot::EMPTY;
+    }
+    seal_slot(&slot.recovery_pub_key, new_sec_key)
+}
+
+/// 조각 하나를 맡을 사람에게 줄 안내문. `slot_index`는 조각으로 여는 키 슬롯 번호
+pub fn share_sheet(share: &Share, count: usize, slot_index: usize, vault_file: &Path) -> Zeroizing<String> {
+    Zeroizing::new(format!(
+        "PW Manager recovery share {} of {}\n\
+         ================================\n\
+         \n\
+         Vault:   {}\n\
+         Created: {}\n\
+         \n\
+         Recovery share:\n\
+         \n\
+         \x20   {}\n\
+         \n\
+         Any {} of the {} shares together unlock the vault and then ask for a new\n\
+         master password. In the CLI start with --slot {}, in the app choose\n\
+         \"use key slot\" and slot {} on the login screen. No network is needed.\n\
+         \n\
+         One share alone reveals nothing. Keep it offline and never collect shares\n\
+         in one place before they are needed. Revoking their key slot makes this\n\
+         one useless.\n",
+        share.index(),
+        count,
+        vault_file.display(),
+        Timestamp::now(),
+        share.to_text().as_str(),
+        share.threshold(),
+        count,
+        slot_index,
+        slot_index,
+    ))
+}
+
+/// 인쇄하거나 파일로 보관할 비상 키트 본문. `slot_index`는 복구 코드로 여는 키 슬롯 번호
+pub fn emergency_kit(recovery_key: &RecoveryKey, slot_index: usize, vault_file: &Path) -> Zeroizing<String> {
+    Zeroizing::new(format!(
+        "PW Manager emergency kit\n\
+         ========================\n\
+         \n\
+         Vault:   {}\n\
+         Created: {}\n\
+         \n\
+         Recovery code:\n\
+         \n\
+         \x20   {}\n\
+         \n\
+         If you forget the master password, this code unlocks the vault and then\n\
+         asks for a new master password. In the CLI start with --slot {}, in the\n\
+         app choose \"use key slot\" and slot {} on the login screen.\n\
+         \n\
+         Anyone holding this code can open the vault. Keep this page offline,\n\
+         somewhere safe, and away from the vault file. Revoking its key slot\n\
+         makes this code useless.\n",
+        vault_file.display(),
+        Timestamp::now(),
+        recovery_key.to_code().as_str(),
+        slot_index,
+        slot_index,
+    ))
+}
@@ -0,0 +1,303 @@
+use crate::data_base::{DB, DBIOError, Generation, NOTES_FIELD_TAG, SiteName, UserID};
+use crate::user_secrets::{SessionKeyNonce, WrappedSessionKey, decrypt_entry_field};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use zeroize::Zeroizing;
+
+/// 메모에서 일치한 줄을 보여줄 때의 최대 글자 수
+const SNIPPET_LEN: usize = 80;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SearchField {
+    /// `SiteName::as_str`
+    Site,
+    /// 등록 가능한 도메인
+    Domain,
+    UserID,
+    Tag,
+    Notes,
+}
+
+impl SearchField {
+    /// 같은 점수로 맞으면 사이트와 ID가 메모보다 앞에 옴
+    fn weight(self) -> u32 {
+        match self {
+            SearchField::Site | SearchField::UserID => 3,
+            SearchField::Domain | SearchField::Tag => 2,
+            SearchField::Notes => 1,
+        }
+    }
+}
+
+impl Display for SearchField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SearchField::Site => "site",
+            SearchField::Domain => "domain",
+            SearchField::UserID => "id",
+            SearchField::Tag => "tag",
+            SearchField::Notes => "notes",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 필드 하나에서 맞은 부분. `ranges`는 `text`의 바이트 범위이고 겹치지 않게 정렬되어 있음.
+/// 메모면 `text`는 맞은 줄의 일부
+pub struct FieldMatch {
+    pub field: SearchField,
+    pub text: Zeroizing<String>,
+    pub ranges: Vec<Range<usize>>,
+}
+
+pub struct SearchHit {
+    pub site: SiteName,
+    pub id: UserID,
+    pub score: u32,
+    pub matches: Vec<FieldMatch>,
+}
+
+impl SearchHit {
+    pub fn field(&self, field: SearchField) -> Option<&FieldMatch> {
+        self.matches.iter().find(|matched| matched.field == field)
+    }
+}
+
+/// 잠금 해제 뒤 메모리에만 두는 검색 색인. 메모는 복호화된 채로 들어 있고, 버릴 때 지워짐.
+/// 메타데이터 암호화 모드에서도 사이트 이름과 ID가 평문으로 들어 있으므로 검색하는 동안만 두고, 검색이 끝나면 버려야 함
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    /// 만들 때의 `DB::generation`
+    generation: Generation,
+}
+
+struct Document {
+    site: SiteName,
+    id: UserID,
+    fields: Vec<(SearchField, Zeroizing<String>)>,
+}
+
+impl SearchIndex {
+    pub fn build(db: &DB, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce) -> Result<Self, DBIOError> {
+        let mut documents = Vec::new();
+        for (site, users) in db.sites()? {
+            for (id, entry) in users {
+                let mut fields = vec![
+                    (SearchField::Site, Zeroizing::new(site.as_str().to_owned())),
+                    (SearchField::Domain, Zeroizing::new(site.reg().to_owned())),
+                    (SearchField::UserID, Zeroizing::new(id.as_str().to_owned())),
+                ];
+                for tag in entry.tags() {
+                    fields.push((SearchField::Tag, Zeroizing::new(tag.clone())));
+                }
+                if let Some(notes) = &entry.notes {
+                    let notes = decrypt_entry_field(&site, &id, NOTES_FIELD_TAG, notes, wrapped_key, user_key_nonce)?;
+                    fields.push((SearchField::Notes, notes));
+                }
+                documents.push(Document {
+                    site: site.clone(),
+                    id,
+                    fields,
+                });
+            }
+        }
+        Ok(Self {
+            documents,
+            generation: db.generation(),
+        })
+    }
+
+    /// `false`면 DB가 바뀌었으므로 다시 만들어야 함
+    pub fn is_current(&self, db: &DB) -> bool {
+        self.generation == db.generation()
+    }
+
+    /// 공백으로 나눈 검색어가 모두 어느 필드에든 맞는 계정을 점수가 높은 순으로 돌려줌
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits = Vec::new();
+        'documents: for document in &self.documents {
+            let mut score = 0;
+            let mut ranges: Vec<Vec<Range<usize>>> = vec![Vec::new(); document.fields.len()];
+            for term in &terms {
+                let mut best = 0;
+                for (index, (field, text)) in document.fields.iter().enumerate() {
+                    // 메모는 길어서 흩어진 글자가 우연히 맞기 쉬우므로 연속으로 맞아야 함
+                    let matched = if *field == SearchField::Notes {
+                        substring_match(term, text)
+                    } else {
+                        fuzzy_match(term, text)
+                    };
+                    if let Some((field_score, field_ranges)) = matched {
+                        best = best.max(field_score * field.weight());
+                        ranges[index].extend(field_ranges);
+                    }
+                }
+                if best == 0 {
+                    continue 'documents;
+                }
+                score += best;
+            }
+
+            let matches = document
+                .fields
+                .iter()
+                .zip(ranges)
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .map(|((field, text), ranges)| {
+                    let ranges = merge_ranges(ranges);
+                    match field {
+                        SearchField::Notes => notes_snippet(text, &ranges),
+                        _ => FieldMatch {
+                            field: *field,
+                            text: text.clone(),
+                            ranges,
+                        },
+                    }
+                })
+                .collect();
+            hits.push(SearchHit {
+                site: document.site.clone(),
+                id: document.id.clone(),
+                score,
+                matches,
+            });
+        }
+
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.site.cmp(&b.site)).then_with(|| a.id.cmp(&b.id)));
+        hits
+    }
+}
+
+/// 대소문자를 가리지 않고 `pattern`의 글자가 `text`에 순서대로 모두 있으면 점수와 맞은 바이트 범위를 돌려줌.
+/// 연속으로 붙어 있을수록, 단어의 처음에서 맞을수록 점수가 높음
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(u32, Vec<Range<usize>>)> {
+    if let Some(matched) = substring_match(pattern, text) {
+        return Some(matched);
+    }
+
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let first = *pattern.first()?;
+
+    // 첫 글자가 맞는 위치마다 앞에서부터 욕심껏 맞춰 보고 가장 좋은 것을 고름
+    let mut best: Option<(u32, Vec<usize>)> = None;
+    for start in (0..chars.len()).filter(|&i| fold(chars[i].1) == first) {
+        let mut positions = vec![start];
+        let mut next = start + 1;
+        for &wanted in &pattern[1..] {
+            match (next..chars.len()).find(|&i| fold(chars[i].1) == wanted) {
+                Some(i) => {
+                    positions.push(i);
+                    next = i + 1;
+                }
+                None => break,
+            }
+        }
+        if positions.len() < pattern.len() {
+            // 뒤에서 시작하면 더 적게 맞음
+            break;
+        }
+
+        let mut score: i64 = 0;
+        for (n, &i) in positions.iter().enumerate() {
+            score += 10;
+            if is_boundary(&chars, i) {
+                score += 8;
+            }
+            if n > 0 {
+                let gap = (i - positions[n - 1] - 1) as i64;
+                score += if gap == 0 { 12 } else { -gap.min(10) };
+            }
+        }
+        let score = score.max(1) as u32;
+        if best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+            best = Some((score, positions));
+        }
+    }
+
+    let (score, positions) = best?;
+    let ranges = positions
+        .into_iter()
+        .map(|i| {
+            let (start, c) = chars[i];
+            start..start + c.len_utf8()
+        })
+        .collect();
+    Some((score, merge_ranges(ranges)))
+}
+
+/// 연속으로 맞는 경우. 흩어져 맞는 것보다 점수를 크게 줌
+fn substring_match(pattern: &str, text: &str) -> Option<(u32, Vec<Range<usize>>)> {
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    if pattern.is_empty() {
+        return None;
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let start = chars
+        .windows(pattern.len())
+        .position(|window| window.iter().zip(&pattern).all(|((_, c), wanted)| fold(*c) == *wanted))?;
+
+    let end = chars.get(start + pattern.len()).map_or(text.len(), |(i, _)| *i);
+    let mut score = 20 * pattern.len() as u32 + 100;
+    if is_boundary(&chars, start) {
+        score += 40;
+    }
+    if pattern.len() == chars.len() {
+        score += 60;
+    }
+    score -= (start as u32).min(20);
+    Some((score, std::iter::once(chars[start].0..end).collect()))
+}
+
+/// 한 글자씩 비교하려고 소문자로 바꿈. 여러 글자가 되는 경우는 첫 글자만 봄
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_boundary(chars: &[(usize, char)], i: usize) -> bool {
+    i == 0 || !chars[i - 1].1.is_alphanumeric()
+}
+
+fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// 처음 맞은 곳이 있는 줄을 `SNIPPET_LEN` 글자 안쪽으로 자름. 범위는 잘린 줄 기준으로 옮김
+fn notes_snippet(notes: &str, ranges: &[Range<usize>]) -> FieldMatch {
+    let first = ranges.first().map_or(0, |range| range.start);
+    let line_start = notes[..first].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = notes[first..].find('\n').map_or(notes.len(), |i| first + i);
+
+    // 맞은 곳이 잘리지 않도록 앞쪽을 덜어냄
+    let mut start = line_start;
+    while notes[start..first].chars().count() > SNIPPET_LEN / 2 {
+        start += notes[start..].chars().next().map_or(1, char::len_utf8);
+    }
+    let end = notes[start..line_end]
+        .char_indices()
+        .nth(SNIPPET_LEN)
+        .map_or(line_end, |(i, _)| start + i);
+
+    FieldMatch {
+        field: SearchField::Notes,
+        text: Zeroizing::new(notes[start..end].to_owned()),
+        ranges: ranges
+            .iter()
+            .filter(|range| range.start >= start && range.end <= end)
+            .map(|range| range.start - start..range.end - start)
+            .collect(),
+    }
+}
@@ -0,0 +1,62 @@
+//! HIBP 목록과 TOTP/HOTP에 필요한 SHA-1. libsodium에 없어서 직접 구현함
+
+use zeroize::Zeroizing;
+
+pub(crate) const SHA1_SIZE: usize = 20;
+
+/// 평문이 거쳐 가는 버퍼는 모두 지움
+pub(crate) fn sha1(data: &[u8]) -> Zeroizing<[u8; SHA1_SIZE]> {
+    let mut state = Zeroizing::new([0x67452301u32, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0]);
+
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+        sha1_block(&mut state, block);
+    }
+
+    let rest = blocks.remainder();
+    let mut tail = Zeroizing::new([0u8; 128]);
+    tail[..rest.len()].copy_from_slice(rest);
+    tail[rest.len()] = 0x80;
+    let tail_len = if rest.len() < 56 { 64 } else { 128 };
+    tail[tail_len - 8..tail_len].copy_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in tail[..tail_len].chunks_exact(64) {
+        sha1_block(&mut state, block);
+    }
+
+    let mut digest = Zeroizing::new([0u8; SHA1_SIZE]);
+    for (out, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn sha1_block(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = Zeroizing::new([0u32; 80]);
+    for (word, bytes) in w.iter_mut().zip(block.as_chunks::<4>().0) {
+        *word = u32::from_be_bytes(*bytes);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let mut v = Zeroizing::new(*state);
+    for (i, word) in w.iter().enumerate() {
+        let [a, b, c, d, e] = *v;
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let t = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        *v = [t, a, b.rotate_left(30), c, d];
+    }
+    for (s, x) in state.iter_mut().zip(v.iter()) {
+        *s = s.wrapping_add(*x);
+    }
+}
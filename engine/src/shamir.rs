@@ -0,0 +1,204 @@
+//! GF(256) 위의 Shamir 비밀 분산. 바이트마다 `threshold - 1`차 다항식을 따로 만들고,
+//! 나눈 조각은 체크섬이 붙은 base32 텍스트로 주고받음
+
+use crate::base32;
+use libsodium_sys::rust_wrappings::hasher::Blake2b;
+use libsodium_sys::rust_wrappings::randombytes::random_buf;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use zeroize::{Zeroize, Zeroizing};
+
+/// 나누는 비밀의 크기
+pub const SHARE_SECRET_SIZE: usize = 32;
+/// x = 0은 비밀 자체이므로 조각 번호는 1..=255
+pub const MAX_SHARES: u8 = u8::MAX;
+const SET_ID_SIZE: usize = 4;
+/// 조각 하나를 잘못 옮겨 적은 경우를 잡는 용도. 위조 방지는 슬롯의 AEAD가 맡음
+const CHECKSUM_SIZE: usize = 4;
+const SHARE_BODY_SIZE: usize = SET_ID_SIZE + 2 + SHARE_SECRET_SIZE;
+const SHARE_SIZE: usize = SHARE_BODY_SIZE + CHECKSUM_SIZE;
+/// 받아 적기 쉽도록 이 글자 수마다 `-`를 넣음
+const SHARE_GROUP_LEN: usize = 4;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ShamirError {
+    /// 2 <= threshold <= count <= 255가 아님
+    InvalidThreshold,
+    /// base32가 아니거나 길이, 조각 번호가 맞지 않음
+    InvalidShare,
+    /// 옮겨 적다 틀린 조각
+    ChecksumMismatch,
+    /// 다른 분산에서 나온 조각이 섞임
+    MixedShares,
+    DuplicateShare(u8),
+    NotEnoughShares { needed: u8, given: usize },
+}
+impl Display for ShamirError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShamirError::InvalidThreshold => {
+                write!(f, "Shares needed must be at least 2 and at most the number of shares ({})", MAX_SHARES)
+            }
+            ShamirError::InvalidShare => write!(f, "Not a recovery share"),
+            ShamirError::ChecksumMismatch => write!(f, "Recovery share has a typo (checksum mismatch)"),
+            ShamirError::MixedShares => write!(f, "Recovery shares come from different splits"),
+            ShamirError::DuplicateShare(index) => write!(f, "Recovery share {} was given twice", index),
+            ShamirError::NotEnoughShares { needed, given } => {
+                write!(f, "{} recovery shares are needed, {} given", needed, given)
+            }
+        }
+    }
+}
+impl Error for ShamirError {}
+
+/// 나눈 비밀의 조각 하나. 같은 분산의 조각끼리는 `set_id`가 같음
+pub struct Share {
+    set_id: [u8; SET_ID_SIZE],
+    threshold: u8,
+    index: u8,
+    value: Zeroizing<[u8; SHARE_SECRET_SIZE]>,
+}
+impl Share {
+    /// 다항식에서 x좌표
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+    /// 비밀을 되찾는 데 필요한 조각 수
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// 대소문자, 공백, `-`는 가리지 않음
+    pub fn parse(input: &str) -> Result<Self, ShamirError> {
+        let decoded = base32::decode(input.trim()).ok_or(ShamirError::InvalidShare)?;
+        if decoded.len() != SHARE_SIZE {
+            return Err(ShamirError::InvalidShare);
+        }
+        let (body, checksum) = decoded.split_at(SHARE_BODY_SIZE);
+        if checksum != share_checksum(body) {
+            return Err(ShamirError::ChecksumMismatch);
+        }
+
+        let mut set_id = [0u8; SET_ID_SIZE];
+        set_id.copy_from_slice(&body[..SET_ID_SIZE]);
+        let threshold = body[SET_ID_SIZE];
+        let index = body[SET_ID_SIZE + 1];
+        if threshold < 2 || index == 0 {
+            return Err(ShamirError::InvalidShare);
+        }
+        let mut value = Zeroizing::new([0u8; SHARE_SECRET_SIZE]);
+        value.copy_from_slice(&body[SET_ID_SIZE + 2..]);
+        Ok(Self { set_id, threshold, index, value })
+    }
+
+    /// `ABCD-EFGH-...` 꼴
+    pub fn to_text(&self) -> Zeroizing<String> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(SHARE_SIZE));
+        bytes.extend_from_slice(&self.set_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(self.value.as_slice());
+        let checksum = share_checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        base32::encode_grouped(&bytes, SHARE_GROUP_LEN)
+    }
+}
+
+fn share_checksum(body: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut hasher = Blake2b::<16>::new();
+    hasher.update(b"pw-manager recovery share");
+    hasher.update(body);
+    let mut digest = [0u8; 16];
+    hasher.finalize_write_to(digest.as_mut_ptr());
+    std::array::from_fn(|i| digest[i])
+}
+
+/// 비밀 바이트에 따라 분기하지 않도록 곱셈은 고정 8회 반복, 역원은 a^254로 구함
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        // x^8 + x^4 + x^3 + x + 1 (AES와 같은 기약 다항식)
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut square = a;
+    for bit in 0..8 {
+        if (254u8 >> bit) & 1 == 1 {
+            result = gf_mul(result, square);
+        }
+        square = gf_mul(square, square);
+    }
+    result
+}
+
+/// `secret`을 `count`개로 나눔. 아무 `threshold`개로 되찾을 수 있고, 그보다 적으면 비밀에 대해 아무것도 알 수 없음
+pub fn split_secret(secret: &[u8; SHARE_SECRET_SIZE], threshold: u8, count: u8) -> Result<Vec<Share>, ShamirError> {
+    if threshold < 2 || threshold > count {
+        return Err(ShamirError::InvalidThreshold);
+    }
+    let mut set_id = [0u8; SET_ID_SIZE];
+    random_buf(&mut set_id);
+
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|index| Share {
+            set_id,
+            threshold,
+            index,
+            value: Zeroizing::new([0u8; SHARE_SECRET_SIZE]),
+        })
+        .collect();
+    // 상수항이 비밀이고 나머지 계수는 무작위
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for (i, &secret_byte) in secret.iter().enumerate() {
+        coefficients[0] = secret_byte;
+        random_buf(&mut coefficients[1..]);
+        for share in shares.iter_mut() {
+            let mut y = 0u8;
+            for &coefficient in coefficients.iter().rev() {
+                y = gf_mul(y, share.index) ^ coefficient;
+            }
+            share.value[i] = y;
+        }
+    }
+    coefficients.zeroize();
+    Ok(shares)
+}
+
+/// 라그랑주 보간으로 x = 0의 값을 구함. 같은 분산의 조각이 `threshold`개 이상이어야 함.
+/// 조각이 모두 맞는지는 알 수 없으므로 결과는 쓰는 쪽에서 확인해야 함
+pub fn combine_shares(shares: &[Share]) -> Result<Zeroizing<[u8; SHARE_SECRET_SIZE]>, ShamirError> {
+    let Some(first) = shares.first() else {
+        return Err(ShamirError::NotEnoughShares { needed: 2, given: 0 });
+    };
+    for (i, share) in shares.iter().enumerate() {
+        if share.set_id != first.set_id || share.threshold != first.threshold {
+            return Err(ShamirError::MixedShares);
+        }
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(ShamirError::DuplicateShare(share.index));
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(ShamirError::NotEnoughShares { needed: first.threshold, given: shares.len() });
+    }
+
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = Zeroizing::new([0u8; SHARE_SECRET_SIZE]);
+    for share in shares {
+        // L_i(0) = prod x_m / (x_m - x_i). GF(2^8)에서 뺄셈은 XOR
+        let mut basis = 1u8;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            basis = gf_mul(basis, gf_mul(other.index, gf_inv(other.index ^ share.index)));
+        }
+        for (out, &y) in secret.iter_mut().zip(share.value.iter()) {
+            *out ^= gf_mul(basis, y);
+        }
+    }
+    Ok(secret)
+}
@@ -0,0 +1,57 @@
+use crate::data_base::{DB, DBIOError, TrashedEntry, Timestamp, get_entry};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// `index`는 `DB::trash`의 위치. 같은 (사이트, ID)의 계정이 이미 있으면 되살리지 않음
+pub fn restore_trashed(db: &mut DB, index: usize) -> Result<(), DBIOError> {
+    let trashed = db.trash.get(index).ok_or(DBIOError::TrashedEntryNotFound)?;
+    let (site, id) = db.trashed_names(trashed)?;
+    if get_entry(db, &site, &id).is_ok() {
+        return Err(DBIOError::UserAlreadyExists);
+    }
+
+    let TrashedEntry { entry, .. } = db.trash.remove(index);
+    db.insert_entry(site, id, entry)
+}
+
+/// 휴지통에서 완전히 지움
+pub fn purge_trashed(db: &mut DB, index: usize) -> Result<(), DBIOError> {
+    if index >= db.trash.len() {
+        return Err(DBIOError::TrashedEntryNotFound);
+    }
+    db.trash.remove(index);
+    db.bump_generation();
+    Ok(())
+}
+
+/// 지운 항목 수
+pub fn empty_trash(db: &mut DB) -> usize {
+    let count = db.trash.len();
+    db.trash.clear();
+    db.bump_generation();
+    count
+}
+
+/// 보관 기간이 지난 항목을 지우고 그 수를 돌려줌. 로그인 직후에 부름
+pub fn purge_expired(db: &mut DB) -> usize {
+    purge_older_than(db, Timestamp::now())
+}
+
+/// `now` 기준으로 보관 기간이 지난 항목을 지움
+pub fn purge_older_than(db: &mut DB, now: Timestamp) -> usize {
+    if db.trash_retention_days == 0 {
+        return 0;
+    }
+    let max_age = db.trash_retention_days as u64 * SECS_PER_DAY;
+    let before = db.trash.len();
+    db.bump_generation();
+    db.trash
+        .retain(|trashed| now.unix_secs().saturating_sub(trashed.deleted.unix_secs()) < max_age);
+    before - db.trash.len()
+}
+
+/// 0이면 자동으로 지우지 않음
+pub fn set_trash_retention_days(db: &mut DB, days: u32) {
+    db.trash_retention_days = days;
+    db.bump_generation();
+}
@@ -2,17 +2,18 @@ use crate::data_base::{DBIOError, SiteName, UserID, UserPW};
 
 use crate::master_secrets::static_type_zeroize;
 use argon2::{Argon2, Params};
-use libsodium_sys::rust_wrappings::aes256gcm::{aes256gcm_decrypt, aes256gcm_decrypt_from_ptr, aes256gcm_encrypt, aes256gcm_encrypt_from_ptr_to_sodium_box, get_aes256gcm_ciphertext_len, AesKey, AesNonce, AES_KEY_SIZE, AES_NONCE_SIZE};
-use libsodium_sys::rust_wrappings::hasher::Sha256;
+use libsodium_sys::rust_wrappings::aes256gcm::{aes256gcm_decrypt, aes256gcm_decrypt_from_ptr, aes256gcm_decrypt_with_ad, aes256gcm_encrypt_from_ptr_to_sodium_box, aes256gcm_encrypt_with_ad_write_to_ptr, get_aes256gcm_ciphertext_len, AesKey, AesNonce, AES_KEY_SIZE, AES_NONCE_SIZE};
+use libsodium_sys::rust_wrappings::hasher::{Blake2b, Sha256};
 use libsodium_sys::rust_wrappings::sodium_box::SodiumBox;
 use rkyv::rancor::Fallible;
 use rkyv::vec::{ArchivedVec, VecResolver};
 use rkyv::with::{ArchiveWith, DeserializeWith, SerializeWith};
 use rkyv::{Archive, Archived, Deserialize, Place, Serialize};
 use secrecy::{ExposeSecret, SecretBox};
+use std::ptr::addr_of_mut;
 use std::{hint, process};
 use sysinfo::{CpuExt, Pid, PidExt, ProcessExt, System, SystemExt};
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 struct SecretBoxRef;
 
@@ -48,6 +49,12 @@ pub struct EncryptedSiteName {
     reg: SecretBox<[u8] >,
 }
 impl EncryptedSiteName {
+    fn from_vecs(full: Vec<u8>, reg: Vec<u8>) -> Self {
+        EncryptedSiteName {
+            full: SecretBox::from(Box::from(full)),
+            reg: SecretBox::from(Box::from(reg)),
+        }
+    }
     pub fn full_as_bytes(&self) -> &[u8] {
         self.full.expose_secret().as_ref()
     }
@@ -69,6 +76,9 @@ pub struct EncryptedUserID (
     SecretBox<[u8]>,
 );
 impl EncryptedUserID {
+    fn from_vec(v: Vec<u8>) -> Self {
+        EncryptedUserID(SecretBox::from(Box::from(v)))
+    }
     pub fn as_bytes(&self) -> &[u8] {
         self.0.expose_secret().as_ref()
     }
@@ -101,6 +111,28 @@ impl Zeroize for EncryptedUserPW {
 }
 impl ZeroizeOnDrop for EncryptedUserPW {}
 
+/// 비밀번호 외에 항목에 암호화해서 저장하는 값 (메모, 숨김 필드). 레이아웃은 `EncryptedUserPW`와 같음
+#[derive(Archive, Deserialize, Serialize)]
+pub struct EncryptedField (
+    #[rkyv(with = SecretBoxRef)]
+    SecretBox<[u8]>,
+);
+impl EncryptedField {
+    pub fn from_vec(v: Vec<u8>) -> Self {
+        let secret_boxed = SecretBox::from(Box::from(v));
+        EncryptedField(secret_boxed)
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.expose_secret().as_ref()
+    }
+}
+impl Zeroize for EncryptedField {
+    fn zeroize(&mut self) {
+        self.0.zeroize()
+    }
+}
+impl ZeroizeOnDrop for EncryptedField {}
+
 
 const USER_PW_NONCE_SIZE: usize = AES_NONCE_SIZE;
 pub struct UserPWNonce {
@@ -249,6 +281,40 @@ impl Default for SessionKey {
     }
 }
 
+pub const NAME_HASH_SIZE: usize = 32;
+
+/// 메타데이터 암호화 모드에서 사이트 이름과 ID 대신 색인의 키로 쓰는 keyed hash
+#[derive(Archive, Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[rkyv(derive(PartialEq, Eq, Hash))]
+pub struct NameHash([u8; NAME_HASH_SIZE]);
+
+const NAME_SUBKEY_SIZE: usize = 32;
+/// 세션 키에서 유도한 이름용 키. [ 해시 키 (32) | 암호화 키 (32) ].
+/// 세션 키가 바뀌면 다시 유도해야 하고, 그 전에 만든 해시와 암호문은 쓸 수 없음
+pub struct NameKey {
+    inner: SodiumBox<u8>,
+}
+impl NameKey {
+    const SIZE: usize = 2 * NAME_SUBKEY_SIZE;
+    pub fn derive(wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce) -> Result<Self, DBIOError> {
+        let session_key = unwrap_session_key(wrapped_key, user_key_nonce)?;
+        let mut inner: SodiumBox<u8> = SodiumBox::new_with_size(Self::SIZE);
+        for (i, label) in [b"name-hash".as_slice(), b"name-cipher".as_slice()].into_iter().enumerate() {
+            let mut hasher = Blake2b::<NAME_SUBKEY_SIZE>::new_keyed(session_key.as_ptr(), SESSION_KEY_SIZE);
+            hasher.update(label);
+            hasher.finalize_write_to(inner.as_mut_ptr().wrapping_add(i * NAME_SUBKEY_SIZE));
+        }
+        drop(session_key);
+        Ok(Self { inner })
+    }
+    fn hasher(&self) -> Blake2b<NAME_HASH_SIZE> {
+        Blake2b::new_keyed(self.inner.as_ptr(), NAME_SUBKEY_SIZE)
+    }
+    fn cipher_key(&self) -> AesKey {
+        AesKey::from_raw(self.inner.as_ptr().wrapping_add(NAME_SUBKEY_SIZE))
+    }
+}
+
 
 pub fn get_session_key_wrapper() -> SessionKeyWrapper {
     let mut hasher: Sha256 = Sha256::new();
@@ -300,8 +366,9 @@ pub fn get_session_key_wrapper() -> SessionKeyWrapper {
     result
 }
 
+/// 0.1.2 포맷까지 쓰이던 (사이트, ID) 기반 nonce
 #[inline]
-fn get_user_pw_nonce(site: &SiteName, id: &UserID)
+fn get_legacy_user_pw_nonce(site: &SiteName, id: &UserID)
     -> UserPWNonce {
     let mut processed_id = id.as_str().to_owned().into_bytes();
     let halo = [203u8, 118, 6, 1, 225, 226, 197, 127, 221, 214, 24, 5, 239, 38, 75, 82, 65, 111, 91, 110, 158, 25, 48, 178, 116, 137, 136, 49, 57, 192, 56, 52];
@@ -360,28 +427,213 @@ pub fn unwrap_session_key(wrapped_key: &WrappedSessionKey, nonce: &SessionKeyNon
     Ok(session_key)
 }
 
-//todo
-pub fn encrypt_user_pw(site: &SiteName, id: &UserID, user_pw: UserPW, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
-                       -> Result<EncryptedUserPW, DBIOError> {
+/// 비밀번호 ciphertext를 DB의 (사이트, ID) 키에 묶는 associated data.
+/// 필드 경계가 모호하지 않도록 각 필드 앞에 u64 길이를 붙임
+fn get_user_pw_ad(site: &SiteName, id: &UserID) -> Vec<u8> {
+    let mut ad = Vec::new();
+    for field in [site.full.as_bytes(), site.reg.as_bytes(), id.as_str().as_bytes()] {
+        ad.extend_from_slice(&(field.len() as u64).to_le_bytes());
+        ad.extend_from_slice(field);
+    }
+    ad
+}
+
+/// 항목의 다른 암호화 필드용 associated data. 비밀번호의 AD 뒤에 필드 태그를 붙여서
+/// 같은 항목 안에서도 메모와 비밀번호를 서로 바꿔 끼울 수 없게 함
+fn get_entry_field_ad(site: &SiteName, id: &UserID, field_tag: &str) -> Vec<u8> {
+    let mut ad = get_user_pw_ad(site, id);
+    ad.extend_from_slice(&(field_tag.len() as u64).to_le_bytes());
+    ad.extend_from_slice(field_tag.as_bytes());
+    ad
+}
+
+/// 반환 레이아웃: [ nonce (12) | ciphertext + tag ]
+fn encrypt_with_key(key: &AesKey, plaintext: &[u8], ad: &[u8]) -> Vec<u8> {
+    let nonce = UserPWNonce::gen_rand();
+    let mut encrypted = vec![0u8; USER_PW_NONCE_SIZE + get_aes256gcm_ciphertext_len(plaintext.len())];
+    nonce.copy_to(addr_of_mut!(encrypted[0]));
+    let nonce: AesNonce = nonce.into();
+    aes256gcm_encrypt_with_ad_write_to_ptr(key, &nonce, plaintext, ad, addr_of_mut!(encrypted[USER_PW_NONCE_SIZE]));
+    drop(nonce);
+
+    encrypted
+}
+
+fn decrypt_with_key(key: &AesKey, bytes: &[u8], ad: &[u8]) -> Result<Vec<u8>, DBIOError> {
+    if bytes.len() < USER_PW_NONCE_SIZE + get_aes256gcm_ciphertext_len(0) {
+        return Err(DBIOError::EntryTampered);
+    }
+    let nonce: AesNonce = UserPWNonce::from_raw(bytes.as_ptr()).into();
+    let plaintext =
+        aes256gcm_decrypt_with_ad(key, &nonce, &bytes[USER_PW_NONCE_SIZE..], ad)
+            .map_err(|_| DBIOError::EntryTampered)?
+            .into();
+    drop(nonce);
+
+    Ok( plaintext )
+}
+
+fn encrypt_with_session_key(plaintext: &[u8], ad: &[u8], wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                            -> Result<Vec<u8>, DBIOError> {
     let session_key: AesKey = unwrap_session_key(wrapped_key, user_key_nonce)?.into();
-    let user_pw_nonce: AesNonce = get_user_pw_nonce(site, id).into();
-    let ciphertext =
-        aes256gcm_encrypt(&session_key, &user_pw_nonce, user_pw.as_str().as_bytes());
+    let encrypted = encrypt_with_key(&session_key, plaintext, ad);
     drop(session_key);
-    drop(user_pw_nonce);
-    let encrypted_pw = EncryptedUserPW::from_vec(ciphertext);
 
-    Ok( encrypted_pw )
+    Ok( encrypted )
 }
 
+fn decrypt_with_session_key(bytes: &[u8], ad: &[u8], wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                            -> Result<Vec<u8>, DBIOError> {
+    let session_key: AesKey = unwrap_session_key(wrapped_key, user_key_nonce)?.into();
+    let plaintext = decrypt_with_key(&session_key, bytes, ad);
+    drop(session_key);
+
+    plaintext
+}
+
+/// `EncryptedUserPW` 레이아웃: [ nonce (12) | ciphertext + tag ]
+pub fn encrypt_user_pw(site: &SiteName, id: &UserID, user_pw: UserPW, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                       -> Result<EncryptedUserPW, DBIOError> {
+    let mut ad = get_user_pw_ad(site, id);
+    let encrypted = encrypt_with_session_key(user_pw.as_str().as_bytes(), &ad, wrapped_key, user_key_nonce);
+    ad.zeroize();
+
+    Ok( EncryptedUserPW::from_vec(encrypted?) )
+}
+
+/// 태그 검증 실패는 ciphertext가 변조되었거나 다른 (사이트, ID)에서 옮겨졌다는 뜻
 #[inline(always)]
 pub fn decrypt_user_pw(site: &SiteName, id: &UserID, encrypted_pw: &EncryptedUserPW, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
                        -> Result<UserPW, DBIOError> {
+    let mut ad = get_user_pw_ad(site, id);
+    let plaintext = decrypt_with_session_key(encrypted_pw.as_bytes(), &ad, wrapped_key, user_key_nonce);
+    ad.zeroize();
+
+    let user_pw = UserPW::from_unchecked(
+        String::from_utf8(plaintext?).unwrap()
+    );
+
+    Ok( user_pw )
+}
+
+/// `field_tag`는 항목 안에서 필드를 구분하는 이름 (예: `notes`)
+pub fn encrypt_entry_field(site: &SiteName, id: &UserID, field_tag: &str, value: &str, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                           -> Result<EncryptedField, DBIOError> {
+    let mut ad = get_entry_field_ad(site, id, field_tag);
+    let encrypted = encrypt_with_session_key(value.as_bytes(), &ad, wrapped_key, user_key_nonce);
+    ad.zeroize();
+
+    Ok( EncryptedField::from_vec(encrypted?) )
+}
+
+pub fn decrypt_entry_field(site: &SiteName, id: &UserID, field_tag: &str, encrypted: &EncryptedField, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                           -> Result<Zeroizing<String>, DBIOError> {
+    let mut ad = get_entry_field_ad(site, id, field_tag);
+    let plaintext = decrypt_with_session_key(encrypted.as_bytes(), &ad, wrapped_key, user_key_nonce);
+    ad.zeroize();
+
+    Ok( Zeroizing::new(String::from_utf8(plaintext?).map_err(|_| DBIOError::EntryTampered)?) )
+}
+
+fn update_with_len(hasher: &mut Blake2b<NAME_HASH_SIZE>, field: &[u8]) {
+    hasher.update(&(field.len() as u64).to_le_bytes());
+    hasher.update(field);
+}
+
+pub fn hash_site_name(key: &NameKey, site: &SiteName) -> NameHash {
+    let mut hasher = key.hasher();
+    update_with_len(&mut hasher, b"site");
+    update_with_len(&mut hasher, site.full.as_bytes());
+    update_with_len(&mut hasher, site.reg.as_bytes());
+    let mut hash = NameHash([0u8; NAME_HASH_SIZE]);
+    hasher.finalize_write_to(hash.0.as_mut_ptr());
+    hash
+}
+
+/// 사이트의 해시를 섞어서 같은 ID라도 사이트마다 해시가 다름
+pub fn hash_user_id(key: &NameKey, site_hash: &NameHash, id: &UserID) -> NameHash {
+    let mut hasher = key.hasher();
+    update_with_len(&mut hasher, b"id");
+    update_with_len(&mut hasher, &site_hash.0);
+    update_with_len(&mut hasher, id.as_str().as_bytes());
+    let mut hash = NameHash([0u8; NAME_HASH_SIZE]);
+    hasher.finalize_write_to(hash.0.as_mut_ptr());
+    hash
+}
+
+/// 이름 암호문을 사이트의 해시에 묶는 associated data. 다른 사이트의 이름이나 ID를 옮겨 끼울 수 없게 함
+fn get_name_ad(site_hash: &NameHash, field_tag: &str) -> Vec<u8> {
+    let mut ad = Vec::with_capacity(field_tag.len() + NAME_HASH_SIZE);
+    ad.extend_from_slice(field_tag.as_bytes());
+    ad.extend_from_slice(&site_hash.0);
+    ad
+}
+
+fn open_name(key: &NameKey, site_hash: &NameHash, field_tag: &str, bytes: &[u8]) -> Result<String, DBIOError> {
+    let cipher_key = key.cipher_key();
+    let plaintext = decrypt_with_key(&cipher_key, bytes, &get_name_ad(site_hash, field_tag))?;
+    drop(cipher_key);
+    String::from_utf8(plaintext).map_err(|_| DBIOError::EntryTampered)
+}
+
+/// `EncryptedSiteName`의 두 필드 레이아웃: [ nonce (12) | ciphertext + tag ]
+pub fn seal_site_name(key: &NameKey, site_hash: &NameHash, site: &SiteName) -> EncryptedSiteName {
+    let cipher_key = key.cipher_key();
+    let full = encrypt_with_key(&cipher_key, site.full.as_bytes(), &get_name_ad(site_hash, "site-full"));
+    let reg = encrypt_with_key(&cipher_key, site.reg.as_bytes(), &get_name_ad(site_hash, "site-reg"));
+    drop(cipher_key);
+    EncryptedSiteName::from_vecs(full, reg)
+}
+
+pub fn open_site_name(key: &NameKey, site_hash: &NameHash, encrypted: &EncryptedSiteName) -> Result<SiteName, DBIOError> {
+    Ok(SiteName {
+        full: open_name(key, site_hash, "site-full", encrypted.full_as_bytes())?,
+        reg: open_name(key, site_hash, "site-reg", encrypted.reg_as_bytes())?,
+    })
+}
+
+/// 등록 가능한 도메인만 복호화함. 접두어로 찾을 때 씀
+pub fn open_site_reg(key: &NameKey, site_hash: &NameHash, encrypted: &EncryptedSiteName) -> Result<Zeroizing<String>, DBIOError> {
+    open_name(key, site_hash, "site-reg", encrypted.reg_as_bytes()).map(Zeroizing::new)
+}
+
+pub fn seal_user_id(key: &NameKey, site_hash: &NameHash, id: &UserID) -> EncryptedUserID {
+    let cipher_key = key.cipher_key();
+    let encrypted = encrypt_with_key(&cipher_key, id.as_str().as_bytes(), &get_name_ad(site_hash, "user-id"));
+    drop(cipher_key);
+    EncryptedUserID::from_vec(encrypted)
+}
+
+pub fn open_user_id(key: &NameKey, site_hash: &NameHash, encrypted: &EncryptedUserID) -> Result<UserID, DBIOError> {
+    Ok(UserID(open_name(key, site_hash, "user-id", encrypted.as_bytes())?))
+}
+
+/// 현재 포맷 이전의 비밀번호 암호화 방식
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyUserPWFormat {
+    /// 0.1.2: (사이트, ID) 기반 nonce, ciphertext만 저장
+    DerivedNonce,
+    /// 0.1.3: 랜덤 nonce 저장, associated data 없음
+    UnboundNonce,
+}
+
+/// 구버전 포맷의 비밀번호 복호화. 마이그레이션에서만 사용
+pub(crate) fn decrypt_legacy_user_pw(site: &SiteName, id: &UserID, encrypted_pw: &EncryptedUserPW, format: LegacyUserPWFormat, wrapped_key: &WrappedSessionKey, user_key_nonce: &SessionKeyNonce)
+                       -> Result<UserPW, DBIOError> {
+    let bytes = encrypted_pw.as_bytes();
+    let (user_pw_nonce, ciphertext): (AesNonce, &[u8]) = match format {
+        LegacyUserPWFormat::DerivedNonce => (get_legacy_user_pw_nonce(site, id).into(), bytes),
+        LegacyUserPWFormat::UnboundNonce => {
+            if bytes.len() < USER_PW_NONCE_SIZE + get_aes256gcm_ciphertext_len(0) {
+                return Err(DBIOError::EntryTampered);
+            }
+            (UserPWNonce::from_raw(bytes.as_ptr()).into(), &bytes[USER_PW_NONCE_SIZE..])
+        }
+    };
     let user_key: AesKey = unwrap_session_key(wrapped_key, user_key_nonce)?.into();
-    let user_pw_nonce: AesNonce = get_user_pw_nonce(site, id).into();
     let plaintext =
-        aes256gcm_decrypt(&user_key, &user_pw_nonce, encrypted_pw.as_bytes())
-            .map_err(|_| DBIOError::InvalidSession)?
+        aes256gcm_decrypt(&user_key, &user_pw_nonce, ciphertext)
+            .map_err(|_| DBIOError::EntryTampered)?
             .into();
     drop(user_key);
     drop(user_pw_nonce);
@@ -0,0 +1,158 @@
+use crate::backup::RetentionPolicy;
+use crate::header::FormatVersion;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 볼트 파일 경로를 지정하는 환경 변수
+pub const VAULT_ENV_VAR: &str = "PW_MANAGER_VAULT";
+const APP_DIR_NAME: &str = "pw-manager";
+const VAULT_EXTENSION: &str = "bin";
+const DEFAULT_VAULT_NAME: &str = "default";
+/// 0.1.x는 현재 작업 디렉터리의 `db.bin`을 썼음
+const LEGACY_DB_FILE: &str = "db.bin";
+
+/// 볼트 하나의 위치. DB 파일과 그 옆에 생기는 백업, 격리 파일의 경로를 모두 여기서 만듦
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VaultPath {
+    db_file: PathBuf,
+    retention_policy: RetentionPolicy,
+}
+
+impl VaultPath {
+    pub fn new(db_file: impl Into<PathBuf>) -> Self {
+        Self {
+            db_file: db_file.into(),
+            retention_policy: RetentionPolicy::default(),
+        }
+    }
+
+    pub fn with_retention_policy(mut self, retention_policy: RetentionPolicy) -> Self {
+        self.retention_policy = retention_policy;
+        self
+    }
+    pub fn retention_policy(&self) -> &RetentionPolicy {
+        &self.retention_policy
+    }
+
+    /// 기본 데이터 디렉터리 안의 `<name>.bin`
+    pub fn named(name: &str) -> Self {
+        Self::new(default_vault_dir().join(format!("{}.{}", name, VAULT_EXTENSION)))
+    }
+
+    /// `--vault` 인자, 환경 변수, 기본 볼트 순으로 고름.
+    /// 기본 볼트가 아직 없고 작업 디렉터리에 0.1.x의 `db.bin`이 있으면 그것을 씀
+    pub fn resolve(cli_path: Option<PathBuf>) -> Self {
+        if let Some(path) = cli_path {
+            return Self::new(path);
+        }
+        if let Some(path) = env::var_os(VAULT_ENV_VAR).filter(|path| !path.is_empty()) {
+            return Self::new(path);
+        }
+
+        let default_vault = Self::named(DEFAULT_VAULT_NAME);
+        let legacy_vault = Self::new(LEGACY_DB_FILE);
+        if !default_vault.exists() && legacy_vault.exists() {
+            return legacy_vault;
+        }
+        default_vault
+    }
+
+    pub fn db_file(&self) -> &Path {
+        &self.db_file
+    }
+    /// 0.1.x는 세션 동안 DB 파일을 `.bak`으로 옮겨 두는 것으로 비정상 종료를 표시했음
+    pub fn bak_file(&self) -> PathBuf {
+        self.with_suffix(".bak")
+    }
+    /// 저장하지 않은 변경이 있다는 표시. DB 파일과는 별개의 빈 파일
+    pub fn dirty_marker_file(&self) -> PathBuf {
+        self.with_suffix(".dirty")
+    }
+    /// 세션 동안 잠그는 파일. DB 파일은 저장할 때마다 rename으로 바뀌므로 따로 둠
+    pub(crate) fn lock_file(&self) -> PathBuf {
+        self.with_suffix(".lock")
+    }
+    /// `save_db`가 쓴 뒤 DB 파일 자리로 rename하는 임시 파일. 같은 디렉터리에 있어야 함
+    pub(crate) fn temp_file(&self) -> PathBuf {
+        self.with_suffix(".tmp")
+    }
+    pub(crate) fn quarantine_prefix(&self) -> OsString {
+        self.with_suffix(".corrupted-").into_os_string()
+    }
+    /// 마이그레이션 직전의 원본 DB 파일 사본
+    pub fn pre_migration_file(&self, from: FormatVersion) -> PathBuf {
+        self.with_suffix(&format!(".pre-migration-{}", from))
+    }
+    /// 스냅샷을 모아두는 `<db file>.backups` 디렉터리
+    pub fn backup_dir(&self) -> PathBuf {
+        self.with_suffix(".backups")
+    }
+
+    pub fn dir(&self) -> &Path {
+        match self.db_file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    /// 볼트 선택 화면에 보여줄 이름. 확장자를 뺀 파일 이름
+    pub fn name(&self) -> String {
+        self.db_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn exists(&self) -> bool {
+        matches!(fs::exists(&self.db_file), Ok(true))
+            || matches!(fs::exists(self.bak_file()), Ok(true))
+    }
+
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.db_file.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+}
+
+impl Default for VaultPath {
+    fn default() -> Self {
+        Self::resolve(None)
+    }
+}
+
+/// 플랫폼별 사용자 데이터 디렉터리 아래의 앱 디렉터리
+/// (`$XDG_DATA_HOME`, `~/.local/share`, `%APPDATA%`, `~/Library/Application Support`)
+pub fn default_vault_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+
+    base.unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR_NAME)
+}
+
+/// 기본 데이터 디렉터리에 있는 볼트들. 이름순으로 정렬됨
+pub fn list_vaults() -> Vec<VaultPath> {
+    let Ok(entries) = fs::read_dir(default_vault_dir()) else {
+        return Vec::new();
+    };
+
+    let mut vaults: Vec<VaultPath> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == VAULT_EXTENSION))
+        .map(VaultPath::new)
+        .collect();
+    vaults.sort_by_key(|vault| vault.name());
+    vaults
+}
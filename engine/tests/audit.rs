@@ -0,0 +1,170 @@
+//! 재사용, 약한, 오래된, 바꾼 적 없는 비밀번호와 같은 도메인의 서로 다른 비밀번호 점검, JSON 출력
+
+use engine::audit::{AuditIssue, AuditPolicy, AuditReport, AuditedEntry, audit_db, audit_db_at};
+use engine::data_base::{DB, SiteName, Timestamp, UserID, UserPW, add_user_pw, change_user_pw};
+use engine::init::sodium_init;
+use engine::pw_policy::PWScore;
+use engine::user_secrets::{SessionKey, SessionKeyNonce, WrappedSessionKey, wrap_session_key};
+
+const DAY: u64 = 24 * 60 * 60;
+const STRONG_PW: &str = "Vq8#tLz2!mR7$wXp4&nB";
+const OTHER_STRONG_PW: &str = "k9@Fh3^Yd6*Jw1%Qs5!e";
+
+struct Vault {
+    db: DB,
+    wrapped_key: WrappedSessionKey,
+    user_key_nonce: SessionKeyNonce,
+}
+
+impl Vault {
+    fn new(accounts: &[(&str, &str, &str)]) -> Self {
+        let _ = sodium_init();
+        let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+        let mut vault = Self { db: DB::new(), wrapped_key, user_key_nonce };
+        for &(site, id, pw) in accounts {
+            add_user_pw(
+                &mut vault.db,
+                SiteName::new(site).unwrap(),
+                UserID::new(id).unwrap(),
+                UserPW::new(pw).unwrap(),
+                &vault.wrapped_key,
+                &vault.user_key_nonce,
+            )
+            .unwrap();
+        }
+        vault
+    }
+
+    fn change(&mut self, site: &str, id: &str, pw: &str) {
+        let (site, id) = (SiteName::new(site).unwrap(), UserID::new(id).unwrap());
+        change_user_pw(&mut self.db, &site, &id, UserPW::new(pw).unwrap(), &self.wrapped_key, &self.user_key_nonce).unwrap();
+    }
+
+    fn audit(&self) -> AuditReport {
+        audit_db(&self.db, &AuditPolicy::default(), &self.wrapped_key, &self.user_key_nonce).unwrap()
+    }
+}
+
+fn site_ids(entries: &[AuditedEntry]) -> Vec<(&str, &str)> {
+    entries.iter().map(|entry| (entry.site.as_str(), entry.id.as_str())).collect()
+}
+
+#[test]
+fn finds_reused_passwords_across_accounts() {
+    let vault = Vault::new(&[
+        ("https://www.example.com", "alice", STRONG_PW),
+        ("https://mail.example.org", "bob", STRONG_PW),
+        ("https://shop.example.net", "carol", OTHER_STRONG_PW),
+    ]);
+    let report = vault.audit();
+    assert_eq!(report.entry_count, 3);
+    assert_eq!(report.count("reused"), 1);
+    let Some(AuditIssue::Reused { entries }) = report.issues.iter().find(|issue| issue.kind() == "reused") else {
+        unreachable!();
+    };
+    assert_eq!(site_ids(entries), [("example.com", "alice"), ("mail.example.org", "bob")]);
+}
+
+#[test]
+fn same_id_on_one_domain_is_one_account() {
+    // 같은 비밀번호면 재사용이 아니고, 다르면 한쪽만 바꾼 것으로 봄
+    let vault = Vault::new(&[
+        ("https://www.example.com", "alice", STRONG_PW),
+        ("https://login.example.com", "alice", STRONG_PW),
+    ]);
+    assert_eq!(vault.audit().count("reused"), 0);
+    assert_eq!(vault.audit().count("divergent-credentials"), 0);
+
+    let vault = Vault::new(&[
+        ("https://www.example.com", "alice", STRONG_PW),
+        ("https://login.example.com", "alice", OTHER_STRONG_PW),
+        ("https://login.example.com", "bob", STRONG_PW),
+    ]);
+    let report = vault.audit();
+    assert_eq!(report.count("divergent-credentials"), 1);
+    let Some(AuditIssue::DivergentCredentials { domain, id, sites }) =
+        report.issues.iter().find(|issue| issue.kind() == "divergent-credentials")
+    else {
+        unreachable!();
+    };
+    assert_eq!(domain, "example.com");
+    assert_eq!(id.as_str(), "alice");
+    let sites: Vec<_> = sites.iter().map(SiteName::as_str).collect();
+    assert_eq!(sites, ["example.com", "login.example.com"]);
+    // bob은 다른 ID이므로 alice의 비밀번호를 같이 써도 재사용
+    assert_eq!(report.count("reused"), 1);
+}
+
+#[test]
+fn finds_weak_passwords_below_the_policy() {
+    let vault = Vault::new(&[
+        ("https://www.example.com", "alice", "password"),
+        ("https://mail.example.org", "bob", STRONG_PW),
+    ]);
+    let report = vault.audit();
+    assert_eq!(report.count("weak"), 1);
+    let Some(AuditIssue::Weak { entry, score, warning }) = report.issues.iter().find(|issue| issue.kind() == "weak") else {
+        unreachable!();
+    };
+    assert_eq!(entry.id.as_str(), "alice");
+    assert!(*score < PWScore::Strong);
+    assert!(warning.is_some());
+
+    let lenient = AuditPolicy { min_score: PWScore::VeryWeak, ..AuditPolicy::default() };
+    let report = audit_db(&vault.db, &lenient, &vault.wrapped_key, &vault.user_key_nonce).unwrap();
+    assert_eq!(report.count("weak"), 0);
+}
+
+#[test]
+fn finds_old_and_never_changed_passwords() {
+    let mut vault = Vault::new(&[
+        ("https://www.example.com", "alice", STRONG_PW),
+        ("https://mail.example.org", "bob", OTHER_STRONG_PW),
+    ]);
+    let report = vault.audit();
+    assert_eq!(report.count("old"), 0);
+    assert_eq!(report.count("never-changed"), 2);
+
+    vault.change("https://www.example.com", "alice", "Zr5&pW2!cN8#hT4$gL7m");
+    let report = vault.audit();
+    assert_eq!(report.count("never-changed"), 1);
+    let Some(AuditIssue::NeverChanged { entry, .. }) = report.issues.iter().find(|issue| issue.kind() == "never-changed")
+    else {
+        unreachable!();
+    };
+    assert_eq!(entry.id.as_str(), "bob");
+
+    let policy = AuditPolicy::default();
+    let now = Timestamp::now().unix_secs();
+    let within = Timestamp::from_unix_secs(now + policy.max_age_days * DAY);
+    let report = audit_db_at(&vault.db, &policy, within, &vault.wrapped_key, &vault.user_key_nonce).unwrap();
+    assert_eq!(report.count("old"), 0);
+
+    let later = Timestamp::from_unix_secs(now + (policy.max_age_days + 2) * DAY);
+    let report = audit_db_at(&vault.db, &policy, later, &vault.wrapped_key, &vault.user_key_nonce).unwrap();
+    assert_eq!(report.count("old"), 2);
+    assert!(report.issues.iter().all(|issue| match issue {
+        AuditIssue::Old { age_days, .. } => (policy.max_age_days + 1..=policy.max_age_days + 2).contains(age_days),
+        _ => true,
+    }));
+}
+
+#[test]
+fn json_escapes_quotes_backslashes_and_control_characters() {
+    let vault = Vault::new(&[
+        ("https://www.example.com", "al\"i\\ce\t\u{1}x", "password"),
+        ("https://mail.example.org", "bob\nsmith", "password"),
+    ]);
+    let json = vault.audit().to_json();
+    assert!(json.starts_with("{\"audited_at\":\""));
+    assert!(json.contains(r#""id":"al\"i\\ce\t\u0001x""#));
+    assert!(json.contains(r#""id":"bob\nsmith""#));
+    assert!(json.contains("\"kind\":\"reused\""));
+    assert!(json.contains("\"kind\":\"weak\""));
+    assert!(json.ends_with("]}"));
+    // 이스케이프하지 않은 제어 문자가 남지 않음
+    assert!(!json.chars().any(char::is_control));
+
+    let empty = Vault::new(&[]).audit().to_json();
+    assert!(empty.ends_with("\"entry_count\":0,\"issues\":[]}"));
+}
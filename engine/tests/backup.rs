@@ -0,0 +1,148 @@
+//! 저장할 때 남기는 백업 스냅샷, 보존 정책에 따른 정리, 스냅샷으로 되돌리기
+
+mod common;
+
+use common::{ALICE, TestLogin, db_with_alice, temp_vault, test_login};
+use engine::backup::{BackupError, RetentionPolicy, list_snapshots, prune_snapshots, restore_snapshot};
+use engine::data_base::{DB, SiteName, UserID, get_entry};
+use engine::file_io::{FileIOWarn, check_can_directly_exit, load_db, mark_as_ungraceful_exited_to_file, save_db};
+use engine::init::sodium_init;
+use engine::master_secrets::{decrypt_db, encrypt_db};
+use engine::vault_path::VaultPath;
+use std::fs;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+/// 2024-01-01, 월요일
+const MONDAY: u64 = 19723 * DAY;
+
+/// `secs` 시각에 만든 것처럼 이름 붙인 빈 스냅샷들
+fn write_snapshots(vault: &VaultPath, secs: &[u64]) {
+    fs::create_dir_all(vault.backup_dir()).unwrap();
+    for secs in secs {
+        fs::write(vault.backup_dir().join(format!("{}.bin", secs * 1000)), b"").unwrap();
+    }
+}
+
+/// 정리하고 남은 스냅샷의 시각. 최신순
+fn kept_after_prune(name: &str, secs: &[u64], policy: RetentionPolicy) -> Vec<u64> {
+    let vault = temp_vault(name);
+    write_snapshots(&vault, secs);
+    let removed = prune_snapshots(&vault, &policy).unwrap();
+    let kept: Vec<u64> = list_snapshots(&vault).unwrap().iter().map(|s| s.created_unix_secs()).collect();
+    assert_eq!(removed.len() + kept.len(), secs.len());
+    assert!(removed.iter().all(|path| !path.exists()));
+    fs::remove_dir_all(vault.dir()).unwrap();
+    kept
+}
+
+#[test]
+fn failed_snapshot_still_saves() {
+    let _ = sodium_init();
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+    let vault = temp_vault("snapshot-failed");
+    // 백업 디렉터리 자리에 파일이 있어 스냅샷을 쓸 수 없음
+    fs::write(vault.backup_dir(), b"").unwrap();
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+
+    let encrypted_db = encrypt_db(&db, &header, &pub_key);
+    let warn = save_db(&vault, &mut header, encrypted_db).unwrap();
+    assert!(matches!(warn, Some(FileIOWarn::SnapshotFailed(_))));
+    assert!(check_can_directly_exit(&vault));
+
+    let (warn, header, encrypted_db) = load_db(&vault).unwrap();
+    assert!(warn.is_none());
+    assert!(decrypt_db(&encrypted_db.unwrap(), &header, &sec_key).is_ok());
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn keeps_the_latest_snapshots() {
+    let secs = [MONDAY, MONDAY + 1, MONDAY + 2, MONDAY + 3, MONDAY + 4];
+    let policy = RetentionPolicy { keep_last: 3, keep_daily: 0, keep_weekly: 0 };
+    assert_eq!(kept_after_prune("keep-last", &secs, policy), [MONDAY + 4, MONDAY + 3, MONDAY + 2]);
+}
+
+#[test]
+fn keeps_the_last_snapshot_of_each_day() {
+    let secs = [
+        MONDAY + 20 * HOUR,
+        MONDAY + 10 * HOUR,
+        MONDAY - DAY + 18 * HOUR,
+        MONDAY - DAY + 9 * HOUR,
+        MONDAY - 2 * DAY + 12 * HOUR,
+        MONDAY - 3 * DAY + 12 * HOUR,
+    ];
+    let policy = RetentionPolicy { keep_last: 1, keep_daily: 3, keep_weekly: 0 };
+    assert_eq!(
+        kept_after_prune("keep-daily", &secs, policy),
+        [MONDAY + 20 * HOUR, MONDAY - DAY + 18 * HOUR, MONDAY - 2 * DAY + 12 * HOUR]
+    );
+}
+
+#[test]
+fn weeks_start_on_monday() {
+    // 일요일 밤과 월요일 아침은 다른 주이고, 지난주 월요일과 일요일은 같은 주
+    let secs = [
+        MONDAY + 8 * HOUR,
+        MONDAY - DAY + 23 * HOUR,
+        MONDAY - DAY + 10 * HOUR,
+        MONDAY - 7 * DAY + 12 * HOUR,
+        MONDAY - 8 * DAY + 12 * HOUR,
+    ];
+    let policy = RetentionPolicy { keep_last: 0, keep_daily: 0, keep_weekly: 2 };
+    assert_eq!(kept_after_prune("keep-weekly", &secs, policy), [MONDAY + 8 * HOUR, MONDAY - DAY + 23 * HOUR]);
+}
+
+#[test]
+fn periods_overlap_instead_of_adding_up() {
+    // 최근 것은 세 조건에 모두 걸려도 한 번만 남고, 주 단위는 일 단위가 남긴 것보다 오래된 주까지 감
+    let secs = [
+        MONDAY + 12 * HOUR,
+        MONDAY - DAY + 12 * HOUR,
+        MONDAY - 2 * DAY + 12 * HOUR,
+        MONDAY - 9 * DAY + 12 * HOUR,
+        MONDAY - 10 * DAY + 12 * HOUR,
+        MONDAY - 30 * DAY,
+    ];
+    let policy = RetentionPolicy { keep_last: 1, keep_daily: 2, keep_weekly: 3 };
+    assert_eq!(
+        kept_after_prune("keep-overlap", &secs, policy),
+        [MONDAY + 12 * HOUR, MONDAY - DAY + 12 * HOUR, MONDAY - 9 * DAY + 12 * HOUR]
+    );
+}
+
+#[test]
+fn restores_an_older_snapshot() {
+    let _ = sodium_init();
+    let login = test_login();
+    let first = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+    let vault = temp_vault("restore-snapshot");
+
+    let encrypted_db = encrypt_db(&first, &header, &pub_key);
+    assert!(save_db(&vault, &mut header, encrypted_db).unwrap().is_none());
+    let encrypted_db = encrypt_db(&DB::new(), &header, &pub_key);
+    assert!(save_db(&vault, &mut header, encrypted_db).unwrap().is_none());
+
+    let snapshots = list_snapshots(&vault).unwrap();
+    assert_eq!(snapshots.len(), 2);
+    // 세션 도중에 되돌려도 다음에 열 때 비정상 종료로 보지 않음
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+    restore_snapshot(&vault, &snapshots[1]).unwrap();
+    assert!(check_can_directly_exit(&vault));
+
+    let (warn, header, encrypted_db) = load_db(&vault).unwrap();
+    assert!(warn.is_none());
+    let db = decrypt_db(&encrypted_db.unwrap(), &header, &sec_key).unwrap();
+    let (site, id, _) = ALICE;
+    assert!(get_entry(&db, &SiteName::new(site).unwrap(), &UserID::new(id).unwrap()).is_ok());
+
+    fs::remove_file(snapshots[0].path()).unwrap();
+    assert!(matches!(restore_snapshot(&vault, &snapshots[0]), Err(BackupError::NoSuchSnapshot)));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
@@ -0,0 +1,124 @@
+//! 유출 목록에서 해시를 찾는 이분 탐색. 처음과 마지막 줄, 줄 끝 형식, 대소문자, 깨진 줄, 범위 파일 디렉터리
+
+mod common;
+
+use common::temp_dir;
+use engine::breach::{BreachError, BreachList};
+use engine::data_base::UserPW;
+use std::fs;
+use std::path::PathBuf;
+
+/// 비밀번호와 대문자 SHA-1
+const CORRECT_HORSE: (&str, &str) = ("correct horse", "2F9E53523B62ABC141A2B4D6019D23CBA835DBD0");
+const PASSWORD: (&str, &str) = ("password", "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+const HUNTER2: (&str, &str) = ("hunter2", "F3BBBD66A63D4BF1747940578EC3D0103530E21D");
+/// 목록에 넣지 않는 비밀번호. 해시는 B4537A8E...
+const ABSENT: &str = "not in the list";
+
+/// `(해시, 횟수)`에 채우기용 해시 수백 개를 더해 정렬한 `HASH:COUNT` 줄들
+fn sorted_lines(hashes: &[(&str, u64)]) -> Vec<String> {
+    let mut lines: Vec<String> = (1..500u64)
+        .map(|i| {
+            let spread = i.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            format!("{:016X}{:016X}{:08X}:{}", spread, spread.rotate_left(17), i, i)
+        })
+        .chain(hashes.iter().map(|(hash, count)| format!("{}:{}", hash, count)))
+        .collect();
+    lines.sort();
+    lines
+}
+
+fn count(list: &BreachList, pw: &str) -> Result<u64, BreachError> {
+    list.breach_count(&UserPW::new(pw).unwrap())
+}
+
+fn single_file(dir: &str, contents: &str) -> (PathBuf, BreachList) {
+    let dir = temp_dir(dir);
+    let path = dir.join("pwned-passwords-sha1-ordered-by-hash.txt");
+    fs::write(&path, contents).unwrap();
+    let list = BreachList::open(&path).unwrap();
+    (dir, list)
+}
+
+#[test]
+fn finds_first_middle_and_last_lines() {
+    let lines = sorted_lines(&[(CORRECT_HORSE.1, 7), (PASSWORD.1, 9545824), (HUNTER2.1, 17)]);
+    // 채우기용 해시는 2F9E..보다 작은 것도 있으므로 맨 앞과 맨 뒤에 따로 붙임
+    let mut lines: Vec<String> = lines.into_iter().filter(|line| line.as_str() > "2F9F" && line.as_str() < "F3BB").collect();
+    lines.insert(0, format!("{}:7", CORRECT_HORSE.1));
+    lines.push(format!("{}:17", HUNTER2.1));
+    assert!(lines.len() > 100);
+
+    let (dir, list) = single_file("breach-lines", &(lines.join("\n") + "\n"));
+    assert_eq!(count(&list, CORRECT_HORSE.0).unwrap(), 7);
+    assert_eq!(count(&list, PASSWORD.0).unwrap(), 9545824);
+    assert_eq!(count(&list, HUNTER2.0).unwrap(), 17);
+    assert_eq!(count(&list, ABSENT).unwrap(), 0);
+    fs::remove_dir_all(dir).unwrap();
+
+    // 마지막 줄 뒤에 줄바꿈이 없어도 찾음
+    let (dir, list) = single_file("breach-no-newline", &lines.join("\n"));
+    assert_eq!(count(&list, HUNTER2.0).unwrap(), 17);
+    assert_eq!(count(&list, CORRECT_HORSE.0).unwrap(), 7);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reads_crlf_and_lowercase_lists() {
+    let lines = sorted_lines(&[(CORRECT_HORSE.1, 7), (PASSWORD.1, 3), (HUNTER2.1, 17)]);
+
+    let (dir, list) = single_file("breach-crlf", &(lines.join("\r\n") + "\r\n"));
+    for (pw, expected) in [(CORRECT_HORSE.0, 7), (PASSWORD.0, 3), (HUNTER2.0, 17), (ABSENT, 0)] {
+        assert_eq!(count(&list, pw).unwrap(), expected, "{}", pw);
+    }
+    fs::remove_dir_all(dir).unwrap();
+
+    // 소문자로 바꿔도 16진수의 순서는 그대로임
+    let (dir, list) = single_file("breach-lowercase", &lines.join("\n").to_lowercase());
+    for (pw, expected) in [(CORRECT_HORSE.0, 7), (PASSWORD.0, 3), (HUNTER2.0, 17), (ABSENT, 0)] {
+        assert_eq!(count(&list, pw).unwrap(), expected, "{}", pw);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn malformed_and_empty_lists() {
+    let lines = sorted_lines(&[(CORRECT_HORSE.1, 7), (HUNTER2.1, 17)]);
+    let mut lines = lines.join("\n");
+    lines = lines.replace(&format!("{}:7", CORRECT_HORSE.1), &format!("{}:seven", CORRECT_HORSE.1));
+    lines = lines.replace(&format!("{}:17", HUNTER2.1), HUNTER2.1);
+
+    let (dir, list) = single_file("breach-malformed", &lines);
+    assert!(matches!(count(&list, CORRECT_HORSE.0), Err(BreachError::MalformedLine(path)) if path == list.path()));
+    assert!(matches!(count(&list, HUNTER2.0), Err(BreachError::MalformedLine(_))));
+    // 깨진 줄이 있어도 다른 해시는 찾음
+    assert_eq!(count(&list, PASSWORD.0).unwrap(), 0);
+    fs::remove_dir_all(dir).unwrap();
+
+    let (dir, list) = single_file("breach-empty", "");
+    assert_eq!(count(&list, PASSWORD.0).unwrap(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn range_file_directory() {
+    let dir = temp_dir("breach-ranges");
+    // 범위 파일에는 해시 앞 5자리를 뺀 나머지만 있음
+    let suffix = |hash: &str| hash[5..].to_string();
+    let range = [format!("{}:2", suffix("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD7")), format!("{}:42", suffix(PASSWORD.1))].join("\r\n");
+    fs::write(dir.join("5BAA6"), range).unwrap();
+    fs::write(dir.join("F3BBB.txt"), format!("{}:17\n", suffix(HUNTER2.1))).unwrap();
+
+    let list = BreachList::open(&dir).unwrap();
+    assert_eq!(count(&list, PASSWORD.0).unwrap(), 42);
+    assert_eq!(count(&list, HUNTER2.0).unwrap(), 17);
+    assert!(matches!(count(&list, CORRECT_HORSE.0), Err(BreachError::RangeFileMissing(prefix)) if prefix == "2F9E5"));
+    assert!(matches!(count(&list, ABSENT), Err(BreachError::RangeFileMissing(prefix)) if prefix == "B4537"));
+
+    // 범위 파일 이름의 디렉터리는 파일로 보지 않음
+    fs::create_dir(dir.join("2F9E5")).unwrap();
+    assert!(matches!(count(&list, CORRECT_HORSE.0), Err(BreachError::RangeFileMissing(_))));
+
+    assert!(matches!(BreachList::open(&dir.join("list.txt")), Err(BreachError::FileOpenFailed(..))));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,127 @@
+//! 여러 통합 테스트가 함께 쓰는 픽스처 경로, 임시 볼트, 빠른 KDF 설정으로 만든 볼트.
+//! 테스트 파일마다 쓰는 것만 골라 쓰므로 나머지는 쓰이지 않는다는 경고가 남
+
+#![allow(dead_code)]
+
+use engine::data_base::{DB, SiteName, UserID, UserPW, add_user_pw, get_user_pw};
+use engine::file_io::{VaultHandle, load_db, save_db};
+use engine::header::DBHeader;
+use engine::kdf::KdfParams;
+use engine::key_slots::{SlotCredential, key_slot_login};
+use engine::master_secrets::{decrypt_db, encrypt_db, first_login};
+use engine::user_secrets::{SessionKeyNonce, WrappedSessionKey};
+use engine::vault_path::VaultPath;
+use engine::x25519::{PubKey, SecKey};
+use std::fs;
+use std::path::PathBuf;
+
+/// `fixtures/db_<version>.bin`을 만들 때 쓴 마스터 비밀번호
+pub const FIXTURE_MASTER_PW: &str = "fixture-master-pw";
+/// `test_login`으로 만든 볼트의 마스터 비밀번호
+pub const MASTER_PW: &str = "test master pw";
+pub const ALICE: (&str, &str, &str) = ("https://www.example.com", "alice", "alice-pw");
+
+pub fn fixture_path(file_name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(file_name)
+}
+
+/// `version`은 `0_2_8`처럼 씀
+pub fn fixture_db_path(version: &str) -> PathBuf {
+    fixture_path(&format!("db_{}.bin", version))
+}
+
+/// 테스트마다 따로 쓰는 빈 디렉터리. 테스트를 병렬로 돌려도 겹치지 않게 이름을 붙임
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pw-manager-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `temp_dir`에 DB 파일이 아직 없는 볼트
+pub fn temp_vault(name: &str) -> VaultHandle {
+    VaultHandle::open(VaultPath::new(temp_dir(name).join("db.bin"))).unwrap()
+}
+
+/// `temp_vault`와 같지만 그 버전의 픽스처를 DB 파일 자리에 복사해 둠
+pub fn temp_vault_from_fixture(name: &str, version: &str) -> VaultHandle {
+    let vault = VaultPath::new(temp_dir(name).join("db.bin"));
+    fs::copy(fixture_db_path(version), vault.db_file()).unwrap();
+    VaultHandle::open(vault).unwrap()
+}
+
+/// `first_login`이 돌려주는 키와 그 헤더
+pub struct TestLogin {
+    pub header: DBHeader,
+    pub sec_key: SecKey,
+    pub pub_key: PubKey,
+    pub wrapped_key: WrappedSessionKey,
+    pub user_key_nonce: SessionKeyNonce,
+}
+
+/// 가장 가벼운 KDF 설정과 `MASTER_PW`로 새 볼트를 만듦
+pub fn test_login() -> TestLogin {
+    let mut header = DBHeader::empty_valid();
+    header.kdf_params = KdfParams::argon2id(8 * 1024, 1, 1).unwrap();
+    let mut master_pw = MASTER_PW.to_string();
+    let (sec_key, pub_key, wrapped_key, user_key_nonce) = first_login(&mut master_pw, &mut header, None);
+    TestLogin {
+        header,
+        sec_key,
+        pub_key,
+        wrapped_key,
+        user_key_nonce,
+    }
+}
+
+/// alice의 계정 하나만 있는 DB
+pub fn db_with_alice(login: &TestLogin) -> DB {
+    let mut db = DB::new();
+    let (site, id, pw) = ALICE;
+    add_user_pw(
+        &mut db,
+        SiteName::new(site).unwrap(),
+        UserID::new(id).unwrap(),
+        UserPW::new(pw).unwrap(),
+        &login.wrapped_key,
+        &login.user_key_nonce,
+    )
+    .unwrap();
+    db
+}
+
+/// 픽스처를 슬롯 하나로 열어 bob의 비밀번호를 읽음
+pub fn opens_fixture(header: &DBHeader, encrypted_db: &Vec<u8>, index: usize, credential: &SlotCredential) -> bool {
+    let Ok((sec_key, _, wrapped_key, user_key_nonce)) = key_slot_login(header, index, credential) else {
+        return false;
+    };
+    let Ok(mut db) = decrypt_db(encrypted_db, header, &sec_key) else {
+        return false;
+    };
+    db.unlock_names(&wrapped_key, &user_key_nonce).unwrap();
+    let site = SiteName::new("https://mail.example.org").unwrap();
+    let id = UserID::new("bob").unwrap();
+    get_user_pw(&mut db, &site, &id, &wrapped_key, &user_key_nonce).is_ok_and(|pw| pw.as_str() == "hunter2hunter2")
+}
+
+/// 지금의 헤더와 공개키로 저장한 DB를 다시 읽어 슬롯 하나로 열고 alice의 비밀번호를 읽음
+pub fn opens_with(
+    index: usize,
+    credential: &SlotCredential,
+    vault: &VaultHandle,
+    db: &DB,
+    header: &mut DBHeader,
+    pub_key: &PubKey,
+) -> bool {
+    save_db(vault, header, encrypt_db(db, header, pub_key)).unwrap();
+    let (_, header, encrypted_db) = load_db(vault).unwrap();
+    let Ok((sec_key, _, wrapped_key, user_key_nonce)) = key_slot_login(&header, index, credential) else {
+        return false;
+    };
+    let Ok(mut db) = decrypt_db(&encrypted_db.unwrap(), &header, &sec_key) else {
+        return false;
+    };
+    let site = SiteName::new(ALICE.0).unwrap();
+    let id = UserID::new(ALICE.1).unwrap();
+    get_user_pw(&mut db, &site, &id, &wrapped_key, &user_key_nonce).is_ok_and(|pw| pw.as_str() == ALICE.2)
+}
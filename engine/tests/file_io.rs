@@ -0,0 +1,152 @@
+//! 손상된 파일 격리와 되살리기, 볼트 잠금, 임시 파일로 저장하기와 저장하지 않은 변경 표시
+
+mod common;
+
+use common::{TestLogin, db_with_alice, fixture_path, temp_dir, temp_vault, test_login};
+use engine::file_io::{
+    FileIOError, FileIOWarn, VaultHandle, check_can_directly_exit, list_quarantined_dbs, load_db,
+    mark_as_graceful_exited_to_file, mark_as_ungraceful_exited_to_file, quarantine_db, restore_db_from, save_db,
+};
+use engine::header::{FormatVersion, HEADER_LEN};
+use engine::init::sodium_init;
+use engine::master_secrets::{decrypt_db, encrypt_db};
+use engine::vault_path::VaultPath;
+use std::fs;
+
+#[test]
+fn corrupted_file_is_quarantined_untouched() {
+    let vault = temp_vault("quarantine");
+    let mut bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    bytes[HEADER_LEN] ^= 1;
+    fs::write(vault.db_file(), &bytes).unwrap();
+
+    let Err(FileIOError::Quarantined(path, cause)) = load_db(&vault) else {
+        panic!("corrupted file was not quarantined");
+    };
+    assert!(matches!(*cause, FileIOError::ChecksumMissMatch));
+    assert!(path.file_name().unwrap().to_string_lossy().starts_with("db.bin.corrupted-"));
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    assert!(!vault.db_file().exists());
+    assert_eq!(list_quarantined_dbs(&vault).unwrap(), std::slice::from_ref(&path));
+
+    // 다음에 열면 빈 DB 대신 격리된 파일이 있다고 알림
+    let (warn, _, encrypted_db) = load_db(&vault).unwrap();
+    assert!(matches!(warn, Some(FileIOWarn::QuarantinedDBExists(paths)) if paths == [path.clone()]));
+    assert!(encrypted_db.is_none());
+
+    // 검증을 통과하지 못한 파일은 되살리지 않음
+    assert!(matches!(restore_db_from(&vault, &path), Err(FileIOError::ChecksumMissMatch)));
+    assert!(!vault.db_file().exists());
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn quarantined_file_can_be_restored() {
+    let vault = temp_vault("restore-quarantined");
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    fs::write(vault.db_file(), &bytes).unwrap();
+    let path = quarantine_db(&vault).unwrap();
+    // 같은 초에 격리해도 덮어쓰지 않음
+    fs::write(vault.db_file(), &bytes).unwrap();
+    let second = quarantine_db(&vault).unwrap();
+    assert_ne!(path, second);
+    assert_eq!(list_quarantined_dbs(&vault).unwrap().len(), 2);
+
+    restore_db_from(&vault, &path).unwrap();
+    assert_eq!(fs::read(vault.db_file()).unwrap(), bytes);
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    assert!(matches!(restore_db_from(&vault, &second), Err(FileIOError::DBAlreadyExists)));
+
+    let (warn, header, encrypted_db) = load_db(&vault).unwrap();
+    assert!(warn.is_none());
+    assert!(header.is_current_version());
+    assert!(encrypted_db.is_some());
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn legacy_format_warning_comes_first() {
+    let vault = temp_vault("legacy-warning");
+    fs::copy(fixture_path("db_0_2_7.bin"), vault.db_file()).unwrap();
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+
+    let (warn, _, _) = load_db(&vault).unwrap();
+    assert!(matches!(warn, Some(FileIOWarn::LegacyFormatWillBeMigrated(v)) if v == FormatVersion::V0_2_7));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn vault_is_locked_while_the_handle_lives() {
+    let path = VaultPath::new(temp_dir("vault-lock").join("db.bin"));
+    let first = VaultHandle::open(path.clone()).unwrap();
+    let Err(FileIOError::VaultInUse(owner)) = VaultHandle::open(path.clone()) else {
+        panic!("second handle opened a locked vault");
+    };
+    assert_eq!(owner, Some(std::process::id()));
+
+    drop(first);
+    let second = VaultHandle::open(path).unwrap();
+    fs::remove_dir_all(second.dir()).unwrap();
+}
+
+/// `save_db`가 rename 전에 쓰는 임시 파일
+fn temp_file(vault: &VaultPath) -> std::path::PathBuf {
+    let mut path = vault.db_file().as_os_str().to_owned();
+    path.push(".tmp");
+    path.into()
+}
+
+#[test]
+fn leftover_temp_file_does_not_replace_the_db() {
+    let vault = temp_vault("leftover-temp");
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    fs::write(vault.db_file(), &bytes).unwrap();
+    // rename 전에 멈춘 저장이 남긴 반쯤 쓴 파일
+    fs::write(temp_file(&vault), &bytes[..bytes.len() / 2]).unwrap();
+
+    let (warn, header, encrypted_db) = load_db(&vault).unwrap();
+    assert!(warn.is_none());
+    assert!(header.is_current_version());
+    assert!(encrypted_db.is_some());
+    assert!(!temp_file(&vault).exists());
+    assert_eq!(fs::read(vault.db_file()).unwrap(), bytes);
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn dirty_marker_is_set_by_changes_and_cleared_by_save() {
+    let _ = sodium_init();
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+    let vault = temp_vault("dirty-marker");
+    assert!(check_can_directly_exit(&vault));
+
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+    // 이미 있어도 다시 표시할 수 있음
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+    assert!(!check_can_directly_exit(&vault));
+    assert!(vault.dirty_marker_file().exists());
+
+    let encrypted_db = encrypt_db(&db, &header, &pub_key);
+    save_db(&vault, &mut header, encrypted_db).unwrap();
+    assert!(check_can_directly_exit(&vault));
+    assert!(!temp_file(&vault).exists());
+
+    // 저장하지 않고 끝난 세션은 다음에 열 때 마지막 저장본을 읽고 알림
+    mark_as_ungraceful_exited_to_file(&vault).unwrap();
+    let (warn, loaded, encrypted_db) = load_db(&vault).unwrap();
+    assert!(matches!(warn, Some(FileIOWarn::RevertedForUngracefulExited)));
+    assert!(check_can_directly_exit(&vault));
+    assert!(decrypt_db(&encrypted_db.unwrap(), &loaded, &sec_key).is_ok());
+
+    mark_as_graceful_exited_to_file(&vault).unwrap();
+    assert!(check_can_directly_exit(&vault));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
@@ -0,0 +1,139 @@
+//! 문자 단위 비밀번호의 엔트로피 계산, 필수 종류와 헷갈리는 문자 빼기, 패스프레이즈
+
+use engine::generator::{
+    CharClass, CharsetPolicy, GeneratorError, GeneratorPolicy, LOOK_ALIKES, PassphrasePolicy, generate, wordlist,
+};
+use engine::init::sodium_init;
+
+fn charset(length: usize, classes: &[CharClass], required: &[CharClass], exclude_look_alikes: bool) -> GeneratorPolicy {
+    GeneratorPolicy::Charset(CharsetPolicy {
+        length,
+        classes: classes.to_vec(),
+        required: required.to_vec(),
+        exclude_look_alikes,
+    })
+}
+
+fn class_alphabet(class: CharClass, exclude_look_alikes: bool) -> Vec<char> {
+    class.chars().chars().filter(|c| !(exclude_look_alikes && LOOK_ALIKES.contains(*c))).collect()
+}
+
+/// 길이 `length`인 문자열을 모두 만들어 보고 필수 종류를 모두 가진 것을 셈.
+/// 두 번째 값은 그 문자열들에 들어간 숫자 개수의 합
+fn brute_force(length: usize, classes: &[CharClass], required: &[CharClass], exclude_look_alikes: bool) -> (u64, u64) {
+    let alphabet: Vec<(char, CharClass)> = classes
+        .iter()
+        .flat_map(|class| class_alphabet(*class, exclude_look_alikes).into_iter().map(|c| (c, *class)))
+        .collect();
+    let (mut count, mut digits) = (0, 0);
+    for mut n in 0..(alphabet.len() as u64).pow(length as u32) {
+        let mut used = Vec::with_capacity(length);
+        for _ in 0..length {
+            used.push(alphabet[(n % alphabet.len() as u64) as usize].1);
+            n /= alphabet.len() as u64;
+        }
+        if required.iter().all(|class| used.contains(class)) {
+            count += 1;
+            digits += used.iter().filter(|class| **class == CharClass::Digit).count() as u64;
+        }
+    }
+    (count, digits)
+}
+
+#[test]
+fn charset_entropy_matches_brute_force() {
+    use CharClass::*;
+    for (classes, required, exclude_look_alikes) in [
+        (&[Upper, Digit][..], &[Upper, Digit][..], false),
+        (&[Upper, Digit], &[Upper, Digit], true),
+        (&[Upper, Digit], &[Digit], true),
+        (&[Upper, Digit], &[], false),
+    ] {
+        let policy = charset(4, classes, required, exclude_look_alikes);
+        let (count, _) = brute_force(4, classes, required, exclude_look_alikes);
+        let bits = policy.entropy_bits().unwrap();
+        assert!((bits - (count as f64).log2()).abs() < 1e-9, "{:?}: {} != log2({})", policy, bits, count);
+    }
+
+    // 필수 종류가 없으면 알파벳 크기로만 정해짐
+    let policy = charset(20, &CharClass::ALL, &[], false);
+    assert!((policy.entropy_bits().unwrap() - 20.0 * 94f64.log2()).abs() < 1e-9);
+    // 필수 종류는 경우의 수를 줄이기만 함
+    let required = charset(20, &CharClass::ALL, &CharClass::ALL, false);
+    assert!(required.entropy_bits().unwrap() < policy.entropy_bits().unwrap());
+    assert!(required.entropy_bits().unwrap() > 20.0 * 94f64.log2() - 1.0);
+}
+
+#[test]
+fn required_classes_are_drawn_uniformly() {
+    use CharClass::*;
+    let _ = sodium_init();
+    let (classes, required) = ([Upper, Digit], [Upper, Digit]);
+    let policy = charset(4, &classes, &required, true);
+    let digit_alphabet = class_alphabet(Digit, true);
+    let upper_alphabet = class_alphabet(Upper, true);
+
+    // 조건을 만족하는 문자열 중에서 균등하게 고르면 숫자 개수의 평균이 전수 조사와 같음.
+    // 종류마다 한 글자씩 먼저 넣는 방식이면 숫자가 더 많이 나옴
+    let (count, digits) = brute_force(4, &classes, &required, true);
+    let expected = digits as f64 / count as f64;
+    let samples = 20_000;
+    let mut total = 0;
+    for _ in 0..samples {
+        let generated = generate(&policy).unwrap();
+        let pw = generated.pw.as_str();
+        assert_eq!(pw.chars().count(), 4);
+        assert!(pw.chars().all(|c| digit_alphabet.contains(&c) || upper_alphabet.contains(&c)), "{}", pw);
+        assert!(pw.chars().any(|c| digit_alphabet.contains(&c)), "{}", pw);
+        assert!(pw.chars().any(|c| upper_alphabet.contains(&c)), "{}", pw);
+        total += pw.chars().filter(|c| digit_alphabet.contains(c)).count();
+    }
+    // 숫자 개수의 표준편차는 1보다 작으므로 평균의 오차는 1/sqrt(samples)의 5배 안에 있음
+    let mean = total as f64 / samples as f64;
+    assert!((mean - expected).abs() < 5.0 / (samples as f64).sqrt(), "{} != {}", mean, expected);
+}
+
+#[test]
+fn look_alikes_are_excluded() {
+    let _ = sodium_init();
+    let policy = charset(128, &CharClass::ALL, &CharClass::ALL, true);
+    for _ in 0..200 {
+        let generated = generate(&policy).unwrap();
+        assert!(!generated.pw.as_str().chars().any(|c| LOOK_ALIKES.contains(c)), "{}", generated.pw.as_str());
+    }
+    let alphabet_len: usize = CharClass::ALL.iter().map(|class| class_alphabet(*class, true).len()).sum();
+    assert_eq!(alphabet_len, 94 - LOOK_ALIKES.chars().count());
+    let without_requirements = charset(128, &CharClass::ALL, &[], true);
+    assert!((without_requirements.entropy_bits().unwrap() - 128.0 * (alphabet_len as f64).log2()).abs() < 1e-9);
+}
+
+#[test]
+fn invalid_policies_are_rejected() {
+    use CharClass::*;
+    assert!(matches!(charset(3, &[Lower], &[], false).validate(), Err(GeneratorError::LengthOutOfRange)));
+    assert!(matches!(charset(129, &[Lower], &[], false).validate(), Err(GeneratorError::LengthOutOfRange)));
+    assert!(matches!(charset(8, &[], &[], false).validate(), Err(GeneratorError::NoCharClass)));
+    assert!(matches!(
+        charset(8, &[Lower], &[Lower, Digit], false).entropy_bits(),
+        Err(GeneratorError::RequiredClassNotUsed(Digit))
+    ));
+    let passphrase = |word_count| GeneratorPolicy::Passphrase(PassphrasePolicy { word_count, ..PassphrasePolicy::default() });
+    assert!(matches!(passphrase(2).validate(), Err(GeneratorError::WordCountOutOfRange)));
+    assert!(matches!(passphrase(21).validate(), Err(GeneratorError::WordCountOutOfRange)));
+}
+
+#[test]
+fn passphrase_uses_the_wordlist() {
+    let _ = sodium_init();
+    let words: Vec<&str> = wordlist().collect();
+    let policy = PassphrasePolicy { word_count: 5, separator: " ".to_string(), capitalize: true };
+    let generated = generate(&GeneratorPolicy::Passphrase(policy)).unwrap();
+    let parts: Vec<&str> = generated.pw.as_str().split(' ').collect();
+    assert_eq!(parts.len(), 5);
+    for part in parts {
+        let mut chars = part.chars();
+        assert!(chars.next().unwrap().is_uppercase(), "{}", part);
+        assert!(words.contains(&part.to_lowercase().as_str()), "{}", part);
+    }
+    assert!((generated.entropy_bits - 5.0 * (words.len() as f64).log2()).abs() < 1e-9);
+}
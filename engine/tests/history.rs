@@ -0,0 +1,94 @@
+//! 비밀번호를 바꾼 기록과 기록에서 되살리기
+
+use engine::data_base::{
+    DB, DBIOError, PW_HISTORY_LEN, SiteName, UserID, UserPW, add_user_pw, change_user_pw, get_entry,
+    get_past_user_pw, get_user_pw, restore_user_pw,
+};
+use engine::init::sodium_init;
+use engine::user_secrets::{SessionKey, SessionKeyNonce, WrappedSessionKey, wrap_session_key};
+
+struct Account {
+    db: DB,
+    site: SiteName,
+    id: UserID,
+    wrapped_key: WrappedSessionKey,
+    user_key_nonce: SessionKeyNonce,
+}
+
+impl Account {
+    /// `pw-0`으로 만들고 `pw-1`부터 `pw-<changes>`까지 차례로 바꾼 계정
+    fn with_changes(changes: usize) -> Self {
+        let _ = sodium_init();
+        let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+        let site = SiteName::new("https://www.example.com").unwrap();
+        let id = UserID::new("alice").unwrap();
+        let mut db = DB::new();
+        add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw-0").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+        for i in 1..=changes {
+            let pw = UserPW::new(&format!("pw-{}", i)).unwrap();
+            change_user_pw(&mut db, &site, &id, pw, &wrapped_key, &user_key_nonce).unwrap();
+        }
+        Self { db, site, id, wrapped_key, user_key_nonce }
+    }
+
+    fn current(&mut self) -> String {
+        get_user_pw(&mut self.db, &self.site, &self.id, &self.wrapped_key, &self.user_key_nonce).unwrap().as_str().to_owned()
+    }
+
+    /// 최신순
+    fn history(&self) -> Vec<String> {
+        let len = get_entry(&self.db, &self.site, &self.id).unwrap().pw_history().len();
+        (0..len)
+            .map(|i| {
+                get_past_user_pw(&self.db, &self.site, &self.id, i, &self.wrapped_key, &self.user_key_nonce)
+                    .unwrap()
+                    .as_str()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    fn restore(&mut self, index: usize) -> Result<(), DBIOError> {
+        restore_user_pw(&mut self.db, &self.site, &self.id, index, &self.wrapped_key, &self.user_key_nonce)
+    }
+}
+
+fn pws(numbers: impl Iterator<Item = usize>) -> Vec<String> {
+    numbers.map(|i| format!("pw-{}", i)).collect()
+}
+
+#[test]
+fn keeps_the_latest_passwords_up_to_the_limit() {
+    let mut account = Account::with_changes(PW_HISTORY_LEN + 2);
+    assert_eq!(account.current(), format!("pw-{}", PW_HISTORY_LEN + 2));
+    assert_eq!(account.history(), pws((2..PW_HISTORY_LEN + 2).rev()));
+}
+
+#[test]
+fn restore_makes_the_past_password_current() {
+    let mut account = Account::with_changes(2);
+    assert_eq!(account.history(), pws([1, 0].into_iter()));
+
+    account.restore(1).unwrap();
+    assert_eq!(account.current(), "pw-0");
+    // 바뀐 비밀번호가 맨 앞에 남고 되살린 기록은 빠짐
+    assert_eq!(account.history(), pws([2, 1].into_iter()));
+}
+
+#[test]
+fn restore_the_oldest_of_a_full_history() {
+    let mut account = Account::with_changes(PW_HISTORY_LEN);
+    assert_eq!(account.history(), pws((0..PW_HISTORY_LEN).rev()));
+
+    account.restore(PW_HISTORY_LEN - 1).unwrap();
+    assert_eq!(account.current(), "pw-0");
+    assert_eq!(account.history(), pws((1..=PW_HISTORY_LEN).rev()));
+}
+
+#[test]
+fn restore_out_of_range() {
+    let mut account = Account::with_changes(1);
+    assert!(matches!(account.restore(1), Err(DBIOError::PastPWNotFound)));
+    assert_eq!(account.current(), "pw-1");
+    assert_eq!(account.history(), pws([0].into_iter()));
+}
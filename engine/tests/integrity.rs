@@ -0,0 +1,101 @@
+//! 헤더의 magic, version, ciphertext 길이를 바꾸거나 파일을 자른 경우의 오류
+
+mod common;
+
+use common::{FIXTURE_MASTER_PW, fixture_path, temp_vault};
+use engine::file_io::{FileIOError, load_db};
+use engine::header::{DBHeader, HEADER_LEN};
+use engine::init::sodium_init;
+use engine::key_file::KeyFile;
+use engine::master_secrets::{MasterPWError, decrypt_db_payload, general_login};
+use std::fs;
+
+/// 고친 파일을 픽스처의 마스터 비밀번호로 열어 봄
+fn unlock(bytes: &[u8], key_file: Option<&KeyFile>) -> Result<(), MasterPWError> {
+    let (header, encrypted_db) = DBHeader::parse_header(bytes).unwrap();
+    let mut master_pw = FIXTURE_MASTER_PW.to_string();
+    let (sec_key, _, _, _) = general_login(&mut master_pw, &header, key_file)?;
+    decrypt_db_payload(&encrypted_db, &header, &sec_key).map(drop)
+}
+
+fn version_at(bytes: &[u8]) -> usize {
+    bytes.windows(8).position(|w| w == b"DB Ver: ").unwrap()
+}
+
+#[test]
+fn untouched_fixture_unlocks() {
+    let _ = sodium_init();
+    let key_file = KeyFile::read(&fixture_path("db_0_2_8.key")).unwrap();
+    assert_eq!(unlock(&fs::read(fixture_path("db_0_2_8.bin")).unwrap(), Some(&key_file)), Ok(()));
+    assert_eq!(unlock(&fs::read(fixture_path("db_0_2_1.bin")).unwrap(), None), Ok(()));
+}
+
+#[test]
+fn changed_magic_is_not_a_db() {
+    let mut bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    bytes[3] ^= 0x20;
+    assert!(matches!(DBHeader::parse_header(&bytes), Err(FileIOError::InvalidHeader)));
+}
+
+#[test]
+fn changed_version_is_tampering() {
+    let _ = sodium_init();
+    // 0.2.0과 0.2.1은 헤더 레이아웃이 같으므로 버전만 바꾸면 그대로 읽힘
+    let mut bytes = fs::read(fixture_path("db_0_2_1.bin")).unwrap();
+    let at = version_at(&bytes);
+    bytes[at..at + 18].copy_from_slice(b"DB Ver: 0.2.0.000\n");
+    assert_eq!(unlock(&bytes, None), Err(MasterPWError::TamperedHeader));
+
+    // 헤더를 묶지 않던 버전으로 내려도 키 확인값 자리가 비어 있지 않음
+    let mut bytes = fs::read(fixture_path("db_0_2_1.bin")).unwrap();
+    bytes[at..at + 18].copy_from_slice(b"DB Ver: 0.1.4.000\n");
+    assert_eq!(unlock(&bytes, None), Err(MasterPWError::TamperedHeader));
+
+    let mut bytes = fs::read(fixture_path("db_0_2_1.bin")).unwrap();
+    bytes[at..at + 18].copy_from_slice(b"DB Ver: 0.2.1.001\n");
+    assert!(matches!(DBHeader::parse_header(&bytes), Err(FileIOError::DBVersionMissMatch(_))));
+}
+
+#[test]
+fn changed_salt_reads_as_a_wrong_password() {
+    let _ = sodium_init();
+    let mut bytes = fs::read(fixture_path("db_0_2_1.bin")).unwrap();
+    let salt_at = version_at(&bytes) + 18;
+    bytes[salt_at] ^= 1;
+    let err = unlock(&bytes, None).unwrap_err();
+    assert_eq!(err, MasterPWError::IncorrectPW);
+    assert!(err.to_string().contains("modified header"));
+}
+
+#[test]
+fn changed_ciphertext_len_is_tampering() {
+    let _ = sodium_init();
+    let key_file = KeyFile::read(&fixture_path("db_0_2_8.key")).unwrap();
+    let mut bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    // 헤더의 마지막 필드
+    let len_at = HEADER_LEN - size_of::<usize>();
+    let len = usize::from_le_bytes(bytes[len_at..HEADER_LEN].try_into().unwrap());
+    assert_eq!(len, bytes.len() - HEADER_LEN);
+    bytes[len_at..HEADER_LEN].copy_from_slice(&(len - 1).to_le_bytes());
+    assert_eq!(unlock(&bytes, Some(&key_file)), Err(MasterPWError::TamperedHeader));
+}
+
+#[test]
+fn truncated_file_is_tampering() {
+    let _ = sodium_init();
+    let key_file = KeyFile::read(&fixture_path("db_0_2_8.key")).unwrap();
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+
+    assert!(matches!(DBHeader::parse_header(&bytes[..HEADER_LEN - 1]), Err(FileIOError::TruncatedFile)));
+    assert_eq!(unlock(&bytes[..bytes.len() - 1], Some(&key_file)), Err(MasterPWError::TamperedHeader));
+    assert_eq!(unlock(&bytes[..HEADER_LEN + 8], Some(&key_file)), Err(MasterPWError::TamperedHeader));
+
+    // 길이 검사는 복호화 전에 파일을 격리함
+    let vault = temp_vault("truncated");
+    fs::write(vault.db_file(), &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(
+        load_db(&vault),
+        Err(FileIOError::Quarantined(_, err)) if matches!(*err, FileIOError::TruncatedFile)
+    ));
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
@@ -0,0 +1,65 @@
+//! KDF 설정을 재는 벤치마크의 범위, 다음 로그인에 새 KDF 설정으로 바꾸기
+
+mod common;
+
+use common::{ALICE, MASTER_PW, TestLogin, db_with_alice, temp_vault, test_login};
+use engine::data_base::{SiteName, UserID, get_user_pw};
+use engine::file_io::{load_db, save_db};
+use engine::init::sodium_init;
+use engine::kdf::{KdfParams, benchmark_kdf_params};
+use engine::master_secrets::{apply_kdf_upgrade, decrypt_db, encrypt_db, general_login, verify_master_pw};
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn benchmark_stays_within_limits() {
+    for target in [Duration::ZERO, Duration::from_millis(30)] {
+        let params = benchmark_kdf_params(target);
+        // 파일에서 읽을 때와 같은 범위 검사를 통과함
+        assert!(KdfParams::argon2id(params.m_cost_kib(), params.t_cost(), params.p_cost()).is_ok(), "{}", params);
+        assert!(params.m_cost_kib() >= 8 * 1024);
+    }
+    // 목표가 0이면 가장 가벼운 설정에서 멈춤
+    let lightest = benchmark_kdf_params(Duration::ZERO);
+    assert_eq!((lightest.m_cost_kib(), lightest.t_cost()), (8 * 1024, 1));
+}
+
+#[test]
+fn pending_kdf_params_apply_on_next_login() {
+    let _ = sodium_init();
+    let vault = temp_vault("kdf-upgrade");
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+
+    // 대기 중인 변경이 없으면 할 일이 없음
+    assert!(!apply_kdf_upgrade(&mut header, MASTER_PW, None, &sec_key));
+
+    let upgraded = KdfParams::argon2id(9 * 1024, 2, 1).unwrap();
+    header.request_kdf_upgrade(upgraded);
+    let encrypted_db = encrypt_db(&db, &header, &pub_key);
+    save_db(&vault, &mut header, encrypted_db).unwrap();
+
+    let (_, mut header, encrypted_db) = load_db(&vault).unwrap();
+    assert_eq!(header.pending_kdf_params(), Some(upgraded));
+    let mut master_pw = MASTER_PW.to_string();
+    let (sec_key, pub_key, _, _) = general_login(&mut master_pw, &header, None).unwrap();
+    let db = decrypt_db(&encrypted_db.unwrap(), &header, &sec_key).unwrap();
+    assert!(apply_kdf_upgrade(&mut header, MASTER_PW, None, &sec_key));
+    let encrypted_db = encrypt_db(&db, &header, &pub_key);
+    save_db(&vault, &mut header, encrypted_db).unwrap();
+
+    let (_, header, encrypted_db) = load_db(&vault).unwrap();
+    assert_eq!(header.kdf_params, upgraded);
+    assert_eq!(header.pending_kdf_params(), None);
+    // 마스터 비밀번호 슬롯만 다시 감쌌으므로 볼트 비밀 키는 그대로임
+    assert!(verify_master_pw(MASTER_PW, &header, None, &pub_key).is_ok());
+    let mut master_pw = MASTER_PW.to_string();
+    let (sec_key, _, wrapped_key, user_key_nonce) = general_login(&mut master_pw, &header, None).unwrap();
+    let mut db = decrypt_db(&encrypted_db.unwrap(), &header, &sec_key).unwrap();
+    let site = SiteName::new(ALICE.0).unwrap();
+    let id = UserID::new(ALICE.1).unwrap();
+    assert_eq!(get_user_pw(&mut db, &site, &id, &wrapped_key, &user_key_nonce).unwrap().as_str(), ALICE.2);
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
@@ -0,0 +1,217 @@
+//! 키 슬롯으로 열기, 슬롯 추가/이름 바꾸기/없애기, 마스터 비밀번호를 바꾼 뒤에도 슬롯이 열리는지
+
+mod common;
+
+use common::{
+    FIXTURE_MASTER_PW, MASTER_PW, TestLogin, db_with_alice, fixture_path, opens_fixture, opens_with, temp_dir, temp_vault,
+    test_login,
+};
+use engine::header::{DBHeader, KEY_SLOT_COUNT, MASTER_SLOT};
+use engine::init::sodium_init;
+use engine::key_file::KeyFile;
+use engine::key_slots::{
+    KeySlotError, KeySlotInfo, KeySlotKind, NewKeySlot, SlotCredential, TeammateKey, add_key_slot, add_recovery_code_slot,
+    key_slot_login, label_key_slot, list_key_slots, revoke_key_slot,
+};
+use engine::master_secrets::{MasterPWError, change_master_pw, decrypt_db_payload, verify_master_pw};
+use engine::recovery::RecoveryKey;
+use std::fs;
+
+#[test]
+fn fixture_opens_with_each_key_slot() {
+    let _ = sodium_init();
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+    let slots = list_key_slots(&header);
+    assert_eq!(
+        slots,
+        [
+            KeySlotInfo { index: 0, kind: KeySlotKind::PasswordAndKeyFile, label: "master password".to_string() },
+            KeySlotInfo { index: 1, kind: KeySlotKind::RecoveryCode, label: "recovery code".to_string() },
+            KeySlotInfo { index: 2, kind: KeySlotKind::RecoveryShares, label: "recovery shares".to_string() },
+            KeySlotInfo { index: 3, kind: KeySlotKind::Password, label: "spare password".to_string() },
+            KeySlotInfo { index: 4, kind: KeySlotKind::Teammate, label: "bob's laptop".to_string() },
+            KeySlotInfo { index: 5, kind: KeySlotKind::RecoveryCode, label: "safe deposit box".to_string() },
+        ]
+    );
+
+    // 키 파일을 요구하는 볼트도 마스터 비밀번호 슬롯이 아니면 슬롯 하나만으로 열림
+    assert!(header.requires_key_file());
+    let key_file = KeyFile::read(&fixture_path("db_0_2_8.key")).unwrap();
+    let teammate = KeyFile::read(&fixture_path("db_0_2_8.teammate")).unwrap();
+    let code = fs::read_to_string(fixture_path("db_0_2_8.slot_code")).unwrap();
+    let recovery_key = RecoveryKey::parse(&code).unwrap();
+    for (index, credential) in [
+        (MASTER_SLOT, SlotCredential::PasswordAndKeyFile(FIXTURE_MASTER_PW, &key_file)),
+        (3, SlotCredential::Password("fixture-slot-pw")),
+        (4, SlotCredential::KeyFile(&teammate)),
+        (5, SlotCredential::RecoveryCode(&recovery_key)),
+    ] {
+        assert!(opens_fixture(&header, &encrypted_db, index, &credential));
+    }
+
+    assert!(matches!(
+        key_slot_login(&header, 3, &SlotCredential::Password(FIXTURE_MASTER_PW)),
+        Err(KeySlotError::IncorrectCredential)
+    ));
+    assert!(matches!(
+        key_slot_login(&header, MASTER_SLOT, &SlotCredential::Password(FIXTURE_MASTER_PW)),
+        Err(KeySlotError::WrongCredential(KeySlotKind::PasswordAndKeyFile))
+    ));
+    assert!(matches!(
+        key_slot_login(&header, 3, &SlotCredential::KeyFile(&teammate)),
+        Err(KeySlotError::WrongCredential(KeySlotKind::Password))
+    ));
+    assert!(matches!(
+        key_slot_login(&header, 4, &SlotCredential::KeyFile(&key_file)),
+        Err(KeySlotError::IncorrectCredential)
+    ));
+    assert!(matches!(
+        key_slot_login(&header, 6, &SlotCredential::Password("fixture-slot-pw")),
+        Err(KeySlotError::NoSuchSlot(6))
+    ));
+    assert!(matches!(
+        key_slot_login(&header, KEY_SLOT_COUNT, &SlotCredential::Password("fixture-slot-pw")),
+        Err(KeySlotError::NoSuchSlot(KEY_SLOT_COUNT))
+    ));
+}
+
+#[test]
+fn slot_table_fixture_opens_before_migration() {
+    let _ = sodium_init();
+    let bytes = fs::read(fixture_path("db_0_2_7.bin")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+
+    // 0.2.7의 고정 슬롯과 슬롯 표를 0.2.8과 같은 번호로 읽음. 항목은 옮긴 뒤에 읽음
+    let kinds: Vec<(usize, KeySlotKind)> = list_key_slots(&header).into_iter().map(|slot| (slot.index, slot.kind)).collect();
+    assert_eq!(
+        kinds,
+        [
+            (0, KeySlotKind::PasswordAndKeyFile),
+            (1, KeySlotKind::RecoveryCode),
+            (2, KeySlotKind::RecoveryShares),
+            (3, KeySlotKind::Password),
+            (4, KeySlotKind::Teammate),
+            (5, KeySlotKind::RecoveryCode),
+        ]
+    );
+
+    let key_file = KeyFile::read(&fixture_path("db_0_2_7.key")).unwrap();
+    let teammate = KeyFile::read(&fixture_path("db_0_2_7.teammate")).unwrap();
+    let recovery_key = RecoveryKey::parse(&fs::read_to_string(fixture_path("db_0_2_7.recovery")).unwrap()).unwrap();
+    let slot_key = RecoveryKey::parse(&fs::read_to_string(fixture_path("db_0_2_7.slot_code")).unwrap()).unwrap();
+    for (index, credential) in [
+        (MASTER_SLOT, SlotCredential::PasswordAndKeyFile(FIXTURE_MASTER_PW, &key_file)),
+        (1, SlotCredential::RecoveryCode(&recovery_key)),
+        (3, SlotCredential::Password("fixture-slot-pw")),
+        (4, SlotCredential::KeyFile(&teammate)),
+        (5, SlotCredential::RecoveryCode(&slot_key)),
+    ] {
+        let (sec_key, ..) = key_slot_login(&header, index, &credential).unwrap();
+        assert!(decrypt_db_payload(&encrypted_db, &header, &sec_key).is_ok());
+    }
+}
+
+#[test]
+fn teammate_key_text_catches_typos() {
+    let _ = sodium_init();
+    let dir = temp_dir("teammate-key");
+    let key_file = KeyFile::generate(&dir.join("teammate.key")).unwrap();
+    let text = TeammateKey::from_key_file(&key_file).to_text();
+
+    // 대소문자와 구분 기호는 가리지 않음
+    let loose = text.replace('-', " ").to_lowercase();
+    assert_eq!(TeammateKey::parse(&loose).unwrap(), TeammateKey::from_key_file(&key_file));
+
+    let mut typo: Vec<char> = text.chars().collect();
+    typo[10] = if typo[10] == 'A' { 'B' } else { 'A' };
+    let typo: String = typo.into_iter().collect();
+    assert_eq!(TeammateKey::parse(&typo), Err(KeySlotError::InvalidTeammateKey));
+    assert_eq!(TeammateKey::parse("ABCD-EFGH"), Err(KeySlotError::InvalidTeammateKey));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn slots_survive_master_pw_change_until_revoked() {
+    let _ = sodium_init();
+    let vault = temp_vault("key-slots");
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+
+    let mut wrong_pw = "not the master pw".to_string();
+    let mut slot_pw = "slot password".to_string();
+    assert!(matches!(
+        add_key_slot(&mut header, &mut wrong_pw, None, &pub_key, "laptop", NewKeySlot::Password(&mut slot_pw)),
+        Err(KeySlotError::MasterPW(MasterPWError::IncorrectPW))
+    ));
+    assert!(slot_pw.is_empty());
+    let mut master_pw = MASTER_PW.to_string();
+    let mut slot_pw = "slot password".to_string();
+    assert!(matches!(
+        add_key_slot(&mut header, &mut master_pw, None, &pub_key, "", NewKeySlot::Password(&mut slot_pw)),
+        Err(KeySlotError::InvalidLabel)
+    ));
+    assert_eq!(list_key_slots(&header).len(), 1);
+
+    let mut master_pw = MASTER_PW.to_string();
+    let mut slot_pw = "slot password".to_string();
+    let password_slot = add_key_slot(&mut header, &mut master_pw, None, &pub_key, "laptop", NewKeySlot::Password(&mut slot_pw)).unwrap();
+    assert!(master_pw.is_empty() && slot_pw.is_empty());
+    let teammate = KeyFile::generate(&vault.dir().join("teammate.key")).unwrap();
+    let mut master_pw = MASTER_PW.to_string();
+    let teammate_slot = add_key_slot(&mut header, &mut master_pw, None, &pub_key, "carol", NewKeySlot::Teammate(&TeammateKey::from_key_file(&teammate))).unwrap();
+    let mut master_pw = MASTER_PW.to_string();
+    let (code_slot, recovery_key) = add_recovery_code_slot(&mut header, &mut master_pw, None, &pub_key, "vault").unwrap();
+    assert_eq!((password_slot, teammate_slot, code_slot), (1, 2, 3));
+    assert!(opens_with(password_slot, &SlotCredential::Password("slot password"), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(teammate_slot, &SlotCredential::KeyFile(&teammate), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(code_slot, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+
+    // 마스터 비밀번호를 바꿔도 마스터 비밀번호 슬롯만 다시 감싸므로 다른 슬롯과 항목은 그대로임
+    let other_slots = list_key_slots(&header);
+    let mut new_master_pw = "another master password".to_string();
+    change_master_pw(&mut header, &mut new_master_pw, None, &sec_key);
+    assert!(new_master_pw.is_empty());
+    assert_eq!(list_key_slots(&header), other_slots);
+    assert!(verify_master_pw("another master password", &header, None, &pub_key).is_ok());
+    assert!(!opens_with(MASTER_SLOT, &SlotCredential::Password(MASTER_PW), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(MASTER_SLOT, &SlotCredential::Password("another master password"), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(password_slot, &SlotCredential::Password("slot password"), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(teammate_slot, &SlotCredential::KeyFile(&teammate), &vault, &db, &mut header, &pub_key));
+
+    // 이름은 마스터 비밀번호 없이 바꿈
+    label_key_slot(&mut header, teammate_slot, "carol's desktop").unwrap();
+    assert_eq!(list_key_slots(&header)[teammate_slot].label, "carol's desktop");
+    assert_eq!(label_key_slot(&mut header, 5, "nothing"), Err(KeySlotError::NoSuchSlot(5)));
+    assert_eq!(label_key_slot(&mut header, 1, &"x".repeat(33)), Err(KeySlotError::InvalidLabel));
+
+    // 마스터 비밀번호 슬롯은 비울 수 없음
+    let mut master_pw = "another master password".to_string();
+    assert_eq!(
+        revoke_key_slot(&mut header, &mut master_pw, None, &pub_key, MASTER_SLOT),
+        Err(KeySlotError::MasterSlot)
+    );
+
+    let mut master_pw = "another master password".to_string();
+    revoke_key_slot(&mut header, &mut master_pw, None, &pub_key, password_slot).unwrap();
+    assert!(!opens_with(password_slot, &SlotCredential::Password("slot password"), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(code_slot, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+
+    // 빈 슬롯은 다시 채우고, 다 차면 더 넣을 수 없음
+    for i in 0..KEY_SLOT_COUNT - 3 {
+        let mut master_pw = "another master password".to_string();
+        let mut slot_pw = format!("slot password {}", i);
+        add_key_slot(&mut header, &mut master_pw, None, &pub_key, "spare", NewKeySlot::Password(&mut slot_pw)).unwrap();
+    }
+    assert_eq!(list_key_slots(&header).len(), KEY_SLOT_COUNT);
+    assert!(opens_with(password_slot, &SlotCredential::Password("slot password 0"), &vault, &db, &mut header, &pub_key));
+    let mut master_pw = "another master password".to_string();
+    assert!(matches!(
+        add_recovery_code_slot(&mut header, &mut master_pw, None, &pub_key, "one too many"),
+        Err(KeySlotError::NoFreeSlot)
+    ));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
@@ -0,0 +1,100 @@
+//! 메타데이터 암호화 모드에서 이름을 찾고, 나열하고, 키를 바꾸고, 되돌리기
+
+mod common;
+
+use common::{TestLogin, test_login};
+use engine::data_base::{
+    DB, DBIOError, EntryDetails, SiteName, UserID, UserPW, add_user_pw, get_entry, get_user_pw, prefix_range,
+    read_entry_details, remove_user_pw, update_entry_details,
+};
+use engine::init::sodium_init;
+use engine::master_secrets::{change_master_pw, general_login};
+use engine::metadata::{disable_metadata_encryption, enable_metadata_encryption};
+use engine::search::SearchIndex;
+use engine::trash::restore_trashed;
+use engine::user_secrets::{SessionKey, wrap_session_key};
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle.as_bytes())
+}
+
+#[test]
+fn sealed_names_stay_out_of_the_serialized_db() {
+    let _ = sodium_init();
+    let TestLogin { mut header, sec_key, wrapped_key, user_key_nonce, .. } = test_login();
+    let mut db = DB::new();
+    for (site, id) in [
+        ("https://mail.example.com", "alice"),
+        ("https://www.example.com", "bob"),
+        ("https://gitlab.com", "carol"),
+        ("https://old.example.net", "dave"),
+    ] {
+        add_user_pw(&mut db, SiteName::new(site).unwrap(), UserID::new(id).unwrap(), UserPW::new(id).unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    }
+    let bob = (SiteName::new("https://www.example.com").unwrap(), UserID::new("bob").unwrap());
+    let details = EntryDetails {
+        urls: vec!["https://sso.example.com/gitlab".to_owned()],
+        ..EntryDetails::default()
+    };
+    update_entry_details(&mut db, &bob.0, &bob.1, &details, &wrapped_key, &user_key_nonce).unwrap();
+    let dave = (SiteName::new("https://old.example.net").unwrap(), UserID::new("dave").unwrap());
+    update_entry_details(&mut db, &dave.0, &dave.1, &details, &wrapped_key, &user_key_nonce).unwrap();
+    remove_user_pw(&mut db, &dave.0, &dave.1).unwrap();
+
+    enable_metadata_encryption(&mut db, &wrapped_key, &user_key_nonce).unwrap();
+    assert!(db.is_metadata_encrypted());
+    assert_eq!((db.site_count(), db.entry_count()), (3, 3));
+    let serialized = rkyv::to_bytes::<rkyv::rancor::Error>(&db).unwrap();
+    // URL도 사이트를 드러내므로 암호화됨
+    for name in ["example", "gitlab", "sso", "alice", "dave"] {
+        assert!(!contains(&serialized, name), "{} is stored in plaintext", name);
+    }
+
+    // 같은 등록 도메인끼리 정렬되고, 사이트 안에서는 ID 순
+    let sites: Vec<_> = prefix_range(&db, "EXAMPLE").unwrap().into_iter().map(|(site, _)| site.as_str().to_owned()).collect();
+    assert_eq!(sites, ["example.com", "mail.example.com"]);
+    assert!(get_entry(&db, &bob.0, &bob.1).is_ok());
+    assert_eq!(db.user_ids(&bob.0).unwrap(), std::slice::from_ref(&bob.1));
+    assert!(matches!(get_entry(&db, &bob.0, &UserID::new("alice").unwrap()), Err(DBIOError::UserNotFound)));
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    assert_eq!(index.search("gtlb")[0].id, UserID::new("carol").unwrap());
+
+    // 마스터 비밀번호를 바꿔도 볼트 비밀 키에서 같은 세션 키를 얻으므로 해시는 그대로임
+    let mut new_master_pw = "another master password".to_string();
+    change_master_pw(&mut header, &mut new_master_pw, None, &sec_key);
+    let mut new_master_pw = "another master password".to_string();
+    let (_, _, wrapped_key, user_key_nonce) = general_login(&mut new_master_pw, &header, None).unwrap();
+    db.unlock_names(&wrapped_key, &user_key_nonce).unwrap();
+    assert_eq!(get_user_pw(&mut db, &bob.0, &bob.1, &wrapped_key, &user_key_nonce).unwrap().as_str(), "bob");
+    assert_eq!(read_entry_details(&db, &bob.0, &bob.1, &wrapped_key, &user_key_nonce).unwrap(), details);
+    assert_eq!(db.trashed_names(&db.trash()[0]).unwrap(), dave);
+    restore_trashed(&mut db, 0).unwrap();
+    assert_eq!(get_user_pw(&mut db, &dave.0, &dave.1, &wrapped_key, &user_key_nonce).unwrap().as_str(), "dave");
+    assert_eq!(read_entry_details(&db, &dave.0, &dave.1, &wrapped_key, &user_key_nonce).unwrap(), details);
+
+    disable_metadata_encryption(&mut db).unwrap();
+    assert!(!db.is_metadata_encrypted());
+    assert_eq!(db.site_count(), 4);
+    assert_eq!(get_user_pw(&mut db, &bob.0, &bob.1, &wrapped_key, &user_key_nonce).unwrap().as_str(), "bob");
+}
+
+#[test]
+fn sealed_db_needs_unlocked_names() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let site = SiteName::new("https://www.example.com").unwrap();
+    let id = UserID::new("alice").unwrap();
+    let mut db = DB::new();
+    add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    enable_metadata_encryption(&mut db, &wrapped_key, &user_key_nonce).unwrap();
+
+    // 저장했다가 다시 읽으면 이름 키가 없음
+    let serialized = rkyv::to_bytes::<rkyv::rancor::Error>(&db).unwrap();
+    let mut db = rkyv::from_bytes::<DB, rkyv::rancor::Error>(&serialized).unwrap();
+    assert!(matches!(get_entry(&db, &site, &id), Err(DBIOError::NamesLocked)));
+    assert!(matches!(db.sites(), Err(DBIOError::NamesLocked)));
+
+    db.unlock_names(&wrapped_key, &user_key_nonce).unwrap();
+    assert!(get_entry(&db, &site, &id).is_ok());
+    assert_eq!(db.sites().unwrap()[0].0, site);
+}
@@ -0,0 +1,424 @@
+//! `fixtures/db_<version>.bin`은 각 버전의 앱으로 만든 DB 파일.
+//! 모두 마스터 비밀번호 `fixture-master-pw`와 `ENTRIES`의 계정을 담고 있음.
+//! 0.1.7부터는 alice 항목에 메모, URL, 사용자 정의 필드가 있고,
+//! 0.1.8부터는 alice의 비밀번호를 두 번 바꾼 기록이,
+//! 0.1.9부터는 alice에 SHA256, 8자리, 60초짜리 TOTP가,
+//! 0.2.0부터는 alice에 태그와 폴더, 즐겨찾기가, bob에 폴더가 있고,
+//! 0.2.1부터는 `https://old.example.net`의 dave가 휴지통에 있고,
+//! 0.2.2부터는 사이트 이름과 ID가 암호화되어 있고,
+//! 0.2.3부터는 `fixtures/db_<version>.key`의 키 파일도 있어야 열리고,
+//! 0.2.4부터는 `fixtures/db_<version>.recovery`의 복구 코드로도,
+//! 0.2.5부터는 `fixtures/db_<version>.shares`의 5개 조각 중 3개로도 열리고,
+//! 0.2.6부터는 비밀번호, 팀원, 복구 코드 키 슬롯이 하나씩 있고,
+//! 0.2.7부터는 URL이 암호화되어 있고,
+//! 0.2.8부터는 무작위 볼트 비밀 키를 마스터 비밀번호, 복구 코드, 조각 슬롯까지 모든 키 슬롯이 감싸고 있음
+
+mod common;
+
+use common::{FIXTURE_MASTER_PW, fixture_db_path, temp_vault_from_fixture};
+use engine::data_base::{
+    CustomFieldKind, DEFAULT_TRASH_RETENTION_DAYS, FolderPath, SiteName, UserID, get_entry, get_past_user_pw, get_user_pw,
+    read_entry_details,
+};
+use engine::file_io::{FileIOError, FileIOWarn, backup_before_migration, load_db, save_db};
+use engine::header::{DBHeader, FormatVersion};
+use engine::init::sodium_init;
+use engine::key_file::KeyFile;
+use engine::key_slots::{KeySlotKind, SlotCredential, key_slot_login, list_key_slots};
+use engine::master_secrets::{MasterPWError, check_key_file, decrypt_db, decrypt_db_payload, encrypt_db, general_login};
+use engine::migration::{MigrationError, dry_run_migration, migrate_db, migration_plan, migrations};
+use engine::organize::{EntryFilter, filter_sites, folder_tree};
+use engine::trash::restore_trashed;
+use engine::otp::{OtpAlgorithm, OtpKind, read_otp};
+use engine::recovery::RecoveryKey;
+use std::fs;
+use std::sync::Once;
+
+const ENTRIES: [(&str, &str, &str); 3] = [
+    ("https://www.example.com", "alice", "correct-horse-battery"),
+    ("https://www.example.com", "carol", "p@ssw0rd!"),
+    ("https://mail.example.org", "bob", "hunter2hunter2"),
+];
+
+/// 이미 초기화된 뒤에 다시 부르면 에러이므로 한 번만 부름
+fn init_sodium() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| sodium_init().unwrap());
+}
+
+/// 픽스처 옆에 키 파일이 있으면 읽음
+fn fixture_key_file(version: &str) -> Option<KeyFile> {
+    let path = fixture_db_path(version).with_extension("key");
+    path.exists().then(|| KeyFile::read(&path).unwrap())
+}
+
+/// 마스터 비밀번호 슬롯부터 차례로, 그 버전의 픽스처에 들어 있는 키 슬롯의 종류
+fn fixture_slot_kinds(version: FormatVersion) -> Vec<KeySlotKind> {
+    let master = match version >= FormatVersion::V0_2_3 {
+        true => KeySlotKind::PasswordAndKeyFile,
+        false => KeySlotKind::Password,
+    };
+    let mut kinds = vec![master];
+    if version >= FormatVersion::V0_2_4 {
+        kinds.push(KeySlotKind::RecoveryCode);
+    }
+    if version >= FormatVersion::V0_2_5 {
+        kinds.push(KeySlotKind::RecoveryShares);
+    }
+    if version >= FormatVersion::V0_2_6 {
+        kinds.extend([KeySlotKind::Password, KeySlotKind::Teammate, KeySlotKind::RecoveryCode]);
+    }
+    kinds
+}
+
+fn slot_kinds(header: &DBHeader) -> Vec<KeySlotKind> {
+    list_key_slots(header).into_iter().map(|slot| slot.kind).collect()
+}
+
+/// 픽스처를 열어 현재 포맷으로 올리고, 저장한 뒤 다시 읽어서 계정이 그대로인지 확인함
+fn migrate_fixture(version: &str, expected: FormatVersion) {
+    init_sodium();
+    let vault = temp_vault_from_fixture(&format!("migrate-{}", version), version);
+
+    let (warn, mut header, encrypted_db) = load_db(&vault).unwrap();
+    assert_eq!(header.version().unwrap(), expected);
+    if expected == FormatVersion::CURRENT {
+        assert!(warn.is_none());
+    } else {
+        assert!(matches!(warn, Some(FileIOWarn::LegacyFormatWillBeMigrated(v)) if v == expected));
+    }
+
+    let key_file = fixture_key_file(version);
+    assert_eq!(header.requires_key_file(), expected >= FormatVersion::V0_2_3);
+    assert_eq!(slot_kinds(&header), fixture_slot_kinds(expected));
+    check_key_file(&header, key_file.as_ref()).unwrap();
+    let mut master_pw = FIXTURE_MASTER_PW.to_string();
+    let (mut sec_key, mut pub_key, mut wrapped_key, mut user_key_nonce) =
+        general_login(&mut master_pw, &header, key_file.as_ref()).unwrap();
+    let payload = decrypt_db_payload(&encrypted_db.unwrap(), &header, &sec_key).unwrap();
+    let (db, steps) =
+        migrate_db(&mut header, payload, &mut sec_key, &mut pub_key, &mut wrapped_key, &mut user_key_nonce).unwrap();
+    assert!(header.is_current_version());
+    assert_eq!(steps.len(), migration_plan(expected).unwrap().len());
+
+    let encrypted_db = encrypt_db(&db, &header, &pub_key);
+    save_db(&vault, &mut header, encrypted_db).unwrap();
+
+    let (warn, header, encrypted_db) = load_db(&vault).unwrap();
+    assert!(warn.is_none());
+    // 빈 번호 없이 앞으로 모인 슬롯 표만 남음
+    let slots = list_key_slots(&header);
+    assert!(slots.iter().enumerate().all(|(index, slot)| slot.index == index));
+    assert_eq!(slot_kinds(&header), fixture_slot_kinds(expected));
+    let mut master_pw = FIXTURE_MASTER_PW.to_string();
+    let (sec_key, _, wrapped_key, user_key_nonce) = general_login(&mut master_pw, &header, key_file.as_ref()).unwrap();
+    let encrypted_db = encrypted_db.unwrap();
+    // 옮겨 간 복구 코드 슬롯도 같은 볼트 비밀 키를 꺼냄
+    if expected >= FormatVersion::V0_2_4 {
+        let code = fs::read_to_string(fixture_db_path(version).with_extension("recovery")).unwrap();
+        let recovery_key = RecoveryKey::parse(&code).unwrap();
+        let (sec_key, ..) = key_slot_login(&header, 1, &SlotCredential::RecoveryCode(&recovery_key)).unwrap();
+        assert!(decrypt_db(&encrypted_db, &header, &sec_key).is_ok());
+    }
+    let mut db = decrypt_db(&encrypted_db, &header, &sec_key).unwrap();
+    db.unlock_names(&wrapped_key, &user_key_nonce).unwrap();
+
+    assert_eq!(db.is_metadata_encrypted(), expected >= FormatVersion::V0_2_2);
+    assert_eq!(db.entry_count(), ENTRIES.len());
+    for (site, id, pw) in ENTRIES {
+        let user_pw = get_user_pw(
+            &mut db,
+            &SiteName::new(site).unwrap(),
+            &UserID::new(id).unwrap(),
+            &wrapped_key,
+            &user_key_nonce,
+        )
+        .unwrap();
+        assert_eq!(user_pw.as_str(), pw);
+    }
+
+    let alice = (SiteName::new(ENTRIES[0].0).unwrap(), UserID::new(ENTRIES[0].1).unwrap());
+    let entry = get_entry(&db, &alice.0, &alice.1).unwrap();
+    assert!(entry.created() <= entry.modified());
+    assert!(entry.last_used().is_some());
+    let details = read_entry_details(&db, &alice.0, &alice.1, &wrapped_key, &user_key_nonce).unwrap();
+    if expected < FormatVersion::V0_1_7 {
+        assert!(details.notes.is_empty());
+        assert!(details.urls.is_empty());
+        assert!(details.custom_fields.is_empty());
+    } else {
+        assert_eq!(details.notes.as_str(), "door code is 4321\nask for the second key");
+        assert_eq!(details.urls, ["https://login.example.com"]);
+        let fields: Vec<_> = details
+            .custom_fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.kind, field.value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("recovery email", CustomFieldKind::Email, "alice@example.net"),
+                ("PIN", CustomFieldKind::Hidden, "0000"),
+                ("support", CustomFieldKind::Phone, "+1 555-0100"),
+            ]
+        );
+    }
+
+    let entry = get_entry(&db, &alice.0, &alice.1).unwrap();
+    if expected < FormatVersion::V0_1_8 {
+        assert!(entry.pw_history().is_empty());
+    } else {
+        assert_eq!(entry.pw_history().len(), 2);
+        for (index, pw) in ["old-alice-pw-2", "old-alice-pw-1"].into_iter().enumerate() {
+            let past = get_past_user_pw(&db, &alice.0, &alice.1, index, &wrapped_key, &user_key_nonce).unwrap();
+            assert_eq!(past.as_str(), pw);
+        }
+        assert_eq!(entry.pw_history()[0].replaced_at(), entry.pw_changed());
+        assert_eq!(entry.pw_history()[1].replaced_at(), entry.pw_history()[0].set_at());
+    }
+
+    let otp = read_otp(&db, &alice.0, &alice.1, &wrapped_key, &user_key_nonce).unwrap();
+    if expected < FormatVersion::V0_1_9 {
+        assert!(otp.is_none());
+    } else {
+        let otp = otp.unwrap();
+        assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+        assert_eq!(otp.algorithm, OtpAlgorithm::Sha256);
+        assert_eq!(otp.issuer.as_deref(), Some("Example"));
+        // RFC 6238의 SHA1 키를 SHA256에 씀. 59초는 60초 주기의 첫 구간
+        assert_eq!(otp.code_at(59).as_str(), "74875740");
+    }
+
+    let entry = get_entry(&db, &alice.0, &alice.1).unwrap();
+    let tree = folder_tree(&db);
+    if expected < FormatVersion::V0_2_0 {
+        assert!(entry.tags().is_empty());
+        assert!(entry.folder().is_root());
+        assert!(!entry.is_favourite());
+        assert!(tree.children.is_empty());
+    } else {
+        assert_eq!(entry.tags(), ["shared", "work"]);
+        assert!(entry.has_tag("WORK"));
+        assert_eq!(entry.folder().to_string(), "Clients/Example");
+        assert!(entry.is_favourite());
+
+        let clients = &tree.children["Clients"];
+        assert_eq!((clients.entry_count, clients.total_count), (1, 2));
+        assert_eq!(clients.children["Example"].entry_count, 1);
+        assert_eq!(tree.entry_count, 1);
+
+        let filter = EntryFilter {
+            folder: Some(FolderPath::new("Clients").unwrap()),
+            ..EntryFilter::default()
+        };
+        assert_eq!(filter_sites(db.sites().unwrap(), &filter).len(), 2);
+        let filter = EntryFilter {
+            tags: vec!["Work".to_owned()],
+            favourites_only: true,
+            ..filter
+        };
+        let matched = filter_sites(db.sites().unwrap(), &filter);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].1.len(), 1);
+        assert_eq!(matched[0].1[0].0, alice.1);
+    }
+
+    if expected < FormatVersion::V0_2_1 {
+        assert!(db.trash().is_empty());
+    } else {
+        assert_eq!(db.trash().len(), 1);
+        let dave = db.trashed_names(&db.trash()[0]).unwrap();
+        assert_eq!((dave.0.as_str(), dave.1.as_str()), ("old.example.net", "dave"));
+        assert!(get_entry(&db, &dave.0, &dave.1).is_err());
+        restore_trashed(&mut db, 0).unwrap();
+        let pw = get_user_pw(&mut db, &dave.0, &dave.1, &wrapped_key, &user_key_nonce).unwrap();
+        assert_eq!(pw.as_str(), "dave-old-pw");
+    }
+    assert_eq!(db.trash_retention_days(), DEFAULT_TRASH_RETENTION_DAYS);
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn migrates_0_1_2() {
+    migrate_fixture("0_1_2", FormatVersion::V0_1_2);
+}
+
+#[test]
+fn migrates_0_1_3() {
+    migrate_fixture("0_1_3", FormatVersion::V0_1_3);
+}
+
+#[test]
+fn migrates_0_1_4() {
+    migrate_fixture("0_1_4", FormatVersion::V0_1_4);
+}
+
+#[test]
+fn migrates_0_1_5() {
+    migrate_fixture("0_1_5", FormatVersion::V0_1_5);
+}
+
+#[test]
+fn migrates_0_1_6() {
+    migrate_fixture("0_1_6", FormatVersion::V0_1_6);
+}
+
+#[test]
+fn migrates_0_1_7() {
+    migrate_fixture("0_1_7", FormatVersion::V0_1_7);
+}
+
+#[test]
+fn migrates_0_1_8() {
+    migrate_fixture("0_1_8", FormatVersion::V0_1_8);
+}
+
+#[test]
+fn migrates_0_1_9() {
+    migrate_fixture("0_1_9", FormatVersion::V0_1_9);
+}
+
+#[test]
+fn migrates_0_2_0() {
+    migrate_fixture("0_2_0", FormatVersion::V0_2_0);
+}
+
+#[test]
+fn migrates_0_2_1() {
+    migrate_fixture("0_2_1", FormatVersion::V0_2_1);
+}
+
+#[test]
+fn migrates_0_2_2() {
+    migrate_fixture("0_2_2", FormatVersion::V0_2_2);
+}
+
+#[test]
+fn migrates_0_2_3() {
+    migrate_fixture("0_2_3", FormatVersion::V0_2_3);
+}
+
+#[test]
+fn migrates_0_2_4() {
+    migrate_fixture("0_2_4", FormatVersion::V0_2_4);
+}
+
+#[test]
+fn migrates_0_2_5() {
+    migrate_fixture("0_2_5", FormatVersion::V0_2_5);
+}
+
+#[test]
+fn migrates_0_2_6() {
+    migrate_fixture("0_2_6", FormatVersion::V0_2_6);
+}
+
+#[test]
+fn migrates_0_2_7() {
+    migrate_fixture("0_2_7", FormatVersion::V0_2_7);
+}
+
+#[test]
+fn opens_current_version() {
+    migrate_fixture("0_2_8", FormatVersion::CURRENT);
+}
+
+#[test]
+fn key_file_vault_needs_the_right_key_file() {
+    init_sodium();
+    let bytes = fs::read(fixture_db_path("0_2_3")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+    assert!(matches!(check_key_file(&header, None), Err(MasterPWError::KeyFileRequired)));
+
+    let wrong_key_file = KeyFile::from_bytes(b"not the fixture key file").unwrap();
+    let mut master_pw = FIXTURE_MASTER_PW.to_string();
+    let (sec_key, ..) = general_login(&mut master_pw, &header, Some(&wrong_key_file)).unwrap();
+    assert!(matches!(decrypt_db_payload(&encrypted_db, &header, &sec_key), Err(MasterPWError::IncorrectPW)));
+
+    // 0.2.8부터는 마스터 비밀번호 슬롯을 열 때 바로 거름
+    let bytes = fs::read(fixture_db_path("0_2_8")).unwrap();
+    let (header, _) = DBHeader::parse_header(&bytes).unwrap();
+    let mut master_pw = FIXTURE_MASTER_PW.to_string();
+    assert!(matches!(
+        general_login(&mut master_pw, &header, Some(&wrong_key_file)),
+        Err(MasterPWError::IncorrectPW)
+    ));
+
+    // 키 파일을 쓰지 않는 볼트에 키 파일을 주면 미리 거름
+    let bytes = fs::read(fixture_db_path("0_2_2")).unwrap();
+    let (header, _) = DBHeader::parse_header(&bytes).unwrap();
+    assert!(matches!(check_key_file(&header, Some(&wrong_key_file)), Err(MasterPWError::KeyFileNotUsed)));
+}
+
+#[test]
+fn every_registered_version_reaches_current() {
+    for migration in migrations() {
+        let plan = migration_plan(migration.from).unwrap();
+        assert_eq!(plan.last().unwrap().to, FormatVersion::CURRENT);
+        assert!(plan.windows(2).all(|steps| steps[0].to == steps[1].from));
+    }
+    assert!(migration_plan(FormatVersion::CURRENT).unwrap().is_empty());
+}
+
+#[test]
+fn dry_run_does_not_touch_the_file() {
+    init_sodium();
+    let bytes = fs::read(fixture_db_path("0_1_2")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+
+    let report = dry_run_migration(&header, &encrypted_db, FIXTURE_MASTER_PW, None).unwrap();
+    assert_eq!(report.from, FormatVersion::V0_1_2);
+    assert_eq!(report.site_count, 2);
+    assert_eq!(report.entry_count, ENTRIES.len());
+    assert_eq!(fs::read(fixture_db_path("0_1_2")).unwrap(), bytes);
+
+    assert!(matches!(
+        dry_run_migration(&header, &encrypted_db, "wrong-master-pw", None),
+        Err(MigrationError::MasterPW(_))
+    ));
+}
+
+#[test]
+fn dry_run_uses_the_key_file() {
+    init_sodium();
+    let bytes = fs::read(fixture_db_path("0_2_7")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+    let key_file = fixture_key_file("0_2_7").unwrap();
+
+    let report = dry_run_migration(&header, &encrypted_db, FIXTURE_MASTER_PW, Some(&key_file)).unwrap();
+    assert_eq!(report.from, FormatVersion::V0_2_7);
+    assert_eq!(report.site_count, 2);
+    assert_eq!(report.entry_count, ENTRIES.len());
+    assert_eq!(fs::read(fixture_db_path("0_2_7")).unwrap(), bytes);
+
+    assert!(matches!(
+        dry_run_migration(&header, &encrypted_db, FIXTURE_MASTER_PW, None),
+        Err(MigrationError::MasterPW(MasterPWError::KeyFileRequired))
+    ));
+}
+
+#[test]
+fn backs_up_the_original_before_migrating() {
+    let vault = temp_vault_from_fixture("backup", "0_1_5");
+    let original = fs::read(vault.db_file()).unwrap();
+
+    let (_, header, _) = load_db(&vault).unwrap();
+    let backup_path = backup_before_migration(&vault, &header).unwrap();
+    assert_eq!(backup_path, vault.pre_migration_file(FormatVersion::V0_1_5));
+    assert_eq!(fs::read(&backup_path).unwrap(), original);
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
+
+#[test]
+fn rejects_newer_versions() {
+    let mut bytes = fs::read(fixture_db_path("0_2_8")).unwrap();
+    let version_at = bytes.windows(8).position(|w| w == b"DB Ver: ").unwrap();
+    bytes[version_at..version_at + 18].copy_from_slice(b"DB Ver: 0.2.9.000\n");
+
+    assert!(matches!(
+        DBHeader::parse_header(&bytes),
+        Err(FileIOError::DBVersionMissMatch(v)) if v == FormatVersion::new(0, 2, 9, 0)
+    ));
+}
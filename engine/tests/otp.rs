@@ -0,0 +1,131 @@
+//! RFC 4226 부록 D와 RFC 6238 부록 B의 테스트 벡터, `otpauth://` URI 처리
+
+use engine::data_base::{DB, SiteName, UserID, UserPW, add_user_pw, get_entry};
+use engine::init::sodium_init;
+use engine::otp::{
+    OtpAlgorithm, OtpConfig, OtpError, OtpKind, generate_otp_code, hotp, read_otp, set_otp, totp,
+};
+use engine::user_secrets::{SessionKey, wrap_session_key};
+
+/// RFC 4226의 비밀 키 "12345678901234567890"의 base32
+const RFC_SECRET_BASE32: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+#[test]
+fn hotp_rfc4226_vectors() {
+    let expected = [
+        "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871",
+        "520489",
+    ];
+    for (counter, code) in expected.into_iter().enumerate() {
+        assert_eq!(
+            hotp(b"12345678901234567890", counter as u64, 6, OtpAlgorithm::Sha1).as_str(),
+            code
+        );
+    }
+}
+
+#[test]
+fn totp_rfc6238_vectors() {
+    // 해시마다 키 길이가 다름
+    let secrets: [(OtpAlgorithm, &[u8]); 3] = [
+        (OtpAlgorithm::Sha1, b"12345678901234567890"),
+        (OtpAlgorithm::Sha256, b"12345678901234567890123456789012"),
+        (
+            OtpAlgorithm::Sha512,
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+        ),
+    ];
+    let vectors: [(u64, [&str; 3]); 6] = [
+        (59, ["94287082", "46119246", "90693936"]),
+        (1111111109, ["07081804", "68084774", "25091201"]),
+        (1111111111, ["14050471", "67062674", "99943326"]),
+        (1234567890, ["89005924", "91819424", "93441116"]),
+        (2000000000, ["69279037", "90698825", "38618901"]),
+        (20000000000, ["65353130", "77737706", "47863826"]),
+    ];
+    for (time, codes) in vectors {
+        for ((algorithm, secret), code) in secrets.iter().zip(codes) {
+            assert_eq!(totp(secret, time, 30, 8, *algorithm).as_str(), code, "{} at {}", algorithm, time);
+        }
+    }
+}
+
+#[test]
+fn parses_otpauth_uri() {
+    let otp = OtpConfig::parse(
+        "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+    )
+    .unwrap();
+    assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+    assert_eq!(otp.algorithm, OtpAlgorithm::Sha256);
+    assert_eq!(otp.digits, 8);
+    assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+    assert_eq!(otp.account.as_deref(), Some("john.doe@email.com"));
+    assert_eq!(otp.remaining_secs(61), Some(59));
+
+    // 다시 만든 URI도 같은 코드를 냄
+    let reparsed = OtpConfig::parse(&otp.to_uri()).unwrap();
+    assert_eq!(reparsed.kind, otp.kind);
+    assert_eq!(reparsed.issuer, otp.issuer);
+    assert_eq!(reparsed.account, otp.account);
+    assert_eq!(reparsed.code_at(1234567890).as_str(), otp.code_at(1234567890).as_str());
+
+    // 비밀 키만 주면 기본값의 TOTP
+    let bare = OtpConfig::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+    assert_eq!(bare.kind, OtpKind::Totp { period: 30 });
+    assert_eq!(bare.code_at(59).as_str(), "287082");
+
+    let hotp = OtpConfig::parse(&format!("otpauth://hotp/bob?secret={}&counter=3", RFC_SECRET_BASE32)).unwrap();
+    assert_eq!(hotp.kind, OtpKind::Hotp { counter: 3 });
+    assert_eq!(hotp.code_at(0).as_str(), "969429");
+    assert_eq!(hotp.remaining_secs(0), None);
+}
+
+#[test]
+fn rejects_invalid_uris() {
+    let cases = [
+        ("otpauth://totp/bob?issuer=x", "missing secret"),
+        ("otpauth://totp/bob?secret=not*base32", "invalid secret"),
+        ("otpauth://hotp/bob?secret=GEZDGNBV", "hotp without counter"),
+        ("otpauth://totp/bob?secret=GEZDGNBV&algorithm=MD5", "unknown algorithm"),
+        ("otpauth://totp/bob?secret=GEZDGNBV&digits=4", "too few digits"),
+        ("otpauth://totp/bob?secret=GEZDGNBV&period=0", "zero period"),
+        ("otpauth://motp/bob?secret=GEZDGNBV", "unknown type"),
+        ("otpauth://totp/b%ZZob?secret=GEZDGNBV", "bad percent-encoding"),
+    ];
+    for (uri, why) in cases {
+        assert!(OtpConfig::parse(uri).is_err(), "{}", why);
+    }
+    assert!(matches!(
+        OtpConfig::parse("otpauth://totp/bob?secret=GEZDGNBV&algorithm=MD5"),
+        Err(OtpError::UnknownAlgorithm(_))
+    ));
+}
+
+#[test]
+fn hotp_counter_advances_in_db() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let site = SiteName::new("https://www.example.com").unwrap();
+    let id = UserID::new("alice").unwrap();
+    let mut db = DB::new();
+    add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    assert!(matches!(
+        generate_otp_code(&mut db, &site, &id, &wrapped_key, &user_key_nonce),
+        Err(OtpError::NoOtp)
+    ));
+
+    let otp = OtpConfig::parse(&format!("otpauth://hotp/alice?secret={}&counter=0", RFC_SECRET_BASE32)).unwrap();
+    set_otp(&mut db, &site, &id, Some(&otp), &wrapped_key, &user_key_nonce).unwrap();
+    assert!(get_entry(&db, &site, &id).unwrap().has_otp());
+    for code in ["755224", "287082", "359152"] {
+        let generated = generate_otp_code(&mut db, &site, &id, &wrapped_key, &user_key_nonce).unwrap();
+        assert_eq!(generated.code.as_str(), code);
+        assert_eq!(generated.remaining_secs, None);
+    }
+    let stored = read_otp(&db, &site, &id, &wrapped_key, &user_key_nonce).unwrap().unwrap();
+    assert_eq!(stored.kind, OtpKind::Hotp { counter: 3 });
+
+    set_otp(&mut db, &site, &id, None, &wrapped_key, &user_key_nonce).unwrap();
+    assert!(!get_entry(&db, &site, &id).unwrap().has_otp());
+}
@@ -0,0 +1,111 @@
+//! 강도 추정이 흔한 비밀번호와 키보드, 연속 문자, 날짜 패턴을 잡는지, 새 마스터 비밀번호의 글자 수와 공백 검사
+
+use engine::master_secrets::{MasterPWError, check_new_master_pw};
+use engine::pw_policy::{MasterPWPolicy, PWScore, PWWarning, char_count, estimate_strength};
+
+/// 한글 음절을 첫가끝 자모열로 풀어 씀. 자모로 입력하는 입력기가 보내는 형태
+fn decompose_hangul(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        let code = c as u32;
+        if !(0xAC00..0xAC00 + 11172).contains(&code) {
+            out.push(c);
+            continue;
+        }
+        let index = code - 0xAC00;
+        out.push(char::from_u32(0x1100 + index / (21 * 28)).unwrap());
+        out.push(char::from_u32(0x1161 + index % (21 * 28) / 28).unwrap());
+        if !index.is_multiple_of(28) {
+            out.push(char::from_u32(0x11A7 + index % 28).unwrap());
+        }
+    }
+    out
+}
+
+#[test]
+fn common_passwords_score_low() {
+    for pw in ["password", "123456", "qwerty"] {
+        let estimate = estimate_strength(pw);
+        assert_eq!(estimate.score(), PWScore::VeryWeak, "{}", pw);
+        assert_eq!(estimate.feedback.warning, Some(PWWarning::TopPassword), "{}", pw);
+    }
+    // 대문자나 l33t 치환을 섞어도 흔한 비밀번호로 읽힘
+    for pw in ["Password", "P@ssw0rd", "drowssap"] {
+        let estimate = estimate_strength(pw);
+        assert!(estimate.score() < PWScore::Strong, "{}: {}", pw, estimate.feedback);
+        assert!(
+            matches!(estimate.feedback.warning, Some(PWWarning::TopPassword | PWWarning::SimilarToCommonPassword)),
+            "{}: {}",
+            pw,
+            estimate.feedback
+        );
+    }
+}
+
+#[test]
+fn patterns_score_below_strong() {
+    for (pw, warning) in [
+        ("sdfghjkl;'", PWWarning::KeyboardPattern),
+        ("zxcvbnm,./", PWWarning::KeyboardPattern),
+        ("mnopqrstuv", PWWarning::Sequence),
+        ("9876543210", PWWarning::Sequence),
+        ("aaaaaaaaaaaa", PWWarning::Repeat),
+        ("1991-03-15", PWWarning::Date),
+        ("15.03.1991", PWWarning::Date),
+    ] {
+        let estimate = estimate_strength(pw);
+        assert!(estimate.score() < PWScore::Strong, "{}: {}", pw, estimate.feedback);
+        assert_eq!(estimate.feedback.warning, Some(warning), "{}: {}", pw, estimate.feedback);
+    }
+
+    // 같은 길이의 무작위 문자열은 훨씬 강함
+    let random = estimate_strength("Tq8#vL2m!xZ9@wR4");
+    assert_eq!(random.score(), PWScore::VeryStrong, "{}", random.feedback);
+    assert_eq!(random.feedback.warning, None);
+    assert!(random.guesses_log10 > estimate_strength("qwertyuiopasdfgh").guesses_log10);
+}
+
+#[test]
+fn hangul_jamo_counts_per_syllable() {
+    let syllables = "비밀번호는한글로";
+    let jamo = decompose_hangul(syllables);
+    assert_ne!(jamo, syllables);
+    assert_eq!(jamo.chars().count(), 21);
+    assert_eq!(char_count(syllables), 8);
+    assert_eq!(char_count(&jamo), 8);
+    // 입력 방법이 달라도 강도는 같음
+    assert_eq!(estimate_strength(&jamo).guesses_log10, estimate_strength(syllables).guesses_log10);
+    // 자모만 있는 낱자는 그대로 셈
+    assert_eq!(char_count("\u{1100}\u{1100}"), 2);
+}
+
+#[test]
+fn new_master_pw_whitespace() {
+    let policy = MasterPWPolicy::default();
+    let strong = "Tq8#vL2m!xZ9@wR4";
+    assert!(check_new_master_pw(strong, &policy).is_ok());
+    assert!(check_new_master_pw(&format!("{} {}", strong, strong), &policy).is_ok());
+    // 앞뒤 공백과 줄바꿈은 잘라냄
+    assert!(check_new_master_pw(&format!("{}\n", strong), &policy).is_ok());
+
+    for pw in [format!("{}\t{}", strong, strong), format!("{}\u{3000}{}", strong, strong), format!("{}\u{a0}{}", strong, strong)] {
+        assert_eq!(check_new_master_pw(&pw, &policy).unwrap_err(), MasterPWError::ContainsWhitespace, "{:?}", pw);
+    }
+    assert_eq!(check_new_master_pw(" \n", &policy).unwrap_err(), MasterPWError::Empty);
+}
+
+#[test]
+fn too_short_counts_characters() {
+    let policy = MasterPWPolicy { min_chars: 8, min_score: PWScore::VeryWeak };
+    // 일곱 음절은 21바이트지만 일곱 글자임
+    let seven = "가나다라마바사";
+    assert!(seven.len() > 8);
+    assert_eq!(check_new_master_pw(seven, &policy).unwrap_err(), MasterPWError::TooShort(8));
+    assert_eq!(check_new_master_pw(&decompose_hangul(seven), &policy).unwrap_err(), MasterPWError::TooShort(8));
+    assert!(check_new_master_pw("가나다라마바사아", &policy).is_ok());
+    assert!(check_new_master_pw(&decompose_hangul("가나다라마바사아"), &policy).is_ok());
+
+    assert_eq!(check_new_master_pw("abcdefg", &policy).unwrap_err(), MasterPWError::TooShort(8));
+    let err = check_new_master_pw("password", &MasterPWPolicy::default()).unwrap_err();
+    assert!(matches!(err, MasterPWError::TooWeak(feedback) if feedback.score == PWScore::VeryWeak));
+}
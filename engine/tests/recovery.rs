@@ -0,0 +1,98 @@
+//! 복구 코드로 열기, 마스터 비밀번호와 KDF를 바꾼 뒤에도 같은 코드로 열리는지, 다시 만들거나 없애기
+
+mod common;
+
+use common::{MASTER_PW, TestLogin, db_with_alice, fixture_path, opens_fixture, opens_with, temp_vault, test_login};
+use engine::header::DBHeader;
+use engine::init::sodium_init;
+use engine::kdf::KdfParams;
+use engine::key_slots::{
+    KeySlotError, KeySlotKind, SlotCredential, add_recovery_code_slot, key_slot_login, list_key_slots, revoke_key_slot,
+};
+use engine::master_secrets::{MasterPWError, apply_kdf_upgrade, change_master_pw};
+use engine::recovery::{RecoveryError, RecoveryKey, emergency_kit};
+use std::fs;
+use std::path::Path;
+
+/// 0.2.7 이전의 복구 코드 슬롯이 옮겨 가는 번호
+const RECOVERY_SLOT: usize = 1;
+
+#[test]
+fn fixture_opens_with_its_recovery_code() {
+    let _ = sodium_init();
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+    assert_eq!(list_key_slots(&header)[RECOVERY_SLOT].kind, KeySlotKind::RecoveryCode);
+    let code = fs::read_to_string(fixture_path("db_0_2_8.recovery")).unwrap();
+
+    // 키 파일을 요구하는 볼트도 복구 코드만으로 열림
+    assert!(header.requires_key_file());
+    let recovery_key = RecoveryKey::parse(&code).unwrap();
+    assert!(opens_fixture(&header, &encrypted_db, RECOVERY_SLOT, &SlotCredential::RecoveryCode(&recovery_key)));
+
+    // 대소문자와 구분 기호는 가리지 않음
+    let loose = RecoveryKey::parse(&code.trim().replace('-', " ").to_lowercase()).unwrap();
+    assert!(key_slot_login(&header, RECOVERY_SLOT, &SlotCredential::RecoveryCode(&loose)).is_ok());
+
+    assert!(matches!(RecoveryKey::parse("ABCD-EFGH"), Err(RecoveryError::InvalidCode)));
+    assert!(matches!(RecoveryKey::parse("1111-1111-1111-1111-1111-1111-1111-1111"), Err(RecoveryError::InvalidCode)));
+    let wrong = RecoveryKey::parse("AAAA-AAAA-AAAA-AAAA-AAAA-AAAA-AAAA-AAAA").unwrap();
+    assert!(matches!(
+        key_slot_login(&header, RECOVERY_SLOT, &SlotCredential::RecoveryCode(&wrong)),
+        Err(KeySlotError::IncorrectCredential)
+    ));
+
+    let bytes = fs::read(fixture_path("db_0_2_3.bin")).unwrap();
+    let (header, _) = DBHeader::parse_header(&bytes).unwrap();
+    assert!(matches!(
+        key_slot_login(&header, RECOVERY_SLOT, &SlotCredential::RecoveryCode(&wrong)),
+        Err(KeySlotError::NoSuchSlot(RECOVERY_SLOT))
+    ));
+}
+
+#[test]
+fn recovery_code_survives_key_changes_until_replaced() {
+    let _ = sodium_init();
+    let vault = temp_vault("recovery");
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+
+    let mut wrong_pw = "not the master pw".to_string();
+    assert!(matches!(
+        add_recovery_code_slot(&mut header, &mut wrong_pw, None, &pub_key, "recovery code"),
+        Err(KeySlotError::MasterPW(MasterPWError::IncorrectPW))
+    ));
+    assert_eq!(list_key_slots(&header).len(), 1);
+    let mut master_pw = MASTER_PW.to_string();
+    let (index, recovery_key) = add_recovery_code_slot(&mut header, &mut master_pw, None, &pub_key, "recovery code").unwrap();
+    assert!(master_pw.is_empty());
+    let code = recovery_key.to_code();
+    let kit = emergency_kit(&recovery_key, index, Path::new("db.bin"));
+    assert!(kit.contains(code.as_str()) && kit.contains(&format!("--slot {}", index)));
+
+    assert!(opens_with(index, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+
+    // 복구 코드로 연 뒤 새 마스터 비밀번호를 정하는 경우
+    let mut new_master_pw = "another master password".to_string();
+    change_master_pw(&mut header, &mut new_master_pw, None, &sec_key);
+    assert!(opens_with(index, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+
+    header.request_kdf_upgrade(KdfParams::argon2id(9 * 1024, 1, 1).unwrap());
+    assert!(apply_kdf_upgrade(&mut header, "another master password", None, &sec_key));
+    assert!(opens_with(index, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+
+    // 새로 만든 코드는 다른 슬롯에 들어가고, 전의 코드는 그 슬롯을 비워야 쓸 수 없음
+    let mut master_pw = "another master password".to_string();
+    let (new_index, new_recovery_key) =
+        add_recovery_code_slot(&mut header, &mut master_pw, None, &pub_key, "new recovery code").unwrap();
+    assert!(!opens_with(new_index, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(new_index, &SlotCredential::RecoveryCode(&new_recovery_key), &vault, &db, &mut header, &pub_key));
+
+    let mut master_pw = "another master password".to_string();
+    revoke_key_slot(&mut header, &mut master_pw, None, &pub_key, index).unwrap();
+    assert!(!opens_with(index, &SlotCredential::RecoveryCode(&recovery_key), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(new_index, &SlotCredential::RecoveryCode(&new_recovery_key), &vault, &db, &mut header, &pub_key));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
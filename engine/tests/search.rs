@@ -0,0 +1,119 @@
+//! 검색 색인의 순위와 강조 범위
+
+use engine::data_base::{
+    DB, DBIOError, EntryDetails, SiteName, UserID, UserPW, add_user_pw, get_user_pw, remove_user_pw, set_tags,
+    update_entry_details,
+};
+use engine::init::sodium_init;
+use engine::search::{SearchField, SearchIndex, fuzzy_match};
+use engine::trash::restore_trashed;
+use std::ops::Range;
+use engine::user_secrets::{SessionKey, wrap_session_key};
+
+#[test]
+fn fuzzy_match_highlights_scattered_characters() {
+    let (_, ranges) = fuzzy_match("gml", "gmail.com").unwrap();
+    assert_eq!(ranges, [0..2, 4..5]);
+    assert!(fuzzy_match("gmx", "gmail.com").is_none());
+
+    // 연속으로 맞으면 흩어져 맞는 것보다 점수가 높음
+    let (contiguous, ranges) = fuzzy_match("MAIL", "gmail.com").unwrap();
+    assert_eq!(ranges, [Range { start: 1, end: 5 }]);
+    let (scattered, _) = fuzzy_match("mail", "m-a-i-l.com").unwrap();
+    assert!(contiguous > scattered);
+}
+
+#[test]
+fn searches_sites_ids_tags_and_notes() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let mut db = DB::new();
+    for (site, id) in [
+        ("https://mail.google.com", "alice@gmail.com"),
+        ("https://www.example.com", "bob"),
+        ("https://mail.example.org", "carol"),
+    ] {
+        add_user_pw(&mut db, SiteName::new(site).unwrap(), UserID::new(id).unwrap(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    }
+    let bob = (SiteName::new("https://www.example.com").unwrap(), UserID::new("bob").unwrap());
+    let details = EntryDetails {
+        notes: "first line\nthe mailbox key is under the mat".to_string().into(),
+        ..EntryDetails::default()
+    };
+    update_entry_details(&mut db, &bob.0, &bob.1, &details, &wrapped_key, &user_key_nonce).unwrap();
+    set_tags(&mut db, &bob.0, &bob.1, &["Family"]).unwrap();
+
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    assert!(index.is_current(&db));
+
+    // 접두어가 아니어도 찾고, 메모보다 사이트가 앞에 옴
+    let hits = index.search("mail");
+    let order: Vec<_> = hits.iter().map(|hit| hit.id.as_str()).collect();
+    assert_eq!(order.len(), 3);
+    assert_eq!(order[2], "bob");
+    let notes = hits[2].field(SearchField::Notes).unwrap();
+    assert_eq!(notes.text.as_str(), "the mailbox key is under the mat");
+    assert_eq!(notes.ranges, [Range { start: 4, end: 8 }]);
+
+    let hits = index.search("gmail");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].field(SearchField::UserID).unwrap().ranges, [Range { start: 6, end: 11 }]);
+
+    // 검색어는 모두 맞아야 함
+    assert_eq!(index.search("family mat").len(), 1);
+    assert!(index.search("family gmail").is_empty());
+    assert!(index.search("   ").is_empty());
+
+    add_user_pw(&mut db, SiteName::new("https://gitlab.com").unwrap(), UserID::new("dave").unwrap(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    assert!(!index.is_current(&db));
+}
+
+#[test]
+fn index_goes_stale_on_same_second_edits() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let mut db = DB::new();
+    let bob = (SiteName::new("https://www.example.com").unwrap(), UserID::new("bob").unwrap());
+    add_user_pw(&mut db, bob.0.clone(), bob.1.clone(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+
+    // 같은 초 안에 고쳐서 계정 수와 수정 시각이 그대로여도 낡은 색인으로 봄
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    set_tags(&mut db, &bob.0, &bob.1, &["work"]).unwrap();
+    assert!(!index.is_current(&db));
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    set_tags(&mut db, &bob.0, &bob.1, &["home"]).unwrap();
+    assert!(!index.is_current(&db));
+
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    remove_user_pw(&mut db, &bob.0, &bob.1).unwrap();
+    restore_trashed(&mut db, 0).unwrap();
+    assert!(!index.is_current(&db));
+
+    // 고치지 않고 읽기만 하면 그대로 씀
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+    assert_eq!(index.search("home").len(), 1);
+    assert!(index.is_current(&db));
+    // 다른 DB로 바꿔 끼우면 내용이 같아도 다시 만듦
+    assert!(!index.is_current(&DB::new()));
+}
+
+#[test]
+fn reads_and_failed_lookups_keep_the_index() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let mut db = DB::new();
+    let bob = (SiteName::new("https://www.example.com").unwrap(), UserID::new("bob").unwrap());
+    add_user_pw(&mut db, bob.0.clone(), bob.1.clone(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    set_tags(&mut db, &bob.0, &bob.1, &["work"]).unwrap();
+    let index = SearchIndex::build(&db, &wrapped_key, &user_key_nonce).unwrap();
+
+    // 비밀번호를 꺼내면 마지막 사용 시각만 바뀜
+    assert_eq!(get_user_pw(&mut db, &bob.0, &bob.1, &wrapped_key, &user_key_nonce).unwrap().as_str(), "pw");
+    assert!(index.is_current(&db));
+    let carol = UserID::new("carol").unwrap();
+    assert!(matches!(set_tags(&mut db, &bob.0, &carol, &["home"]), Err(DBIOError::UserNotFound)));
+    assert!(matches!(get_user_pw(&mut db, &bob.0, &carol, &wrapped_key, &user_key_nonce), Err(DBIOError::UserNotFound)));
+    // 같은 태그로 다시 정하면 바뀐 것이 없음
+    set_tags(&mut db, &bob.0, &bob.1, &["work"]).unwrap();
+    assert!(index.is_current(&db));
+}
@@ -0,0 +1,161 @@
+//! 비밀을 조각으로 나누고 합치기, 조각 텍스트의 체크섬, 조각으로 볼트 열기
+
+mod common;
+
+use common::{MASTER_PW, TestLogin, db_with_alice, fixture_path, opens_fixture, opens_with, temp_vault, test_login};
+use engine::header::DBHeader;
+use engine::init::sodium_init;
+use engine::key_slots::{
+    KeySlotError, KeySlotKind, SlotCredential, add_recovery_shares_slot, key_slot_login, list_key_slots, revoke_key_slot,
+};
+use engine::master_secrets::{MasterPWError, change_master_pw};
+use engine::recovery::share_sheet;
+use engine::shamir::{SHARE_SECRET_SIZE, ShamirError, Share, combine_shares, split_secret};
+use std::fs;
+use std::path::Path;
+
+/// 0.2.7 이전의 조각 슬롯이 옮겨 가는 번호
+const SHARES_SLOT: usize = 2;
+
+/// `Share`는 복사되지 않으므로 텍스트에서 다시 읽음
+fn pick(texts: &[String], indices: &[usize]) -> Vec<Share> {
+    indices.iter().map(|&i| Share::parse(&texts[i]).unwrap()).collect()
+}
+
+#[test]
+fn any_threshold_shares_recover_the_secret() {
+    let _ = sodium_init();
+    let secret: [u8; SHARE_SECRET_SIZE] = std::array::from_fn(|i| (i * 37 + 11) as u8);
+    let shares = split_secret(&secret, 3, 5).unwrap();
+    assert_eq!(shares.len(), 5);
+    assert!(shares.iter().all(|share| share.threshold() == 3));
+    assert_eq!(shares.iter().map(Share::index).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+    let texts: Vec<String> = shares.iter().map(|share| share.to_text().to_string()).collect();
+
+    for a in 0..5 {
+        for b in a + 1..5 {
+            for c in b + 1..5 {
+                assert_eq!(*combine_shares(&pick(&texts, &[c, a, b])).unwrap(), secret);
+            }
+        }
+    }
+    assert_eq!(*combine_shares(&pick(&texts, &[4, 0, 2, 1, 3])).unwrap(), secret);
+
+    assert_eq!(
+        combine_shares(&pick(&texts, &[0, 3])).err(),
+        Some(ShamirError::NotEnoughShares { needed: 3, given: 2 })
+    );
+    assert_eq!(combine_shares(&pick(&texts, &[0, 1, 1])).err(), Some(ShamirError::DuplicateShare(2)));
+    let other: Vec<String> = split_secret(&secret, 3, 5).unwrap().iter().map(|share| share.to_text().to_string()).collect();
+    let mut mixed = pick(&texts, &[0, 1]);
+    mixed.push(Share::parse(&other[2]).unwrap());
+    assert_eq!(combine_shares(&mixed).err(), Some(ShamirError::MixedShares));
+
+    assert_eq!(split_secret(&secret, 1, 5).err(), Some(ShamirError::InvalidThreshold));
+    assert_eq!(split_secret(&secret, 4, 3).err(), Some(ShamirError::InvalidThreshold));
+    assert_eq!(split_secret(&secret, 255, 255).unwrap().len(), 255);
+}
+
+#[test]
+fn share_text_catches_typos() {
+    let _ = sodium_init();
+    let secret = [7u8; SHARE_SECRET_SIZE];
+    let text = split_secret(&secret, 2, 2).unwrap()[0].to_text().to_string();
+
+    // 대소문자와 구분 기호는 가리지 않음
+    let loose = text.replace('-', " ").to_lowercase();
+    assert_eq!(Share::parse(&loose).unwrap().to_text().as_str(), text);
+
+    // 한 글자를 다른 base32 글자로 바꿔 적은 경우
+    let mut typo: Vec<char> = text.chars().collect();
+    typo[20] = if typo[20] == 'A' { 'B' } else { 'A' };
+    let typo: String = typo.into_iter().collect();
+    assert_eq!(Share::parse(&typo).err(), Some(ShamirError::ChecksumMismatch));
+
+    assert_eq!(Share::parse(&text[..text.len() - 5]).err(), Some(ShamirError::InvalidShare));
+    assert_eq!(Share::parse("1111-1111").err(), Some(ShamirError::InvalidShare));
+}
+
+#[test]
+fn fixture_opens_with_any_three_shares() {
+    let _ = sodium_init();
+    let bytes = fs::read(fixture_path("db_0_2_8.bin")).unwrap();
+    let (header, encrypted_db) = DBHeader::parse_header(&bytes).unwrap();
+    assert_eq!(list_key_slots(&header)[SHARES_SLOT].kind, KeySlotKind::RecoveryShares);
+    let texts: Vec<String> = fs::read_to_string(fixture_path("db_0_2_8.shares"))
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    assert_eq!(texts.len(), 5);
+
+    // 키 파일을 요구하는 볼트도 조각만으로 열림
+    assert!(header.requires_key_file());
+    let shares = pick(&texts, &[4, 0, 2]);
+    assert!(opens_fixture(&header, &encrypted_db, SHARES_SLOT, &SlotCredential::Shares(&shares)));
+
+    let shares = pick(&texts, &[1, 3]);
+    assert!(matches!(
+        key_slot_login(&header, SHARES_SLOT, &SlotCredential::Shares(&shares)),
+        Err(KeySlotError::Shares(ShamirError::NotEnoughShares { needed: 3, given: 2 }))
+    ));
+    let stranger = split_secret(&[0u8; SHARE_SECRET_SIZE], 3, 5).unwrap();
+    assert!(matches!(
+        key_slot_login(&header, SHARES_SLOT, &SlotCredential::Shares(&stranger)),
+        Err(KeySlotError::IncorrectCredential)
+    ));
+
+    let bytes = fs::read(fixture_path("db_0_2_4.bin")).unwrap();
+    let (header, _) = DBHeader::parse_header(&bytes).unwrap();
+    assert!(matches!(
+        key_slot_login(&header, SHARES_SLOT, &SlotCredential::Shares(&stranger)),
+        Err(KeySlotError::NoSuchSlot(SHARES_SLOT))
+    ));
+}
+
+#[test]
+fn shares_survive_master_pw_change_until_revoked() {
+    let _ = sodium_init();
+    let vault = temp_vault("shares");
+    let login = test_login();
+    let db = db_with_alice(&login);
+    let TestLogin { mut header, sec_key, pub_key, .. } = login;
+
+    let mut wrong_pw = "not the master pw".to_string();
+    assert!(matches!(
+        add_recovery_shares_slot(&mut header, &mut wrong_pw, None, &pub_key, "family", 2, 3),
+        Err(KeySlotError::MasterPW(MasterPWError::IncorrectPW))
+    ));
+    let mut master_pw = MASTER_PW.to_string();
+    assert!(matches!(
+        add_recovery_shares_slot(&mut header, &mut master_pw, None, &pub_key, "family", 4, 3),
+        Err(KeySlotError::Shares(ShamirError::InvalidThreshold))
+    ));
+    assert_eq!(list_key_slots(&header).len(), 1);
+
+    let mut master_pw = MASTER_PW.to_string();
+    let (index, shares) = add_recovery_shares_slot(&mut header, &mut master_pw, None, &pub_key, "family", 2, 3).unwrap();
+    assert!(master_pw.is_empty());
+    assert_eq!(list_key_slots(&header)[index].kind, KeySlotKind::RecoveryShares);
+    let texts: Vec<String> = shares.iter().map(|share| share.to_text().to_string()).collect();
+    let sheet = share_sheet(&shares[1], shares.len(), index, Path::new("db.bin"));
+    assert!(sheet.contains(&texts[1]) && sheet.contains(&format!("--slot {}", index)));
+    assert!(opens_with(index, &SlotCredential::Shares(&pick(&texts, &[0, 2])), &vault, &db, &mut header, &pub_key));
+
+    let mut new_master_pw = "another master password".to_string();
+    change_master_pw(&mut header, &mut new_master_pw, None, &sec_key);
+    assert!(opens_with(index, &SlotCredential::Shares(&pick(&texts, &[2, 1])), &vault, &db, &mut header, &pub_key));
+
+    // 다른 조각 묶음은 다른 슬롯에 들어가므로 전의 조각으로는 새 슬롯을 열 수 없음
+    let mut master_pw = "another master password".to_string();
+    let (new_index, new_shares) = add_recovery_shares_slot(&mut header, &mut master_pw, None, &pub_key, "friends", 2, 2).unwrap();
+    assert!(!opens_with(new_index, &SlotCredential::Shares(&pick(&texts, &[0, 1])), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(new_index, &SlotCredential::Shares(&new_shares), &vault, &db, &mut header, &pub_key));
+
+    let mut master_pw = "another master password".to_string();
+    revoke_key_slot(&mut header, &mut master_pw, None, &pub_key, index).unwrap();
+    assert!(!opens_with(index, &SlotCredential::Shares(&pick(&texts, &[0, 1])), &vault, &db, &mut header, &pub_key));
+    assert!(opens_with(new_index, &SlotCredential::Shares(&new_shares), &vault, &db, &mut header, &pub_key));
+
+    fs::remove_dir_all(vault.dir()).unwrap();
+}
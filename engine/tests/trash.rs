@@ -0,0 +1,75 @@
+//! 휴지통으로 옮기기, 되살리기, 보관 기간이 지난 항목 지우기
+
+mod common;
+
+use common::{TestLogin, test_login};
+use engine::data_base::{
+    DB, DBIOError, SiteName, Timestamp, UserID, UserPW, add_user_pw, get_entry, get_user_pw,
+    remove_user_pw,
+};
+use engine::init::sodium_init;
+use engine::master_secrets::{change_master_pw, general_login};
+use engine::trash::{purge_older_than, purge_trashed, restore_trashed, set_trash_retention_days};
+use engine::user_secrets::{SessionKey, wrap_session_key};
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[test]
+fn removed_entries_can_be_restored_after_rekey() {
+    let _ = sodium_init();
+    let TestLogin { mut header, sec_key, wrapped_key, user_key_nonce, .. } = test_login();
+    let site = SiteName::new("https://www.example.com").unwrap();
+    let id = UserID::new("alice").unwrap();
+    let mut db = DB::new();
+    add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw-1").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+
+    remove_user_pw(&mut db, &site, &id).unwrap();
+    assert_eq!(db.entry_count(), 0);
+    assert_eq!(db.trash().len(), 1);
+    assert_eq!(db.trashed_names(&db.trash()[0]).unwrap().0, site);
+
+    // 같은 계정을 다시 만들면 되살릴 수 없음
+    add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw-2").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+    assert!(matches!(restore_trashed(&mut db, 0), Err(DBIOError::UserAlreadyExists)));
+    remove_user_pw(&mut db, &site, &id).unwrap();
+    assert_eq!(db.trash().len(), 2);
+
+    // 마스터 비밀번호를 바꾼 뒤 다시 로그인해도 휴지통의 항목을 같은 세션 키로 엶
+    let mut new_master_pw = "another master password".to_string();
+    change_master_pw(&mut header, &mut new_master_pw, None, &sec_key);
+    let mut new_master_pw = "another master password".to_string();
+    let (_, _, wrapped_key, user_key_nonce) = general_login(&mut new_master_pw, &header, None).unwrap();
+
+    // 최신순이므로 1번이 처음 지운 것
+    restore_trashed(&mut db, 1).unwrap();
+    assert_eq!(get_user_pw(&mut db, &site, &id, &wrapped_key, &user_key_nonce).unwrap().as_str(), "pw-1");
+    purge_trashed(&mut db, 0).unwrap();
+    assert!(db.trash().is_empty());
+    assert!(matches!(purge_trashed(&mut db, 0), Err(DBIOError::TrashedEntryNotFound)));
+}
+
+#[test]
+fn purges_entries_past_retention() {
+    let _ = sodium_init();
+    let (wrapped_key, user_key_nonce) = wrap_session_key(SessionKey::gen_rand());
+    let site = SiteName::new("https://www.example.com").unwrap();
+    let mut db = DB::new();
+    for id in ["alice", "bob"] {
+        let id = UserID::new(id).unwrap();
+        add_user_pw(&mut db, site.clone(), id.clone(), UserPW::new("pw").unwrap(), &wrapped_key, &user_key_nonce).unwrap();
+        remove_user_pw(&mut db, &site, &id).unwrap();
+    }
+    let deleted = db.trash()[0].deleted_at().unix_secs();
+
+    set_trash_retention_days(&mut db, 7);
+    assert_eq!(purge_older_than(&mut db, Timestamp::from_unix_secs(deleted + 6 * DAY)), 0);
+
+    // 0이면 기간이 지나도 남김
+    set_trash_retention_days(&mut db, 0);
+    assert_eq!(purge_older_than(&mut db, Timestamp::from_unix_secs(deleted + 365 * DAY)), 0);
+
+    set_trash_retention_days(&mut db, 7);
+    assert_eq!(purge_older_than(&mut db, Timestamp::from_unix_secs(deleted + 8 * DAY)), 2);
+    assert!(db.trash().is_empty());
+    assert!(get_entry(&db, &site, &UserID::new("alice").unwrap()).is_err());
+}
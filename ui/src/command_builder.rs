@@ -4,6 +4,7 @@ use eframe::egui::{Ui, Vec2, ViewportBuilder};
 use engine::data_base::DB;
 use zeroize::Zeroize;
 use crate::graphical_user_interface::KeyPair;
+use crate::window::PasswordGenerator;
 
 // 하나의 입력 필드를 표현
 pub struct InputField<'a> {
@@ -20,6 +21,7 @@ pub struct CommandBuilder<'a, Output> {
     database: Option<&'a mut DB>,
     key: Option<&'a KeyPair>,
     key_mut: Option<&'a mut KeyPair>,
+    generator: Option<&'a mut PasswordGenerator>,
     on_success: Box<dyn FnMut(Output) + 'a>,
     // execute closure를 저장
     #[allow(clippy::complexity)]
@@ -49,6 +51,7 @@ impl<'a, Output> CommandBuilder<'a, Output> {
             database: None,
             key: None,
             key_mut: None,
+            generator: None,
             on_success: Box::new(|_| {}),
             execute: None,
             size: size.unwrap_or(Vec2::new(800.0, 600.0)),
@@ -106,6 +109,12 @@ impl<'a, Output> CommandBuilder<'a, Output> {
         self
     }
 
+    /// 마지막 sensitive_input을 생성기로 채울 수 있게 함
+    pub fn password_generator(mut self, generator: &'a mut PasswordGenerator) -> Self {
+        self.generator = Some(generator);
+        self
+    }
+
     pub fn on_success<F>(mut self, callback: F) -> Self
     where
         F: FnMut(Output) + 'a,
@@ -170,6 +179,20 @@ impl<'a, Output> CommandBuilderWithError<'a, Output> {
                         }
                     }
 
+                    if let Some(generator) = self.inner.generator.as_deref_mut() {
+                        if ui.button("generator").clicked() {
+                            generator.open();
+                        }
+                        if generator.is_open() {
+                            if let Some(password) = generator.display(ui, self.inner.title, #[cfg(target_os = "windows")] self.inner.center) {
+                                if let Some(field) = self.inner.inputs.iter_mut().rev().find(|field| field.want_zeroize) {
+                                    field.value.zeroize();
+                                    field.value.push_str(password.as_str());
+                                }
+                            }
+                        }
+                    }
+
                     ui.label(&*self.error_message);
 
                     if ui.button("submit").clicked() || ui.input(|input| input.key_pressed(egui::Key::Enter)) {
@@ -2,17 +2,24 @@ use std::{
     collections::{BTreeMap, HashMap},
     error::Error,
     fmt::Display,
+    ops::Range,
+    rc::Rc,
     time::{Instant, Duration},
 };
 use eframe::egui::{self, Pos2, Ui, ViewportBuilder, ViewportCommand, ViewportId};
+use eframe::egui::text::{LayoutJob, TextFormat};
 use eframe::wgpu::rwh::{HasDisplayHandle, HasRawWindowHandle, HasWindowHandle};
 use zeroize::Zeroize;
 use engine::{
-    data_base::{DB, SiteName, UserID, get_user_pw, prefix_range},
-    file_io::{FileIOError, load_db, mark_as_graceful_exited_to_file, save_db},
+    data_base::{DB, FolderPath, SiteName, UserID, get_entry, get_user_pw},
+    file_io::{FileIOError, FileIOWarn, VaultHandle, load_db, mark_as_graceful_exited_to_file, mark_as_ungraceful_exited_to_file, save_db},
     header::DBHeader,
-    master_secrets::encrypt_db,
-    sodium::rust_wrappings::x25519::PubKey,
+    key_file::KeyFile,
+    master_secrets::{encrypt_db, EncryptedDB},
+    metadata::{disable_metadata_encryption, enable_metadata_encryption},
+    organize::{EntryFilter, FolderNode, filter_sites, folder_tree, tag_counts},
+    search::{SearchField, SearchHit, SearchIndex},
+    sodium::rust_wrappings::x25519::{PubKey, SecKey},
     user_secrets::{SessionKeyNonce, WrappedSessionKey},
 };
 use engine::file_io::check_can_directly_exit;
@@ -20,25 +27,39 @@ use crate::window::{
     exit_root,
     AddUserPassword,
     AddUserPasswordWithSiteName,
+    AuditDashboard,
+    Backups,
+    BackupsType,
     ChangeMasterPassword,
     ChangeUserPassword,
     ChangeUserPasswordWithSiteName,
     ChangeUserPasswordWithSiteNameWithUserIdentifier,
+    EditEntry,
     ExistingUser,
     FirstLogin,
+    KeyFileSettings,
+    KeySlotsSettings,
+    PasswordHistory,
+    QuarantineRecovery,
+    QuarantineRecoveryType,
     RemoveUserPassword,
     RemoveUserPasswordWithSiteName,
     RemoveUserPasswordWithSiteNameWithUserIdentifier,
     RootSave,
     RootSaveType,
+    Trash,
+    VaultPicker,
 };
 
 pub type KeyPair = (WrappedSessionKey, SessionKeyNonce);
 
+const SIDEBAR_WIDTH: f32 = 180.0;
+
 #[derive(Debug)]
 enum SaveError {
     FileIOError(FileIOError),
     NotingPublicKey,
+    ReadOnly,
 }
 
 impl Display for SaveError {
@@ -68,6 +89,8 @@ struct WindowOpenList {
     change_user_password: Option<ChangeUserPassword>,
     remove_user_password: Option<RemoveUserPassword>,
     change_master_password: Option<ChangeMasterPassword>,
+    key_file_settings: Option<KeyFileSettings>,
+    key_slots_settings: Option<KeySlotsSettings>,
     add_user_password_with_site_name: BTreeMap<SiteName, AddUserPasswordWithSiteName>,
     change_user_password_with_site_name: BTreeMap<SiteName, ChangeUserPasswordWithSiteName>,
     remove_user_password_with_site_name: BTreeMap<SiteName, RemoveUserPasswordWithSiteName>,
@@ -75,8 +98,15 @@ struct WindowOpenList {
     BTreeMap<SiteName, HashMap<UserID, ChangeUserPasswordWithSiteNameWithUserIdentifier>>,
     remove_user_password_with_site_name_with_user_identifier:
     BTreeMap<SiteName, HashMap<UserID, RemoveUserPasswordWithSiteNameWithUserIdentifier>>,
+    edit_entry: BTreeMap<SiteName, HashMap<UserID, EditEntry>>,
+    password_history: BTreeMap<SiteName, HashMap<UserID, PasswordHistory>>,
     existing_user: Option<ExistingUser>,
     first_login: Option<FirstLogin>,
+    quarantine_recovery: Option<QuarantineRecovery>,
+    vault_picker: Option<VaultPicker>,
+    backups: Option<Backups>,
+    audit_dashboard: Option<AuditDashboard>,
+    trash: Option<Trash>,
     user_state: UserState,
 }
 
@@ -88,7 +118,11 @@ struct MasterLogin {
 #[derive(Default)]
 struct StringValues {
     search_data_base: String,
+    /// 계정 창의 유출 확인에 쓰는 로컬 HIBP 목록 경로
+    hibp_path: String,
     save_data_base_label: String,
+    /// 이름 암호화를 켜거나 끄다 난 오류
+    encrypt_names_error: String,
     master_login: MasterLogin,
 }
 
@@ -99,18 +133,87 @@ pub struct GraphicalUserInterface {
     not_first_frame: bool,
     string_values: StringValues,
     window_open_list: WindowOpenList,
+    /// 사이드바에서 고른 폴더, 태그, 즐겨찾기. 검색어와 함께 사이트 목록을 거름
+    entry_filter: EntryFilter,
+    /// 검색을 시작할 때 만들고 DB가 바뀌면 다시 만듦. 복호화한 이름과 메모가 들어 있어서 검색어를 지우면 버림
+    search_index: Option<SearchIndex>,
     data_base: DB,
     data_base_header: DBHeader,
+    /// 볼트 선택 창에서 고르기 전까지는 `None`. 고른 뒤로는 종료할 때까지 볼트를 잠가 둠
+    vault: Option<Rc<VaultHandle>>,
     key: Option<KeyPair>,
+    /// 볼트 비밀 키. 마스터 비밀번호나 키 파일을 바꿀 때 마스터 비밀번호 슬롯을 다시 감싸는 데 씀
+    secret_key: Option<SecKey>,
     public_key: Option<PubKey>,
+    /// 로그인에 쓴 키 파일. 마스터 비밀번호를 바꾸거나 백업을 열 때 다시 씀
+    key_file: Option<KeyFile>,
+    /// 복구 코드나 조각 슬롯으로 열었으면 새 마스터 비밀번호를 정할 때까지 다른 화면을 막음
+    must_change_master_password: bool,
+    /// 격리된 DB를 읽기 전용으로 연 경우의 암호문. 이때는 저장과 수정이 막힘
+    read_only_data_base: Option<EncryptedDB>,
     time: Option<Instant>,
     #[cfg(target_os = "windows")]
     pub center: [i32; 2],
 }
 
 impl GraphicalUserInterface {
+    fn pick_vault(&mut self, ui: &Ui) {
+        let vault_picker = self.window_open_list.vault_picker.get_or_insert_with(VaultPicker::default);
+        if let Some(vault) = vault_picker.display(ui, #[cfg(target_os = "windows")] self.center) {
+            self.window_open_list.vault_picker = None;
+            self.vault = Some(Rc::new(vault));
+        }
+    }
+
     fn login(&mut self, ui: &mut Ui) {
-        match load_db() {
+        let Some(vault) = &self.vault else {
+            return;
+        };
+        if let Some(encrypted_data_base) = &self.read_only_data_base {
+            if self.window_open_list.existing_user.is_none() {
+                self.window_open_list.existing_user = Some(ExistingUser::read_only())
+            }
+            if let Some(existing_user) = &mut self.window_open_list.existing_user {
+                if !existing_user.display(
+                    ui,
+                    vault,
+                    encrypted_data_base,
+                    &mut self.window_open_list.root,
+                    &mut self.data_base_header,
+                    &mut self.data_base,
+                    &mut self.secret_key,
+                    &mut self.public_key,
+                    &mut self.key_file,
+                    &mut self.key,
+                    &mut self.login,
+                    &mut self.must_change_master_password,
+                    &self.string_values.master_login.warning_message,
+                    &mut self.string_values.save_data_base_label,
+                    #[cfg(target_os = "windows")]
+                    self.center
+                ) {
+                    self.window_open_list.existing_user = None;
+                }
+            }
+            return;
+        }
+
+        if let Some(quarantine_recovery) = &mut self.window_open_list.quarantine_recovery {
+            match quarantine_recovery.display(ui, vault, &mut self.window_open_list.root, #[cfg(target_os = "windows")] self.center) {
+                Some(QuarantineRecoveryType::Reload) => {
+                    self.window_open_list.quarantine_recovery = None;
+                }
+                Some(QuarantineRecoveryType::OpenReadOnly(data_base_header, encrypted_data_base)) => {
+                    self.window_open_list.quarantine_recovery = None;
+                    self.data_base_header = data_base_header;
+                    self.read_only_data_base = Some(encrypted_data_base);
+                }
+                None => {}
+            }
+            return;
+        }
+
+        match load_db(vault) {
             Ok((user_warning, data_base_header, encrypted_data_base)) => {
                 self.data_base_header = data_base_header;
                 if let Some(user_warning) = user_warning {
@@ -124,14 +227,19 @@ impl GraphicalUserInterface {
                         if let Some(existing_user) = &mut self.window_open_list.existing_user {
                             if !existing_user.display(
                                 ui,
+                                vault,
                                 &encrypted_data_base,
                                 &mut self.window_open_list.root,
-                                &self.data_base_header.master_pw_salt,
+                                &mut self.data_base_header,
                                 &mut self.data_base,
+                                &mut self.secret_key,
                                 &mut self.public_key,
+                                &mut self.key_file,
                                 &mut self.key,
                                 &mut self.login,
+                                &mut self.must_change_master_password,
                                 &self.string_values.master_login.warning_message,
+                                &mut self.string_values.save_data_base_label,
                                 #[cfg(target_os = "windows")]
                                 self.center
 
@@ -148,10 +256,13 @@ impl GraphicalUserInterface {
                         if let Some(first_login) = &mut self.window_open_list.first_login {
                             if !first_login.display(
                                 ui,
+                                vault,
                                 &mut self.data_base_header,
                                 &mut self.key,
                                 &mut self.data_base,
+                                &mut self.secret_key,
                                 &mut self.public_key,
+                                &mut self.key_file,
                                 &mut self.login,
                                 &mut self.window_open_list.root,
                                 &self.string_values.master_login.warning_message,
@@ -165,6 +276,10 @@ impl GraphicalUserInterface {
                     }
                 }
             }
+            Err(FileIOError::Quarantined(quarantine_path, cause)) => {
+                self.window_open_list.quarantine_recovery =
+                    Some(QuarantineRecovery::new(quarantine_path, cause.to_string()));
+            }
             Err(error) => {
                 ui.show_viewport_immediate(
                     ViewportId::from_hash_of("master_login_err"),
@@ -177,7 +292,7 @@ impl GraphicalUserInterface {
                         .with_minimize_button(false),
                     |ui, _| {
                         if ui.input(|input_state| input_state.viewport().close_requested()) {
-                            exit_root(ui, &mut self.window_open_list.root);
+                            exit_root(ui, vault, &mut self.window_open_list.root);
                             return;
                         }
                         egui::CentralPanel::default().show_inside(ui, |ui| {
@@ -190,25 +305,55 @@ impl GraphicalUserInterface {
         }
     }
 
-    fn save_data_base(&mut self) -> Result<(), SaveError> {
+    fn is_read_only(&self) -> bool {
+        self.read_only_data_base.is_some()
+    }
+
+    fn save_data_base(&mut self) -> Result<Option<FileIOWarn>, SaveError> {
+        if self.is_read_only() {
+            return Err(SaveError::ReadOnly);
+        }
         let encrypt_db = encrypt_db(
             &self.data_base,
+            &self.data_base_header,
             self.public_key.as_ref().ok_or(SaveError::NotingPublicKey)?,
         );
-        save_db(&mut self.data_base_header, encrypt_db).map_err(SaveError::from)
+        save_db(self.vault.as_ref().expect("unreachable"), &mut self.data_base_header, encrypt_db).map_err(SaveError::from)
+    }
+
+    /// DB 파일이 바뀌었으므로 세션을 버리고 로그인 화면으로 돌아감
+    fn reload_after_restore(&mut self) {
+        self.login = false;
+        self.data_base = DB::default();
+        self.data_base_header = DBHeader::default();
+        self.search_index = None;
+        self.key = None;
+        self.secret_key = None;
+        self.public_key = None;
+        self.key_file = None;
+        self.must_change_master_password = false;
+        self.read_only_data_base = None;
+        self.window_open_list = WindowOpenList::default();
     }
 
     fn user_main_view(&mut self, ui: &mut Ui) {
+        let read_only = self.is_read_only();
+        let vault = self.vault.clone().expect("unreachable");
+        let vault = &vault;
         egui::CentralPanel::default().show_inside(ui, |ui| {
-            ui.horizontal(|ui| {
+            ui.add_enabled_ui(!read_only, |ui| ui.horizontal(|ui| {
                 let save_data_base = ui.button("save data base");
                 if self.loading {
                     self.loading = false;
                     match self.save_data_base() {
-                        Ok(()) => {
+                        Ok(None) => {
                             self.time = Some(Instant::now());
                             self.string_values.save_data_base_label = "saved data base".to_string();
                         }
+                        // 저장은 됐으므로 바로 지우지 않고 남겨 둠
+                        Ok(Some(warning)) => {
+                            self.string_values.save_data_base_label = warning.to_string();
+                        }
                         Err(error) => {
                             self.string_values.save_data_base_label = error.to_string();
                         }
@@ -220,15 +365,15 @@ impl GraphicalUserInterface {
                     return;
                 }
                 ui.label(&self.string_values.save_data_base_label);
-            });
+            }));
 
-            ui.horizontal(|ui| {
+            ui.add_enabled_ui(!read_only, |ui| ui.horizontal(|ui| {
 
                 if ui.button("add user password").on_hover_text("add user password").clicked() {
                     self.window_open_list.add_user_password = Some(AddUserPassword::default());
                 }
                 if let Some(add_user_password) = &mut self.window_open_list.add_user_password {
-                    if !add_user_password.display(ui, self.key.as_ref().expect("unreachable"), &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
+                    if !add_user_password.display(ui, vault, self.key.as_ref().expect("unreachable"), &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
                         self.window_open_list.add_user_password = None;
                     }
                 }
@@ -236,7 +381,7 @@ impl GraphicalUserInterface {
                     self.window_open_list.change_user_password = Some(ChangeUserPassword::default());
                 }
                 if let Some(change_user_password) = &mut self.window_open_list.change_user_password {
-                    if !change_user_password.display(ui, self.key.as_ref().expect("unreachable"), &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
+                    if !change_user_password.display(ui, vault, self.key.as_ref().expect("unreachable"), &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
                         self.window_open_list.change_user_password = None;
                     }
                 }
@@ -244,7 +389,7 @@ impl GraphicalUserInterface {
                     self.window_open_list.remove_user_password = Some(RemoveUserPassword::default());
                 }
                 if let Some(remove_user_password) = &mut self.window_open_list.remove_user_password {
-                    if !remove_user_password.display(ui, &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
+                    if !remove_user_password.display(ui, vault, &mut self.data_base, #[cfg(target_os = "windows")] self.center) {
                         self.window_open_list.remove_user_password = None;
                     }
                 }
@@ -252,11 +397,78 @@ impl GraphicalUserInterface {
                     self.window_open_list.change_master_password = Some(ChangeMasterPassword::default())
                 }
                 if let Some(change_master_password) = &mut self.window_open_list.change_master_password {
-                    if !change_master_password.display(ui, &mut self.data_base, self.key.as_mut().expect("unreachable"), &mut self.data_base_header, &mut self.public_key) {
+                    if !change_master_password.display(ui, vault, &self.data_base, self.secret_key.as_ref().expect("unreachable"), &mut self.data_base_header, self.public_key.as_ref().expect("unreachable"), self.key_file.as_ref()) {
                         self.window_open_list.change_master_password = None;
                     }
                 }
-            });
+                if ui.button("key file").on_hover_text("마스터 비밀번호와 함께 쓰는 키 파일").clicked() {
+                    self.window_open_list.key_file_settings = Some(KeyFileSettings::default())
+                }
+                if let Some(key_file_settings) = &mut self.window_open_list.key_file_settings {
+                    if !key_file_settings.display(ui, vault, &self.data_base, self.secret_key.as_ref().expect("unreachable"), &mut self.data_base_header, self.public_key.as_ref().expect("unreachable"), &mut self.key_file) {
+                        self.window_open_list.key_file_settings = None;
+                    }
+                }
+                if ui.button("key slots").on_hover_text("마스터 비밀번호와 함께 볼트를 여는 비밀번호, 키 파일, 복구 코드, 복구 조각, 팀원 공개키").clicked() {
+                    self.window_open_list.key_slots_settings = Some(KeySlotsSettings::default())
+                }
+                if let Some(key_slots_settings) = &mut self.window_open_list.key_slots_settings {
+                    if !key_slots_settings.display(ui, vault, &self.data_base, &mut self.data_base_header, &self.public_key, self.key_file.as_ref()) {
+                        self.window_open_list.key_slots_settings = None;
+                    }
+                }
+                let mut encrypt_names = self.data_base.is_metadata_encrypted();
+                if ui.checkbox(&mut encrypt_names, "encrypt names").on_hover_text("사이트 이름과 ID도 암호화해서 저장").changed() {
+                    let (wrapped_session_key, session_key_nonce) = self.key.as_ref().expect("unreachable");
+                    let result = if encrypt_names {
+                        enable_metadata_encryption(&mut self.data_base, wrapped_session_key, session_key_nonce)
+                    } else {
+                        disable_metadata_encryption(&mut self.data_base)
+                    };
+                    self.string_values.encrypt_names_error = match result {
+                        Ok(()) => mark_as_ungraceful_exited_to_file(vault).err().map(|error| error.to_string()).unwrap_or_default(),
+                        Err(error) => error.to_string(),
+                    };
+                    self.search_index = None;
+                }
+                if !self.string_values.encrypt_names_error.is_empty() {
+                    ui.label(format!("error: {}", self.string_values.encrypt_names_error));
+                }
+            }));
+            if ui.button("backups").on_hover_text("backups").clicked() {
+                self.window_open_list.backups = Some(Backups::new(vault));
+            }
+            if let Some(backups) = &mut self.window_open_list.backups {
+                match backups.display(ui, vault, self.key_file.as_ref(), #[cfg(target_os = "windows")] self.center) {
+                    Some(BackupsType::Close) => {
+                        self.window_open_list.backups = None;
+                    }
+                    Some(BackupsType::Restored) => {
+                        self.window_open_list.backups = None;
+                        self.reload_after_restore();
+                        return;
+                    }
+                    None => {}
+                }
+            }
+            if ui.button("trash").on_hover_text("지운 계정").clicked() {
+                self.window_open_list.trash = Some(Trash::default());
+            }
+            if let Some(trash) = &mut self.window_open_list.trash {
+                if !trash.display(ui, vault, &mut self.data_base, read_only, #[cfg(target_os = "windows")] self.center) {
+                    self.window_open_list.trash = None;
+                }
+            }
+            if let Some(key) = &self.key {
+                if ui.button("password health").on_hover_text("재사용, 약한 비밀번호, 오래된 비밀번호 점검").clicked() {
+                    self.window_open_list.audit_dashboard = Some(AuditDashboard::new(&self.data_base, key));
+                }
+                if let Some(audit_dashboard) = &mut self.window_open_list.audit_dashboard {
+                    if !audit_dashboard.display(ui, key, &self.data_base, #[cfg(target_os = "windows")] self.center) {
+                        self.window_open_list.audit_dashboard = None;
+                    }
+                }
+            }
             ui.label("search");
             let response = ui.add(egui::TextEdit::singleline(
                 &mut self.string_values.search_data_base,
@@ -266,31 +478,162 @@ impl GraphicalUserInterface {
 
             self.not_first_frame = true;
 
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                for (site_name, _) in
-                    prefix_range(&self.data_base, &self.string_values.search_data_base)
-                {
-                    ui.horizontal(|ui| {
-                        ui.label(site_name.as_str());
-                        let button = ui.button(site_name.as_str().to_string());
-                        if button.hovered() {
-                            ui.label(site_name.as_str().to_string());
-                        }
-                        if button.clicked() {
-                            self.window_open_list
-                                .user_state
-                                .site_names
-                                .insert(site_name.clone(), true);
+            ui.horizontal_top(|ui| {
+                ui.vertical(|ui| {
+                    ui.set_width(SIDEBAR_WIDTH);
+                    self.organize_sidebar(ui);
+                });
+                ui.separator();
+                ui.vertical(|ui| {
+                    egui::ScrollArea::vertical().id_salt("site_list").show(ui, |ui| {
+                        if self.string_values.search_data_base.trim().is_empty() {
+                            // 색인에는 복호화한 이름과 메모가 있으므로 검색하는 동안만 둠
+                            self.search_index = None;
+                            self.site_list(ui);
+                        } else {
+                            self.search_results(ui);
                         }
                     });
-                }
+                });
             });
 
             self.user_passwords_windows(ui);
         });
     }
 
+    fn site_list(&mut self, ui: &mut Ui) {
+        let sites = match self.data_base.sites() {
+            Ok(sites) => sites,
+            Err(error) => {
+                ui.label(format!("error: {}", error));
+                return;
+            }
+        };
+        for (site_name, _) in filter_sites(sites, &self.entry_filter) {
+            ui.horizontal(|ui| {
+                ui.label(site_name.as_str());
+                let button = ui.button(site_name.as_str().to_string());
+                if button.hovered() {
+                    ui.label(site_name.as_str().to_string());
+                }
+                if button.clicked() {
+                    self.window_open_list
+                        .user_state
+                        .site_names
+                        .insert(site_name.clone(), true);
+                }
+            });
+        }
+    }
+
+    /// 검색 결과를 점수 순으로, 같은 사이트의 계정은 처음 나온 자리에 모아서 보여줌
+    fn search_results(&mut self, ui: &mut Ui) {
+        if !self.search_index.as_ref().is_some_and(|index| index.is_current(&self.data_base)) {
+            let Some((wrapped_session_key, session_key_nonce)) = &self.key else {
+                return;
+            };
+            match SearchIndex::build(&self.data_base, wrapped_session_key, session_key_nonce) {
+                Ok(index) => self.search_index = Some(index),
+                Err(error) => {
+                    ui.label(format!("error: {}", error));
+                    return;
+                }
+            }
+        }
+        let index = self.search_index.as_ref().expect("unreachable");
+
+        let mut groups: Vec<(SiteName, Vec<SearchHit>)> = Vec::new();
+        for hit in index.search(&self.string_values.search_data_base) {
+            let Ok(entry) = get_entry(&self.data_base, &hit.site, &hit.id) else {
+                continue;
+            };
+            if !self.entry_filter.matches(entry) {
+                continue;
+            }
+            match groups.iter_mut().find(|(site_name, _)| *site_name == hit.site) {
+                Some((_, hits)) => hits.push(hit),
+                None => groups.push((hit.site.clone(), vec![hit])),
+            }
+        }
+        if groups.is_empty() {
+            ui.label("검색 결과가 없습니다");
+        }
+
+        for (site_name, hits) in groups {
+            // 사이트 이름이 맞은 계정이 있으면 그 강조를 씀
+            let site_text = match hits.iter().find_map(|hit| hit.field(SearchField::Site)) {
+                Some(matched) => highlighted(ui, &matched.text, &matched.ranges),
+                None => highlighted(ui, site_name.as_str(), &[]),
+            };
+            if ui.button(site_text).clicked() {
+                self.window_open_list
+                    .user_state
+                    .site_names
+                    .insert(site_name.clone(), true);
+            }
+            ui.indent(site_name.as_str(), |ui| {
+                for hit in &hits {
+                    let id_text = match hit.field(SearchField::UserID) {
+                        Some(matched) => highlighted(ui, &matched.text, &matched.ranges),
+                        None => highlighted(ui, hit.id.as_str(), &[]),
+                    };
+                    ui.label(id_text);
+                    for matched in &hit.matches {
+                        if matches!(matched.field, SearchField::Site | SearchField::Domain | SearchField::UserID) {
+                            continue;
+                        }
+                        ui.horizontal(|ui| {
+                            ui.weak(matched.field.to_string());
+                            ui.label(highlighted(ui, &matched.text, &matched.ranges));
+                        });
+                    }
+                }
+            });
+        }
+    }
+
+    /// 모든 계정, 즐겨찾기, 폴더 트리, 태그. 태그는 여러 개를 고르면 모두 붙은 계정만 남김
+    fn organize_sidebar(&mut self, ui: &mut Ui) {
+        let filter = &mut self.entry_filter;
+        egui::ScrollArea::vertical().id_salt("organize_sidebar").show(ui, |ui| {
+            if ui.selectable_label(filter.is_empty(), "all").clicked() {
+                *filter = EntryFilter::default();
+            }
+            if ui.selectable_label(filter.favourites_only, "favourites").clicked() {
+                filter.favourites_only = !filter.favourites_only;
+            }
+
+            ui.separator();
+            ui.label("folders");
+            let tree = folder_tree(&self.data_base);
+            if tree.children.is_empty() {
+                ui.weak("없음");
+            }
+            folder_tree_ui(ui, &tree, &mut filter.folder);
+
+            ui.separator();
+            ui.label("tags");
+            let tags = tag_counts(&self.data_base);
+            if tags.is_empty() {
+                ui.weak("없음");
+            }
+            for (tag, count) in tags {
+                let selected = filter.tags.contains(&tag);
+                if ui.selectable_label(selected, format!("{} ({})", tag, count)).clicked() {
+                    if selected {
+                        filter.tags.retain(|selected_tag| selected_tag != &tag);
+                    } else {
+                        filter.tags.push(tag);
+                    }
+                }
+            }
+        });
+    }
+
     fn user_passwords_windows(&mut self, ui: &Ui) {
+        let read_only = self.is_read_only();
+        let vault = self.vault.clone().expect("unreachable");
+        let vault = &vault;
         let user_state = &mut self.window_open_list.user_state;
         let UserState {
             user_data,
@@ -309,12 +652,12 @@ impl GraphicalUserInterface {
 
                         egui::CentralPanel::default().show_inside(ui, |ui| {
                             ui.label(site_name.as_str());
-                            ui.horizontal(|ui| {
+                            ui.add_enabled_ui(!read_only, |ui| ui.horizontal(|ui| {
                                 if ui.button("add user password").on_hover_text("add user password").clicked() {
                                     self.window_open_list.add_user_password_with_site_name.insert(site_name.clone(), AddUserPasswordWithSiteName::default());
                                 }
                                 if let Some(add_user_password_site_name) = self.window_open_list.add_user_password_with_site_name.get_mut(site_name) {
-                                    if !add_user_password_site_name.display(ui, self.key.as_ref().unwrap(), &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
+                                    if !add_user_password_site_name.display(ui, vault, self.key.as_ref().unwrap(), &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
                                         self.window_open_list.add_user_password_with_site_name.remove(site_name);
                                     }
                                 }
@@ -322,7 +665,7 @@ impl GraphicalUserInterface {
                                     self.window_open_list.change_user_password_with_site_name.insert(site_name.clone(), ChangeUserPasswordWithSiteName::default());
                                 }
                                 if let Some(change_user_password_with_site_name) = self.window_open_list.change_user_password_with_site_name.get_mut(site_name) {
-                                    if !change_user_password_with_site_name.display(ui, self.key.as_ref().unwrap(), &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
+                                    if !change_user_password_with_site_name.display(ui, vault, self.key.as_ref().unwrap(), &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
                                         self.window_open_list.change_user_password_with_site_name.remove(site_name);
                                     }
                                 }
@@ -330,12 +673,12 @@ impl GraphicalUserInterface {
                                     self.window_open_list.remove_user_password_with_site_name.insert(site_name.clone(), RemoveUserPasswordWithSiteName::default());
                                 }
                                 if let Some(remove_user_password_with_site_name) = self.window_open_list.remove_user_password_with_site_name.get_mut(site_name) {
-                                    if !remove_user_password_with_site_name.display(ui, &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
+                                    if !remove_user_password_with_site_name.display(ui, vault, &mut self.data_base, site_name, #[cfg(target_os = "windows")] self.center) {
                                         self.window_open_list.remove_user_password_with_site_name.remove(site_name);
                                     }
                                 }
-                            });
-                            let Some(passwords): Option<Vec<_>> = self.data_base.get(site_name).map(|map| map.keys().cloned().collect()) else {
+                            }));
+                            let Ok(passwords) = self.data_base.user_ids(site_name) else {
                                 return;
                             };
                             egui::ScrollArea::vertical().show(ui, |ui| {
@@ -343,29 +686,46 @@ impl GraphicalUserInterface {
                                     ui.vertical(|ui| {
                                         ui.label(user_identifier.as_str());
                                         ui.horizontal(|ui| {
-                                            if ui.button("change user password").clicked() {
+                                            if ui.add_enabled(!read_only, egui::Button::new("change user password")).clicked() {
                                                 self.window_open_list.change_user_password_with_site_name_with_user_identifier.entry(site_name.clone()).or_default().entry(user_identifier.clone()).or_default();
                                             }
                                             if let Some(change_user_password_with_size_name_with_user_identifier) = self.window_open_list.change_user_password_with_site_name_with_user_identifier.get_mut(site_name).and_then(|value| value.get_mut(&user_identifier)) {
-                                                if !change_user_password_with_size_name_with_user_identifier.display(ui, self.key.as_ref().unwrap(), &mut self.data_base, site_name, &user_identifier, #[cfg(target_os = "windows")] self.center) {
+                                                if !change_user_password_with_size_name_with_user_identifier.display(ui, vault, self.key.as_ref().unwrap(), &mut self.data_base, site_name, &user_identifier, #[cfg(target_os = "windows")] self.center) {
                                                     self.window_open_list.change_user_password_with_site_name_with_user_identifier.entry(site_name.clone()).or_default().remove(&user_identifier);
                                                 }
                                             }
-                                            if ui.button("remove user password").clicked() {
+                                            if ui.add_enabled(!read_only, egui::Button::new("remove user password")).clicked() {
                                                 self.window_open_list.remove_user_password_with_site_name_with_user_identifier.entry(site_name.clone()).or_default().entry(user_identifier.clone()).or_default();
                                             }
                                             if let Some(remove_user_password_with_size_name_with_user_identifier) = self.window_open_list.remove_user_password_with_site_name_with_user_identifier.get_mut(site_name).and_then(|value| value.get_mut(&user_identifier)) {
-                                                if !remove_user_password_with_size_name_with_user_identifier.display(ui, &mut self.data_base, site_name, &user_identifier, #[cfg(target_os = "windows")] self.center) {
+                                                if !remove_user_password_with_size_name_with_user_identifier.display(ui, vault, &mut self.data_base, site_name, &user_identifier, #[cfg(target_os = "windows")] self.center) {
                                                     self.window_open_list.remove_user_password_with_site_name_with_user_identifier.entry(site_name.clone()).or_default().remove(&user_identifier);
                                                 }
                                             }
+                                            if ui.button("edit entry").on_hover_text("notes, urls, custom fields, tags, folder").clicked() {
+                                                let edit_entry = EditEntry::new(&self.data_base, self.key.as_ref().unwrap(), site_name, &user_identifier);
+                                                self.window_open_list.edit_entry.entry(site_name.clone()).or_default().insert(user_identifier.clone(), edit_entry);
+                                            }
+                                            if let Some(edit_entry) = self.window_open_list.edit_entry.get_mut(site_name).and_then(|value| value.get_mut(&user_identifier)) {
+                                                if !edit_entry.display(ui, vault, self.key.as_ref().unwrap(), &mut self.data_base, site_name, &user_identifier, read_only, &mut self.string_values.hibp_path, #[cfg(target_os = "windows")] self.center) {
+                                                    self.window_open_list.edit_entry.entry(site_name.clone()).or_default().remove(&user_identifier);
+                                                }
+                                            }
+                                            if ui.button("history").on_hover_text("previous passwords").clicked() {
+                                                self.window_open_list.password_history.entry(site_name.clone()).or_default().entry(user_identifier.clone()).or_default();
+                                            }
+                                            if let Some(password_history) = self.window_open_list.password_history.get_mut(site_name).and_then(|value| value.get_mut(&user_identifier)) {
+                                                if !password_history.display(ui, vault, self.key.as_ref().unwrap(), &mut self.data_base, site_name, &user_identifier, read_only, #[cfg(target_os = "windows")] self.center) {
+                                                    self.window_open_list.password_history.entry(site_name.clone()).or_default().remove(&user_identifier);
+                                                }
+                                            }
                                             let copy_password_button = ui.button("copy password");
                                             if copy_password_button.clicked() {
                                                 let user_password = {
                                                     let Some((wrapped_session_key, session_key_nonce)) = &self.key else {
                                                         return;
                                                     };
-                                                    match get_user_pw(&self.data_base, site_name, &user_identifier, wrapped_session_key, session_key_nonce) {
+                                                    match get_user_pw(&mut self.data_base, site_name, &user_identifier, wrapped_session_key, session_key_nonce) {
                                                         Ok(password) => password,
                                                         Err(error) => {
                                                             ui.label(format!("error: {}", error));
@@ -381,7 +741,7 @@ impl GraphicalUserInterface {
                                                     let Some((wrapped_session_key, session_key_nonce)) = &self.key else {
                                                         return;
                                                     };
-                                                    match get_user_pw(&self.data_base, site_name, &user_identifier, wrapped_session_key, session_key_nonce) {
+                                                    match get_user_pw(&mut self.data_base, site_name, &user_identifier, wrapped_session_key, session_key_nonce) {
                                                         Ok(password) => password,
                                                         Err(error) => {
                                                             ui.label(format!("error: {}", error));
@@ -407,7 +767,7 @@ impl GraphicalUserInterface {
 impl eframe::App for GraphicalUserInterface {
     fn ui(&mut self, ui: &mut Ui, _frame: &mut eframe::Frame) {
         if ui.input(|input| input.viewport().close_requested()) {
-            if check_can_directly_exit() {
+            if self.vault.as_ref().is_none_or(|vault| check_can_directly_exit(vault)) {
                 ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
             } else {
                 ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::CancelClose);
@@ -434,7 +794,7 @@ impl eframe::App for GraphicalUserInterface {
                             }
                         }
                         RootSaveType::DontSave => {
-                            mark_as_graceful_exited_to_file().unwrap();
+                            mark_as_graceful_exited_to_file(self.vault.as_ref().expect("unreachable")).unwrap();
                             ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
                             return;
                         }
@@ -444,11 +804,26 @@ impl eframe::App for GraphicalUserInterface {
             }
         }
 
+        if self.vault.is_none() {
+            self.pick_vault(ui);
+            return;
+        }
+
         if !self.login {
             self.login(ui);
             return;
         }
 
+        if self.must_change_master_password {
+            let vault = self.vault.clone().expect("unreachable");
+            let change_master_password = self.window_open_list.change_master_password.get_or_insert_with(ChangeMasterPassword::after_recovery);
+            if !change_master_password.display(ui, &vault, &self.data_base, self.secret_key.as_ref().expect("unreachable"), &mut self.data_base_header, self.public_key.as_ref().expect("unreachable"), None) {
+                self.window_open_list.change_master_password = None;
+                self.must_change_master_password = false;
+            }
+            return;
+        }
+
         if let Some(time) = self.time {
             if time.elapsed() > Duration::from_secs(1) {
                 self.string_values.save_data_base_label = "".to_string();
@@ -457,7 +832,13 @@ impl eframe::App for GraphicalUserInterface {
 
         ui.add_enabled_ui(!self.window_open_list.root.is_some(), |ui| {
             ui.send_viewport_cmd(ViewportCommand::Visible(true));
-            ui.send_viewport_cmd(ViewportCommand::Title("비밀번호 관리자".to_string()));
+            let vault_name = self.vault.as_ref().map(|vault| vault.name()).unwrap_or_default();
+            let title = if self.is_read_only() {
+                format!("비밀번호 관리자 - {} (읽기 전용)", vault_name)
+            } else {
+                format!("비밀번호 관리자 - {}", vault_name)
+            };
+            ui.send_viewport_cmd(ViewportCommand::Title(title));
             ui.send_viewport_cmd(ViewportCommand::InnerSize([800.0, 600.0].into()));
             self.user_main_view(ui);
         });
@@ -477,3 +858,46 @@ pub(crate) fn loading(ui: &Ui) {
         },
     )
 }
+
+/// 폴더를 고르면 하위 폴더까지 보여줌. 고른 폴더를 다시 누르면 선택을 풂
+fn folder_tree_ui(ui: &mut Ui, node: &FolderNode, selected: &mut Option<FolderPath>) {
+    for child in node.children.values() {
+        let is_selected = selected.as_ref() == Some(&child.path);
+        let label = format!("{} ({})", child.name(), child.total_count);
+        let mut select = |ui: &mut Ui| {
+            if ui.selectable_label(is_selected, label.as_str()).clicked() {
+                *selected = if is_selected { None } else { Some(child.path.clone()) };
+            }
+        };
+        if child.children.is_empty() {
+            select(ui);
+            continue;
+        }
+        let id = ui.make_persistent_id(("folder", child.path.to_string()));
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+            .show_header(ui, select)
+            .body(|ui| folder_tree_ui(ui, child, selected));
+    }
+}
+
+/// `ranges` 부분에 선택 영역 색을 깔아서 강조함
+fn highlighted(ui: &Ui, text: &str, ranges: &[Range<usize>]) -> LayoutJob {
+    let normal = TextFormat {
+        font_id: egui::TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..TextFormat::default()
+    };
+    let marked = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        ..normal.clone()
+    };
+    let mut job = LayoutJob::default();
+    let mut last = 0;
+    for range in ranges {
+        job.append(&text[last..range.start], 0.0, normal.clone());
+        job.append(&text[range.clone()], 0.0, marked.clone());
+        last = range.end;
+    }
+    job.append(&text[last..], 0.0, normal);
+    job
+}
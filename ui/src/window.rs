@@ -1,17 +1,40 @@
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use anyhow::{anyhow, Error};
 use eframe::egui::{self, ViewportBuilder, ViewportCommand, ViewportId, Key, Pos2, TextEdit, Ui, Vec2, vec2, pos2};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 use engine::{
-    data_base::{add_user_pw, change_user_pw, remove_user_pw, SiteName, UserID, UserPW, DB},
-    file_io::{check_can_directly_exit, mark_as_ungraceful_exited_to_file, save_db},
-    header::{DBHeader, Salt},
-    master_secrets::{decrypt_db, encrypt_db, general_login, master_pw_validation, EncryptedDB},
-    x25519::PubKey,
+    data_base::{
+        add_user_pw, change_user_pw, get_entry, get_past_user_pw, normalize_tag, read_entry_details, remove_user_pw,
+        restore_user_pw, set_favourite, set_folder, set_tags, update_entry_details,
+        CustomField, CustomFieldKind, EntryDetails, FolderPath, SiteName, Timestamp, UserID, UserPW, DB,
+    },
+    file_io::{FileIOWarn, check_can_directly_exit, mark_as_ungraceful_exited_to_file, save_db},
+    generator::{generate, CharClass, CharsetPolicy, Generated, GeneratorPolicy, PassphrasePolicy, MAX_PW_LENGTH, MAX_WORD_COUNT, MIN_PW_LENGTH, MIN_WORD_COUNT},
+    header::{DBHeader, KEY_SLOT_LABEL_LEN, MASTER_SLOT},
+    key_file::KeyFile,
+    master_secrets::{check_new_master_pw, decrypt_db_payload, encrypt_db, general_login, master_pw_validation, EncryptedDB},
+    pw_policy::{estimate_strength, MasterPWPolicy, StrengthEstimate},
+    migration::migrate_db,
+    otp::{generate_otp_code, read_otp, set_otp, OtpConfig, OtpKind},
+    recovery::{emergency_kit, share_sheet, RecoveryKey},
+    key_slots::{
+        add_key_slot, add_recovery_code_slot, add_recovery_shares_slot, key_slot_login, label_key_slot, list_key_slots,
+        revoke_key_slot, KeySlotKind, NewKeySlot, SlotCredential, TeammateKey,
+    },
+    shamir::Share,
+    vault_path::{list_vaults, VaultPath},
+    x25519::{PubKey, SecKey},
     file_io::mark_as_graceful_exited_to_file,
-    master_secrets::{change_master_pw, first_login}
+    master_secrets::{apply_kdf_upgrade, change_key_file, change_master_pw, first_login, verify_master_pw}
 };
-use engine::file_io::remove_db;
+use engine::file_io::{backup_before_migration, open_db_read_only, remove_db, restore_db_from, VaultHandle};
+use engine::backup::{inspect_snapshot, list_snapshots, restore_snapshot, Snapshot};
+use engine::audit::{audit_db, AuditIssue, AuditPolicy, AuditReport};
+use engine::breach::{check_entry, BreachList};
+use engine::pw_policy::PWScore;
+use engine::trash::{empty_trash, purge_expired, purge_trashed, restore_trashed, set_trash_retention_days};
 use crate::{
     command_builder::CommandBuilder,
     graphical_user_interface::KeyPair
@@ -26,30 +49,74 @@ pub enum RootSaveType {
 #[derive(Default)]
 pub struct ExistingUser {
     password: String,
+    /// 헤더가 키 파일을 요구할 때만 보여줌
+    key_file_path: String,
+    /// 복구 조각 슬롯을 고르면 비밀번호 칸 대신 한 줄에 하나씩 받음
+    recovery_shares: String,
+    /// 켜면 고른 키 슬롯의 종류에 맞는 자격 증명을 받음
+    use_key_slot: bool,
+    key_slot_index: usize,
     error_message: String,
     reset: Option<Reset>,
     loading: bool,
-    not_first_frame: bool
+    not_first_frame: bool,
+    read_only: bool
 }
 
 impl ExistingUser {
+    /// 격리된 DB를 여는 로그인 창. 포맷 업그레이드 결과를 파일에 저장하지 않음
+    pub fn read_only() -> Self {
+        Self {
+            read_only: true,
+            ..Self::default()
+        }
+    }
+
     pub fn display(
         &mut self,
         ui: &mut Ui,
+        vault: &VaultHandle,
         encrypted_data_base: &EncryptedDB,
         root_window: &mut Option<RootSave>,
-        master_password_salt: &Salt,
+        data_base_header: &mut DBHeader,
         data_base: &mut DB,
+        graphical_user_interface_secret_key: &mut Option<SecKey>,
         graphical_user_interface_public_key: &mut Option<PubKey>,
+        graphical_user_interface_key_file: &mut Option<KeyFile>,
         key: &mut Option<KeyPair>,
         login: &mut bool,
+        must_change_master_password: &mut bool,
         warning_message: &String,
+        save_data_base_label: &mut String,
         #[cfg(target_os = "windows")]
         center: [i32; 2]
     ) -> bool {
         let mut keep = true;
 
-        let size = [300.0, 175.0];
+        // 마스터 비밀번호 슬롯은 기본 입력 칸으로 엶
+        let key_slots = if self.read_only {
+            Vec::new()
+        } else {
+            list_key_slots(data_base_header).into_iter().filter(|slot| slot.index != MASTER_SLOT).collect()
+        };
+        self.use_key_slot &= !key_slots.is_empty();
+        if self.use_key_slot && !key_slots.iter().any(|slot| slot.index == self.key_slot_index) {
+            self.key_slot_index = key_slots[0].index;
+        }
+        let key_slot_kind = key_slots.iter().find(|slot| self.use_key_slot && slot.index == self.key_slot_index).map(|slot| slot.kind);
+        let requires_key_file = data_base_header.requires_key_file() && !self.use_key_slot;
+        let use_recovery_shares = key_slot_kind == Some(KeySlotKind::RecoveryShares);
+        let mut height = if requires_key_file { 220.0 } else { 175.0 };
+        if !key_slots.is_empty() {
+            height += 25.0;
+        }
+        if self.use_key_slot {
+            height += 25.0;
+        }
+        if use_recovery_shares {
+            height += 60.0;
+        }
+        let size = [300.0, height];
 
         let mut viewport_builder = ViewportBuilder::default()
             .with_title("마스터 로그인")
@@ -69,66 +136,170 @@ impl ExistingUser {
             viewport_builder,
             |ui, _| {
                 if ui.input(|i| i.viewport().close_requested()) {
-                    exit_root(ui, root_window);
+                    exit_root(ui, vault, root_window);
                 }
                 egui::CentralPanel::default().show_inside(ui, |ui| {
                     let (login_button, password_text_input) = ui.add_enabled_ui(!self.reset.is_some(), |ui| {
-                        ui.label("input master password");
-                        let response = ui.add(
-                            TextEdit::singleline(&mut self.password)
-                                .password(true),
-                        );
+                        if self.read_only {
+                            ui.label("격리된 DB를 읽기 전용으로 엽니다");
+                        }
+                        if self.use_key_slot {
+                            egui::ComboBox::from_id_salt("login_key_slot")
+                                .selected_text(key_slots.iter().find(|slot| slot.index == self.key_slot_index).map(|slot| slot.label.as_str()).unwrap_or_default())
+                                .show_ui(ui, |ui| {
+                                    for slot in &key_slots {
+                                        ui.selectable_value(&mut self.key_slot_index, slot.index, format!("{} ({})", slot.label, slot.kind));
+                                    }
+                                });
+                        }
+                        let response = if use_recovery_shares {
+                            ui.label("input recovery shares, one per line");
+                            ui.add(TextEdit::multiline(&mut self.recovery_shares).desired_rows(4).font(egui::TextStyle::Monospace))
+                        } else if let Some(KeySlotKind::KeyFile | KeySlotKind::Teammate) = key_slot_kind {
+                            ui.label("key file");
+                            ui.add(TextEdit::singleline(&mut self.key_file_path).hint_text("path to the key file"))
+                        } else if let Some(kind) = key_slot_kind {
+                            ui.label(if kind == KeySlotKind::RecoveryCode { "input recovery code" } else { "input slot password" });
+                            ui.add(
+                                TextEdit::singleline(&mut self.password)
+                                    .password(true),
+                            )
+                        } else {
+                            ui.label("input master password");
+                            ui.add(
+                                TextEdit::singleline(&mut self.password)
+                                    .password(true),
+                            )
+                        };
                         if !self.not_first_frame { response.request_focus() }
                         self.not_first_frame = true;
+                        if requires_key_file {
+                            ui.label("key file");
+                            ui.add(TextEdit::singleline(&mut self.key_file_path).hint_text("path to the key file"));
+                        }
+                        if !key_slots.is_empty() {
+                            ui.checkbox(&mut self.use_key_slot, "use key slot")
+                                .on_hover_text("마스터 비밀번호 대신 추가한 비밀번호, 키 파일, 복구 코드, 복구 조각으로 엶. 복구 코드나 조각으로 열면 새 마스터 비밀번호를 정해야 함");
+                        }
                         ui.label(&self.error_message);
                         (ui.button("login"), response)
                     }).inner;
                     if self.loading {
                         self.loading = false;
 
-                        let result = || -> Result<(DB, PubKey, KeyPair), Error> {
-                            if let Err(error) = master_pw_validation(&self.password) {
-                                return Err(error.into());
+                        let result = || -> Result<(DB, SecKey, PubKey, KeyPair, Option<KeyFile>, Option<FileIOWarn>), Error> {
+                            if key_slot_kind.is_none() {
+                                if let Err(error) = master_pw_validation(&self.password) {
+                                    return Err(error.into());
+                                }
                             }
-
-                            let (secret_key, public_key, wrapped_session_key, session_key_nonce) =
-                                general_login(&mut self.password, master_password_salt);
+                            let key_file = if requires_key_file {
+                                Some(KeyFile::read(Path::new(self.key_file_path.trim()))?)
+                            } else {
+                                None
+                            };
+
+                            // 다른 슬롯으로 열면 마스터 비밀번호 슬롯은 새 마스터 비밀번호를 정할 때 현재 KDF 설정으로 다시 감쌈
+                            let (upgrade_password, (mut secret_key, mut public_key, mut wrapped_session_key, mut session_key_nonce)) = if let Some(kind) = key_slot_kind {
+                                let keys = match kind {
+                                    KeySlotKind::Password => key_slot_login(data_base_header, self.key_slot_index, &SlotCredential::Password(&self.password))?,
+                                    KeySlotKind::KeyFile | KeySlotKind::Teammate => {
+                                        let slot_key_file = KeyFile::read(Path::new(self.key_file_path.trim()))?;
+                                        key_slot_login(data_base_header, self.key_slot_index, &SlotCredential::KeyFile(&slot_key_file))?
+                                    }
+                                    KeySlotKind::RecoveryCode => {
+                                        let recovery_key = RecoveryKey::parse(&self.password)?;
+                                        key_slot_login(data_base_header, self.key_slot_index, &SlotCredential::RecoveryCode(&recovery_key))?
+                                    }
+                                    KeySlotKind::RecoveryShares => {
+                                        let shares = self.recovery_shares
+                                            .lines()
+                                            .filter(|line| !line.trim().is_empty())
+                                            .map(Share::parse)
+                                            .collect::<Result<Vec<_>, _>>()?;
+                                        key_slot_login(data_base_header, self.key_slot_index, &SlotCredential::Shares(&shares))?
+                                    }
+                                    // 마스터 비밀번호 슬롯은 목록에서 뺐음
+                                    KeySlotKind::PasswordAndKeyFile => return Err(anyhow!("use the master password login")),
+                                };
+                                (None, keys)
+                            } else {
+                                // 대기 중인 KDF 변경은 비밀번호가 맞는 것을 확인한 뒤에 새 설정으로 다시 감쌈
+                                let upgrade_password = data_base_header.pending_kdf_params().map(|_| Zeroizing::new(self.password.clone()));
+                                (upgrade_password, general_login(&mut self.password, data_base_header, key_file.as_ref())?)
+                            };
 
                             self.password.zeroize();
-                            match decrypt_db(encrypted_data_base, secret_key) {
-                                Ok(decrypted_data_base) => Ok((decrypted_data_base, public_key, (wrapped_session_key, session_key_nonce))),
-                                Err(error) => { Err(error.into()) }
+                            self.recovery_shares.zeroize();
+                            let payload = decrypt_db_payload(encrypted_data_base, data_base_header, &secret_key)?;
+                            if !data_base_header.is_current_version() && !self.read_only {
+                                backup_before_migration(vault, data_base_header)?;
+                            }
+                            let (mut decrypted_data_base, migrations) = migrate_db(
+                                data_base_header,
+                                payload,
+                                &mut secret_key,
+                                &mut public_key,
+                                &mut wrapped_session_key,
+                                &mut session_key_nonce,
+                            )?;
+                            // 스냅샷을 못 남겨도 저장은 됐으므로 로그인은 계속하고 경고만 보여줌
+                            let mut save_warning = None;
+                            if !migrations.is_empty() && !self.read_only {
+                                let upgraded_data_base = encrypt_db(&decrypted_data_base, data_base_header, &public_key);
+                                save_warning = save_db(vault, data_base_header, upgraded_data_base)?;
                             }
+                            let kdf_upgraded = upgrade_password
+                                .filter(|_| !self.read_only)
+                                .is_some_and(|password| apply_kdf_upgrade(data_base_header, &password, key_file.as_ref(), &secret_key));
+                            if kdf_upgraded {
+                                let upgraded_data_base = encrypt_db(&decrypted_data_base, data_base_header, &public_key);
+                                save_warning = save_db(vault, data_base_header, upgraded_data_base)?.or(save_warning);
+                            }
+                            // 보관 기간이 지난 휴지통 항목은 로그인할 때 지움
+                            if !self.read_only && purge_expired(&mut decrypted_data_base) != 0 {
+                                mark_as_ungraceful_exited_to_file(vault)?;
+                            }
+                            Ok((decrypted_data_base, secret_key, public_key, (wrapped_session_key, session_key_nonce), key_file, save_warning))
                         }();
 
                         match result {
-                            Ok((decrypted_data_base, public_key, key_pair)) => {
+                            Ok((decrypted_data_base, secret_key, public_key, key_pair, key_file, save_warning)) => {
+                                if let Some(save_warning) = save_warning {
+                                    *save_data_base_label = save_warning.to_string();
+                                }
                                 *data_base = decrypted_data_base;
+                                *graphical_user_interface_secret_key = Some(secret_key);
                                 *graphical_user_interface_public_key = Some(public_key);
+                                *graphical_user_interface_key_file = key_file;
                                 *key = Some(key_pair);
 
                                 *login = true;
+                                *must_change_master_password = key_slot_kind.is_some_and(KeySlotKind::is_recovery);
                                 keep = false;
                             }
                             Err(error) => {
                                 self.password.zeroize();
+                                self.recovery_shares.zeroize();
                                 self.error_message = error.to_string();
                                 password_text_input.request_focus();
                             }
                         }
                     }
-                    ui.add_enabled_ui(!self.reset.is_some(), |ui| {
+                    ui.add_enabled_ui(!self.reset.is_some() && !self.read_only, |ui| {
                         if ui.button("reset").clicked() {
                             self.reset = Some(Reset::default());
                         }
                     });
                     if let Some(reset) = &mut self.reset {
-                        if !reset.display(ui) {
+                        if !reset.display(ui, vault) {
                             self.reset = None;
                         }
                     }
                     ui.label(warning_message);
-                    if login_button.clicked() || ui.input(|input_state| input_state.key_pressed(Key::Enter)) {
+                    // 조각 입력 칸에서 Enter는 줄바꿈
+                    let enter_pressed = !use_recovery_shares && ui.input(|input_state| input_state.key_pressed(Key::Enter));
+                    if login_button.clicked() || enter_pressed {
                         ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                             ui.label("loading");
                         });
@@ -147,6 +318,10 @@ impl ExistingUser {
 pub struct FirstLogin {
     password: String,
     recheck_password: String,
+    /// 비어 있으면 키 파일 없이 만듦
+    key_file_path: String,
+    generate_key_file: bool,
+    strength: Option<StrengthEstimate>,
     error_message: String,
     loading: bool,
     not_first_frame: bool
@@ -156,10 +331,13 @@ impl FirstLogin {
     pub fn display(
         &mut self,
         ui: &Ui,
+        vault: &VaultHandle,
         data_base_header: &mut DBHeader,
         key: &mut Option<KeyPair>,
         data_base: &mut DB,
+        graphical_user_interface_secret_key: &mut Option<SecKey>,
         graphical_user_interface_public_key: &mut Option<PubKey>,
+        graphical_user_interface_key_file: &mut Option<KeyFile>,
         login: &mut bool,
         root_window: &mut Option<RootSave>,
         warning_message: &String,
@@ -168,7 +346,7 @@ impl FirstLogin {
     ) -> bool {
         let mut keep = true;
 
-        let size = [300.0, 175.0];
+        let size = [300.0, 340.0];
 
         let mut viewport_builder = ViewportBuilder::default()
             .with_title("첫 마스터 로그인")
@@ -187,7 +365,7 @@ impl FirstLogin {
             viewport_builder,
             |ui, _| {
                 if ui.input(|input_state| input_state.viewport().close_requested()) {
-                    exit_root(ui, root_window);
+                    exit_root(ui, vault, root_window);
                     return;
                 }
                 egui::CentralPanel::default().show_inside(ui, |ui| {
@@ -198,6 +376,10 @@ impl FirstLogin {
                     );
                     if !self.not_first_frame { response.request_focus() }
                     self.not_first_frame = true;
+                    if response.changed() {
+                        self.strength = (!self.password.is_empty()).then(|| estimate_strength(&self.password));
+                    }
+                    show_strength(ui, &self.strength);
                     ui.label("recheck master password");
                     ui.add(
                         egui::TextEdit::singleline(
@@ -205,36 +387,62 @@ impl FirstLogin {
                         )
                             .password(true),
                     );
+                    ui.label("key file (optional)");
+                    ui.add(TextEdit::singleline(&mut self.key_file_path).hint_text("path to the key file"));
+                    ui.checkbox(&mut self.generate_key_file, "create a new random key file")
+                        .on_hover_text("키 파일이 있어야 볼트를 열 수 있습니다. 사본을 따로 보관하세요");
                     ui.label(&self.error_message);
                     let sign_in_button = ui.button("sign in");
                     if self.loading {
                         self.loading = false;
                         if let Err(err) =
-                            master_pw_validation(&self.password)
+                            check_new_master_pw(&self.password, &MasterPWPolicy::default())
                         {
                             self.error_message =
                                 format!("Master password validation error: {}", err);
                             self.password.zeroize();
                             self.recheck_password.zeroize();
+                            self.strength = None;
                             return;
                         }
                         if self.password
                             == self.recheck_password
                         {
+                            let key_file_path = Path::new(self.key_file_path.trim());
+                            let key_file = if self.key_file_path.trim().is_empty() {
+                                Ok(None)
+                            } else if self.generate_key_file {
+                                KeyFile::generate(key_file_path).map(Some)
+                            } else {
+                                KeyFile::read(key_file_path).map(Some)
+                            };
+                            let key_file = match key_file {
+                                Ok(key_file) => key_file,
+                                Err(error) => {
+                                    self.password.zeroize();
+                                    self.recheck_password.zeroize();
+                                    self.strength = None;
+                                    self.error_message = error.to_string();
+                                    return;
+                                }
+                            };
                             let (
+                                secret_key,
                                 public_key,
-                                data_base_header_salt,
                                 wrapped_session_key,
                                 session_key_nonce,
-                            ) = first_login(&mut self.password);
+                            ) = first_login(&mut self.password, data_base_header, key_file.as_ref());
                             self.password.zeroize();
                             self.recheck_password.zeroize();
-                            data_base_header.master_pw_salt = data_base_header_salt;
+                            *graphical_user_interface_secret_key = Some(secret_key);
+                            *graphical_user_interface_key_file = key_file;
                             *key = Some((wrapped_session_key, session_key_nonce));
                             *data_base = DB::default();
+                            let encrypted_data_base = encrypt_db(data_base, data_base_header, &public_key);
                             save_db(
+                                vault,
                                 data_base_header,
-                                encrypt_db(data_base, &public_key),
+                                encrypted_data_base,
                             )
                                 .expect("unreachable");
                             *graphical_user_interface_public_key = Some(public_key);
@@ -243,6 +451,7 @@ impl FirstLogin {
                         } else {
                             self.password.zeroize();
                             self.recheck_password.zeroize();
+                            self.strength = None;
                             self.error_message =
                                 "password is mismatch".to_string();
                             response.request_focus();
@@ -270,7 +479,7 @@ struct Reset {
 }
 
 impl Reset {
-    pub fn display(&mut self, ui: &Ui) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle) -> bool {
         let mut keep = true;
 
         egui::Window::new("reset")
@@ -292,7 +501,7 @@ impl Reset {
                 ui.label("");
                 ui.horizontal(|ui| {
                     if ui.button("submit").clicked() {
-                        match remove_db()
+                        match remove_db(vault)
                         {
                             Ok(_) => keep = false,
                             Err(error) => {
@@ -374,15 +583,17 @@ pub struct AddUserPassword {
     site_name: String,
     identifier: String,
     password: String,
+    generator: PasswordGenerator,
     error_message: String,
 }
 
 impl AddUserPassword {
-    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("add user password", "add user password", None, #[cfg(target_os = "windows")] center)
             .input("site name", &mut self.site_name)
             .input("user identifier", &mut self.identifier)
             .sensitive_input("password", &mut self.password)
+            .password_generator(&mut self.generator)
             .set_database(data_base)
             .set_key(key)
             .execute(|inputs, data_base, key, _| {
@@ -400,7 +611,7 @@ impl AddUserPassword {
                     wrapped_session_key,
                     session_key_nonce,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -414,15 +625,17 @@ pub struct ChangeUserPassword {
     site_name: String,
     identifier: String,
     password: String,
+    generator: PasswordGenerator,
     error_message: String,
 }
 
 impl ChangeUserPassword {
-    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("change user password", "change user password", None, #[cfg(target_os = "windows")] center)
             .input("site name", &mut self.site_name)
             .input("user identifier", &mut self.identifier)
             .sensitive_input("password", &mut self.password)
+            .password_generator(&mut self.generator)
             .set_database(data_base)
             .set_key(key)
             .execute(|inputs, data_base, key, _| {
@@ -440,7 +653,7 @@ impl ChangeUserPassword {
                     wrapped_session_key,
                     session_key_nonce,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -457,7 +670,7 @@ pub struct RemoveUserPassword {
 }
 
 impl RemoveUserPassword {
-    pub fn display(&mut self, ui: &Ui, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, data_base: &mut DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("remove user password", "remove user password", None, #[cfg(target_os = "windows")] center)
             .input("site name", &mut self.site_name)
             .input("user identifier", &mut self.identifier)
@@ -470,7 +683,7 @@ impl RemoveUserPassword {
                     &site_name,
                     &user_identifier,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -483,14 +696,16 @@ impl RemoveUserPassword {
 pub struct AddUserPasswordWithSiteName {
     user_identifier: String,
     password: String,
+    generator: PasswordGenerator,
     error_message: String,
 }
 
 impl AddUserPasswordWithSiteName {
-    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("add user password with", "add user password with", None, #[cfg(target_os = "windows")] center)
             .input("user identifier", &mut self.user_identifier)
             .sensitive_input("password", &mut self.password)
+            .password_generator(&mut self.generator)
             .set_database(data_base)
             .set_key(key)
             .execute(|inputs, data_base, key, _| {
@@ -507,7 +722,7 @@ impl AddUserPasswordWithSiteName {
                     wrapped_session_key,
                     session_key_nonce,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -520,14 +735,16 @@ impl AddUserPasswordWithSiteName {
 pub struct ChangeUserPasswordWithSiteName {
     user_identifier: String,
     password: String,
+    generator: PasswordGenerator,
     error_message: String,
 }
 
 impl ChangeUserPasswordWithSiteName {
-    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("change user password", "change user password", None, #[cfg(target_os = "windows")] center)
             .input("user identifier", &mut self.user_identifier)
             .sensitive_input("password", &mut self.password)
+            .password_generator(&mut self.generator)
             .set_database(data_base)
             .set_key(key)
             .execute(|inputs, data_base, key, _| {
@@ -544,7 +761,7 @@ impl ChangeUserPasswordWithSiteName {
                     wrapped_session_key,
                     session_key_nonce,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -560,14 +777,14 @@ pub struct RemoveUserPasswordWithSiteName {
 }
 
 impl RemoveUserPasswordWithSiteName {
-    pub fn display(&mut self, ui: &Ui, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, data_base: &mut DB, site_name: &SiteName, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("remove user password", "remove user password", None, #[cfg(target_os = "windows")] center)
             .input("user identifier", &mut self.user_identifier)
             .set_database(data_base)
             .execute(|inputs, data_base, _, _| {
                 let user_identifier = UserID::new(inputs[0].value)?;
                 remove_user_pw(data_base.expect("unreachable"), site_name, &user_identifier)?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -579,13 +796,15 @@ impl RemoveUserPasswordWithSiteName {
 #[derive(Default)]
 pub struct ChangeUserPasswordWithSiteNameWithUserIdentifier {
     password: String,
+    generator: PasswordGenerator,
     error_message: String,
 }
 
 impl ChangeUserPasswordWithSiteNameWithUserIdentifier {
-    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("change user password", "change user password", None, #[cfg(target_os = "windows")] center)
             .sensitive_input("password", &mut self.password)
+            .password_generator(&mut self.generator)
             .set_database(data_base)
             .set_key(key)
             .execute(|inputs, data_base, key, _| {
@@ -601,7 +820,7 @@ impl ChangeUserPasswordWithSiteNameWithUserIdentifier {
                     wrapped_session_key,
                     session_key_nonce,
                 )?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -616,12 +835,12 @@ pub struct RemoveUserPasswordWithSiteNameWithUserIdentifier {
 }
 
 impl RemoveUserPasswordWithSiteNameWithUserIdentifier {
-    pub fn display(&mut self, ui: &Ui, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
         CommandBuilder::new("remove user password", "remove user password", None, #[cfg(target_os = "windows")] center)
             .set_database(data_base)
             .execute(|_, data_base, _, _| {
                 remove_user_pw(data_base.expect("unreachable"), site_name, user_identifier)?;
-                mark_as_ungraceful_exited_to_file()?;
+                mark_as_ungraceful_exited_to_file(vault)?;
                 Ok(())
             })
             .on_success(|_| {})
@@ -630,21 +849,503 @@ impl RemoveUserPasswordWithSiteNameWithUserIdentifier {
     }
 }
 
+/// 비밀번호 추가/변경 창에서 여는 생성기 창.
+/// "use"를 누르면 만든 비밀번호를 돌려주고 닫힘
+pub struct PasswordGenerator {
+    open: bool,
+    passphrase: bool,
+    charset: CharsetPolicy,
+    passphrase_policy: PassphrasePolicy,
+    /// 고른 문자 종류가 각각 한 글자 이상 들어가게 함
+    require_each: bool,
+    generated: Option<Generated>,
+    error_message: String,
+}
+
+impl Default for PasswordGenerator {
+    fn default() -> Self {
+        Self {
+            open: false,
+            passphrase: false,
+            charset: CharsetPolicy::default(),
+            passphrase_policy: PassphrasePolicy::default(),
+            require_each: true,
+            generated: None,
+            error_message: String::new(),
+        }
+    }
+}
+
+impl PasswordGenerator {
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn policy(&self) -> GeneratorPolicy {
+        if self.passphrase {
+            return GeneratorPolicy::Passphrase(self.passphrase_policy.clone());
+        }
+        let mut charset = self.charset.clone();
+        charset.required = if self.require_each { charset.classes.clone() } else { Vec::new() };
+        GeneratorPolicy::Charset(charset)
+    }
+
+    pub fn display(&mut self, ui: &Ui, owner_title: &str, #[cfg(target_os = "windows")] center: [i32; 2]) -> Option<UserPW> {
+        let mut chosen = None;
+
+        let size = [360.0, 280.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("password generator")
+            .with_inner_size(size);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of(format!("{}_password_generator", owner_title)),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    self.open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.passphrase, false, "characters");
+                        ui.radio_value(&mut self.passphrase, true, "passphrase");
+                    });
+
+                    if self.passphrase {
+                        ui.add(egui::Slider::new(&mut self.passphrase_policy.word_count, MIN_WORD_COUNT..=MAX_WORD_COUNT).text("words"));
+                        ui.horizontal(|ui| {
+                            ui.label("separator");
+                            ui.add(TextEdit::singleline(&mut self.passphrase_policy.separator).desired_width(40.0));
+                        });
+                        ui.checkbox(&mut self.passphrase_policy.capitalize, "capitalize words");
+                    } else {
+                        ui.add(egui::Slider::new(&mut self.charset.length, MIN_PW_LENGTH..=MAX_PW_LENGTH).text("length"));
+                        ui.horizontal(|ui| {
+                            for class in CharClass::ALL {
+                                let mut used = self.charset.classes.contains(&class);
+                                if ui.checkbox(&mut used, class.to_string()).changed() {
+                                    if used {
+                                        self.charset.classes.push(class);
+                                    } else {
+                                        self.charset.classes.retain(|c| *c != class);
+                                    }
+                                }
+                            }
+                        });
+                        ui.checkbox(&mut self.require_each, "use every selected class");
+                        ui.checkbox(&mut self.charset.exclude_look_alikes, "exclude look-alikes (I l 1 O 0 o)");
+                    }
+
+                    if ui.button("generate").clicked() {
+                        match generate(&self.policy()) {
+                            Ok(generated) => {
+                                self.generated = Some(generated);
+                                self.error_message.clear();
+                            }
+                            Err(error) => {
+                                self.generated = None;
+                                self.error_message = error.to_string();
+                            }
+                        }
+                    }
+
+                    let mut use_generated = false;
+                    if let Some(generated) = &self.generated {
+                        ui.monospace(generated.pw.as_str());
+                        ui.label(format!("entropy: {:.1} bits", generated.entropy_bits));
+                        use_generated = ui.button("use").clicked();
+                    }
+                    if use_generated {
+                        chosen = self.generated.take().map(|generated| generated.pw);
+                        self.open = false;
+                    }
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        chosen
+    }
+}
+
+/// 계정 하나의 메모, URL, 사용자 정의 필드, 태그, 폴더 편집 창
+pub struct EditEntry {
+    details: EntryDetails,
+    /// 한 줄에 URL 하나
+    urls: String,
+    breach_status: String,
+    /// 코드를 계속 보여주려고 복호화해 둔 것. 창을 닫으면 지워짐
+    otp: Option<OtpConfig>,
+    otp_input: String,
+    /// 쉼표로 구분
+    tags: String,
+    /// `a/b/c` 꼴. 비어 있으면 최상위
+    folder: String,
+    favourite: bool,
+    error_message: String,
+}
+
+impl EditEntry {
+    pub fn new(data_base: &DB, key: &KeyPair, site_name: &SiteName, user_identifier: &UserID) -> Self {
+        let (wrapped_session_key, session_key_nonce) = key;
+        let (details, mut error_message) = match read_entry_details(data_base, site_name, user_identifier, wrapped_session_key, session_key_nonce) {
+            Ok(details) => (details, String::new()),
+            Err(error) => (EntryDetails::default(), error.to_string()),
+        };
+        let otp = read_otp(data_base, site_name, user_identifier, wrapped_session_key, session_key_nonce).unwrap_or_else(|error| {
+            error_message = error.to_string();
+            None
+        });
+        let (tags, folder, favourite) = match get_entry(data_base, site_name, user_identifier) {
+            Ok(entry) => {
+                let folder = if entry.folder().is_root() { String::new() } else { entry.folder().to_string() };
+                (entry.tags().join(", "), folder, entry.is_favourite())
+            }
+            Err(_) => (String::new(), String::new(), false),
+        };
+        Self {
+            urls: details.urls.join("\n"),
+            details,
+            breach_status: String::new(),
+            otp,
+            otp_input: String::new(),
+            tags,
+            folder,
+            favourite,
+            error_message,
+        }
+    }
+
+    /// `hibp_path`는 모든 계정 창이 같이 씀
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, read_only: bool, hibp_path: &mut String, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+        let mut keep_open = true;
+
+        let size = [460.0, 620.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title(format!("{} - {}", site_name.as_str(), user_identifier.as_str()))
+            .with_inner_size(size);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of(format!("{}_{}_edit_entry", site_name.as_str(), user_identifier.as_str())),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    if let Ok(entry) = get_entry(data_base, site_name, user_identifier) {
+                        ui.label(format!("created: {}", entry.created()));
+                        ui.label(format!("modified: {}", entry.modified()));
+                        ui.label(format!(
+                            "last used: {}",
+                            entry.last_used().map(|last_used| last_used.to_string()).unwrap_or_else(|| "never".to_string())
+                        ));
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("breaches");
+                        ui.add(TextEdit::singleline(hibp_path).desired_width(220.0).hint_text("HIBP SHA-1 file or directory"));
+                        if ui.button("check").on_hover_text("네트워크를 쓰지 않고 로컬 목록에서 찾음").clicked() {
+                            let (wrapped_session_key, session_key_nonce) = key;
+                            self.breach_status = match BreachList::open(Path::new(hibp_path.trim()))
+                                .and_then(|breach_list| check_entry(&breach_list, data_base, site_name, user_identifier, wrapped_session_key, session_key_nonce))
+                            {
+                                Ok(0) => "유출 목록에 없습니다".to_string(),
+                                Ok(count) => format!("유출 목록에서 {}번 발견됨. 비밀번호를 바꾸세요", count),
+                                Err(error) => error.to_string(),
+                            };
+                        }
+                    });
+                    ui.label(&self.breach_status);
+                    self.display_otp(ui, vault, key, data_base, site_name, user_identifier, read_only);
+                    ui.separator();
+
+                    egui::ScrollArea::vertical().max_height(size[1] - 160.0).show(ui, |ui| {
+                        ui.add_enabled_ui(!read_only, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("folder");
+                                ui.add(TextEdit::singleline(&mut self.folder).desired_width(220.0).hint_text("a/b/c"));
+                                ui.checkbox(&mut self.favourite, "favourite");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("tags");
+                                ui.add(TextEdit::singleline(&mut self.tags).desired_width(320.0).hint_text("쉼표로 구분"));
+                            });
+
+                            ui.label("urls (한 줄에 하나)");
+                            ui.add(TextEdit::multiline(&mut self.urls).desired_rows(2));
+
+                            ui.label("custom fields");
+                            let mut removed = None;
+                            for (index, field) in self.details.custom_fields.iter_mut().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.add(TextEdit::singleline(&mut field.name).desired_width(110.0).hint_text("name"));
+                                    egui::ComboBox::from_id_salt(("custom_field_kind", index))
+                                        .selected_text(field.kind.to_string())
+                                        .width(70.0)
+                                        .show_ui(ui, |ui| {
+                                            for kind in CustomFieldKind::ALL {
+                                                ui.selectable_value(&mut field.kind, kind, kind.to_string());
+                                            }
+                                        });
+                                    let hidden = field.kind == CustomFieldKind::Hidden;
+                                    ui.add(TextEdit::singleline(&mut *field.value).password(hidden).desired_width(150.0).hint_text("value"));
+                                    if ui.button("remove").clicked() {
+                                        removed = Some(index);
+                                    }
+                                });
+                            }
+                            if let Some(index) = removed {
+                                self.details.custom_fields.remove(index);
+                            }
+                            if ui.button("add field").clicked() {
+                                self.details.custom_fields.push(CustomField::default());
+                            }
+
+                            ui.label("notes");
+                            ui.add(TextEdit::multiline(&mut *self.details.notes).desired_rows(5));
+                        });
+                    });
+
+                    ui.label(&self.error_message);
+                    if ui.add_enabled(!read_only, egui::Button::new("save")).clicked() {
+                        self.details.urls = self.urls.lines().map(str::trim).filter(|url| !url.is_empty()).map(str::to_owned).collect();
+                        let (wrapped_session_key, session_key_nonce) = key;
+                        let result = (|| -> Result<(), Error> {
+                            // 일부만 저장되지 않도록 태그와 폴더를 먼저 검사함
+                            let folder = FolderPath::new(&self.folder)?;
+                            let tags = self.tags
+                                .split(',')
+                                .filter(|tag| !tag.trim().is_empty())
+                                .map(normalize_tag)
+                                .collect::<Result<Vec<_>, _>>()?;
+                            update_entry_details(data_base, site_name, user_identifier, &self.details, wrapped_session_key, session_key_nonce)?;
+                            set_tags(data_base, site_name, user_identifier, &tags)?;
+                            set_folder(data_base, site_name, user_identifier, folder)?;
+                            set_favourite(data_base, site_name, user_identifier, self.favourite)?;
+                            mark_as_ungraceful_exited_to_file(vault)?;
+                            Ok(())
+                        })();
+                        match result {
+                            Ok(()) => keep_open = false,
+                            Err(error) => self.error_message = error.to_string(),
+                        }
+                    }
+                });
+            },
+        );
+
+        keep_open
+    }
+
+    fn display_otp(&mut self, ui: &mut Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, read_only: bool) {
+        let (wrapped_session_key, session_key_nonce) = key;
+        ui.horizontal(|ui| {
+            ui.label("one-time code");
+            let Some(otp) = &self.otp else {
+                ui.label("없음");
+                return;
+            };
+            let kind = otp.kind;
+            match kind {
+                OtpKind::Totp { period } => {
+                    let now = Timestamp::now().unix_secs();
+                    let code = otp.code_at(now);
+                    let remaining = otp.remaining_secs(now).unwrap_or(period);
+                    ui.monospace(code.as_str());
+                    ui.add(egui::ProgressBar::new(remaining as f32 / period as f32).desired_width(90.0).text(format!("{}s", remaining)));
+                    if ui.button("copy").clicked() {
+                        ui.copy_text(code.as_str().to_string());
+                    }
+                    // 코드와 남은 시간이 바뀌도록 다시 그림
+                    ui.ctx().request_repaint_after(Duration::from_secs(1));
+                }
+                OtpKind::Hotp { counter } => {
+                    ui.label(format!("HOTP, counter {}", counter));
+                    if ui.add_enabled(!read_only, egui::Button::new("copy next code")).on_hover_text("카운터가 올라갑니다").clicked() {
+                        let result = (|| -> Result<Option<OtpConfig>, Error> {
+                            let generated = generate_otp_code(data_base, site_name, user_identifier, wrapped_session_key, session_key_nonce)?;
+                            ui.copy_text(generated.code.as_str().to_string());
+                            mark_as_ungraceful_exited_to_file(vault)?;
+                            Ok(read_otp(data_base, site_name, user_identifier, wrapped_session_key, session_key_nonce)?)
+                        })();
+                        match result {
+                            Ok(otp) => self.otp = otp,
+                            Err(error) => self.error_message = error.to_string(),
+                        }
+                    }
+                }
+            }
+        });
+
+        ui.add_enabled_ui(!read_only, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut self.otp_input).password(true).desired_width(220.0).hint_text("otpauth:// URI or secret"));
+                let set = ui.button("set").clicked();
+                let remove = self.otp.is_some() && ui.button("remove").clicked();
+                if !set && !remove {
+                    return;
+                }
+                let result = (|| -> Result<Option<OtpConfig>, Error> {
+                    let otp = if set { Some(OtpConfig::parse(&self.otp_input)?) } else { None };
+                    set_otp(data_base, site_name, user_identifier, otp.as_ref(), wrapped_session_key, session_key_nonce)?;
+                    mark_as_ungraceful_exited_to_file(vault)?;
+                    Ok(otp)
+                })();
+                self.otp_input.zeroize();
+                match result {
+                    Ok(otp) => {
+                        self.otp = otp;
+                        self.error_message.clear();
+                    }
+                    Err(error) => self.error_message = error.to_string(),
+                }
+            });
+        });
+    }
+}
+
+/// 계정 하나의 이전 비밀번호 목록. 복사, 보기, 복원
+#[derive(Default)]
+pub struct PasswordHistory {
+    error_message: String,
+}
+
+impl PasswordHistory {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key: &KeyPair, data_base: &mut DB, site_name: &SiteName, user_identifier: &UserID, read_only: bool, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+        let mut keep_open = true;
+
+        let size = [460.0, 320.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title(format!("{} - {} history", site_name.as_str(), user_identifier.as_str()))
+            .with_inner_size(size);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of(format!("{}_{}_password_history", site_name.as_str(), user_identifier.as_str())),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    let (wrapped_session_key, session_key_nonce) = key;
+                    let Ok(entry) = get_entry(data_base, site_name, user_identifier) else {
+                        keep_open = false;
+                        return;
+                    };
+                    ui.label(format!("current password since {}", entry.pw_changed()));
+                    if entry.pw_history().is_empty() {
+                        ui.label("이전 비밀번호가 없습니다");
+                    }
+                    let periods: Vec<_> = entry.pw_history().iter().map(|past| format!("{} ~ {}", past.set_at(), past.replaced_at())).collect();
+
+                    let mut restore = None;
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for (index, period) in periods.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(period);
+                                if ui.button("copy").clicked() {
+                                    match get_past_user_pw(data_base, site_name, user_identifier, index, wrapped_session_key, session_key_nonce) {
+                                        Ok(password) => ui.copy_text(password.as_str().to_string()),
+                                        Err(error) => self.error_message = error.to_string(),
+                                    }
+                                }
+                                if ui.button("view").is_pointer_button_down_on() {
+                                    match get_past_user_pw(data_base, site_name, user_identifier, index, wrapped_session_key, session_key_nonce) {
+                                        Ok(mut password) => {
+                                            ui.label(password.as_str());
+                                            password.zeroize();
+                                        }
+                                        Err(error) => self.error_message = error.to_string(),
+                                    }
+                                }
+                                if ui.add_enabled(!read_only, egui::Button::new("restore")).on_hover_text("지금 비밀번호는 기록에 남습니다").clicked() {
+                                    restore = Some(index);
+                                }
+                            });
+                        }
+                    });
+
+                    if let Some(index) = restore {
+                        let result = (|| -> Result<(), Error> {
+                            restore_user_pw(data_base, site_name, user_identifier, index, wrapped_session_key, session_key_nonce)?;
+                            mark_as_ungraceful_exited_to_file(vault)?;
+                            Ok(())
+                        })();
+                        match result {
+                            Ok(()) => self.error_message.clear(),
+                            Err(error) => self.error_message = error.to_string(),
+                        }
+                    }
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        keep_open
+    }
+}
+
 #[derive(Default)]
 pub struct ChangeMasterPassword {
     password: String,
+    strength: Option<StrengthEstimate>,
     error_message: String,
     loading: bool,
+    /// 복구 코드나 조각으로 연 뒤에는 새 비밀번호를 정하기 전에 닫을 수 없고, 닫으면 앱을 끝냄
+    required: bool,
 }
 
 impl ChangeMasterPassword {
+    pub fn after_recovery() -> Self {
+        Self {
+            required: true,
+            ..Self::default()
+        }
+    }
+
     pub fn display(
         &mut self,
         ui: &Ui,
-        data_base: &mut DB,
-        key: &mut KeyPair,
+        vault: &VaultHandle,
+        data_base: &DB,
+        secret_key: &SecKey,
         data_base_header: &mut DBHeader,
-        graphical_user_interface_public_key: &mut Option<PubKey>
+        public_key: &PubKey,
+        key_file: Option<&KeyFile>
     ) -> bool {
         let mut keep_open = true;
 
@@ -653,17 +1354,27 @@ impl ChangeMasterPassword {
             ViewportBuilder::default().with_title("change master password").with_resizable(false),
             |ui, _| {
                 if ui.input(|i| i.viewport().close_requested()) {
-                    keep_open = false;
+                    if self.required {
+                        ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
+                    } else {
+                        keep_open = false;
+                    }
                     return;
                 }
 
                 egui::CentralPanel::default().show_inside(ui, |ui| {
                     ui.label("change master password");
+                    if self.required {
+                        ui.label("복구 코드나 조각으로 열었습니다. 새 마스터 비밀번호를 정해야 계속할 수 있습니다");
+                    }
 
                     ui.horizontal(|ui| {
                         ui.label("master password");
-                        ui.add(TextEdit::singleline(&mut self.password).password(true));
+                        if ui.add(TextEdit::singleline(&mut self.password).password(true)).changed() {
+                            self.strength = (!self.password.is_empty()).then(|| estimate_strength(&self.password));
+                        }
                     });
+                    show_strength(ui, &self.strength);
 
                     ui.label(&*self.error_message);
 
@@ -679,39 +1390,26 @@ impl ChangeMasterPassword {
                         }
 
                         // execute
-                        let result = (|| -> Result<(), Error> {
-                            if let Err(error) = master_pw_validation(&self.password) {
-                                return Err(error.into());
-                            }
-                            let (wrapped_session_key, session_key_nonce) = key;
-                            let (public_key, salt) = change_master_pw(
-                                data_base,
-                                &mut self.password,
-                                wrapped_session_key,
-                                session_key_nonce,
-                            )?;
-
-                            data_base_header.master_pw_salt = salt;
-                            *graphical_user_interface_public_key = Some(public_key);
-
-                            save_db(
-                                data_base_header,
-                                encrypt_db(
-                                    data_base,
-                                    graphical_user_interface_public_key
-                                        .as_ref()
-                                        .expect("unreachable"),
-                                ),
-                            )?;
-                            mark_as_graceful_exited_to_file()?;
-                            Ok(())
+                        let result = (|| -> Result<Option<FileIOWarn>, Error> {
+                            check_new_master_pw(&self.password, &MasterPWPolicy::default())?;
+                            change_master_pw(data_base_header, &mut self.password, key_file, secret_key);
+
+                            let encrypted_data_base = encrypt_db(data_base, data_base_header, public_key);
+                            let save_warning = save_db(vault, data_base_header, encrypted_data_base)?;
+                            mark_as_graceful_exited_to_file(vault)?;
+                            Ok(save_warning)
                         })();
 
                         // zeroize는 성공/실패 무관하게
                         self.password.zeroize();
+                        self.strength = None;
 
                         match result {
-                            Ok(_) => {
+                            // 바뀐 비밀번호는 저장됐으므로 창을 열어 둔 채 경고만 보여줌
+                            Ok(Some(save_warning)) => {
+                                self.error_message = save_warning.to_string();
+                            }
+                            Ok(None) => {
                                 self.error_message.clear();
                                 keep_open = false;
                             }
@@ -735,8 +1433,930 @@ impl ChangeMasterPassword {
     }
 }
 
-pub fn exit_root(ui: &Ui, root_window: &mut Option<RootSave>) {
-    if check_can_directly_exit() {
+#[derive(Default)]
+pub struct KeyFileSettings {
+    password: String,
+    key_file_path: String,
+    generate_key_file: bool,
+    error_message: String,
+}
+
+impl KeyFileSettings {
+    /// 키 파일을 추가, 교체하거나 없앰. 바꾸면 바로 저장함
+    pub fn display(
+        &mut self,
+        ui: &Ui,
+        vault: &VaultHandle,
+        data_base: &DB,
+        secret_key: &SecKey,
+        data_base_header: &mut DBHeader,
+        public_key: &PubKey,
+        graphical_user_interface_key_file: &mut Option<KeyFile>
+    ) -> bool {
+        let mut keep_open = true;
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("key_file"),
+            ViewportBuilder::default().with_title("key file").with_resizable(false),
+            |ui, _| {
+                if ui.input(|i| i.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    if data_base_header.requires_key_file() {
+                        ui.label("이 볼트는 마스터 비밀번호와 키 파일이 있어야 열립니다");
+                    } else {
+                        ui.label("이 볼트는 마스터 비밀번호만으로 열립니다");
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("master password");
+                        ui.add(TextEdit::singleline(&mut self.password).password(true));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("new key file");
+                        ui.add(TextEdit::singleline(&mut self.key_file_path).hint_text("path to the key file"));
+                    });
+                    ui.checkbox(&mut self.generate_key_file, "create a new random key file");
+                    ui.label(&*self.error_message);
+
+                    let (set_button, remove_button) = ui.horizontal(|ui| {
+                        let set_button = ui.add_enabled(!self.key_file_path.trim().is_empty(), egui::Button::new("set key file"));
+                        let remove_button = ui.add_enabled(data_base_header.requires_key_file(), egui::Button::new("remove key file"));
+                        (set_button, remove_button)
+                    }).inner;
+                    if !set_button.clicked() && !remove_button.clicked() {
+                        return;
+                    }
+
+                    let result = (|| -> Result<(), Error> {
+                        verify_master_pw(&self.password, data_base_header, graphical_user_interface_key_file.as_ref(), public_key)?;
+                        let key_file_path = Path::new(self.key_file_path.trim());
+                        let new_key_file = if remove_button.clicked() {
+                            None
+                        } else if self.generate_key_file {
+                            Some(KeyFile::generate(key_file_path)?)
+                        } else {
+                            Some(KeyFile::read(key_file_path)?)
+                        };
+
+                        change_key_file(data_base_header, &mut self.password, new_key_file.as_ref(), secret_key);
+                        *graphical_user_interface_key_file = new_key_file;
+
+                        let encrypted_data_base = encrypt_db(data_base, data_base_header, public_key);
+                        let save_warning = save_db(vault, data_base_header, encrypted_data_base)?;
+                        mark_as_graceful_exited_to_file(vault)?;
+                        Ok(save_warning)
+                    })();
+
+                    // zeroize는 성공/실패 무관하게
+                    self.password.zeroize();
+
+                    match result {
+                        Ok(Some(save_warning)) => {
+                            self.error_message = save_warning.to_string();
+                        }
+                        Ok(None) => {
+                            self.error_message.clear();
+                            keep_open = false;
+                        }
+                        Err(err) => {
+                            self.error_message = err.to_string();
+                        }
+                    }
+                });
+            },
+        );
+
+        keep_open
+    }
+}
+
+pub struct KeySlotsSettings {
+    password: String,
+    new_slot_kind: KeySlotKind,
+    label: String,
+    slot_password: String,
+    recheck_slot_password: String,
+    /// 키 파일 슬롯의 키 파일 경로
+    slot_key_file_path: String,
+    generate_key_file: bool,
+    /// 팀원이 보낸 공개키 텍스트
+    teammate_key: String,
+    /// 이름을 바꾸는 중인 슬롯과 새 이름
+    renaming: Option<(usize, String)>,
+    /// 방금 만든 복구 코드 슬롯의 비상 키트. 창을 닫으면 버림
+    emergency_kit: Option<Zeroizing<String>>,
+    emergency_kit_path: String,
+    /// 복구 조각 슬롯을 열 조각 수와 나눌 조각 수
+    threshold: u8,
+    count: u8,
+    /// 방금 만든 복구 조각 슬롯의 조각마다의 안내문. 창을 닫으면 버림
+    share_sheets: Vec<Zeroizing<String>>,
+    share_directory: String,
+    /// 내 키 파일에서 팀원 공개키를 만들 때 씀
+    own_key_file_path: String,
+    own_teammate_key: String,
+    error_message: String,
+}
+
+impl Default for KeySlotsSettings {
+    fn default() -> Self {
+        Self {
+            password: String::new(),
+            new_slot_kind: KeySlotKind::Password,
+            label: String::new(),
+            slot_password: String::new(),
+            recheck_slot_password: String::new(),
+            slot_key_file_path: String::new(),
+            generate_key_file: false,
+            teammate_key: String::new(),
+            renaming: None,
+            emergency_kit: None,
+            emergency_kit_path: String::new(),
+            threshold: 3,
+            count: 5,
+            share_sheets: Vec::new(),
+            share_directory: String::new(),
+            own_key_file_path: String::new(),
+            own_teammate_key: String::new(),
+            error_message: String::new(),
+        }
+    }
+}
+
+impl KeySlotsSettings {
+    /// 볼트를 여는 키 슬롯을 추가하거나 이름을 바꾸거나 없앰. 마스터 비밀번호 슬롯은 없앨 수 없음. 바꾸면 바로 저장함
+    pub fn display(
+        &mut self,
+        ui: &Ui,
+        vault: &VaultHandle,
+        data_base: &DB,
+        data_base_header: &mut DBHeader,
+        graphical_user_interface_public_key: &Option<PubKey>,
+        key_file: Option<&KeyFile>
+    ) -> bool {
+        let mut keep_open = true;
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("key_slots"),
+            ViewportBuilder::default().with_title("key slots").with_resizable(false),
+            |ui, _| {
+                if ui.input(|i| i.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    let key_slots = list_key_slots(data_base_header);
+                    let mut rename_clicked = false;
+                    let mut revoke_index = None;
+                    if key_slots.len() == 1 {
+                        ui.label("다른 키 슬롯이 없습니다. 마스터 비밀번호로만 볼트를 열 수 있습니다");
+                    }
+                    egui::Grid::new("key_slot_list").striped(true).show(ui, |ui| {
+                        for slot in &key_slots {
+                            ui.label(slot.index.to_string());
+                            ui.label(slot.kind.to_string());
+                            if let Some((_, label)) = self.renaming.as_mut().filter(|(index, _)| *index == slot.index) {
+                                ui.add(TextEdit::singleline(label).char_limit(KEY_SLOT_LABEL_LEN));
+                                rename_clicked = ui.button("save").clicked();
+                            } else {
+                                ui.label(&slot.label);
+                                if ui.button("rename").clicked() {
+                                    self.renaming = Some((slot.index, slot.label.clone()));
+                                }
+                            }
+                            if ui.add_enabled(slot.index != MASTER_SLOT, egui::Button::new("revoke")).on_hover_text("마스터 비밀번호를 입력해야 함").clicked() {
+                                revoke_index = Some(slot.index);
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("master password");
+                        ui.add(TextEdit::singleline(&mut self.password).password(true));
+                    });
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("new_key_slot_kind")
+                            .selected_text(self.new_slot_kind.to_string())
+                            .show_ui(ui, |ui| {
+                                for kind in [KeySlotKind::Password, KeySlotKind::KeyFile, KeySlotKind::RecoveryCode, KeySlotKind::RecoveryShares, KeySlotKind::Teammate] {
+                                    ui.selectable_value(&mut self.new_slot_kind, kind, kind.to_string());
+                                }
+                            });
+                        ui.add(TextEdit::singleline(&mut self.label).char_limit(KEY_SLOT_LABEL_LEN).hint_text("label"));
+                    });
+                    match self.new_slot_kind {
+                        KeySlotKind::Password => {
+                            ui.add(TextEdit::singleline(&mut self.slot_password).password(true).hint_text("slot password"));
+                            ui.add(TextEdit::singleline(&mut self.recheck_slot_password).password(true).hint_text("confirm slot password"));
+                        }
+                        KeySlotKind::KeyFile => {
+                            ui.add(TextEdit::singleline(&mut self.slot_key_file_path).hint_text("path to the key file"));
+                            ui.checkbox(&mut self.generate_key_file, "create a new random key file");
+                        }
+                        KeySlotKind::RecoveryCode => {
+                            ui.label("새 복구 코드와 비상 키트를 만듭니다");
+                        }
+                        KeySlotKind::RecoveryShares => {
+                            ui.horizontal(|ui| {
+                                ui.add(egui::DragValue::new(&mut self.threshold).range(2..=self.count.max(2)).prefix("any "));
+                                ui.add(egui::DragValue::new(&mut self.count).range(2..=u8::MAX).prefix("of "));
+                                ui.label("shares unlock");
+                            });
+                            self.threshold = self.threshold.min(self.count);
+                        }
+                        KeySlotKind::Teammate => {
+                            ui.add(TextEdit::singleline(&mut self.teammate_key).hint_text("teammate public key"));
+                        }
+                        // 마스터 비밀번호 슬롯의 종류라 목록에 없음
+                        KeySlotKind::PasswordAndKeyFile => {}
+                    }
+                    let add_button = ui.button("add key slot");
+                    ui.label(&*self.error_message);
+
+                    if let Some(emergency_kit) = &self.emergency_kit {
+                        ui.separator();
+                        ui.label("인쇄하거나 오프라인으로 보관하세요. 이 창을 닫으면 다시 볼 수 없습니다");
+                        ui.add(TextEdit::multiline(&mut emergency_kit.as_str()).font(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
+                        ui.horizontal(|ui| {
+                            ui.add(TextEdit::singleline(&mut self.emergency_kit_path).hint_text("path to save the emergency kit"));
+                            if ui.add_enabled(!self.emergency_kit_path.trim().is_empty(), egui::Button::new("save")).clicked() {
+                                self.error_message = match fs::write(self.emergency_kit_path.trim(), emergency_kit.as_bytes()) {
+                                    Ok(_) => "saved emergency kit".to_string(),
+                                    Err(err) => err.to_string(),
+                                };
+                            }
+                        });
+                    }
+                    if !self.share_sheets.is_empty() {
+                        ui.separator();
+                        ui.label("조각마다 다른 사람에게 오프라인으로 전하세요. 이 창을 닫으면 다시 볼 수 없습니다");
+                        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                            for sheet in &self.share_sheets {
+                                ui.add(TextEdit::multiline(&mut sheet.as_str()).font(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(TextEdit::singleline(&mut self.share_directory).hint_text("directory to save one file per share"));
+                            if ui.add_enabled(!self.share_directory.trim().is_empty(), egui::Button::new("save")).clicked() {
+                                let directory = Path::new(self.share_directory.trim());
+                                let saved = fs::create_dir_all(directory).and_then(|_| {
+                                    self.share_sheets.iter().enumerate().try_for_each(|(i, sheet)| {
+                                        fs::write(directory.join(format!("share_{}.txt", i + 1)), sheet.as_bytes())
+                                    })
+                                });
+                                self.error_message = match saved {
+                                    Ok(_) => "saved recovery shares".to_string(),
+                                    Err(err) => err.to_string(),
+                                };
+                            }
+                        });
+                    }
+
+                    ui.separator();
+                    ui.label("다른 볼트의 팀원 슬롯에 쓸 내 공개키");
+                    ui.horizontal(|ui| {
+                        ui.add(TextEdit::singleline(&mut self.own_key_file_path).hint_text("path to my key file"));
+                        if ui.add_enabled(!self.own_key_file_path.trim().is_empty(), egui::Button::new("show public key")).clicked() {
+                            match KeyFile::read(Path::new(self.own_key_file_path.trim())) {
+                                Ok(own_key_file) => self.own_teammate_key = TeammateKey::from_key_file(&own_key_file).to_text(),
+                                Err(err) => self.error_message = err.to_string(),
+                            }
+                        }
+                    });
+                    if !self.own_teammate_key.is_empty() {
+                        ui.add(TextEdit::singleline(&mut self.own_teammate_key.as_str()).font(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
+                    }
+
+                    if !rename_clicked && revoke_index.is_none() && !add_button.clicked() {
+                        return;
+                    }
+
+                    // 새 복구 코드 슬롯의 비상 키트와 새 복구 조각 슬롯의 안내문
+                    let result = (|| -> Result<(Option<Zeroizing<String>>, Vec<Zeroizing<String>>, Option<FileIOWarn>), Error> {
+                        let public_key = graphical_user_interface_public_key.as_ref().ok_or(anyhow!("no public key"))?;
+                        let mut new_emergency_kit = None;
+                        let mut new_share_sheets = Vec::new();
+                        if rename_clicked {
+                            if let Some((index, label)) = &self.renaming {
+                                label_key_slot(data_base_header, *index, label)?;
+                                self.renaming = None;
+                            }
+                        } else if let Some(index) = revoke_index {
+                            revoke_key_slot(data_base_header, &mut self.password, key_file, public_key, index)?;
+                        } else {
+                            match self.new_slot_kind {
+                                KeySlotKind::Password => {
+                                    if self.slot_password != self.recheck_slot_password {
+                                        return Err(anyhow!("slot passwords do not match"));
+                                    }
+                                    check_new_master_pw(&self.slot_password, &MasterPWPolicy::default())?;
+                                    add_key_slot(data_base_header, &mut self.password, key_file, public_key, &self.label, NewKeySlot::Password(&mut self.slot_password))?;
+                                }
+                                KeySlotKind::KeyFile => {
+                                    let key_file_path = Path::new(self.slot_key_file_path.trim());
+                                    let slot_key_file = if self.generate_key_file {
+                                        KeyFile::generate(key_file_path)?
+                                    } else {
+                                        KeyFile::read(key_file_path)?
+                                    };
+                                    add_key_slot(data_base_header, &mut self.password, key_file, public_key, &self.label, NewKeySlot::KeyFile(&slot_key_file))?;
+                                }
+                                KeySlotKind::RecoveryCode => {
+                                    let (index, recovery_key) = add_recovery_code_slot(data_base_header, &mut self.password, key_file, public_key, &self.label)?;
+                                    new_emergency_kit = Some(emergency_kit(&recovery_key, index, vault.db_file()));
+                                }
+                                KeySlotKind::RecoveryShares => {
+                                    let (index, shares) = add_recovery_shares_slot(
+                                        data_base_header,
+                                        &mut self.password,
+                                        key_file,
+                                        public_key,
+                                        &self.label,
+                                        self.threshold,
+                                        self.count,
+                                    )?;
+                                    new_share_sheets = shares.iter().map(|share| share_sheet(share, shares.len(), index, vault.db_file())).collect();
+                                }
+                                KeySlotKind::Teammate => {
+                                    let teammate_key = TeammateKey::parse(&self.teammate_key)?;
+                                    add_key_slot(data_base_header, &mut self.password, key_file, public_key, &self.label, NewKeySlot::Teammate(&teammate_key))?;
+                                }
+                                KeySlotKind::PasswordAndKeyFile => return Err(anyhow!("change the key file in the key file settings")),
+                            }
+                            self.label.clear();
+                        }
+
+                        let encrypted_data_base = encrypt_db(data_base, data_base_header, public_key);
+                        let save_warning = save_db(vault, data_base_header, encrypted_data_base)?;
+                        mark_as_graceful_exited_to_file(vault)?;
+                        Ok((new_emergency_kit, new_share_sheets, save_warning))
+                    })();
+
+                    // zeroize는 성공/실패 무관하게
+                    self.password.zeroize();
+                    self.slot_password.zeroize();
+                    self.recheck_slot_password.zeroize();
+
+                    match result {
+                        Ok((new_emergency_kit, new_share_sheets, save_warning)) => {
+                            self.emergency_kit = new_emergency_kit;
+                            self.share_sheets = new_share_sheets;
+                            self.error_message = save_warning.map(|warning| warning.to_string()).unwrap_or_default();
+                        }
+                        Err(err) => {
+                            self.error_message = err.to_string();
+                        }
+                    }
+                });
+            },
+        );
+
+        keep_open
+    }
+}
+
+pub enum QuarantineRecoveryType {
+    Reload,
+    OpenReadOnly(DBHeader, EncryptedDB),
+}
+
+/// 손상된 DB 파일이 격리되었을 때 복구 방법을 고르는 창
+pub struct QuarantineRecovery {
+    quarantine_path: PathBuf,
+    cause: String,
+    backup_path: String,
+    error_message: String,
+}
+
+impl QuarantineRecovery {
+    pub fn new(quarantine_path: PathBuf, cause: String) -> Self {
+        Self {
+            quarantine_path,
+            cause,
+            backup_path: String::new(),
+            error_message: String::new(),
+        }
+    }
+
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, root_window: &mut Option<RootSave>, #[cfg(target_os = "windows")] center: [i32; 2]) -> Option<QuarantineRecoveryType> {
+        let mut recovery_type = None;
+
+        let size = [420.0, 230.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("DB 복구")
+            .with_inner_size(size)
+            .with_resizable(false)
+            .with_maximize_button(false);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("quarantine_recovery"),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    exit_root(ui, vault, root_window);
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    ui.label(format!("DB 파일이 손상되었습니다: {}", self.cause));
+                    ui.label(format!("원본은 {}에 보관되어 있습니다.", self.quarantine_path.display()));
+                    ui.horizontal(|ui| {
+                        if ui.button("retry").clicked() {
+                            match restore_db_from(vault, &self.quarantine_path) {
+                                Ok(()) => recovery_type = Some(QuarantineRecoveryType::Reload),
+                                Err(error) => self.error_message = error.to_string(),
+                            }
+                        }
+                        if ui.button("open read-only").clicked() {
+                            match open_db_read_only(&self.quarantine_path) {
+                                Ok((header, encrypted_data_base)) => {
+                                    recovery_type = Some(QuarantineRecoveryType::OpenReadOnly(header, encrypted_data_base));
+                                }
+                                Err(error) => self.error_message = error.to_string(),
+                            }
+                        }
+                    });
+                    ui.label("backup file path");
+                    ui.horizontal(|ui| {
+                        ui.add(TextEdit::singleline(&mut self.backup_path));
+                        if ui.button("restore").clicked() {
+                            match restore_db_from(vault, Path::new(self.backup_path.trim())) {
+                                Ok(()) => recovery_type = Some(QuarantineRecoveryType::Reload),
+                                Err(error) => self.error_message = error.to_string(),
+                            }
+                        }
+                    });
+                    if ui.button("start new data base").on_hover_text("격리된 파일은 지우지 않습니다").clicked() {
+                        recovery_type = Some(QuarantineRecoveryType::Reload);
+                    }
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        recovery_type
+    }
+}
+
+pub enum BackupsType {
+    Close,
+    /// DB 파일이 스냅샷으로 교체되었으므로 다시 로그인해야 함
+    Restored,
+}
+
+/// 백업 스냅샷 목록. 스냅샷 당시의 마스터 비밀번호로 내용을 확인하거나 복원함
+pub struct Backups {
+    snapshots: Vec<Snapshot>,
+    selected: Option<usize>,
+    password: String,
+    summary: String,
+    error_message: String,
+}
+
+impl Backups {
+    pub fn new(vault: &VaultHandle) -> Self {
+        let (snapshots, error_message) = match list_snapshots(vault) {
+            Ok(snapshots) => (snapshots, String::new()),
+            Err(error) => (Vec::new(), error.to_string()),
+        };
+        Self {
+            snapshots,
+            selected: None,
+            password: String::new(),
+            summary: String::new(),
+            error_message,
+        }
+    }
+
+    /// 백업이 키 파일을 쓰면 `key_file`로 엶
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, key_file: Option<&KeyFile>, #[cfg(target_os = "windows")] center: [i32; 2]) -> Option<BackupsType> {
+        let mut backups_type = None;
+
+        let size = [420.0, 320.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("backups")
+            .with_inner_size(size)
+            .with_resizable(false)
+            .with_maximize_button(false);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("backups"),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    self.password.zeroize();
+                    backups_type = Some(BackupsType::Close);
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    if self.snapshots.is_empty() {
+                        ui.label("백업이 없습니다");
+                    }
+                    egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+                        for (index, snapshot) in self.snapshots.iter().enumerate() {
+                            let label = ui.selectable_label(self.selected == Some(index), snapshot.created_utc());
+                            if label.on_hover_text(snapshot.path().display().to_string()).clicked() {
+                                self.selected = Some(index);
+                                self.summary.clear();
+                                self.error_message.clear();
+                            }
+                        }
+                    });
+
+                    let Some(snapshot) = self.selected.map(|index| &self.snapshots[index]) else {
+                        return;
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.label("master password");
+                        ui.add(TextEdit::singleline(&mut self.password).password(true));
+                        if ui.button("inspect").on_hover_text("백업 당시의 마스터 비밀번호").clicked() {
+                            match inspect_snapshot(snapshot, &self.password, key_file) {
+                                Ok(summary) => {
+                                    self.summary = format!("사이트 {}개, 계정 {}개", summary.site_count, summary.entry_count);
+                                    self.error_message.clear();
+                                }
+                                Err(error) => self.error_message = error.to_string(),
+                            }
+                            self.password.zeroize();
+                        }
+                    });
+                    ui.label(&self.summary);
+
+                    if ui.button("restore").on_hover_text("저장하지 않은 변경 사항은 사라집니다").clicked() {
+                        match restore_snapshot(vault, snapshot) {
+                            Ok(()) => {
+                                self.password.zeroize();
+                                backups_type = Some(BackupsType::Restored);
+                            }
+                            Err(error) => self.error_message = error.to_string(),
+                        }
+                    }
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        backups_type
+    }
+}
+
+/// 지운 계정 목록. 되살리기, 완전히 지우기, 보관 기간 설정
+#[derive(Default)]
+pub struct Trash {
+    selected: Option<usize>,
+    error_message: String,
+}
+
+impl Trash {
+    pub fn display(&mut self, ui: &Ui, vault: &VaultHandle, data_base: &mut DB, read_only: bool, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+        let mut keep_open = true;
+
+        let size = [420.0, 320.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("trash")
+            .with_inner_size(size)
+            .with_resizable(false)
+            .with_maximize_button(false);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("trash"),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    if data_base.trash().is_empty() {
+                        ui.label("휴지통이 비어 있습니다");
+                    }
+                    egui::ScrollArea::vertical().max_height(180.0).show(ui, |ui| {
+                        for (index, trashed) in data_base.trash().iter().enumerate() {
+                            let text = match data_base.trashed_names(trashed) {
+                                Ok((site, id)) => format!("{} - {}", site.as_str(), id.as_str()),
+                                Err(error) => format!("error: {}", error),
+                            };
+                            let label = ui.selectable_label(self.selected == Some(index), text);
+                            if label.on_hover_text(format!("deleted {}", trashed.deleted_at())).clicked() {
+                                self.selected = Some(index);
+                                self.error_message.clear();
+                            }
+                        }
+                    });
+
+                    ui.add_enabled_ui(!read_only, |ui| {
+                        let mut retention_days = data_base.trash_retention_days();
+                        ui.horizontal(|ui| {
+                            ui.label("purge after");
+                            let changed = ui.add(egui::DragValue::new(&mut retention_days).range(0..=3650).suffix(" days"))
+                                .on_hover_text("0이면 자동으로 지우지 않습니다")
+                                .changed();
+                            if changed {
+                                set_trash_retention_days(data_base, retention_days);
+                                if let Err(error) = mark_as_ungraceful_exited_to_file(vault) {
+                                    self.error_message = error.to_string();
+                                }
+                            }
+                        });
+
+                        let result = ui.horizontal(|ui| -> Result<bool, Error> {
+                            let selected = self.selected.filter(|&index| index < data_base.trash().len());
+                            if ui.add_enabled(selected.is_some(), egui::Button::new("restore")).clicked() && let Some(index) = selected {
+                                restore_trashed(data_base, index)?;
+                                return Ok(true);
+                            }
+                            if ui.add_enabled(selected.is_some(), egui::Button::new("purge")).on_hover_text("되돌릴 수 없습니다").clicked() && let Some(index) = selected {
+                                purge_trashed(data_base, index)?;
+                                return Ok(true);
+                            }
+                            if ui.add_enabled(!data_base.trash().is_empty(), egui::Button::new("empty trash")).on_hover_text("되돌릴 수 없습니다").clicked() {
+                                empty_trash(data_base);
+                                return Ok(true);
+                            }
+                            Ok(false)
+                        }).inner;
+                        match result.and_then(|changed| {
+                            if changed {
+                                self.selected = None;
+                                mark_as_ungraceful_exited_to_file(vault)?;
+                            }
+                            Ok(changed)
+                        }) {
+                            Ok(true) => self.error_message.clear(),
+                            Ok(false) => {}
+                            Err(error) => self.error_message = error.to_string(),
+                        }
+                    });
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        keep_open
+    }
+}
+
+/// 비밀번호 점검 결과. 열 때와 "run audit"을 누를 때만 복호화함
+pub struct AuditDashboard {
+    policy: AuditPolicy,
+    report: Option<AuditReport>,
+    /// `None`이면 모든 종류를 보여 줌
+    kind_filter: Option<&'static str>,
+    error_message: String,
+}
+
+impl AuditDashboard {
+    pub fn new(data_base: &DB, key: &KeyPair) -> Self {
+        let mut audit_dashboard = Self {
+            policy: AuditPolicy::default(),
+            report: None,
+            kind_filter: None,
+            error_message: String::new(),
+        };
+        audit_dashboard.run(data_base, key);
+        audit_dashboard
+    }
+
+    fn run(&mut self, data_base: &DB, key: &KeyPair) {
+        let (wrapped_session_key, session_key_nonce) = key;
+        match audit_db(data_base, &self.policy, wrapped_session_key, session_key_nonce) {
+            Ok(report) => {
+                self.report = Some(report);
+                self.error_message.clear();
+            }
+            Err(error) => self.error_message = error.to_string(),
+        }
+    }
+
+    pub fn display(&mut self, ui: &Ui, key: &KeyPair, data_base: &DB, #[cfg(target_os = "windows")] center: [i32; 2]) -> bool {
+        let mut keep_open = true;
+
+        let size = [520.0, 420.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("password health")
+            .with_inner_size(size);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("password_health"),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    keep_open = false;
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    let mut run = false;
+                    ui.horizontal(|ui| {
+                        ui.label("max age (days)");
+                        ui.add(egui::DragValue::new(&mut self.policy.max_age_days).range(1..=3650));
+                        ui.label("min score");
+                        egui::ComboBox::from_id_salt("audit_min_score")
+                            .selected_text(self.policy.min_score.to_string())
+                            .show_ui(ui, |ui| {
+                                for score in PWScore::ALL {
+                                    ui.selectable_value(&mut self.policy.min_score, score, score.to_string());
+                                }
+                            });
+                        run = ui.button("run audit").clicked();
+                    });
+                    if run {
+                        self.run(data_base, key);
+                    }
+
+                    if let Some(report) = &self.report {
+                        ui.label(format!("{} 기준, 계정 {}개에서 문제 {}개", report.audited_at, report.entry_count, report.issues.len()));
+                        ui.horizontal_wrapped(|ui| {
+                            if ui.selectable_label(self.kind_filter.is_none(), format!("all ({})", report.issues.len())).clicked() {
+                                self.kind_filter = None;
+                            }
+                            for kind in AuditIssue::KINDS {
+                                let label = format!("{} ({})", kind, report.count(kind));
+                                if ui.selectable_label(self.kind_filter == Some(kind), label).clicked() {
+                                    self.kind_filter = Some(kind);
+                                }
+                            }
+                        });
+                        ui.separator();
+
+                        egui::ScrollArea::vertical().max_height(size[1] - 140.0).show(ui, |ui| {
+                            if report.issues.is_empty() {
+                                ui.label("문제가 없습니다");
+                            }
+                            for issue in report.issues.iter().filter(|issue| self.kind_filter.is_none_or(|kind| issue.kind() == kind)) {
+                                ui.label(format!("[{}] {}", issue.kind(), issue));
+                            }
+                        });
+                    }
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        keep_open
+    }
+}
+
+/// 시작할 때 열 볼트를 고르는 창
+pub struct VaultPicker {
+    vaults: Vec<VaultPath>,
+    vault_name: String,
+    vault_file_path: String,
+    error_message: String,
+}
+
+impl Default for VaultPicker {
+    fn default() -> Self {
+        let default_vault = VaultPath::default();
+        let mut vaults = list_vaults();
+        if !vaults.contains(&default_vault) {
+            vaults.insert(0, default_vault);
+        }
+        Self {
+            vaults,
+            vault_name: String::new(),
+            vault_file_path: String::new(),
+            error_message: String::new(),
+        }
+    }
+}
+
+impl VaultPicker {
+    /// 고른 볼트를 잠가서 반환함. 다른 프로세스가 쓰고 있으면 창에 에러를 보여줌
+    pub fn display(&mut self, ui: &Ui, #[cfg(target_os = "windows")] center: [i32; 2]) -> Option<VaultHandle> {
+        let mut picked = None;
+
+        let size = [400.0, 260.0];
+
+        let mut viewport_builder = ViewportBuilder::default()
+            .with_title("볼트 선택")
+            .with_inner_size(size)
+            .with_resizable(false)
+            .with_maximize_button(false);
+
+        #[cfg(target_os = "windows")]
+        {
+            let pixels_per_point = ui.native_pixels_per_point().unwrap_or(ui.pixels_per_point());
+            let center = [center[0] as f32 / pixels_per_point - size[0] / 2.0, center[1] as f32 / pixels_per_point - size[1] / 2.0];
+            viewport_builder = viewport_builder.with_position(center);
+        }
+
+        ui.show_viewport_immediate(
+            ViewportId::from_hash_of("vault_picker"),
+            viewport_builder,
+            |ui, _| {
+                if ui.input(|input_state| input_state.viewport().close_requested()) {
+                    ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
+                    return;
+                }
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    egui::ScrollArea::vertical().max_height(120.0).show(ui, |ui| {
+                        for vault in &self.vaults {
+                            let button = ui.button(vault.name());
+                            let button = button.on_hover_text(vault.db_file().display().to_string());
+                            if button.clicked() {
+                                picked = Some(vault.clone());
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("new vault name");
+                        ui.add(TextEdit::singleline(&mut self.vault_name));
+                        if ui.button("open").clicked() {
+                            let name = self.vault_name.trim();
+                            if name.is_empty() || name.contains(['/', '\\']) {
+                                self.error_message = "올바른 볼트 이름을 입력해주세요".to_string();
+                            } else {
+                                picked = Some(VaultPath::named(name));
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("vault file path");
+                        ui.add(TextEdit::singleline(&mut self.vault_file_path));
+                        if ui.button("open").clicked() {
+                            let path = self.vault_file_path.trim();
+                            if path.is_empty() {
+                                self.error_message = "경로를 입력해주세요".to_string();
+                            } else {
+                                picked = Some(VaultPath::new(path));
+                            }
+                        }
+                    });
+                    ui.label(&self.error_message);
+                });
+            },
+        );
+
+        match VaultHandle::open(picked?) {
+            Ok(vault) => Some(vault),
+            Err(error) => {
+                self.error_message = error.to_string();
+                None
+            }
+        }
+    }
+}
+
+/// 새 마스터 비밀번호 입력 아래의 강도 표시
+fn show_strength(ui: &mut Ui, strength: &Option<StrengthEstimate>) {
+    let Some(strength) = strength else {
+        return;
+    };
+    let feedback = &strength.feedback;
+    ui.label(format!("strength: {} ({}/4)", feedback.score, feedback.score.value()));
+    if let Some(warning) = feedback.warning {
+        ui.colored_label(ui.visuals().warn_fg_color, warning.to_string());
+    }
+    for suggestion in &feedback.suggestions {
+        ui.small(suggestion.to_string());
+    }
+}
+
+pub fn exit_root(ui: &Ui, vault: &VaultHandle, root_window: &mut Option<RootSave>) {
+    if check_can_directly_exit(vault) {
         ui.send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
         return;
     }